@@ -0,0 +1,56 @@
+//! Compares `get_core_ids` with the allocation-free `core_ids`.
+//!
+//! Run with `cargo bench --bench core_ids`.
+
+extern crate core_affinity;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const ITERATIONS: u32 = 100_000;
+
+// Counts every allocation, so the output shows which path allocates.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn bench<F>(name: &str, f: F)
+    where F: Fn() -> usize
+{
+    // Warm up, so lazily initialized state is not counted.
+    black_box(f());
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+
+    let elapsed = start.elapsed().as_nanos() as f64 / ITERATIONS as f64;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    println!("{:<24} {:>8.1} ns/call {:>6.2} allocs/call",
+             name, elapsed, allocations as f64 / ITERATIONS as f64);
+}
+
+fn main() {
+    bench("get_core_ids().len()", || core_affinity::get_core_ids().map_or(0, |ids| ids.len()));
+    bench("core_ids().count()", || core_affinity::core_ids().map_or(0, |ids| ids.count()));
+}
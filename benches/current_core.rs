@@ -0,0 +1,34 @@
+//! Compares the cost of `current_core` and `current_core_fast`.
+//!
+//! Run with `cargo bench --bench current_core`.
+
+extern crate core_affinity;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 1_000_000;
+
+fn bench<F>(name: &str, f: F)
+    where F: Fn() -> Option<core_affinity::CoreId>
+{
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+
+    println!("{:<28} {:>8.1} ns/call", name, start.elapsed().as_nanos() as f64 / ITERATIONS as f64);
+}
+
+fn main() {
+    bench("current_core (unpinned)", core_affinity::current_core);
+    bench("current_core_fast (unpinned)", core_affinity::current_core_fast);
+
+    if let Some(id) = core_affinity::get_core_ids().and_then(|ids| ids.last().cloned()) {
+        core_affinity::set_for_current(id);
+
+        bench("current_core (pinned)", core_affinity::current_core);
+        bench("current_core_fast (pinned)", core_affinity::current_core_fast);
+    }
+}
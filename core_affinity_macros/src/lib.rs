@@ -0,0 +1,102 @@
+//! Attribute macros for `core_affinity`.
+//!
+//! Use them through the `macros` feature of `core_affinity`,
+//! which re-exports them, rather than depending on this crate
+//! directly.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::parse::Parser;
+use syn::{parse_macro_input, Error, ItemFn, LitInt};
+
+/// Pins the thread that calls the function to a core for the
+/// duration of the call.
+///
+/// `core` names the core by ID. The previous affinity of the
+/// thread is restored when the function returns, panics or
+/// returns early. If pinning fails, the function runs unpinned
+/// after a warning, or panics if `strict` is given. The warning
+/// goes through `tracing` if the `tracing` feature of
+/// `core_affinity` is on, and to stderr otherwise.
+///
+/// The signature is left alone, so generic functions and
+/// `main` functions returning a `Result` work as usual.
+/// `async` functions are rejected, since their body may move
+/// between threads.
+///
+/// ```ignore
+/// #[core_affinity::pinned(core = 0, strict)]
+/// fn main() {
+///     // Runs on core 0.
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn pinned(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut config = Config { core: None, strict: false };
+    let parser = syn::meta::parser(|meta| config.parse(meta));
+    let parsed = parser.parse(args);
+
+    let function = parse_macro_input!(item as ItemFn);
+
+    match parsed.and_then(|()| expand(config, function.clone())) {
+        Ok(tokens) => tokens.into(),
+        // Keep the function, so the only error is ours.
+        Err(err) => {
+            let err = err.to_compile_error();
+            quote!(#err #function).into()
+        },
+    }
+}
+
+struct Config {
+    core: Option<usize>,
+    strict: bool,
+}
+
+impl Config {
+    fn parse(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("core") {
+            let value = meta.value()?;
+            let expected = |span| Error::new(span, "expected an integer core ID, such as `core = 0`");
+            let lit: LitInt = value.parse().map_err(|err| expected(err.span()))?;
+
+            self.core = Some(lit.base10_parse().map_err(|_| expected(lit.span()))?);
+            Ok(())
+        }
+        else if meta.path.is_ident("strict") {
+            self.strict = true;
+            Ok(())
+        }
+        else {
+            Err(meta.error("unknown argument, expected `core = <id>` or `strict`"))
+        }
+    }
+}
+
+fn expand(config: Config, mut function: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let core = match config.core {
+        Some(core) => core,
+        None => return Err(Error::new(Span::call_site(), "missing the core to pin to, such as `core = 0`")),
+    };
+
+    if let Some(asyncness) = function.sig.asyncness {
+        return Err(Error::new(asyncness.span, "`#[pinned]` can not be used on an `async fn`"));
+    }
+
+    let name = function.sig.ident.to_string();
+    let strict = config.strict;
+    let block = function.block;
+
+    // The guard lives until the end of the body, which keeps
+    // `return` and `?` working unchanged.
+    function.block = Box::new(syn::parse_quote!({
+        let __core_affinity_guard = ::core_affinity::__pin_function(#name, #core, #strict);
+        #block
+    }));
+
+    Ok(quote!(#function))
+}
@@ -0,0 +1,4 @@
+#[core_affinity::pinned(core = 0)]
+async fn run() {}
+
+fn main() {}
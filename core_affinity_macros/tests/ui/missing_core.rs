@@ -0,0 +1,2 @@
+#[core_affinity::pinned(strict)]
+fn main() {}
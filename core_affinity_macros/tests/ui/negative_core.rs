@@ -0,0 +1,2 @@
+#[core_affinity::pinned(core = -1)]
+fn main() {}
@@ -0,0 +1,2 @@
+#[core_affinity::pinned(core = "zero")]
+fn main() {}
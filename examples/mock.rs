@@ -0,0 +1,54 @@
+//! Shows how a crate that pins its threads can test that logic
+//! against the mock backend, independent of the machine.
+//!
+//! Run it with `cargo run --example mock --features mock`. In a
+//! real crate, enable the feature for tests only:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! core_affinity = { version = "0.8", features = ["mock"] }
+//! ```
+
+extern crate core_affinity;
+
+use std::thread;
+
+use core_affinity::{CoreId, ErrorKind};
+use core_affinity::mock::{self, Target};
+
+// The code under test: start one worker per core, skipping the
+// cores that can not be pinned, and return where they ran.
+fn start_workers() -> Vec<CoreId> {
+    let handles = core_affinity::get_core_ids().unwrap().into_iter().map(|core_id| {
+        thread::spawn(move || {
+            if core_affinity::set_for_current(core_id) {
+                core_affinity::current_core()
+            }
+            else {
+                None
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    handles.into_iter().filter_map(|handle| handle.join().unwrap()).collect()
+}
+
+fn main() {
+    // A machine with 8 cores, on which core 3 needs privileges
+    // the process does not have.
+    mock::set_core_count(8);
+    mock::fail_on(CoreId { id: 3 }, ErrorKind::PermissionDenied);
+
+    let cores = start_workers();
+    assert_eq!(cores.len(), 7);
+    assert!(!cores.contains(&CoreId { id: 3 }));
+
+    // Every worker asked for its core exactly once.
+    let calls = mock::all_set_calls();
+    assert_eq!(calls.len(), 8);
+    assert!(calls.iter().all(|call| matches!(call.target, Target::Thread(_))));
+    assert_eq!(calls.iter().filter(|call| call.result.is_err()).count(), 1);
+
+    mock::reset();
+    println!("workers ran on {:?}", cores);
+}
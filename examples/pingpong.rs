@@ -0,0 +1,106 @@
+//! Bounces a counter between two threads and prints how long a
+//! round trip takes when both are pinned to the same core, and
+//! when they are pinned to different cores.
+//!
+//! Run it with `cargo run --release --example pingpong`. On the
+//! same core every hand-over needs a context switch; on two
+//! cores it is a cache line moving between them, which is
+//! cheaper on cores that share a cache than on cores of
+//! different packages. Pass two core IDs, such as
+//! `cargo run --release --example pingpong 0 3`, to measure a
+//! particular pair.
+
+extern crate core_affinity;
+
+use std::env;
+use std::sync::{Arc, Barrier};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core_affinity::CoreId;
+
+const ROUND_TRIPS: usize = 20_000;
+
+fn main() {
+    let core_ids = match core_affinity::get_core_ids() {
+        Some(core_ids) if !core_ids.is_empty() => core_ids,
+        _ => {
+            println!("the cores can not be listed on this platform");
+            return;
+        },
+    };
+
+    let args: Vec<usize> = env::args().skip(1).filter_map(|arg| arg.parse().ok()).collect();
+    let (first, second) = match args[..] {
+        [first, second] => (CoreId { id: first }, CoreId { id: second }),
+        _ => (core_ids[0], *core_ids.last().unwrap()),
+    };
+
+    report("same core", first, first);
+
+    if first != second {
+        report("different cores", first, second);
+    }
+    else {
+        println!("only one core is available, so there is nothing to compare");
+    }
+}
+
+fn report(name: &str, ping: CoreId, pong: CoreId) {
+    match pingpong(ping, pong) {
+        Some(elapsed) => {
+            let nanos = elapsed.as_nanos() / ROUND_TRIPS as u128;
+            println!("{} ({} and {}): {} ns per round trip", name, ping, pong, nanos);
+        },
+        None => println!("{} ({} and {}): could not pin the threads", name, ping, pong),
+    }
+}
+
+// Runs the round trips and returns how long they took, or
+// `None` if either thread could not be pinned.
+fn pingpong(ping: CoreId, pong: CoreId) -> Option<Duration> {
+    // Odd values are the turn of the other thread.
+    let counter = Arc::new(AtomicUsize::new(0));
+    let theirs = counter.clone();
+    // Both threads are pinned before the clock starts.
+    let ready = Arc::new(Barrier::new(2));
+    let their_ready = ready.clone();
+
+    let other = thread::spawn(move || {
+        let pinned = core_affinity::set_for_current(pong);
+        their_ready.wait();
+        bounce(&theirs, 1);
+        pinned
+    });
+
+    let pinned = core_affinity::set_for_current(ping);
+    ready.wait();
+    let start = Instant::now();
+    bounce(&counter, 0);
+    wait_for(&counter, ROUND_TRIPS * 2);
+    let elapsed = start.elapsed();
+
+    let other_pinned = other.join().unwrap();
+    core_affinity::clear_for_current();
+
+    if pinned && other_pinned { Some(elapsed) } else { None }
+}
+
+// Waits for each turn of `parity` and hands it over, until
+// every round trip is done.
+fn bounce(counter: &AtomicUsize, parity: usize) {
+    for round in 0..ROUND_TRIPS {
+        let turn = round * 2 + parity;
+
+        wait_for(counter, turn);
+        counter.store(turn + 1, Ordering::Release);
+    }
+}
+
+fn wait_for(counter: &AtomicUsize, value: usize) {
+    while counter.load(Ordering::Acquire) != value {
+        // Lets the other thread run when both share a core.
+        thread::yield_now();
+    }
+}
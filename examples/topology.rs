@@ -0,0 +1,38 @@
+//! Lists every online core and whether the current thread may
+//! run on it.
+//!
+//! Run it with `cargo run --example topology`, or under
+//! `taskset -c 0,2 cargo run --example topology` to see a
+//! restricted mask. Platforms that can not report a mask show
+//! every core as allowed.
+
+extern crate core_affinity;
+
+fn main() {
+    let online = match core_affinity::get_all_core_ids() {
+        Some(online) => online,
+        None => {
+            println!("the online cores can not be listed on this platform");
+            return;
+        },
+    };
+
+    let allowed = core_affinity::get_core_set();
+    let current = core_affinity::current_core();
+
+    println!("{:>5}  {:>7}  {:>7}", "core", "allowed", "current");
+
+    for core_id in online {
+        let is_allowed = allowed.as_ref().is_none_or(|allowed| allowed.contains(core_id));
+        let is_current = current == Some(core_id);
+
+        println!("{:>5}  {:>7}  {:>7}", core_id.id, yes_no(is_allowed), if is_current { "*" } else { "" });
+    }
+
+    println!();
+    print!("{}", core_affinity::report());
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
@@ -0,0 +1,55 @@
+/*
+ * C interface to the core_affinity crate, built with its `ffi`
+ * feature. See `src/ffi.rs` for the full documentation.
+ *
+ * Functions return CORE_AFFINITY_OK or a count on success, and a
+ * negative CORE_AFFINITY_ERROR_* code on failure. The caller owns
+ * every buffer it passes in; no pointer is kept past the call.
+ */
+
+#ifndef CORE_AFFINITY_H
+#define CORE_AFFINITY_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CORE_AFFINITY_OK 0
+#define CORE_AFFINITY_ERROR_PERMISSION_DENIED (-1)
+#define CORE_AFFINITY_ERROR_INVALID_CORE (-2)
+#define CORE_AFFINITY_ERROR_UNSUPPORTED (-3)
+#define CORE_AFFINITY_ERROR_EMPTY_SET (-4)
+#define CORE_AFFINITY_ERROR_NOT_FOUND (-5)
+#define CORE_AFFINITY_ERROR_NOT_ALLOWED (-6)
+#define CORE_AFFINITY_ERROR_NOT_EFFECTIVE (-7)
+#define CORE_AFFINITY_ERROR_OTHER (-8)
+#define CORE_AFFINITY_ERROR_NULL_POINTER (-9)
+#define CORE_AFFINITY_ERROR_PANIC (-10)
+#define CORE_AFFINITY_ERROR_INVALID_PRIORITY (-11)
+#define CORE_AFFINITY_ERROR_TOO_FEW_CORES (-12)
+
+/*
+ * Writes up to `cap` IDs of the cores the current thread may run
+ * on to `buf`, in ascending order, and returns how many there are,
+ * which may be more than `cap`. `buf` may be NULL if `cap` is 0.
+ */
+ptrdiff_t core_affinity_get_core_ids(size_t *buf, size_t cap);
+
+/*
+ * Pins the current thread to core `core`.
+ */
+int core_affinity_set_for_current(size_t core);
+
+/*
+ * Returns a static description of `code`, which must not be freed.
+ * Never returns NULL.
+ */
+const char *core_affinity_error_message(int code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CORE_AFFINITY_H */
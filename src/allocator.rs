@@ -0,0 +1,666 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use super::{CoreId, Error, ErrorKind, get_affinity_for_current, get_core_ids, record};
+use reservation::reserved_core_ids;
+use package::{ClusterId, clusters};
+use shared_cache::cache_clusters;
+use topology::{CoreInfo, core_info};
+
+/// The order in which cores are assigned to threads.
+///
+/// The placements need to know the package, die and NUMA node
+/// of every core, as reported by `packages`, `dies` and
+/// `numa_node_of`. Where that is not known, they fall back to
+/// plain ID order.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Placement {
+    /// Fills the cores of one NUMA node, die and package
+    /// before moving on to the next, so that consecutive
+    /// threads share caches.
+    #[default]
+    Compact,
+    /// Takes turns between packages, and between the NUMA
+    /// nodes of each package, so that consecutive threads are
+    /// as far apart as possible and share memory bandwidth.
+    Scatter,
+    /// Takes turns between NUMA nodes or packages, as chosen
+    /// by the granularity, and fills each of them in the
+    /// same order as `Compact`. On a machine with a single
+    /// node or package, this is the same as `Compact`.
+    Interleave(NumaGranularity),
+}
+
+/// What `Placement::Interleave` takes turns between.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum NumaGranularity {
+    /// Every NUMA node gets the next core in turn.
+    #[default]
+    Node,
+    /// Every package, or socket, gets the next core in turn.
+    Package,
+}
+
+/// This function splits the cores on which the current thread
+/// is allowed to run into `n` groups of consecutive cores,
+/// such as one group for each shard of a service.
+///
+/// It is `distribute_with` using `Placement::Compact`, so the
+/// cores of a group are as close to each other as possible.
+///
+/// # Arguments
+///
+/// * n - number of groups
+pub fn distribute(n: usize) -> Result<Vec<Vec<CoreId>>, Error> {
+    distribute_with(n, Placement::Compact)
+}
+
+/// This function splits the cores on which the current thread
+/// is allowed to run into `n` groups, taking the cores in the
+/// order given by `placement`.
+///
+/// The groups differ in size by at most one core, with the
+/// larger groups first. If `n` is greater than the number of
+/// cores, every group gets a single core and the cores are
+/// reused in turn, so no group is empty. Fails with
+/// `ErrorKind::EmptySet` if `n` is zero.
+///
+/// # Arguments
+///
+/// * n - number of groups
+/// * placement - order in which to take the cores
+pub fn distribute_with(n: usize, placement: Placement) -> Result<Vec<Vec<CoreId>>, Error> {
+    let core_ids = record(super::core_ids_helper())?.collect::<Vec<_>>();
+
+    record(split(&place(&core_info(&core_ids), placement), n))
+}
+
+/// This function splits the cores on which the current thread
+/// is allowed to run into `n` groups like `distribute`, but
+/// keeps every group within one die where it can.
+///
+/// Dies of one package can be far apart, so each die gets a
+/// share of the groups for its cores instead of groups
+/// straddling two dies. If `n` is less than the number of
+/// dies, or the dies are not known, this is `distribute`.
+///
+/// # Arguments
+///
+/// * n - number of groups
+pub fn distribute_within_dies(n: usize) -> Result<Vec<Vec<CoreId>>, Error> {
+    let core_ids = record(super::core_ids_helper())?.collect::<Vec<_>>();
+
+    record(split_within_dies(&core_info(&core_ids), n))
+}
+
+/// This function splits the cores on which the current thread
+/// is allowed to run into `n` groups like
+/// `distribute_within_dies`, but keeps every group within one
+/// cache cluster, such as a CCX, where it can.
+///
+/// # Arguments
+///
+/// * n - number of groups
+pub fn distribute_within_cache_clusters(n: usize) -> Result<Vec<Vec<CoreId>>, Error> {
+    let core_ids = record(super::core_ids_helper())?.collect::<Vec<_>>();
+    let clusters = cache_clusters().map(|clusters| clusters.into_iter().map(|(_, core_ids)| core_ids).collect());
+
+    record(split_within(&place(&core_info(&core_ids), Placement::Compact), clusters, n))
+}
+
+fn split_within_dies(infos: &[CoreInfo], n: usize) -> Result<Vec<Vec<CoreId>>, Error> {
+    let dies = if infos.iter().all(|info| info.package.zip(info.die).is_some()) {
+        let dies = group_by(infos, |info| info.package.zip(info.die));
+        Some(dies.into_iter().map(|die| die.into_iter().map(|info| info.core_id).collect()).collect())
+    }
+    else {
+        None
+    };
+
+    split_within(&place(infos, Placement::Compact), dies, n)
+}
+
+// Splits `core_ids` into `n` groups that each stay within one
+// of `parts`, in the order of `parts` and then of `core_ids`.
+// Without two parts to split between, or if a core is in none
+// of them, or if there are fewer groups than parts, this is
+// `split`.
+fn split_within(core_ids: &[CoreId], parts: Option<Vec<Vec<CoreId>>>, n: usize) -> Result<Vec<Vec<CoreId>>, Error> {
+    let parts: Vec<Vec<CoreId>> = parts.unwrap_or_default().iter()
+        .map(|part| core_ids.iter().cloned().filter(|core_id| part.contains(core_id)).collect::<Vec<_>>())
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    if parts.len() < 2 || n < parts.len() || parts.iter().map(Vec::len).sum::<usize>() != core_ids.len() {
+        return split(core_ids, n);
+    }
+
+    // Each part gets a group, and then the others go to the
+    // parts with the most cores per group.
+    let mut shares = vec![1; parts.len()];
+
+    for _ in parts.len()..n {
+        let index = (0..parts.len())
+            .max_by(|&a, &b| (parts[a].len() * shares[b]).cmp(&(parts[b].len() * shares[a])).then(b.cmp(&a)))
+            .unwrap();
+
+        shares[index] += 1;
+    }
+
+    let mut groups = Vec::with_capacity(n);
+
+    for (part, share) in parts.iter().zip(shares) {
+        groups.extend(split(part, share)?);
+    }
+
+    Ok(groups)
+}
+
+// Orders the cores of `infos` for `placement`.
+fn place(infos: &[CoreInfo], placement: Placement) -> Vec<CoreId> {
+    match placement {
+        Placement::Compact => {
+            let mut infos = infos.to_vec();
+            infos.sort_by_key(|info| (info.package, info.die, info.node, info.core_id));
+            infos.into_iter().map(|info| info.core_id).collect()
+        },
+        Placement::Interleave(granularity) => {
+            let groups = match granularity {
+                NumaGranularity::Node => group_by(infos, |info| info.node),
+                NumaGranularity::Package => group_by(infos, |info| info.package),
+            };
+
+            interleave(groups.iter().map(|group| place(group, Placement::Compact)).collect())
+        },
+        Placement::Scatter => {
+            let packages = group_by(infos, |info| info.package).into_iter().map(|package| {
+                interleave(group_by(&package, |info| info.node).into_iter()
+                           .map(|node| node.into_iter().map(|info| info.core_id).collect())
+                           .collect())
+            }).collect();
+
+            interleave(packages)
+        },
+    }
+}
+
+// Splits `infos` into groups with the same key, in ascending
+// order of key and then of core.
+fn group_by<K, F>(infos: &[CoreInfo], key: F) -> Vec<Vec<CoreInfo>>
+    where K: Ord,
+          F: Fn(&CoreInfo) -> K
+{
+    let mut groups: BTreeMap<K, Vec<CoreInfo>> = BTreeMap::new();
+
+    for &info in infos {
+        groups.entry(key(&info)).or_default().push(info);
+    }
+
+    for group in groups.values_mut() {
+        group.sort_by_key(|info| info.core_id);
+    }
+
+    groups.into_values().collect()
+}
+
+// Takes the first core of every list, then the second, and so on.
+pub(crate) fn interleave(lists: Vec<Vec<CoreId>>) -> Vec<CoreId> {
+    let longest = lists.iter().map(Vec::len).max().unwrap_or(0);
+
+    (0..longest)
+        .flat_map(|i| lists.iter().filter_map(move |list| list.get(i).cloned()))
+        .collect()
+}
+
+fn split(core_ids: &[CoreId], n: usize) -> Result<Vec<Vec<CoreId>>, Error> {
+    if n == 0 || core_ids.is_empty() {
+        return Err(Error::new(ErrorKind::EmptySet));
+    }
+
+    if n >= core_ids.len() {
+        return Ok((0..n).map(|i| vec![core_ids[i % core_ids.len()]]).collect());
+    }
+
+    let size = core_ids.len() / n;
+    let larger = core_ids.len() % n;
+    let mut rest = core_ids;
+
+    Ok((0..n).map(|i| {
+        let (group, tail) = rest.split_at(if i < larger { size + 1 } else { size });
+        rest = tail;
+        group.to_vec()
+    }).collect())
+}
+
+/// Hands out cores to threads as they are created, spreading
+/// them evenly.
+///
+/// `next` returns the core with the fewest threads on it,
+/// taking turns between cores with the same number, so the
+/// cores are assigned round-robin and wrap around once every
+/// core has a thread. `release` gives a core back when its
+/// thread retires. The allocator can be shared between
+/// threads, such as behind an `Arc`.
+///
+/// With `skipping_reserved`, cores held by a `CoreReservation`
+/// are passed over while any other core is left.
+#[derive(Debug)]
+pub struct CoreAllocator {
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    core_ids: Vec<CoreId>,
+    // The number of threads on each core.
+    loads: Vec<usize>,
+    // The core to try first on the next call.
+    cursor: usize,
+    // Whether to pass over reserved cores.
+    skip_reserved: bool,
+}
+
+impl CoreAllocator {
+    /// Creates an allocator for the cores on which the current
+    /// thread is allowed to run.
+    ///
+    /// If the current thread is already pinned, that is only
+    /// its own core; seed the allocator from
+    /// `get_all_core_ids` with `from_core_ids` instead.
+    pub fn new() -> Option<CoreAllocator> {
+        get_core_ids().and_then(|core_ids| CoreAllocator::from_core_ids(&core_ids))
+    }
+
+    /// Creates an allocator like `new`, handing the cores out
+    /// in the order given by `placement`.
+    pub fn with_placement(placement: Placement) -> Option<CoreAllocator> {
+        get_core_ids().and_then(|core_ids| {
+            CoreAllocator::from_core_ids(&place(&core_info(&core_ids), placement))
+        })
+    }
+
+    /// Creates an allocator for `core_ids`, handed out in the
+    /// order given. Returns `None` if the list is empty.
+    pub fn from_core_ids(core_ids: &[CoreId]) -> Option<CoreAllocator> {
+        if core_ids.is_empty() {
+            return None;
+        }
+
+        let mut unique = Vec::with_capacity(core_ids.len());
+
+        for &core_id in core_ids {
+            if !unique.contains(&core_id) {
+                unique.push(core_id);
+            }
+        }
+
+        Some(CoreAllocator {
+            state: Mutex::new(State {
+                loads: vec![0; unique.len()],
+                core_ids: unique,
+                cursor: 0,
+                skip_reserved: false,
+            }),
+        })
+    }
+
+    /// Stops handing out `core_id`, unless it is the only core
+    /// left.
+    pub fn excluding(self, core_id: CoreId) -> CoreAllocator {
+        {
+            let mut state = self.lock();
+
+            if let Some(index) = state.core_ids.iter().position(|&id| id == core_id) {
+                if state.core_ids.len() > 1 {
+                    state.core_ids.remove(index);
+                    state.loads.remove(index);
+                    state.cursor = 0;
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Stops handing out the core the current thread is pinned
+    /// to, such as that of the main thread. Does nothing if the
+    /// current thread is not pinned to a single core, or if
+    /// its affinity can not be read.
+    pub fn excluding_current(self) -> CoreAllocator {
+        match get_affinity_for_current() {
+            Some(ref core_ids) if core_ids.len() == 1 => self.excluding(core_ids[0]),
+            _ => self,
+        }
+    }
+
+    /// Stops handing out cores outside of `cluster`, so that a
+    /// pool of threads stays on cores that share an L2 cache
+    /// and a microarchitecture. Does nothing if none of the
+    /// cores is on the cluster, or where the clusters are not
+    /// known.
+    pub fn within_cluster(self, cluster: ClusterId) -> CoreAllocator {
+        let cores = clusters().and_then(|clusters| {
+            clusters.into_iter().find(|&(other, _)| other == cluster).map(|(_, core_ids)| core_ids)
+        });
+
+        if let Some(cores) = cores {
+            let mut state = self.lock();
+
+            if state.core_ids.iter().any(|core_id| cores.contains(core_id)) {
+                let kept = (0..state.core_ids.len()).filter(|&i| cores.contains(&state.core_ids[i])).collect::<Vec<_>>();
+
+                state.core_ids = kept.iter().map(|&i| state.core_ids[i]).collect();
+                state.loads = kept.iter().map(|&i| state.loads[i]).collect();
+                state.cursor = 0;
+            }
+        }
+
+        self
+    }
+
+    /// Passes over the cores that are reserved at the time of
+    /// each call to `next`, unless every core is.
+    pub fn skipping_reserved(self) -> CoreAllocator {
+        self.lock().skip_reserved = true;
+        self
+    }
+
+    /// Returns the core for the next thread.
+    pub fn next(&self) -> CoreId {
+        let mut state = self.lock();
+        let count = state.core_ids.len();
+        let first = state.cursor;
+        let reserved = if state.skip_reserved { reserved_core_ids() } else { Vec::new() };
+        let order = (0..count).map(|i| (first + i) % count);
+
+        let index = order.clone()
+            .filter(|&index| !reserved.contains(&state.core_ids[index]))
+            .min_by_key(|&index| state.loads[index])
+            .or_else(|| order.min_by_key(|&index| state.loads[index]))
+            .unwrap_or(0);
+
+        state.loads[index] += 1;
+        state.cursor = (index + 1) % count;
+        state.core_ids[index]
+    }
+
+    /// Gives back a core returned by `next`, once its thread
+    /// has retired. Cores the allocator does not hand out are
+    /// ignored.
+    pub fn release(&self, core_id: CoreId) {
+        let mut state = self.lock();
+
+        if let Some(index) = state.core_ids.iter().position(|&id| id == core_id) {
+            state.loads[index] = state.loads[index].saturating_sub(1);
+        }
+    }
+
+    /// Returns the cores the allocator hands out.
+    pub fn core_ids(&self) -> Vec<CoreId> {
+        self.lock().core_ids.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // The state stays consistent even if a holder panicked.
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+    use test_util::ids;
+
+    // 8 cores on 2 packages, each its own NUMA node, with the
+    // cores of the packages numbered alternately.
+    fn two_packages() -> Vec<CoreInfo> {
+        (0..8).map(|id| CoreInfo {
+            core_id: CoreId { id },
+            package: Some(id % 2),
+            die: None,
+            node: Some(id % 2),
+        }).collect()
+    }
+
+    #[test]
+    fn test_place_compact() {
+        let order = place(&two_packages(), Placement::Compact);
+        assert_eq!(order, ids(&[0, 2, 4, 6, 1, 3, 5, 7]));
+    }
+
+    #[test]
+    fn test_place_scatter() {
+        let order = place(&two_packages(), Placement::Scatter);
+        assert_eq!(order, ids(&[0, 1, 2, 3, 4, 5, 6, 7]));
+
+        // Two nodes within each package.
+        let infos = (0..8).map(|id| CoreInfo {
+            core_id: CoreId { id },
+            package: Some(id / 4),
+            die: None,
+            node: Some(id / 2),
+        }).collect::<Vec<_>>();
+
+        assert_eq!(place(&infos, Placement::Scatter), ids(&[0, 4, 2, 6, 1, 5, 3, 7]));
+        assert_eq!(place(&infos, Placement::Compact), ids(&[0, 1, 2, 3, 4, 5, 6, 7]));
+    }
+
+    #[test]
+    fn test_place_interleave() {
+        // 2 nodes of 8 cores each, numbered in order.
+        let infos = (0..16).map(|id| CoreInfo {
+            core_id: CoreId { id },
+            package: Some(id / 8),
+            die: None,
+            node: Some(id / 8),
+        }).collect::<Vec<_>>();
+
+        let expected = ids(&[0, 8, 1, 9, 2, 10, 3, 11, 4, 12, 5, 13, 6, 14, 7, 15]);
+        assert_eq!(place(&infos, Placement::Interleave(NumaGranularity::Node)), expected);
+        assert_eq!(place(&infos, Placement::Interleave(NumaGranularity::Package)), expected);
+
+        // A single node is filled in compact order.
+        let single = two_packages().into_iter()
+            .map(|info| CoreInfo { node: Some(0), ..info })
+            .collect::<Vec<_>>();
+
+        assert_eq!(place(&single, Placement::Interleave(NumaGranularity::Node)),
+                   place(&single, Placement::Compact));
+    }
+
+    #[test]
+    fn test_place_unknown_topology() {
+        let infos = [5, 1, 3].iter().map(|&id| CoreInfo::unknown(CoreId { id })).collect::<Vec<_>>();
+
+        assert_eq!(place(&infos, Placement::Compact), ids(&[1, 3, 5]));
+        assert_eq!(place(&infos, Placement::Scatter), ids(&[1, 3, 5]));
+    }
+
+    #[test]
+    fn test_split_within_dies() {
+        // A package of 2 dies, with 6 and 2 cores.
+        let infos = (0..8).map(|id| CoreInfo {
+            core_id: CoreId { id },
+            package: Some(0),
+            die: Some(if id < 6 { 0 } else { 1 }),
+            node: Some(0),
+        }).collect::<Vec<_>>();
+
+        let groups = split_within_dies(&infos, 3).unwrap();
+        assert_eq!(groups, vec![ids(&[0, 1, 2]), ids(&[3, 4, 5]), ids(&[6, 7])]);
+
+        // Plain splitting would put cores 4 to 7 together.
+        let groups = split_within_dies(&infos, 2).unwrap();
+        assert_eq!(groups, vec![ids(&[0, 1, 2, 3, 4, 5]), ids(&[6, 7])]);
+
+        // Fewer groups than dies, and unknown dies, are split
+        // as usual.
+        assert_eq!(split_within_dies(&infos, 1).unwrap(), vec![ids(&[0, 1, 2, 3, 4, 5, 6, 7])]);
+
+        let unknown = infos.iter().map(|&info| CoreInfo { die: None, ..info }).collect::<Vec<_>>();
+        assert_eq!(split_within_dies(&unknown, 2).unwrap(), vec![ids(&[0, 1, 2, 3]), ids(&[4, 5, 6, 7])]);
+    }
+
+    #[test]
+    fn test_split_within() {
+        // The two CCDs of a 5950X, with the second threads of
+        // the cores numbered after the first ones.
+        let ccds = vec![ids(&[0, 1, 2, 3, 16, 17, 18, 19]), ids(&[4, 5, 6, 7, 20, 21, 22, 23])];
+        let core_ids = ids(&[0, 1, 2, 3, 4, 5, 6, 7, 16, 17, 18, 19, 20, 21, 22, 23]);
+
+        let groups = split_within(&core_ids, Some(ccds.clone()), 4).unwrap();
+        assert_eq!(groups, vec![ids(&[0, 1, 2, 3]), ids(&[16, 17, 18, 19]), ids(&[4, 5, 6, 7]), ids(&[20, 21, 22, 23])]);
+
+        // A core in none of the parts is split as usual.
+        assert_eq!(split_within(&ids(&[0, 8]), Some(ccds), 2).unwrap(), vec![ids(&[0]), ids(&[8])]);
+    }
+
+    #[test]
+    fn test_split() {
+        let groups = split(&ids(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]), 3).unwrap();
+        assert_eq!(groups, vec![ids(&[0, 1, 2, 3]), ids(&[4, 5, 6]), ids(&[7, 8, 9])]);
+
+        let groups = split(&ids(&[0, 1, 2, 3]), 2).unwrap();
+        assert_eq!(groups, vec![ids(&[0, 1]), ids(&[2, 3])]);
+
+        // More groups than cores reuse the cores in turn.
+        let groups = split(&ids(&[4, 5]), 5).unwrap();
+        assert_eq!(groups, vec![ids(&[4]), ids(&[5]), ids(&[4]), ids(&[5]), ids(&[4])]);
+
+        assert_eq!(split(&ids(&[0]), 0).unwrap_err().kind(), ErrorKind::EmptySet);
+    }
+
+    #[test]
+    fn test_distribute() {
+        let all = get_core_ids().unwrap();
+
+        assert_eq!(distribute(1).unwrap(), vec![all.clone()]);
+        assert_eq!(distribute(3).unwrap().len(), 3);
+        assert!(distribute(0).is_err());
+
+        let flat = distribute(all.len()).unwrap().concat();
+        assert_eq!(flat, all);
+
+        let mut flat = distribute_with(all.len(), Placement::Scatter).unwrap().concat();
+        flat.sort();
+        assert_eq!(flat, all);
+
+        let mut handed_out = CoreAllocator::with_placement(Placement::Scatter).unwrap().core_ids();
+        handed_out.sort();
+        assert_eq!(handed_out, all);
+    }
+
+    #[test]
+    fn test_core_allocator_round_robin() {
+        let allocator = CoreAllocator::from_core_ids(&ids(&[0, 1, 2])).unwrap();
+
+        let handed_out = (0..7).map(|_| allocator.next().id).collect::<Vec<_>>();
+        assert_eq!(handed_out, vec![0, 1, 2, 0, 1, 2, 0]);
+
+        assert!(CoreAllocator::from_core_ids(&[]).is_none());
+        assert_eq!(CoreAllocator::new().unwrap().core_ids(), get_core_ids().unwrap());
+    }
+
+    #[test]
+    fn test_core_allocator_release() {
+        let allocator = CoreAllocator::from_core_ids(&ids(&[0, 1, 2])).unwrap();
+
+        for _ in 0..6 {
+            allocator.next();
+        }
+
+        // The released core has the fewest threads now.
+        allocator.release(CoreId { id: 1 });
+        allocator.release(CoreId { id: 99 });
+        assert_eq!(allocator.next(), CoreId { id: 1 });
+        assert_eq!(allocator.next(), CoreId { id: 2 });
+    }
+
+    #[test]
+    fn test_core_allocator_excluding() {
+        let allocator = CoreAllocator::from_core_ids(&ids(&[0, 1, 2])).unwrap()
+            .excluding(CoreId { id: 0 });
+
+        assert_eq!(allocator.core_ids(), ids(&[1, 2]));
+        assert_eq!((0..4).map(|_| allocator.next().id).collect::<Vec<_>>(), vec![1, 2, 1, 2]);
+
+        // The last core is never excluded.
+        let allocator = CoreAllocator::from_core_ids(&ids(&[3])).unwrap().excluding(CoreId { id: 3 });
+        assert_eq!(allocator.core_ids(), ids(&[3]));
+    }
+
+    #[test]
+    fn test_core_allocator_skipping_reserved() {
+        use super::super::reserve_specific;
+
+        // IDs no other test reserves.
+        let allocator = CoreAllocator::from_core_ids(&ids(&[200_000, 200_001, 200_002])).unwrap()
+            .skipping_reserved();
+        let reservation = reserve_specific(CoreId { id: 200_001 }).unwrap();
+
+        let handed_out = (0..4).map(|_| allocator.next().id).collect::<Vec<_>>();
+        assert_eq!(handed_out, vec![200_000, 200_002, 200_000, 200_002]);
+
+        drop(reservation);
+        assert_eq!(allocator.next().id, 200_001);
+
+        // With every core reserved, they are handed out anyway.
+        let allocator = CoreAllocator::from_core_ids(&ids(&[200_003])).unwrap().skipping_reserved();
+        let _reservation = reserve_specific(CoreId { id: 200_003 }).unwrap();
+        assert_eq!(allocator.next().id, 200_003);
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_core_allocator_excluding_current() {
+        use super::super::{get_all_core_ids, set_for_current};
+
+        let all = get_all_core_ids().unwrap();
+        let main = all[0];
+
+        thread::spawn(move || {
+            assert!(set_for_current(main));
+
+            let allocator = CoreAllocator::from_core_ids(&all).unwrap().excluding_current();
+
+            if all.len() > 1 {
+                assert!(!allocator.core_ids().contains(&main));
+            }
+            else {
+                assert_eq!(allocator.core_ids(), vec![main]);
+            }
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_core_allocator_concurrent() {
+        let allocator = Arc::new(CoreAllocator::from_core_ids(&ids(&[0, 1, 2, 3, 4])).unwrap());
+
+        let handles = (0..8).map(|_| {
+            let allocator = allocator.clone();
+
+            thread::spawn(move || {
+                (0..1001).map(|_| allocator.next()).collect::<Vec<_>>()
+            })
+        }).collect::<Vec<_>>();
+
+        let mut counts: HashMap<CoreId, usize> = HashMap::new();
+
+        for handle in handles {
+            for core_id in handle.join().unwrap() {
+                *counts.entry(core_id).or_insert(0) += 1;
+            }
+        }
+
+        let min = *counts.values().min().unwrap();
+        let max = *counts.values().max().unwrap();
+
+        assert_eq!(counts.len(), 5);
+        assert_eq!(counts.values().sum::<usize>(), 8 * 1001);
+        assert!(max - min <= 1, "unbalanced: {:?}", counts);
+    }
+}
@@ -0,0 +1,440 @@
+use std::io;
+#[cfg(not(unix))]
+use std::process::Child;
+use std::process::{self, Command};
+use std::sync::OnceLock;
+use std::thread::Thread;
+
+use super::{CacheLevel, Capabilities, CoreId, CoreIds, CoreKind, CoreState, CpuTimes, Error, ErrorKind};
+use super::{KindSource, NumaNode, StableCoreId};
+use super::platform;
+
+/// The calls this crate makes to change and read affinities,
+/// for running it on a platform it does not support itself.
+///
+/// Only `core_ids` and `set_for_current` must be provided. The
+/// other methods fail with `ErrorKind::Unsupported` by default,
+/// or fall back on the required ones where that is correct.
+/// Those that describe the topology report nothing by default,
+/// so the queries built on them return `None` rather than the
+/// topology of the platform.
+/// Install a backend with `set_backend`; the public functions
+/// of the crate then call it instead of the platform.
+pub trait Backend: Send + Sync {
+    /// Returns the cores the current thread may run on.
+    fn core_ids(&self) -> Result<CoreIds, Error>;
+
+    /// Returns every core that is online on the system.
+    fn get_all_core_ids(&self) -> Option<Vec<CoreId>> {
+        self.core_ids().ok().map(Iterator::collect)
+    }
+
+    /// Reads back the affinity mask of the current thread.
+    fn get_affinity_for_current(&self) -> Result<Vec<CoreId>, Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Pins the current thread to `core_id`.
+    fn set_for_current(&self, core_id: CoreId) -> Result<(), Error>;
+
+    /// Confines the current thread to `core_ids`, which is
+    /// never empty.
+    fn set_for_current_set(&self, core_ids: &[CoreId]) -> Result<(), Error> {
+        match *core_ids {
+            [core_id] => self.set_for_current(core_id),
+            _ => Err(Error::new(ErrorKind::Unsupported)),
+        }
+    }
+
+    /// Pins the running thread `thread` to `core_id`.
+    fn set_for_thread(&self, _thread: &Thread, _core_id: CoreId) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Reads back the affinity mask of the running thread
+    /// `thread`.
+    fn get_for_thread(&self, _thread: &Thread) -> Result<Vec<CoreId>, Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Restricts the process `pid` to `core_ids`, which is
+    /// never empty.
+    fn set_for_process(&self, _pid: u32, _core_ids: &[CoreId]) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Reads back the affinity mask of the process `pid`.
+    fn get_for_process(&self, _pid: u32) -> Result<Vec<CoreId>, Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Makes the child spawned from `command` start restricted
+    /// to `core_ids`. Errors should make `spawn()` fail.
+    #[cfg(unix)]
+    fn set_for_command(&self, command: &mut Command, _core_ids: &[CoreId]) {
+        use std::os::unix::process::CommandExt;
+
+        unsafe {
+            command.pre_exec(|| Err(io::Error::from(io::ErrorKind::Unsupported)));
+        }
+    }
+
+    /// Spawns the child from `command` restricted to
+    /// `core_ids`.
+    #[cfg(not(unix))]
+    fn spawn_with_affinity(&self, _command: &mut Command, _core_ids: &[CoreId]) -> io::Result<Child> {
+        Err(Error::new(ErrorKind::Unsupported).into())
+    }
+
+    /// Returns the core the current thread runs on.
+    fn current_core(&self) -> Result<CoreId, Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Asks the scheduler to prefer `core_id` for the current
+    /// thread, without restricting it.
+    fn set_preferred_for_current(&self, _core_id: CoreId) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Returns the affinity mask of the current thread for
+    /// comparing it to that of the process.
+    fn thread_core_ids(&self) -> Result<CoreIds, Error> {
+        self.get_affinity_for_current().map(|core_ids| core_ids.into_iter().collect())
+    }
+
+    /// Returns the affinity mask of the current process.
+    fn process_core_ids(&self) -> Result<CoreIds, Error> {
+        self.get_for_process(process::id()).map(|core_ids| core_ids.into_iter().collect())
+    }
+
+    /// Reports what the backend can do. The default claims
+    /// nothing.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Lets the current thread run on every core of the
+    /// process again.
+    fn clear_for_current(&self) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Returns the groups of logical cores that share a physical
+    /// core. The default reports none, so `get_physical_cores`
+    /// returns `None`.
+    fn core_siblings(&self) -> Option<Vec<Vec<CoreId>>> {
+        None
+    }
+
+    /// Returns the NUMA nodes with their cores. The default
+    /// reports none, so `numa_nodes` returns `None`.
+    fn numa_nodes(&self) -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+        None
+    }
+
+    /// Returns the core the current thread runs on together with
+    /// its NUMA node.
+    fn current_location(&self) -> Result<(CoreId, NumaNode), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    /// Returns the packages with their cores, by the number the
+    /// platform gives each. The default reports none.
+    fn packages(&self) -> Option<Vec<(usize, Vec<CoreId>)>> {
+        None
+    }
+
+    /// Returns the groups of cores on the same die. The default
+    /// reports none.
+    fn dies(&self) -> Option<Vec<Vec<CoreId>>> {
+        None
+    }
+
+    /// Returns the groups of cores in the same cluster. The
+    /// default reports none.
+    fn clusters(&self) -> Option<Vec<Vec<CoreId>>> {
+        None
+    }
+
+    /// Returns the groups of cores that share a cache of `level`.
+    /// The default reports none.
+    fn cache_groups(&self, _level: usize) -> Option<Vec<Vec<CoreId>>> {
+        None
+    }
+
+    /// Returns the caches of `core_id`. The default reports none.
+    fn cache_info(&self, _core_id: CoreId) -> Option<Vec<CacheLevel>> {
+        None
+    }
+
+    /// Returns the kind of the cores, and where it was read from.
+    /// The default reports none, so `cores_by_kind` returns
+    /// `None`.
+    fn core_kinds(&self) -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+        None
+    }
+
+    /// Returns the highest frequency of `core_id` in kHz. The
+    /// default reports none.
+    fn max_frequency_khz(&self, _core_id: CoreId) -> Option<u64> {
+        None
+    }
+
+    /// Returns the rank of the cores, the higher the faster. The
+    /// default reports none, so `favored_cores` returns `None`.
+    fn core_rankings(&self) -> Option<Vec<(CoreId, u64)>> {
+        None
+    }
+
+    /// Returns the capacity of `core_id`, where 1024 is the
+    /// fastest core. The default reports none.
+    fn capacity(&self, _core_id: CoreId) -> Option<u32> {
+        None
+    }
+
+    /// Returns the frequency `core_id` runs at right now in kHz.
+    /// The default reports none.
+    fn current_frequency_khz(&self, _core_id: CoreId) -> Option<u64> {
+        None
+    }
+
+    /// Returns the time every core has spent busy and idle. The
+    /// default reports none, so `PlatformSampler` fails with
+    /// `ErrorKind::Unsupported`.
+    fn cpu_times(&self) -> Option<Vec<(CoreId, CpuTimes)>> {
+        None
+    }
+
+    /// Returns the cores in `state`, which is never
+    /// `CoreState::Online`. The default reports none.
+    fn core_ids_by_state(&self, _state: CoreState) -> Option<Vec<CoreId>> {
+        None
+    }
+
+    /// Returns the `StableCoreId` of every online core. The
+    /// default reports none, so `CoreId::stable` returns `None`.
+    fn stable_core_ids(&self) -> Option<Vec<(CoreId, StableCoreId)>> {
+        None
+    }
+}
+
+/// The backend this crate uses unless `set_backend` replaced
+/// it: the calls of the platform, or the in-memory mock when
+/// the `mock` feature is enabled.
+///
+/// A custom backend can hand the calls it does not handle
+/// itself on to this one. A `Thread` does not carry the native
+/// handle of the thread, so the `set_for_thread` and
+/// `get_for_thread` methods fail with `ErrorKind::Unsupported`
+/// here. The functions of the same name still reach other
+/// threads as long as no custom backend is installed.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DefaultBackend;
+
+impl Backend for DefaultBackend {
+    fn core_ids(&self) -> Result<CoreIds, Error> {
+        platform::core_ids()
+    }
+
+    fn get_all_core_ids(&self) -> Option<Vec<CoreId>> {
+        platform::get_all_core_ids()
+    }
+
+    fn get_affinity_for_current(&self) -> Result<Vec<CoreId>, Error> {
+        platform::get_affinity_for_current()
+    }
+
+    fn set_for_current(&self, core_id: CoreId) -> Result<(), Error> {
+        platform::set_for_current(core_id)
+    }
+
+    fn set_for_current_set(&self, core_ids: &[CoreId]) -> Result<(), Error> {
+        platform::set_for_current_set(core_ids)
+    }
+
+    fn set_for_process(&self, pid: u32, core_ids: &[CoreId]) -> Result<(), Error> {
+        platform::set_for_process(pid, core_ids)
+    }
+
+    fn get_for_process(&self, pid: u32) -> Result<Vec<CoreId>, Error> {
+        platform::get_for_process(pid)
+    }
+
+    #[cfg(unix)]
+    fn set_for_command(&self, command: &mut Command, core_ids: &[CoreId]) {
+        platform::set_for_command(command, core_ids)
+    }
+
+    #[cfg(not(unix))]
+    fn spawn_with_affinity(&self, command: &mut Command, core_ids: &[CoreId]) -> io::Result<Child> {
+        platform::spawn_with_affinity(command, core_ids)
+    }
+
+    fn current_core(&self) -> Result<CoreId, Error> {
+        platform::current_core()
+    }
+
+    fn set_preferred_for_current(&self, core_id: CoreId) -> Result<(), Error> {
+        platform::set_preferred_for_current(core_id)
+    }
+
+    fn thread_core_ids(&self) -> Result<CoreIds, Error> {
+        platform::thread_core_ids()
+    }
+
+    fn process_core_ids(&self) -> Result<CoreIds, Error> {
+        platform::process_core_ids()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        platform::capabilities()
+    }
+
+    fn clear_for_current(&self) -> Result<(), Error> {
+        platform::clear_for_current()
+    }
+
+    fn core_siblings(&self) -> Option<Vec<Vec<CoreId>>> {
+        platform::core_siblings()
+    }
+
+    fn numa_nodes(&self) -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+        platform::numa_nodes()
+    }
+
+    fn current_location(&self) -> Result<(CoreId, NumaNode), Error> {
+        platform::current_location()
+    }
+
+    fn packages(&self) -> Option<Vec<(usize, Vec<CoreId>)>> {
+        platform::packages()
+    }
+
+    fn dies(&self) -> Option<Vec<Vec<CoreId>>> {
+        platform::dies()
+    }
+
+    fn clusters(&self) -> Option<Vec<Vec<CoreId>>> {
+        platform::clusters()
+    }
+
+    fn cache_groups(&self, level: usize) -> Option<Vec<Vec<CoreId>>> {
+        platform::cache_groups(level)
+    }
+
+    fn cache_info(&self, core_id: CoreId) -> Option<Vec<CacheLevel>> {
+        platform::cache_info(core_id)
+    }
+
+    fn core_kinds(&self) -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+        platform::core_kinds()
+    }
+
+    fn max_frequency_khz(&self, core_id: CoreId) -> Option<u64> {
+        platform::max_frequency_khz(core_id)
+    }
+
+    fn core_rankings(&self) -> Option<Vec<(CoreId, u64)>> {
+        platform::core_rankings()
+    }
+
+    fn capacity(&self, core_id: CoreId) -> Option<u32> {
+        platform::capacity(core_id)
+    }
+
+    fn current_frequency_khz(&self, core_id: CoreId) -> Option<u64> {
+        platform::current_frequency_khz(core_id)
+    }
+
+    fn cpu_times(&self) -> Option<Vec<(CoreId, CpuTimes)>> {
+        platform::cpu_times()
+    }
+
+    fn core_ids_by_state(&self, state: CoreState) -> Option<Vec<CoreId>> {
+        platform::core_ids_by_state(state)
+    }
+
+    fn stable_core_ids(&self) -> Option<Vec<(CoreId, StableCoreId)>> {
+        platform::stable_core_ids()
+    }
+}
+
+// The backend passed to `set_backend`, or `None` once the
+// crate made its first call without one.
+static BACKEND: OnceLock<Option<Box<dyn Backend>>> = OnceLock::new();
+
+/// This function makes every later call of this crate go to
+/// `backend` instead of the platform.
+///
+/// Call it once at startup, before anything else in the
+/// process uses this crate.
+///
+/// # Panics
+///
+/// Panics if a backend was installed before, or if this crate
+/// was already used with the default backend, since affinities
+/// set through one backend mean nothing to another.
+///
+/// # Arguments
+///
+/// * backend - the implementation to use from now on
+pub fn set_backend(backend: Box<dyn Backend>) {
+    if BACKEND.set(Some(backend)).is_err() {
+        panic!("core_affinity::set_backend called after the backend was already in use");
+    }
+}
+
+// Returns the installed backend, if any, and locks in the
+// choice on first use.
+pub(crate) fn installed_backend() -> Option<&'static dyn Backend> {
+    BACKEND.get_or_init(|| None).as_deref()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use super::super::CpuSet;
+
+    // Only implements the required methods, and remembers
+    // the mask it was given.
+    struct Minimal {
+        mask: Mutex<CpuSet>,
+    }
+
+    impl Backend for Minimal {
+        fn core_ids(&self) -> Result<CoreIds, Error> {
+            Ok(self.mask.lock().unwrap().iter().collect())
+        }
+
+        fn set_for_current(&self, core_id: CoreId) -> Result<(), Error> {
+            *self.mask.lock().unwrap() = CpuSet::from(&[core_id][..]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_backend_defaults() {
+        let backend = Minimal { mask: Mutex::new("0-3".parse().unwrap()) };
+        let core = |id| CoreId { id };
+
+        assert_eq!(backend.get_all_core_ids(), Some(vec![core(0), core(1), core(2), core(3)]));
+        assert_eq!(backend.set_for_current_set(&[core(2)]), Ok(()));
+        assert_eq!(backend.core_ids().unwrap().collect::<Vec<_>>(), vec![core(2)]);
+
+        let err = backend.set_for_current_set(&[core(1), core(2)]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert_eq!(backend.process_core_ids().unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_eq!(backend.capabilities(), Capabilities::default());
+    }
+
+    #[test]
+    fn test_default_backend() {
+        let backend = DefaultBackend;
+
+        assert_eq!(backend.core_ids().map(Iterator::collect::<Vec<_>>).ok(), super::super::get_core_ids());
+        assert_eq!(backend.get_all_core_ids(), super::super::get_all_core_ids());
+    }
+}
@@ -0,0 +1,118 @@
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use super::{CoreId, CpuSet, get_all_core_set, get_core_ids};
+
+// The list handed out by `cached_core_ids`. Every list stored
+// here is leaked, so slices returned earlier stay valid.
+static CACHE: AtomicPtr<Vec<CoreId>> = AtomicPtr::new(ptr::null_mut());
+
+// The online cores `is_online` checks against, leaked the same
+// way.
+static ONLINE: AtomicPtr<CpuSet> = AtomicPtr::new(ptr::null_mut());
+
+/// This function is like `get_core_ids`, but only queries
+/// the system the first time it is called.
+///
+/// The list reflects the affinity mask of whichever thread
+/// made that first call, at that moment. It does not follow
+/// later calls to `set_for_current`, affinity changes made
+/// outside this crate or cores going on- or offline; call
+/// `refresh_core_ids` after any of those. Later calls cost
+/// a single atomic load.
+///
+/// It returns an empty slice if the cores can not be read,
+/// and tries again on the next call.
+pub fn cached_core_ids() -> &'static [CoreId] {
+    let cached = CACHE.load(Ordering::Acquire);
+
+    if !cached.is_null() {
+        return unsafe { &*cached };
+    }
+
+    store(&CACHE, get_core_ids, |current| current.is_null()).map_or(&[], Vec::as_slice)
+}
+
+/// This function queries the cores on which the current
+/// thread is allowed to run again and replaces the list
+/// returned by `cached_core_ids`.
+///
+/// It also reads the online cores again for `is_online`.
+/// Slices returned before stay valid but keep the old list.
+/// Every refresh leaks the previous lists, so call this when
+/// the affinity or the online cores changed, not on every
+/// request.
+pub fn refresh_core_ids() -> &'static [CoreId] {
+    store(&ONLINE, get_all_core_set, |_| true);
+    store(&CACHE, get_core_ids, |_| true).map_or(&[], Vec::as_slice)
+}
+
+// The online cores, read on the first call and again on every
+// `refresh_core_ids`. Returns `None` if they can not be read,
+// and tries again on the next call.
+pub(crate) fn cached_online_set() -> Option<&'static CpuSet> {
+    let cached = ONLINE.load(Ordering::Acquire);
+
+    if !cached.is_null() {
+        return Some(unsafe { &*cached });
+    }
+
+    store(&ONLINE, get_all_core_set, |current| current.is_null())
+}
+
+// Loads a value into `cache` and publishes it, unless
+// `replace` rejects the value cached in the meantime.
+fn store<T, L, F>(cache: &AtomicPtr<T>, load: L, replace: F) -> Option<&'static T>
+    where L: FnOnce() -> Option<T>,
+          F: Fn(*mut T) -> bool
+{
+    let value = Box::into_raw(Box::new(load()?));
+    let mut current = cache.load(Ordering::Acquire);
+
+    loop {
+        if !replace(current) {
+            // Another thread filled the cache first.
+            unsafe { drop(Box::from_raw(value)) };
+            return Some(unsafe { &*current });
+        }
+
+        match cache.compare_exchange(current, value, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => return Some(unsafe { &*value }),
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_core_ids() {
+        let all = refresh_core_ids();
+        assert_eq!(Some(all.to_vec()), get_core_ids());
+        assert_eq!(cached_core_ids().as_ptr(), all.as_ptr());
+
+        #[cfg(not(target_os = "macos"))]
+        {
+            use std::thread;
+
+            use super::super::set_for_current;
+
+            let id = *all.last().unwrap();
+
+            thread::spawn(move || {
+                assert!(set_for_current(id));
+
+                // The cache keeps the mask of the first call.
+                assert_eq!(cached_core_ids(), all);
+                assert_eq!(refresh_core_ids(), &[id][..]);
+                assert_eq!(cached_core_ids(), &[id][..]);
+            }).join().unwrap();
+
+            // The old slice is still valid.
+            assert_eq!(Some(all.to_vec()), get_core_ids());
+            assert_eq!(refresh_core_ids(), all);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+/// What the affinity APIs of this crate can do on the current
+/// platform, as returned by `capabilities`.
+///
+/// The default value claims no capabilities at all.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Whether `set_for_current` confines a thread to the
+    /// requested cores, rather than only hinting at them.
+    pub hard_pinning: bool,
+    /// Whether `set_preferred_for_current` is supported.
+    pub soft_affinity: bool,
+    /// Whether `get_affinity_for_current` can read the mask
+    /// of a thread back.
+    pub can_query_affinity: bool,
+    /// The number of core IDs the platform calls can express,
+    /// from zero up; higher IDs fail with
+    /// `ErrorKind::InvalidCore`.
+    pub max_cores_representable: usize,
+}
+
+/// This function probes what the affinity APIs can do for the
+/// current thread.
+///
+/// The answer is found at run time, by reapplying the current
+/// mask of the thread or by trying a call on a scratch thread,
+/// so sandboxes that forbid these calls report `false`. On
+/// macOS, `hard_pinning` is always `false`, since affinity tags
+/// are only hints, and `soft_affinity` is `false` where tags are
+/// not supported at all, such as on Apple Silicon.
+pub fn capabilities() -> Capabilities {
+    super::capabilities_helper()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let caps = capabilities();
+
+        #[cfg(feature = "mock")]
+        {
+            assert!(caps.hard_pinning);
+            assert!(caps.soft_affinity);
+            assert!(caps.can_query_affinity);
+        }
+
+        #[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux", target_os = "freebsd")))]
+        {
+            assert!(caps.hard_pinning);
+            assert!(!caps.soft_affinity);
+            assert!(caps.can_query_affinity);
+        }
+
+        #[cfg(all(not(feature = "mock"), target_os = "windows"))]
+        {
+            assert!(caps.hard_pinning);
+            assert!(caps.soft_affinity);
+            assert!(caps.can_query_affinity);
+            assert_eq!(caps.max_cores_representable, 64);
+        }
+
+        #[cfg(all(not(feature = "mock"), target_os = "macos"))]
+        {
+            assert!(!caps.hard_pinning);
+            assert!(!caps.can_query_affinity);
+
+            #[cfg(target_arch = "aarch64")]
+            assert!(!caps.soft_affinity);
+        }
+
+        if caps.hard_pinning {
+            let max = super::super::max_core_id().unwrap();
+            assert!(max.id < caps.max_cores_representable);
+        }
+    }
+}
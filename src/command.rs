@@ -0,0 +1,64 @@
+use std::io;
+use std::process::{Child, Command};
+
+use super::{CoreId, is_enabled};
+#[cfg(unix)]
+use super::set_for_command_helper;
+#[cfg(not(unix))]
+use super::spawn_with_affinity_helper;
+
+/// Extends `std::process::Command` to start child processes
+/// with a preset affinity.
+pub trait CommandAffinityExt {
+    /// Restricts the child to the specified cores before it
+    /// executes the program, so it never runs anywhere else.
+    ///
+    /// Invalid or empty sets of cores make `spawn()` fail.
+    ///
+    /// Windows can only restrict a process once it exists, so
+    /// this is not available there; use `spawn_with_affinity`.
+    ///
+    /// # Arguments
+    ///
+    /// * core_ids - IDs of the cores the child may run on
+    #[cfg(unix)]
+    fn core_affinity(&mut self, core_ids: &[CoreId]) -> &mut Command;
+
+    /// Spawns the child restricted to the specified cores
+    /// before it runs any code.
+    ///
+    /// On Windows the child is created suspended and resumed
+    /// once its affinity mask is in place, which replaces any
+    /// creation flags set before.
+    ///
+    /// # Arguments
+    ///
+    /// * core_ids - IDs of the cores the child may run on
+    fn spawn_with_affinity(&mut self, core_ids: &[CoreId]) -> io::Result<Child>;
+}
+
+#[cfg(unix)]
+impl CommandAffinityExt for Command {
+    fn core_affinity(&mut self, core_ids: &[CoreId]) -> &mut Command {
+        if is_enabled() {
+            set_for_command_helper(self, core_ids);
+        }
+
+        self
+    }
+
+    fn spawn_with_affinity(&mut self, core_ids: &[CoreId]) -> io::Result<Child> {
+        self.core_affinity(core_ids).spawn()
+    }
+}
+
+#[cfg(not(unix))]
+impl CommandAffinityExt for Command {
+    fn spawn_with_affinity(&mut self, core_ids: &[CoreId]) -> io::Result<Child> {
+        if !is_enabled() {
+            return self.spawn();
+        }
+
+        spawn_with_affinity_helper(self, core_ids)
+    }
+}
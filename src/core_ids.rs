@@ -0,0 +1,190 @@
+use std::fmt;
+use std::iter::{FromIterator, FusedIterator};
+
+use super::CoreId;
+
+const WORD_BITS: usize = 64;
+
+// Enough for `CPU_SETSIZE` on Linux and FreeBSD, and for the
+// 64-bit affinity mask on Windows.
+const MASK_WORDS: usize = 16;
+
+// The largest number of cores a `CoreIds` can hold.
+const MASK_BITS: usize = MASK_WORDS * WORD_BITS;
+
+/// An iterator over the cores on which the current thread is
+/// allowed to run, as returned by `core_ids`.
+///
+/// It keeps a copy of the affinity mask inline and walks its
+/// bits lazily, so it never allocates. Cores are returned in
+/// ascending order.
+#[derive(Clone)]
+pub struct CoreIds {
+    words: [u64; MASK_WORDS],
+    // The lowest and highest word that may still hold bits.
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+impl CoreIds {
+    pub(crate) fn new() -> CoreIds {
+        CoreIds { words: [0; MASK_WORDS], front: 0, back: MASK_WORDS - 1, len: 0 }
+    }
+
+    // The cores `0..count`.
+    #[cfg_attr(any(feature = "mock", not(target_os = "macos")), allow(dead_code))]
+    pub(crate) fn range(count: usize) -> CoreIds {
+        let mut core_ids = CoreIds::new();
+
+        for id in 0..count.min(MASK_BITS) {
+            core_ids.insert(id);
+        }
+
+        core_ids
+    }
+
+    // Adds core `id`. IDs from `MASK_BITS` up are dropped.
+    pub(crate) fn insert(&mut self, id: usize) {
+        if id >= MASK_BITS {
+            return;
+        }
+
+        let word = &mut self.words[id / WORD_BITS];
+        let bit = 1 << (id % WORD_BITS);
+
+        if *word & bit == 0 {
+            *word |= bit;
+            self.len += 1;
+        }
+    }
+}
+
+impl Iterator for CoreIds {
+    type Item = CoreId;
+
+    fn next(&mut self) -> Option<CoreId> {
+        if self.len == 0 {
+            return None;
+        }
+
+        while self.words[self.front] == 0 {
+            self.front += 1;
+        }
+
+        let word = &mut self.words[self.front];
+        let bit = word.trailing_zeros() as usize;
+        *word &= *word - 1;
+        self.len -= 1;
+
+        Some(CoreId { id: self.front * WORD_BITS + bit })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl DoubleEndedIterator for CoreIds {
+    fn next_back(&mut self) -> Option<CoreId> {
+        if self.len == 0 {
+            return None;
+        }
+
+        while self.words[self.back] == 0 {
+            self.back -= 1;
+        }
+
+        let word = &mut self.words[self.back];
+        let bit = WORD_BITS - 1 - word.leading_zeros() as usize;
+        *word &= !(1 << bit);
+        self.len -= 1;
+
+        Some(CoreId { id: self.back * WORD_BITS + bit })
+    }
+}
+
+impl ExactSizeIterator for CoreIds {}
+
+impl FusedIterator for CoreIds {}
+
+// Lets custom backends build the iterator they return. Cores
+// that do not fit are dropped.
+impl FromIterator<CoreId> for CoreIds {
+    fn from_iter<I: IntoIterator<Item = CoreId>>(iter: I) -> CoreIds {
+        let mut core_ids = CoreIds::new();
+
+        for core_id in iter {
+            core_ids.insert(core_id.id);
+        }
+
+        core_ids
+    }
+}
+
+impl fmt::Debug for CoreIds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn core_ids(ids: &[usize]) -> CoreIds {
+        let mut core_ids = CoreIds::new();
+
+        for &id in ids {
+            core_ids.insert(id);
+        }
+
+        core_ids
+    }
+
+    fn to_ids<I: Iterator<Item = CoreId>>(iter: I) -> Vec<usize> {
+        iter.map(|core_id| core_id.id).collect()
+    }
+
+    #[test]
+    fn test_core_ids_forward() {
+        let ids = core_ids(&[1023, 0, 64, 63, 5, 5]);
+
+        assert_eq!(ids.len(), 5);
+        assert_eq!(to_ids(ids), vec![0, 5, 63, 64, 1023]);
+        assert_eq!(CoreIds::new().next(), None);
+    }
+
+    #[test]
+    fn test_core_ids_backward() {
+        let ids = core_ids(&[0, 5, 63, 64, 1023]);
+
+        assert_eq!(to_ids(ids.rev()), vec![1023, 64, 63, 5, 0]);
+    }
+
+    #[test]
+    fn test_core_ids_both_ends() {
+        let mut ids = core_ids(&[1, 2, 3, 200]);
+
+        assert_eq!(ids.next(), Some(CoreId { id: 1 }));
+        assert_eq!(ids.next_back(), Some(CoreId { id: 200 }));
+        assert_eq!(ids.len(), 2);
+        assert_eq!(ids.next_back(), Some(CoreId { id: 3 }));
+        assert_eq!(ids.next(), Some(CoreId { id: 2 }));
+        assert_eq!(ids.next(), None);
+        assert_eq!(ids.next_back(), None);
+    }
+
+    #[test]
+    fn test_core_ids_range() {
+        assert_eq!(to_ids(CoreIds::range(4)), vec![0, 1, 2, 3]);
+        assert_eq!(CoreIds::range(usize::MAX).len(), MASK_BITS);
+        assert_eq!(format!("{:?}", CoreIds::range(2)), "[CoreId(0), CoreId(1)]");
+    }
+
+    #[test]
+    fn test_core_ids_collect() {
+        let ids: CoreIds = [3, 1, 3, MASK_BITS].iter().map(|&id| CoreId { id }).collect();
+        assert_eq!(to_ids(ids), vec![1, 3]);
+    }
+}
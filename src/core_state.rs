@@ -0,0 +1,121 @@
+use super::{CoreId, core_ids_by_state_helper, get_all_core_ids_helper};
+use cache::cached_online_set;
+
+/// Which cores of the system a list holds, after the lists
+/// Linux keeps in `/sys/devices/system/cpu`.
+///
+/// Every online core is present and every present core is
+/// possible, but not the other way around: a core may be
+/// taken offline, and the kernel may reserve IDs for cores
+/// that could be added later.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CoreState {
+    /// Cores the scheduler runs threads on. These are the only
+    /// cores that can be pinned to.
+    Online,
+    /// Possible cores that are not online, such as cores taken
+    /// offline or IDs kept for cores that could be added.
+    Offline,
+    /// Cores that are in the machine, online or not.
+    Present,
+    /// Every core the system could bring online, including the
+    /// IDs kept for cores that could be added.
+    Possible,
+}
+
+/// This function returns the cores in `state`, in ascending
+/// order.
+///
+/// `CoreState::Online` gives the same cores as
+/// `get_all_core_ids`. Linux reads the other lists from sysfs.
+/// Windows reports the active processors as online and the
+/// maximum number of processors as both present and possible.
+/// Other platforms only know the online cores, so they report
+/// those as present and possible too, and no core as offline.
+/// Returns `None` where the cores can not be listed.
+///
+/// Only online cores can be pinned to. None of the other
+/// functions of the crate returns a core that is not online.
+///
+/// # Arguments
+///
+/// * state - the cores to list
+pub fn get_core_ids_by_state(state: CoreState) -> Option<Vec<CoreId>> {
+    let mut core_ids = match state {
+        CoreState::Online => get_all_core_ids_helper()?,
+        _ => core_ids_by_state_helper(state)?,
+    };
+    core_ids.sort();
+    core_ids.dedup();
+
+    Some(core_ids)
+}
+
+/// This function returns whether `core_id` is online, so that
+/// it can be pinned to, such as for checking the cores listed
+/// in a config file.
+///
+/// The online cores are those of `get_all_core_ids`: the
+/// sysfs list on Linux, the active processors on Windows and
+/// the root cpuset on FreeBSD. They are read on the first call
+/// and kept, so later calls neither allocate nor query the
+/// system. `refresh_core_ids` reads them again, like the list
+/// of `cached_core_ids`; call it after cores go on- or offline.
+///
+/// A core beyond the highest core of the system is not online.
+/// Returns `None` where the online cores can not be listed.
+///
+/// # Arguments
+///
+/// * core_id - the core to check
+pub fn is_online(core_id: CoreId) -> Option<bool> {
+    cached_online_set().map(|online| online.contains(core_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{CpuSet, get_all_core_ids, get_core_ids};
+
+    #[test]
+    fn test_get_core_ids_by_state() {
+        let online = match get_core_ids_by_state(CoreState::Online) {
+            Some(online) => online,
+            None => return,
+        };
+
+        assert_eq!(Some(online.clone()), get_all_core_ids());
+
+        let offline = CpuSet::from(get_core_ids_by_state(CoreState::Offline).unwrap());
+        let present = CpuSet::from(get_core_ids_by_state(CoreState::Present).unwrap());
+        let possible = CpuSet::from(get_core_ids_by_state(CoreState::Possible).unwrap());
+
+        for &core_id in &online {
+            assert!(!offline.contains(core_id));
+            assert!(present.contains(core_id));
+        }
+
+        assert!(present.iter().all(|core_id| possible.contains(core_id)));
+        assert!(offline.iter().all(|core_id| possible.contains(core_id)));
+
+        // No core to pin to is offline.
+        assert!(get_core_ids().unwrap().iter().all(|&core_id| online.contains(&core_id)));
+    }
+
+    #[test]
+    fn test_is_online() {
+        let online = match get_all_core_ids() {
+            Some(online) => online,
+            None => return,
+        };
+
+        for &core_id in &online {
+            assert_eq!(is_online(core_id), Some(true));
+        }
+
+        // Past the highest core, nothing is online.
+        let max = online.last().unwrap().id;
+        assert_eq!(is_online(CoreId { id: max + 1 }), Some(false));
+        assert_eq!(is_online(CoreId { id: usize::MAX }), Some(false));
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt::Write;
+
+use super::CoreId;
+use super::parse::{parse_core_id, ParseError, ParseErrorKind};
+
+// Ranges may not span more cores than this, so that a
+// typo in a config file can not exhaust the memory.
+const MAX_RANGE: usize = 1 << 16;
+
+/// This function parses a list of cores in the format
+/// used throughout Linux, such as "0-3,7,9-11".
+///
+/// Besides single cores and ranges, it accepts the stride
+/// syntax of the kernel: "0-7:2" selects every second core,
+/// and "0-15:2/4" the first two of every group of four.
+/// Whitespace around items is ignored and an empty list is
+/// an empty set. The result is sorted and free of duplicates.
+///
+/// # Arguments
+///
+/// * list - the list of cores to parse
+pub fn parse_cpulist(list: &str) -> Result<Vec<CoreId>, ParseError> {
+    let mut core_ids: Vec<CoreId> = Vec::new();
+
+    if list.trim().is_empty() {
+        return Ok(core_ids);
+    }
+
+    let mut offset = 0;
+
+    for item in list.split(',') {
+        parse_item(item, offset, &mut core_ids)?;
+        offset += item.len() + 1;
+    }
+
+    core_ids.sort();
+    core_ids.dedup();
+
+    Ok(core_ids)
+}
+
+/// This function formats a set of cores as a compact list
+/// such as "0-3,7,9-11", which `parse_cpulist` reads back.
+///
+/// The cores are sorted and duplicates are dropped, so equal
+/// sets always produce the same string. An empty set gives an
+/// empty string.
+///
+/// # Arguments
+///
+/// * core_ids - the cores to format
+pub fn format_cpulist(core_ids: &[CoreId]) -> String {
+    let mut ids: Vec<usize> = core_ids.iter().map(|core_id| core_id.id).collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut list = String::new();
+    let mut i = 0;
+
+    while i < ids.len() {
+        // Extend the run for as long as the IDs are consecutive.
+        let first = ids[i];
+        let mut last = first;
+
+        while i + 1 < ids.len() && ids[i + 1] == last + 1 {
+            i += 1;
+            last = ids[i];
+        }
+
+        if !list.is_empty() {
+            list.push(',');
+        }
+
+        if first == last {
+            let _ = write!(list, "{}", first);
+        }
+        else {
+            let _ = write!(list, "{}-{}", first, last);
+        }
+
+        i += 1;
+    }
+
+    list
+}
+
+// Parse one item such as "3", "0-7" or "0-7:2", which starts
+// at byte `offset` of the list.
+fn parse_item(item: &str, offset: usize, core_ids: &mut Vec<CoreId>) -> Result<(), ParseError> {
+    let (item, offset) = trim(item, offset);
+
+    let (range, stride) = match item.find(':') {
+        Some(i) => (&item[..i], Some(trim(&item[i + 1..], offset + i + 1))),
+        None => (item, None),
+    };
+
+    let (first, last) = match range.find('-') {
+        Some(0) => return Err(ParseError::new(ParseErrorKind::Negative, offset)),
+        Some(i) => {
+            let (first, first_offset) = trim(&range[..i], offset);
+            let (last, last_offset) = trim(&range[i + 1..], offset + i + 1);
+
+            (parse_core_id(first, first_offset)?.id, parse_core_id(last, last_offset)?.id)
+        },
+        None => {
+            let (id, id_offset) = trim(range, offset);
+            let id = parse_core_id(id, id_offset)?.id;
+
+            (id, id)
+        },
+    };
+
+    if last < first {
+        return Err(ParseError::new(ParseErrorKind::InvalidRange, offset));
+    }
+
+    if last - first >= MAX_RANGE {
+        return Err(ParseError::new(ParseErrorKind::Overflow, offset));
+    }
+
+    let (used, group) = match stride {
+        Some((stride, stride_offset)) => parse_stride(stride, stride_offset)?,
+        None => (1, 1),
+    };
+
+    core_ids.extend((first..=last)
+                    .filter(|id| (id - first) % group < used)
+                    .map(|id| CoreId { id }));
+
+    Ok(())
+}
+
+// Parse either "stride" or "used/group" into (used, group).
+fn parse_stride(stride: &str, offset: usize) -> Result<(usize, usize), ParseError> {
+    let (used, group) = match stride.find('/') {
+        Some(i) => {
+            let (used, used_offset) = trim(&stride[..i], offset);
+            let (group, group_offset) = trim(&stride[i + 1..], offset + i + 1);
+
+            (parse_core_id(used, used_offset)?.id, parse_core_id(group, group_offset)?.id)
+        },
+        None => (1, parse_core_id(stride, offset)?.id),
+    };
+
+    if used == 0 || group == 0 || used > group {
+        return Err(ParseError::new(ParseErrorKind::InvalidStride, offset));
+    }
+
+    Ok((used, group))
+}
+
+// Strip whitespace from `s`, moving `offset` past any that leads.
+fn trim(s: &str, offset: usize) -> (&str, usize) {
+    let trimmed = s.trim_start();
+
+    (trimmed.trim_end(), offset + s.len() - trimmed.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::ids;
+
+    fn err(list: &str) -> (ParseErrorKind, usize) {
+        let err = parse_cpulist(list).unwrap_err();
+        (err.kind(), err.offset())
+    }
+
+    #[test]
+    fn test_parse_cpulist() {
+        assert_eq!(parse_cpulist(""), Ok(vec![]));
+        assert_eq!(parse_cpulist("0"), Ok(ids(&[0])));
+        assert_eq!(parse_cpulist("0-3,8,10-11"), Ok(ids(&[0, 1, 2, 3, 8, 10, 11])));
+        assert_eq!(parse_cpulist("0-3,7,9-11\n"), Ok(ids(&[0, 1, 2, 3, 7, 9, 10, 11])));
+        assert_eq!(parse_cpulist(" 1 , 3 - 4 "), Ok(ids(&[1, 3, 4])));
+        assert_eq!(parse_cpulist("5,1-2,2"), Ok(ids(&[1, 2, 5])));
+    }
+
+    #[test]
+    fn test_parse_cpulist_stride() {
+        assert_eq!(parse_cpulist("0-7:2"), Ok(ids(&[0, 2, 4, 6])));
+        assert_eq!(parse_cpulist("0-15:2/4"), Ok(ids(&[0, 1, 4, 5, 8, 9, 12, 13])));
+        assert_eq!(err("0-7:0"), (ParseErrorKind::InvalidStride, 4));
+        assert_eq!(err("0-7:3/2"), (ParseErrorKind::InvalidStride, 4));
+    }
+
+    #[test]
+    fn test_format_cpulist() {
+        assert_eq!(format_cpulist(&[]), "");
+        assert_eq!(format_cpulist(&ids(&[3])), "3");
+        assert_eq!(format_cpulist(&ids(&[11, 0, 1, 2, 3, 7, 9, 10, 2])), "0-3,7,9-11");
+        assert_eq!(format_cpulist(&ids(&[1, 3, 5])), "1,3,5");
+    }
+
+    #[test]
+    fn test_format_parse_round_trip() {
+        // A small xorshift generator, so the sets are the same on every run.
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..500 {
+            let len = (next() % 40) as usize;
+            let core_ids: Vec<CoreId> = (0..len).map(|_| CoreId { id: (next() % 128) as usize }).collect();
+
+            let mut normalized = core_ids.clone();
+            normalized.sort();
+            normalized.dedup();
+
+            assert_eq!(parse_cpulist(&format_cpulist(&core_ids)), Ok(normalized));
+        }
+    }
+
+    #[test]
+    fn test_parse_cpulist_errors() {
+        assert_eq!(err("3-1"), (ParseErrorKind::InvalidRange, 0));
+        assert_eq!(err("0,3-1"), (ParseErrorKind::InvalidRange, 2));
+        assert_eq!(err("a-b"), (ParseErrorKind::InvalidDigit, 0));
+        assert_eq!(err("1-b"), (ParseErrorKind::InvalidDigit, 2));
+        assert_eq!(err("1,,2"), (ParseErrorKind::Empty, 2));
+        assert_eq!(err("-1"), (ParseErrorKind::Negative, 0));
+        assert_eq!(err("0-99999999"), (ParseErrorKind::Overflow, 0));
+    }
+}
@@ -0,0 +1,290 @@
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{BitAnd, BitOr, BitXor, Sub};
+use std::str::FromStr;
+
+use super::{CoreId, ParseError, format_cpulist, parse_cpulist};
+
+const WORD_BITS: usize = 64;
+
+/// A set of CPU cores, stored as a bitmask.
+///
+/// The mask grows as needed, so it can hold any core ID the
+/// platform uses, including those beyond the first 64. Its
+/// size follows the highest core in the set. Sets
+/// display in the cpulist format, such as "0-3,7", and parse
+/// from it as well.
+///
+/// There is no complement, since the set does not know which
+/// cores exist; subtract from `get_all_core_set()` instead.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct CpuSet {
+    // Never ends in a zero word, so equal sets compare equal.
+    words: Vec<u64>,
+}
+
+impl CpuSet {
+    /// Creates an empty set.
+    pub fn new() -> CpuSet {
+        CpuSet { words: Vec::new() }
+    }
+
+    /// Adds `core_id` to the set.
+    pub fn set(&mut self, core_id: CoreId) {
+        let (word, bit) = position(core_id);
+
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+
+        self.words[word] |= bit;
+    }
+
+    /// Removes `core_id` from the set.
+    pub fn clear(&mut self, core_id: CoreId) {
+        let (word, bit) = position(core_id);
+
+        if word < self.words.len() {
+            self.words[word] &= !bit;
+            self.trim();
+        }
+    }
+
+    /// Returns whether `core_id` is in the set.
+    pub fn contains(&self, core_id: CoreId) -> bool {
+        let (word, bit) = position(core_id);
+
+        self.words.get(word).is_some_and(|&w| w & bit != 0)
+    }
+
+    /// Returns the number of cores in the set.
+    pub fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Returns the cores in the set in ascending order.
+    pub fn iter(&self) -> CpuSetIter<'_> {
+        CpuSetIter { words: &self.words, word: 0, rest: self.words.first().cloned().unwrap_or(0) }
+    }
+
+    /// Returns the cores in the set in ascending order.
+    pub fn to_vec(&self) -> Vec<CoreId> {
+        self.iter().collect()
+    }
+
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+    }
+
+    fn zip_with<F>(&self, other: &CpuSet, f: F) -> CpuSet
+        where F: Fn(u64, u64) -> u64
+    {
+        let len = self.words.len().max(other.words.len());
+        let word = |words: &[u64], i: usize| words.get(i).cloned().unwrap_or(0);
+
+        let mut set = CpuSet {
+            words: (0..len).map(|i| f(word(&self.words, i), word(&other.words, i))).collect(),
+        };
+        set.trim();
+        set
+    }
+}
+
+fn position(core_id: CoreId) -> (usize, u64) {
+    (core_id.id / WORD_BITS, 1 << (core_id.id % WORD_BITS))
+}
+
+/// An iterator over the cores of a `CpuSet`.
+#[derive(Clone, Debug)]
+pub struct CpuSetIter<'a> {
+    words: &'a [u64],
+    word: usize,
+    // The bits of the current word not yet returned.
+    rest: u64,
+}
+
+impl<'a> Iterator for CpuSetIter<'a> {
+    type Item = CoreId;
+
+    fn next(&mut self) -> Option<CoreId> {
+        while self.rest == 0 {
+            self.word += 1;
+            self.rest = *self.words.get(self.word)?;
+        }
+
+        let bit = self.rest.trailing_zeros() as usize;
+        self.rest &= self.rest - 1;
+
+        Some(CoreId { id: self.word * WORD_BITS + bit })
+    }
+}
+
+impl<'a> IntoIterator for &'a CpuSet {
+    type Item = CoreId;
+    type IntoIter = CpuSetIter<'a>;
+
+    fn into_iter(self) -> CpuSetIter<'a> {
+        self.iter()
+    }
+}
+
+impl FromIterator<CoreId> for CpuSet {
+    fn from_iter<I: IntoIterator<Item = CoreId>>(iter: I) -> CpuSet {
+        let mut set = CpuSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<CoreId> for CpuSet {
+    fn extend<I: IntoIterator<Item = CoreId>>(&mut self, iter: I) {
+        for core_id in iter {
+            self.set(core_id);
+        }
+    }
+}
+
+impl<'a> From<&'a [CoreId]> for CpuSet {
+    fn from(core_ids: &'a [CoreId]) -> CpuSet {
+        core_ids.iter().cloned().collect()
+    }
+}
+
+impl From<Vec<CoreId>> for CpuSet {
+    fn from(core_ids: Vec<CoreId>) -> CpuSet {
+        core_ids.into_iter().collect()
+    }
+}
+
+impl From<CpuSet> for Vec<CoreId> {
+    fn from(set: CpuSet) -> Vec<CoreId> {
+        set.to_vec()
+    }
+}
+
+macro_rules! impl_set_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<'a> $trait<&'a CpuSet> for &'a CpuSet {
+            type Output = CpuSet;
+
+            fn $method(self, other: &CpuSet) -> CpuSet {
+                self.zip_with(other, $op)
+            }
+        }
+
+        impl $trait for CpuSet {
+            type Output = CpuSet;
+
+            fn $method(self, other: CpuSet) -> CpuSet {
+                self.zip_with(&other, $op)
+            }
+        }
+    };
+}
+
+impl_set_op!(BitAnd, bitand, |a, b| a & b);
+impl_set_op!(BitOr, bitor, |a, b| a | b);
+impl_set_op!(BitXor, bitxor, |a, b| a ^ b);
+impl_set_op!(Sub, sub, |a, b| a & !b);
+
+impl fmt::Display for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format_cpulist(&self.to_vec()))
+    }
+}
+
+impl fmt::Debug for CpuSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CpuSet({})", self)
+    }
+}
+
+impl FromStr for CpuSet {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<CpuSet, ParseError> {
+        parse_cpulist(s).map(CpuSet::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ids: &[usize]) -> CpuSet {
+        ids.iter().map(|&id| CoreId { id }).collect()
+    }
+
+    #[test]
+    fn test_cpu_set_basics() {
+        let mut s = CpuSet::new();
+        assert!(s.is_empty());
+
+        s.set(CoreId { id: 3 });
+        s.set(CoreId { id: 3 });
+        s.set(CoreId { id: 130 });
+
+        assert_eq!(s.len(), 2);
+        assert!(s.contains(CoreId { id: 3 }));
+        assert!(s.contains(CoreId { id: 130 }));
+        assert!(!s.contains(CoreId { id: 4 }));
+        assert!(!s.contains(CoreId { id: 10_000 }));
+
+        s.clear(CoreId { id: 130 });
+        s.clear(CoreId { id: 10_000 });
+        assert_eq!(s, set(&[3]));
+    }
+
+    #[test]
+    fn test_cpu_set_iter() {
+        let ids = [0, 1, 63, 64, 65, 127, 128, 1000];
+        let s = set(&ids);
+
+        assert_eq!(s.iter().map(|id| id.id).collect::<Vec<_>>(), ids);
+        assert_eq!(CpuSet::new().iter().next(), None);
+    }
+
+    #[test]
+    fn test_cpu_set_algebra() {
+        let a = set(&[0, 1, 2, 70]);
+        let b = set(&[2, 3, 70, 200]);
+
+        assert_eq!(&a & &b, set(&[2, 70]));
+        assert_eq!(&a | &b, set(&[0, 1, 2, 3, 70, 200]));
+        assert_eq!(&a ^ &b, set(&[0, 1, 3, 200]));
+        assert_eq!(&a - &b, set(&[0, 1]));
+        assert_eq!(b.clone() - a.clone(), set(&[3, 200]));
+
+        // Emptied high words do not affect equality.
+        assert_eq!(set(&[200]) & set(&[1]), CpuSet::new());
+    }
+
+    #[test]
+    fn test_cpu_set_vec_round_trip() {
+        let ids: Vec<CoreId> = [5, 0, 64, 300, 5].iter().map(|&id| CoreId { id }).collect();
+        let s = CpuSet::from(ids.clone());
+
+        let mut normalized = ids;
+        normalized.sort();
+        normalized.dedup();
+
+        assert_eq!(Vec::from(s.clone()), normalized);
+        assert_eq!(CpuSet::from(&normalized[..]), s);
+    }
+
+    #[test]
+    fn test_cpu_set_display_parse() {
+        let s = set(&[0, 1, 2, 3, 7, 9, 10, 11]);
+
+        assert_eq!(s.to_string(), "0-3,7,9-11");
+        assert_eq!(format!("{:?}", s), "CpuSet(0-3,7,9-11)");
+        assert_eq!("0-3,7,9-11".parse(), Ok(s));
+    }
+}
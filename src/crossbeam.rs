@@ -0,0 +1,122 @@
+use std::io;
+
+use crossbeam_utils::thread::{Scope, ScopedJoinHandle};
+
+use super::CoreId;
+use spawn::{handshake, pin_first};
+
+/// Pinned spawning for `crossbeam::thread::Scope`, enabled by
+/// the `crossbeam` feature.
+pub trait CrossbeamScopeExt<'env> {
+    /// Spawns a scoped thread like `Scope::spawn`, pinned to
+    /// the specified core before `f` starts to run.
+    ///
+    /// `f` may borrow from outside the scope and is given the
+    /// scope to spawn more threads from, exactly as with
+    /// `crossbeam::scope`. A panic in `f` makes `crossbeam::scope`
+    /// return an error unless the handle is joined, as usual.
+    /// Failures to pin are reported in the same way as by
+    /// `BuilderExt::spawn_on`, and are not counted as panics of
+    /// the scope.
+    ///
+    /// # Arguments
+    ///
+    /// * core_id - ID of the core to pin the new thread to
+    /// * f - closure to run on the new thread
+    fn spawn_pinned<'scope, F, T>(&'scope self, core_id: CoreId, f: F) -> io::Result<ScopedJoinHandle<'scope, T>>
+        where F: FnOnce(&Scope<'env>) -> T + Send + 'env,
+              T: Send + 'env;
+}
+
+impl<'env> CrossbeamScopeExt<'env> for Scope<'env> {
+    fn spawn_pinned<'scope, F, T>(&'scope self, core_id: CoreId, f: F) -> io::Result<ScopedJoinHandle<'scope, T>>
+        where F: FnOnce(&Scope<'env>) -> T + Send + 'env,
+              T: Send + 'env
+    {
+        // Pinning hands `f` back once it succeeded.
+        let (pin, pinned) = pin_first(core_id, move || f);
+        let handle = self.builder().spawn(move |scope| pin()(scope))?;
+
+        // Joining takes the unwinding of a failed pin, so the
+        // scope does not report it.
+        handshake(pinned, handle, |handle| { let _ = handle.join(); })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crossbeam_utils::thread;
+
+    use super::*;
+    use super::super::{current_core, get_core_ids};
+
+    #[test]
+    fn test_crossbeam_spawn_pinned() {
+        let ids = get_core_ids().unwrap();
+        let data: Vec<u64> = (0..1000).collect();
+        let chunk_size = data.len().div_ceil(ids.len());
+
+        let results = thread::scope(|s| {
+            let handles = ids.iter().zip(data.chunks(chunk_size)).map(|(&id, chunk)| {
+                s.spawn_pinned(id, move |_| (id, chunk.iter().sum::<u64>(), current_core()))
+                    .unwrap()
+            }).collect::<Vec<_>>();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+        }).unwrap();
+
+        for &(id, _, core_id) in &results {
+            // `current_core` is not supported on macOS.
+            assert!(core_id.is_none() || core_id == Some(id));
+        }
+
+        assert_eq!(results.iter().map(|r| r.1).sum::<u64>(), data.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn test_crossbeam_spawn_pinned_nested() {
+        let id = get_core_ids().unwrap()[0];
+
+        let inner = thread::scope(|s| {
+            s.spawn_pinned(id, |s| {
+                s.spawn_pinned(id, |_| current_core()).unwrap().join().unwrap()
+            }).unwrap().join().unwrap()
+        }).unwrap();
+
+        assert!(inner.is_none() || inner == Some(id));
+    }
+
+    #[test]
+    fn test_crossbeam_spawn_pinned_invalid_core() {
+        let ran = AtomicBool::new(false);
+
+        let result = thread::scope(|s| {
+            let err = s.spawn_pinned(CoreId { id: usize::MAX }, |_| ran.store(true, Ordering::SeqCst))
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        });
+
+        // The failed pin is not a panic of the scope.
+        assert!(result.is_ok());
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_crossbeam_spawn_pinned_panic() {
+        let id = get_core_ids().unwrap()[0];
+
+        // An unjoined panic fails the scope, as with `spawn`.
+        let result = thread::scope(|s| {
+            s.spawn_pinned(id, |_| panic!("worker failed")).unwrap();
+        });
+
+        let panics = result.unwrap_err().downcast::<Vec<Box<dyn Any + Send>>>().unwrap();
+        assert_eq!(panics.len(), 1);
+        assert_eq!(panics[0].downcast_ref::<&str>(), Some(&"worker failed"));
+    }
+}
@@ -0,0 +1,113 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{CoreId, Error, current_core_helper};
+
+thread_local! {
+    // The core this crate pinned the current thread to, and
+    // the generation that was current at the time.
+    static PINNED: Cell<Option<(CoreId, usize)>> = const { Cell::new(None) };
+}
+
+// Bumped whenever this crate changes the affinity of a thread
+// other than the current one, or pins the current one without
+// tracking it, which clears every cached core.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// This function returns the core the current thread is
+/// running on, avoiding system calls where possible.
+///
+/// While the thread is pinned to a single core by this crate,
+/// the answer is cached per thread and costs no more than a
+/// thread-local read. Otherwise it falls back to the cheapest
+/// query of the platform: `sched_getcpu` on Linux, which glibc
+/// answers from the vDSO or rseq area, and the user-mode
+/// `GetCurrentProcessorNumberEx` on Windows.
+///
+/// The cache can not see affinity changes made outside this
+/// crate, such as by `taskset` or direct system calls; use
+/// `current_core` if those may happen. Like `current_core`,
+/// the value is advisory for unpinned threads.
+pub fn current_core_fast() -> Option<CoreId> {
+    if let Some((core_id, generation)) = PINNED.with(|pinned| pinned.get()) {
+        if generation == GENERATION.load(Ordering::Acquire) {
+            return Some(core_id);
+        }
+    }
+
+    current_core_helper().ok()
+}
+
+// Passes `result` through and, if it succeeded, remembers
+// that the current thread is now pinned to `core_id`, or to
+// no single core if it is `None`.
+pub(crate) fn track<T>(result: Result<T, Error>, core_id: Option<CoreId>) -> Result<T, Error> {
+    // An affinity tag on macOS does not name the core the
+    // thread runs on, so there is nothing to cache.
+    if result.is_ok() && !cfg!(all(not(feature = "mock"), target_os = "macos")) {
+        let generation = GENERATION.load(Ordering::Acquire);
+        PINNED.with(|pinned| pinned.set(core_id.map(|core_id| (core_id, generation))));
+    }
+
+    result
+}
+
+// Passes `result` through and, if it succeeded, forgets the
+// cached core of every thread.
+pub(crate) fn invalidate<T>(result: Result<T, Error>) -> Result<T, Error> {
+    if result.is_ok() {
+        forget_all();
+    }
+
+    result
+}
+
+// Forgets the cached core of every thread. It only bumps an
+// atomic, so it is async-signal-safe.
+pub(crate) fn forget_all() {
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+// Returns the core if `core_ids` names exactly one.
+pub(crate) fn single_core(core_ids: &[CoreId]) -> Option<CoreId> {
+    match core_ids.split_first() {
+        Some((&first, rest)) if rest.iter().all(|&id| id == first) => Some(first),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_core() {
+        let a = CoreId { id: 1 };
+        let b = CoreId { id: 2 };
+
+        assert_eq!(single_core(&[]), None);
+        assert_eq!(single_core(&[a, a]), Some(a));
+        assert_eq!(single_core(&[a, b]), None);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd"))]
+    #[test]
+    fn test_current_core_fast() {
+        use std::thread;
+
+        use super::super::{clear_for_current, current_core, get_core_ids, set_for_current};
+
+        thread::spawn(|| {
+            let id = *get_core_ids().unwrap().last().unwrap();
+            assert!(set_for_current(id));
+
+            assert_eq!(current_core_fast(), Some(id));
+            assert_eq!(current_core_fast(), current_core());
+
+            // Without a pin the slow path answers again.
+            assert!(clear_for_current());
+            assert!(PINNED.with(|pinned| pinned.get()).is_none());
+            assert!(current_core_fast().is_some());
+        }).join().unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+use std::fmt;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::fs;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::path::Path;
+use std::process;
+use std::thread;
+
+use super::{CoreId, CpuSet, Error, get_all_core_ids_helper, get_for_process_helper, set_for_current_helper};
+
+/// Why pinning to a core would fail, as returned by `diagnose`.
+///
+/// Each variant past `Pinnable` names the first layer that
+/// rejects the core. It displays as a sentence that says what
+/// to change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PinDiagnostic {
+    /// Pinning to the core works.
+    Pinnable(CoreId),
+    /// The system has no such core.
+    DoesNotExist(CoreId),
+    /// The core exists, but is offline.
+    Offline(CoreId),
+    /// The core is online, but not in the affinity mask of the
+    /// process, which is attached.
+    OutsideProcessMask(CoreId, CpuSet),
+    /// The core is excluded by the cpuset of the cgroup the
+    /// process runs in, which is attached.
+    OutsideCgroup(CoreId, CpuSet),
+    /// Every mask allows the core, but the platform refused to
+    /// pin to it.
+    Denied(CoreId, Error),
+}
+
+impl PinDiagnostic {
+    /// Returns the core that was diagnosed.
+    pub fn core_id(&self) -> CoreId {
+        match *self {
+            PinDiagnostic::Pinnable(core_id)
+            | PinDiagnostic::DoesNotExist(core_id)
+            | PinDiagnostic::Offline(core_id)
+            | PinDiagnostic::OutsideProcessMask(core_id, _)
+            | PinDiagnostic::OutsideCgroup(core_id, _)
+            | PinDiagnostic::Denied(core_id, _) => core_id,
+        }
+    }
+
+    /// Returns `true` for `PinDiagnostic::Pinnable`.
+    pub fn is_pinnable(&self) -> bool {
+        matches!(*self, PinDiagnostic::Pinnable(_))
+    }
+}
+
+impl fmt::Display for PinDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PinDiagnostic::Pinnable(core_id) => {
+                write!(f, "core {} can be pinned to", core_id)
+            },
+            PinDiagnostic::DoesNotExist(core_id) => {
+                write!(f, "core {} does not exist on this system; pick one of `get_all_core_ids`", core_id)
+            },
+            PinDiagnostic::Offline(core_id) => {
+                write!(f, "core {} is offline; bring it online or pick an online core", core_id)
+            },
+            PinDiagnostic::OutsideProcessMask(core_id, ref allowed) => {
+                write!(f, "core {} is not in the affinity mask of the process ({}); \
+                           start the process with a wider mask or pick an allowed core",
+                       core_id, allowed)
+            },
+            PinDiagnostic::OutsideCgroup(core_id, ref allowed) => {
+                write!(f, "core {} is excluded by the cpuset of the cgroup ({}); \
+                           widen `cpuset.cpus` of the cgroup or the `--cpuset-cpus` of the container",
+                       core_id, allowed)
+            },
+            PinDiagnostic::Denied(core_id, ref err) => {
+                write!(f, "pinning to core {} failed ({}); check the privileges of the process \
+                           and any sandbox it runs in",
+                       core_id, err)
+            },
+        }
+    }
+}
+
+// The masks a core has to pass, from the widest to the
+// narrowest. Masks that can not be read are `None` and
+// skipped.
+#[derive(Clone, Debug, Default)]
+struct Layers {
+    possible: Option<CpuSet>,
+    online: Option<CpuSet>,
+    process: Option<CpuSet>,
+    cgroup: Option<CpuSet>,
+}
+
+/// This function explains why pinning the current thread to
+/// `core_id` would fail, or that it would work.
+///
+/// It checks, in order, that the core exists, that it is
+/// online, that the affinity mask of the process allows it,
+/// that the cgroup cpuset allows it (Linux and Android only),
+/// and finally tries the pin on a scratch thread, reporting
+/// the first layer that rejects the core. The affinity of the
+/// current thread is left alone. Layers that can not be read
+/// on the platform are skipped.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to check
+pub fn diagnose(core_id: CoreId) -> PinDiagnostic {
+    if let Some(rejected) = check(core_id, &layers()) {
+        return rejected;
+    }
+
+    // A thread that can not be spawned tells nothing about
+    // the core, so do not blame it.
+    match thread::spawn(move || set_for_current_helper(core_id)).join() {
+        Ok(Err(err)) => PinDiagnostic::Denied(core_id, err),
+        _ => PinDiagnostic::Pinnable(core_id),
+    }
+}
+
+// Finds the first layer that does not contain `core_id`.
+fn check(core_id: CoreId, layers: &Layers) -> Option<PinDiagnostic> {
+    let excludes = |layer: &Option<CpuSet>| layer.as_ref().is_some_and(|set| !set.contains(core_id));
+
+    if excludes(&layers.possible) {
+        return Some(PinDiagnostic::DoesNotExist(core_id));
+    }
+
+    if excludes(&layers.online) {
+        // Without a list of possible cores, an ID that is not
+        // online may just as well not exist.
+        return Some(if layers.possible.is_some() {
+            PinDiagnostic::Offline(core_id)
+        }
+        else {
+            PinDiagnostic::DoesNotExist(core_id)
+        });
+    }
+
+    if let Some(ref set) = layers.process {
+        if !set.contains(core_id) {
+            return Some(PinDiagnostic::OutsideProcessMask(core_id, set.clone()));
+        }
+    }
+
+    if let Some(ref set) = layers.cgroup {
+        if !set.contains(core_id) {
+            return Some(PinDiagnostic::OutsideCgroup(core_id, set.clone()));
+        }
+    }
+
+    None
+}
+
+#[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux")))]
+fn layers() -> Layers {
+    let possible = fs::read_to_string("/sys/devices/system/cpu/possible")
+        .ok()
+        .and_then(|list| list.trim().parse().ok());
+    let cgroup = fs::read_to_string("/proc/self/cgroup")
+        .ok()
+        .and_then(|list| read_cgroup_cpus(&list, Path::new("/sys/fs/cgroup")));
+
+    Layers { possible, cgroup, ..common_layers() }
+}
+
+#[cfg(any(feature = "mock", not(any(target_os = "android", target_os = "linux"))))]
+fn layers() -> Layers {
+    common_layers()
+}
+
+// The masks that every platform can read through the backend.
+fn common_layers() -> Layers {
+    Layers {
+        online: get_all_core_ids_helper().map(CpuSet::from),
+        process: get_for_process_helper(process::id()).ok().map(CpuSet::from),
+        ..Layers::default()
+    }
+}
+
+// Reads the effective cpuset of the cgroup named in `cgroups`,
+// the contents of `/proc/self/cgroup`, from a cgroup tree
+// mounted at `root`. The cpuset controller of cgroup v1 wins
+// over the unified hierarchy of v2.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+fn read_cgroup_cpus(cgroups: &str, root: &Path) -> Option<CpuSet> {
+    let mut v1 = None;
+    let mut v2 = None;
+
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+
+        let (controllers, path) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(_), Some(controllers), Some(path)) => (controllers, path.trim_start_matches('/')),
+            _ => continue,
+        };
+
+        if controllers.is_empty() {
+            v2 = Some(root.join(path).join("cpuset.cpus.effective"));
+        }
+        else if controllers.split(',').any(|controller| controller == "cpuset") {
+            v1 = Some(root.join("cpuset").join(path).join("cpuset.effective_cpus"));
+        }
+    }
+
+    v1.into_iter()
+        .chain(v2)
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .find_map(|list| list.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(list: &str) -> Option<CpuSet> {
+        Some(list.parse().unwrap())
+    }
+
+    #[test]
+    fn test_check() {
+        let layers = Layers {
+            possible: set("0-7"),
+            online: set("0-5"),
+            process: set("0-3"),
+            cgroup: set("0-1"),
+        };
+
+        let core = |id| CoreId { id };
+
+        assert_eq!(check(core(8), &layers), Some(PinDiagnostic::DoesNotExist(core(8))));
+        assert_eq!(check(core(6), &layers), Some(PinDiagnostic::Offline(core(6))));
+        assert_eq!(check(core(4), &layers),
+                   Some(PinDiagnostic::OutsideProcessMask(core(4), "0-3".parse().unwrap())));
+        assert_eq!(check(core(2), &layers),
+                   Some(PinDiagnostic::OutsideCgroup(core(2), "0-1".parse().unwrap())));
+        assert_eq!(check(core(1), &layers), None);
+
+        // Unknown layers are skipped.
+        let layers = Layers { online: set("0-5"), ..Layers::default() };
+        assert_eq!(check(core(6), &layers), Some(PinDiagnostic::DoesNotExist(core(6))));
+        assert_eq!(check(core(4), &layers), None);
+    }
+
+    #[test]
+    fn test_pin_diagnostic_display() {
+        let diagnostic = PinDiagnostic::OutsideCgroup(CoreId { id: 2 }, "0-1".parse().unwrap());
+        assert!(diagnostic.to_string().starts_with("core 2 is excluded by the cpuset of the cgroup (0-1)"));
+        assert_eq!(diagnostic.core_id(), CoreId { id: 2 });
+        assert!(!diagnostic.is_pinnable());
+    }
+
+    #[test]
+    fn test_diagnose() {
+        use super::super::get_core_ids;
+
+        let id = get_core_ids().unwrap()[0];
+        assert_eq!(diagnose(id), PinDiagnostic::Pinnable(id));
+
+        let invalid = CoreId { id: usize::MAX };
+        assert_eq!(diagnose(invalid), PinDiagnostic::DoesNotExist(invalid));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    fn test_read_cgroup_cpus() {
+        use test_util::SysfsFixture;
+
+        let root = SysfsFixture::new("cgroup");
+
+        root.file("cpuset/job/cpuset.effective_cpus", "2-3");
+        root.file("app.slice/cpuset.cpus.effective", "0-1,4");
+
+        assert_eq!(read_cgroup_cpus("0::/app.slice\n", &root), set("0-1,4"));
+        assert_eq!(read_cgroup_cpus("3:cpuset:/job\n0::/app.slice\n", &root), set("2-3"));
+        assert_eq!(read_cgroup_cpus("4:cpu,cpuacct:/job\n0::/app.slice\n", &root), set("0-1,4"));
+        assert_eq!(read_cgroup_cpus("0::/missing\n", &root), None);
+    }
+}
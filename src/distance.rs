@@ -0,0 +1,163 @@
+use std::sync::OnceLock;
+
+use super::{CoreId, get_all_core_ids_helper, get_physical_cores};
+use super::{cache_clusters, l2_groups, numa_node_of, packages};
+
+// What two cores may share, from the closest, with the
+// distance of cores that share it.
+const LEVELS: [u32; 5] = [1, 2, 3, 4, 4];
+
+// The distance of cores that share nothing the platform tells.
+const APART: u32 = 5;
+
+// For every level of `LEVELS`, the group of a core: its
+// physical core, L2 group, cache cluster, package and NUMA
+// node.
+pub(crate) type Groups = [Option<usize>; 5];
+
+pub(crate) const PHYSICAL: usize = 0;
+pub(crate) const L2: usize = 1;
+pub(crate) const L3: usize = 2;
+pub(crate) const PACKAGE: usize = 3;
+pub(crate) const NODE: usize = 4;
+
+/// This function returns how far apart the cores `a` and `b`
+/// are, from 0 for the same core to 5 for cores that share
+/// nothing, such as for picking the closest free core when the
+/// preferred one is taken.
+///
+/// The scale is fixed:
+///
+/// * 0 - the same core
+/// * 1 - SMT siblings on one physical core
+/// * 2 - cores that share an L2 cache
+/// * 3 - cores that share an L3 cache, such as a CCX
+/// * 4 - cores on one package or one NUMA node
+/// * 5 - anything else
+///
+/// Parts of the topology the platform does not report count as
+/// not shared, so on a platform that reports none of it, every
+/// other core is 5 away. The first call builds a table of the
+/// topology, so later calls neither allocate nor query the
+/// system. The table is not updated when cores go on- or
+/// offline. Returns `None` if either core is not online.
+///
+/// # Arguments
+///
+/// * a - one core
+/// * b - the other core
+pub fn distance(a: CoreId, b: CoreId) -> Option<u32> {
+    static TABLE: OnceLock<Vec<Option<Groups>>> = OnceLock::new();
+
+    // The topology of the mock changes between tests.
+    if cfg!(feature = "mock") {
+        return between(&group_table(), a, b);
+    }
+
+    between(TABLE.get_or_init(group_table), a, b)
+}
+
+fn between(table: &[Option<Groups>], a: CoreId, b: CoreId) -> Option<u32> {
+    let first = table.get(a.id).cloned().flatten()?;
+    let second = table.get(b.id).cloned().flatten()?;
+
+    if a == b {
+        return Some(0);
+    }
+
+    let shared = first.iter().zip(&second).zip(&LEVELS)
+        .find(|&((first, second), _)| first.is_some() && first == second)
+        .map(|(_, &level)| level);
+
+    Some(shared.unwrap_or(APART))
+}
+
+// The groups of every online core, indexed by core. Groups
+// are numbered in the order of their lowest core.
+pub(crate) fn group_table() -> Vec<Option<Groups>> {
+    let online = get_all_core_ids_helper().unwrap_or_default();
+    let mut table = vec![None; online.iter().map(|core_id| core_id.id + 1).max().unwrap_or(0)];
+
+    for &core_id in &online {
+        table[core_id.id] = Some([None; 5]);
+    }
+
+    let mut set = |level: usize, groups: Vec<Vec<CoreId>>| {
+        for (index, core_ids) in groups.into_iter().enumerate() {
+            for core_id in core_ids {
+                if let Some(Some(groups)) = table.get_mut(core_id.id) {
+                    groups[level] = Some(index);
+                }
+            }
+        }
+    };
+
+    // The physical cores of macOS are only inferred.
+    if !cfg!(all(not(feature = "mock"), target_os = "macos")) {
+        set(PHYSICAL, get_physical_cores().unwrap_or_default().iter().map(|core| core.siblings().to_vec()).collect());
+    }
+
+    set(L2, l2_groups().unwrap_or_default());
+    set(L3, cache_clusters().unwrap_or_default().into_iter().map(|(_, core_ids)| core_ids).collect());
+    set(PACKAGE, packages().unwrap_or_default().into_iter().map(|(_, core_ids)| core_ids).collect());
+
+    for &core_id in &online {
+        if let (Some(groups), Some(node)) = (table[core_id.id].as_mut(), numa_node_of(core_id)) {
+            groups[NODE] = Some(node.id);
+        }
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_all_core_ids;
+
+    #[test]
+    fn test_between() {
+        let core = |id| CoreId { id };
+        let table = vec![
+            Some([Some(0), Some(0), Some(0), Some(0), Some(0)]),
+            Some([Some(0), Some(0), Some(0), Some(0), Some(0)]),
+            Some([Some(1), Some(1), Some(0), Some(0), Some(0)]),
+            None,
+            Some([Some(2), None, None, Some(1), Some(0)]),
+            Some([Some(3), None, None, Some(2), Some(1)]),
+            Some([None, None, None, None, None]),
+        ];
+
+        assert_eq!(between(&table, core(0), core(0)), Some(0));
+        assert_eq!(between(&table, core(0), core(1)), Some(1));
+        assert_eq!(between(&table, core(1), core(2)), Some(3));
+        assert_eq!(between(&table, core(2), core(4)), Some(4));
+        assert_eq!(between(&table, core(4), core(5)), Some(5));
+        // Nothing known is not shared, but the same core is
+        // still the same.
+        assert_eq!(between(&table, core(6), core(5)), Some(5));
+        assert_eq!(between(&table, core(6), core(6)), Some(0));
+        assert_eq!(between(&table, core(3), core(3)), None);
+        assert_eq!(between(&table, core(0), core(7)), None);
+    }
+
+    #[test]
+    fn test_distance() {
+        let core_ids = match get_all_core_ids() {
+            Some(core_ids) => core_ids,
+            None => return,
+        };
+
+        for &a in &core_ids {
+            assert_eq!(distance(a, a), Some(0));
+
+            for &b in &core_ids {
+                let d = distance(a, b).unwrap();
+                assert!(d <= 5);
+                assert_eq!(distance(b, a), Some(d));
+            }
+        }
+
+        assert_eq!(distance(core_ids[0], CoreId { id: usize::MAX }), None);
+    }
+}
@@ -0,0 +1,74 @@
+use std::env;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use super::Error;
+
+// The variable that turns pinning off when set to `1`.
+const DISABLED_VAR: &str = "CORE_AFFINITY_DISABLED";
+
+const UNKNOWN: u8 = 0;
+const ENABLED: u8 = 1;
+const DISABLED: u8 = 2;
+
+// Whether pinning is on, or `UNKNOWN` until the environment
+// has been read or `set_enabled` was called.
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// This function turns pinning on or off for the whole
+/// process.
+///
+/// While it is off, `set_for_current` and every other call
+/// that changes an affinity succeeds without calling into the
+/// platform, so the same binary can be run unpinned for
+/// comparison. Calls that only read affinities keep working.
+/// Checks made before reaching the platform, such as rejecting
+/// an empty list of cores, still apply. This overrides the
+/// `CORE_AFFINITY_DISABLED` variable.
+///
+/// # Arguments
+///
+/// * enabled - whether calls should change affinities
+pub fn set_enabled(enabled: bool) {
+    STATE.store(if enabled { ENABLED } else { DISABLED }, Ordering::Release);
+}
+
+/// This function returns whether calls to this crate change
+/// affinities.
+///
+/// Unless `set_enabled` was called, pinning is off if the
+/// `CORE_AFFINITY_DISABLED` environment variable is `1` at the
+/// time of the first call that pins or asks.
+pub fn is_enabled() -> bool {
+    match STATE.load(Ordering::Acquire) {
+        ENABLED => true,
+        DISABLED => false,
+        _ => {
+            let state = if env::var_os(DISABLED_VAR).is_some_and(|value| value == "1") {
+                DISABLED
+            }
+            else {
+                ENABLED
+            };
+
+            // A concurrent `set_enabled` wins over the environment.
+            match STATE.compare_exchange(UNKNOWN, state, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => state == ENABLED,
+                Err(actual) => actual == ENABLED,
+            }
+        },
+    }
+}
+
+// Runs `set`, or succeeds without running it while pinning
+// is turned off.
+pub(crate) fn unless_disabled<T, F>(set: F) -> Result<T, Error>
+    where T: Default,
+          F: FnOnce() -> Result<T, Error>
+{
+    if is_enabled() {
+        set()
+    }
+    else {
+        Ok(T::default())
+    }
+}
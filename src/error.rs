@@ -0,0 +1,403 @@
+use std::cell::Cell;
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::{CoreId, NumaNode, PhysicalCoreId};
+
+/// A list specifying the general categories of affinity errors.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The calling thread lacks the privileges for the operation.
+    PermissionDenied,
+    /// The requested core does not exist or can not be used.
+    InvalidCore,
+    /// The operation is not supported on this platform.
+    Unsupported,
+    /// The requested set of cores is empty.
+    EmptySet,
+    /// The target thread or process does not exist.
+    NotFound,
+    /// The requested core exists, but is not in the affinity
+    /// mask the thread or process may use.
+    NotAllowed,
+    /// The operation succeeded, but the thread is not
+    /// confined to the requested cores.
+    NotEffective,
+    /// Any other operating system error.
+    Other,
+    /// The requested scheduling priority is out of range for
+    /// the platform.
+    InvalidPriority,
+    /// More cores were asked for than there are to choose
+    /// from.
+    TooFewCores,
+}
+
+impl ErrorKind {
+    fn io_kind(&self) -> io::ErrorKind {
+        match *self {
+            ErrorKind::PermissionDenied => io::ErrorKind::PermissionDenied,
+            ErrorKind::InvalidCore | ErrorKind::EmptySet | ErrorKind::NotAllowed | ErrorKind::InvalidPriority
+                | ErrorKind::TooFewCores => io::ErrorKind::InvalidInput,
+            ErrorKind::Unsupported => io::ErrorKind::Unsupported,
+            ErrorKind::NotFound => io::ErrorKind::NotFound,
+            ErrorKind::NotEffective | ErrorKind::Other => io::ErrorKind::Other,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match *self {
+            ErrorKind::PermissionDenied => "permission denied",
+            ErrorKind::InvalidCore => "invalid core",
+            ErrorKind::Unsupported => "operation not supported",
+            ErrorKind::EmptySet => "empty set of cores",
+            ErrorKind::NotFound => "no such thread or process",
+            ErrorKind::NotAllowed => "core not allowed",
+            ErrorKind::NotEffective => "affinity not in effect",
+            ErrorKind::Other => "operating system error",
+            ErrorKind::InvalidPriority => "invalid priority",
+            ErrorKind::TooFewCores => "too few cores",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The error type for affinity operations.
+///
+/// Besides its kind, an `Error` records the core that was
+/// requested, if any, the raw error code reported by the
+/// operating system: `errno` on Unix, `GetLastError` on Windows,
+/// and a `kern_return_t` for Mach calls on macOS, and whether it
+/// comes from changing the scheduling priority rather than the
+/// affinity. Errors about the topology, as from
+/// `pin_to_physical_core` or `set_for_current_numa_node`,
+/// record the physical core or the NUMA node too, and errors of
+/// a `CoreQuery` the filter that failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    core_id: Option<CoreId>,
+    physical_core_id: Option<PhysicalCoreId>,
+    numa_node: Option<NumaNode>,
+    filter: Option<&'static str>,
+    code: Code,
+    priority: bool,
+}
+
+// Only the platform backends report OS codes, not the mock.
+#[cfg_attr(feature = "mock", allow(dead_code))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Code {
+    None,
+    Os(i32),
+    #[cfg(target_os = "macos")]
+    Mach(i32),
+}
+
+impl Error {
+    /// Creates an error of the given kind without an OS error code.
+    pub fn new(kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            core_id: None,
+            physical_core_id: None,
+            numa_node: None,
+            filter: None,
+            code: Code::None,
+            priority: false,
+        }
+    }
+
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the core the failed operation was requested for.
+    pub fn core_id(&self) -> Option<CoreId> {
+        self.core_id
+    }
+
+    /// Returns the physical core the failed operation was
+    /// requested for.
+    pub fn physical_core_id(&self) -> Option<PhysicalCoreId> {
+        self.physical_core_id
+    }
+
+    /// Returns the NUMA node the failed operation was
+    /// requested for.
+    pub fn numa_node(&self) -> Option<NumaNode> {
+        self.numa_node
+    }
+
+    /// Returns the filter of a `CoreQuery` that failed, as it is
+    /// called in the builder, such as `"numa_node"`.
+    pub fn filter(&self) -> Option<&'static str> {
+        self.filter
+    }
+
+    /// Returns whether the error comes from changing the
+    /// scheduling priority of a thread, as in
+    /// `set_for_current_with_priority`, rather than from
+    /// changing its affinity.
+    pub fn is_priority_error(&self) -> bool {
+        self.priority
+    }
+
+    /// Returns the raw error code reported by the operating system.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self.code {
+            Code::None => None,
+            Code::Os(code) => Some(code),
+            #[cfg(target_os = "macos")]
+            Code::Mach(code) => Some(code),
+        }
+    }
+
+    #[cfg_attr(feature = "mock", allow(dead_code))]
+    pub(crate) fn from_os_error(code: i32) -> Error {
+        Error {
+            kind: os_error_kind(code),
+            core_id: None,
+            physical_core_id: None,
+            numa_node: None,
+            filter: None,
+            code: Code::Os(code),
+            priority: false,
+        }
+    }
+
+    #[cfg_attr(feature = "mock", allow(dead_code))]
+    pub(crate) fn last_os_error() -> Error {
+        match io::Error::last_os_error().raw_os_error() {
+            Some(code) => Error::from_os_error(code),
+            None => Error::new(ErrorKind::Other),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    #[cfg_attr(feature = "mock", allow(dead_code))]
+    pub(crate) fn from_kern_return(code: i32) -> Error {
+        // See <mach/kern_return.h>.
+        let kind = match code {
+            2 => ErrorKind::PermissionDenied, // KERN_PROTECTION_FAILURE
+            4 => ErrorKind::InvalidCore,      // KERN_INVALID_ARGUMENT
+            46 => ErrorKind::Unsupported,     // KERN_NOT_SUPPORTED
+            _ => ErrorKind::Other,
+        };
+
+        Error {
+            kind,
+            core_id: None,
+            physical_core_id: None,
+            numa_node: None,
+            filter: None,
+            code: Code::Mach(code),
+            priority: false,
+        }
+    }
+
+    // Like `io::Error::from`, but never allocates, as is
+    // required between `fork` and `exec`.
+    #[cfg(unix)]
+    pub(crate) fn into_simple_io_error(self) -> io::Error {
+        match self.code {
+            Code::Os(code) => io::Error::from_raw_os_error(code),
+            _ => io::Error::from(self.kind.io_kind()),
+        }
+    }
+
+    pub(crate) fn with_core_id(mut self, core_id: CoreId) -> Error {
+        self.core_id = Some(core_id);
+        self
+    }
+
+    pub(crate) fn with_physical_core_id(mut self, physical_core_id: PhysicalCoreId) -> Error {
+        self.physical_core_id = Some(physical_core_id);
+        self
+    }
+
+    pub(crate) fn with_numa_node(mut self, numa_node: NumaNode) -> Error {
+        self.numa_node = Some(numa_node);
+        self
+    }
+
+    pub(crate) fn with_filter(mut self, filter: &'static str) -> Error {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub(crate) fn in_priority(mut self) -> Error {
+        self.priority = true;
+        self
+    }
+}
+
+#[cfg(any(
+    target_os = "android",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+fn os_error_kind(code: i32) -> ErrorKind {
+    use libc::{EACCES, EINVAL, ENOSYS, EPERM, ESRCH};
+
+    match code {
+        EPERM | EACCES => ErrorKind::PermissionDenied,
+        EINVAL => ErrorKind::InvalidCore,
+        ENOSYS => ErrorKind::Unsupported,
+        ESRCH => ErrorKind::NotFound,
+        _ => ErrorKind::Other,
+    }
+}
+
+#[cfg(target_os = "windows")]
+#[cfg_attr(feature = "mock", allow(dead_code))]
+fn os_error_kind(code: i32) -> ErrorKind {
+    use winapi::shared::winerror::{
+        ERROR_ACCESS_DENIED, ERROR_CALL_NOT_IMPLEMENTED, ERROR_INVALID_PARAMETER,
+    };
+
+    match code as u32 {
+        ERROR_ACCESS_DENIED => ErrorKind::PermissionDenied,
+        ERROR_INVALID_PARAMETER => ErrorKind::InvalidCore,
+        ERROR_CALL_NOT_IMPLEMENTED => ErrorKind::Unsupported,
+        _ => ErrorKind::Other,
+    }
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "windows"
+)))]
+fn os_error_kind(_code: i32) -> ErrorKind {
+    ErrorKind::Other
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.priority {
+            f.write_str("priority: ")?;
+        }
+        else if let Some(filter) = self.filter {
+            write!(f, "filter {}: ", filter)?;
+        }
+        else if let Some(core_id) = self.core_id {
+            write!(f, "core {}: ", core_id.id)?;
+        }
+        else if let Some(physical_core_id) = self.physical_core_id {
+            write!(f, "physical core {}: ", physical_core_id.id)?;
+        }
+        else if let Some(numa_node) = self.numa_node {
+            write!(f, "NUMA node {}: ", numa_node.id)?;
+        }
+
+        f.write_str(self.kind.as_str())?;
+
+        match self.code {
+            Code::None => Ok(()),
+            Code::Os(code) => write!(f, ": {}", io::Error::from_raw_os_error(code)),
+            #[cfg(target_os = "macos")]
+            Code::Mach(code) => write!(f, ": kern_return_t {}", code),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        if let Code::Os(code) = err.code {
+            return io::Error::from_raw_os_error(code);
+        }
+
+        io::Error::new(err.kind.io_kind(), err)
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: Cell<Option<Error>> = const { Cell::new(None) };
+}
+
+/// Returns the error of the last failed call into this
+/// crate from the current thread.
+///
+/// Every call that can fail updates this value, so it is
+/// cleared again by the next successful call.
+pub fn last_error() -> Option<io::Error> {
+    LAST_ERROR.with(|last| last.get()).map(io::Error::from)
+}
+
+pub(crate) fn record<T>(result: Result<T, Error>) -> Result<T, Error> {
+    LAST_ERROR.with(|last| last.set(result.as_ref().err().cloned()));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display() {
+        let err = Error::new(ErrorKind::InvalidCore).with_core_id(CoreId { id: 7 });
+        assert_eq!(err.to_string(), "core 7: invalid core");
+        assert_eq!(err.raw_os_error(), None);
+    }
+
+    #[test]
+    fn test_error_display_physical_core() {
+        let err = Error::new(ErrorKind::NotAllowed).with_physical_core_id(PhysicalCoreId { id: 3 });
+        assert_eq!(err.to_string(), "physical core 3: core not allowed");
+        assert_eq!(err.physical_core_id(), Some(PhysicalCoreId { id: 3 }));
+        assert_eq!(err.core_id(), None);
+
+        let err = Error::new(ErrorKind::NotAllowed).with_numa_node(NumaNode { id: 1 });
+        assert_eq!(err.to_string(), "NUMA node 1: core not allowed");
+        assert_eq!(err.numa_node(), Some(NumaNode { id: 1 }));
+    }
+
+    #[test]
+    fn test_error_display_priority() {
+        let err = Error::new(ErrorKind::PermissionDenied).in_priority();
+        assert_eq!(err.to_string(), "priority: permission denied");
+        assert!(err.is_priority_error());
+        assert!(!Error::new(ErrorKind::PermissionDenied).is_priority_error());
+    }
+
+    #[test]
+    fn test_error_display_filter() {
+        let err = Error::new(ErrorKind::Unsupported).with_filter("kind");
+        assert_eq!(err.to_string(), "filter kind: operation not supported");
+        assert_eq!(err.filter(), Some("kind"));
+        assert_eq!(Error::new(ErrorKind::Unsupported).filter(), None);
+    }
+
+    #[test]
+    fn test_error_into_io_error() {
+        let err = io::Error::from(Error::from_os_error(1));
+        assert_eq!(err.raw_os_error(), Some(1));
+
+        let err = io::Error::from(Error::new(ErrorKind::InvalidCore));
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_error_display_os_error() {
+        let code = 1;
+        let err = Error::from_os_error(code);
+        let message = io::Error::from_raw_os_error(code).to_string();
+        assert_eq!(err.raw_os_error(), Some(code));
+        assert!(err.to_string().contains(&message));
+    }
+}
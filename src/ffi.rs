@@ -0,0 +1,193 @@
+//! A C interface to the crate, enabled by the `ffi` feature.
+//!
+//! The functions are exported unmangled, so a `staticlib` or
+//! `cdylib` that links this crate exposes them to C and C++.
+//! They are declared in `include/core_affinity.h`.
+//!
+//! Every function returns `CORE_AFFINITY_OK` or a positive count
+//! on success, and one of the negative `CORE_AFFINITY_ERROR_*`
+//! codes on failure. None of them unwinds: a panic inside is
+//! caught and reported as `CORE_AFFINITY_ERROR_PANIC`.
+//!
+//! The caller owns every buffer it passes in, and no pointer is
+//! kept past the call. The strings returned by
+//! `core_affinity_error_message` are static and must not be
+//! freed.
+
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, UnwindSafe};
+use std::slice;
+
+use super::{CoreId, Error, ErrorKind, set_for_current_checked, try_get_core_ids};
+
+/// The call succeeded.
+pub const CORE_AFFINITY_OK: c_int = 0;
+/// See `ErrorKind::PermissionDenied`.
+pub const CORE_AFFINITY_ERROR_PERMISSION_DENIED: c_int = -1;
+/// See `ErrorKind::InvalidCore`.
+pub const CORE_AFFINITY_ERROR_INVALID_CORE: c_int = -2;
+/// See `ErrorKind::Unsupported`.
+pub const CORE_AFFINITY_ERROR_UNSUPPORTED: c_int = -3;
+/// See `ErrorKind::EmptySet`.
+pub const CORE_AFFINITY_ERROR_EMPTY_SET: c_int = -4;
+/// See `ErrorKind::NotFound`.
+pub const CORE_AFFINITY_ERROR_NOT_FOUND: c_int = -5;
+/// See `ErrorKind::NotAllowed`.
+pub const CORE_AFFINITY_ERROR_NOT_ALLOWED: c_int = -6;
+/// See `ErrorKind::NotEffective`.
+pub const CORE_AFFINITY_ERROR_NOT_EFFECTIVE: c_int = -7;
+/// See `ErrorKind::Other`.
+pub const CORE_AFFINITY_ERROR_OTHER: c_int = -8;
+/// A pointer argument was null where it may not be.
+pub const CORE_AFFINITY_ERROR_NULL_POINTER: c_int = -9;
+/// The crate panicked. This is a bug.
+pub const CORE_AFFINITY_ERROR_PANIC: c_int = -10;
+/// See `ErrorKind::InvalidPriority`.
+pub const CORE_AFFINITY_ERROR_INVALID_PRIORITY: c_int = -11;
+/// See `ErrorKind::TooFewCores`.
+pub const CORE_AFFINITY_ERROR_TOO_FEW_CORES: c_int = -12;
+
+/// This function writes the IDs of the cores the current thread
+/// may run on to `buf`, in ascending order, and returns how many
+/// there are, or a negative error code.
+///
+/// At most `cap` IDs are written. A result larger than `cap`
+/// means the list was cut short; call again with a buffer that
+/// large to get every ID. Pass a null `buf` and a `cap` of zero
+/// to only count the cores.
+///
+/// # Safety
+///
+/// Unless `cap` is zero, `buf` must point to `cap` writable
+/// `size_t` values.
+#[no_mangle]
+pub unsafe extern "C" fn core_affinity_get_core_ids(buf: *mut usize, cap: usize) -> isize {
+    if buf.is_null() && cap != 0 {
+        return CORE_AFFINITY_ERROR_NULL_POINTER as isize;
+    }
+
+    catch(|| {
+        let core_ids = try_get_core_ids().map_err(|err| error_code(&err))?;
+
+        if cap != 0 {
+            // The caller vouches for `cap` values at `buf`.
+            let buf = unsafe { slice::from_raw_parts_mut(buf, cap) };
+
+            for (slot, core_id) in buf.iter_mut().zip(&core_ids) {
+                *slot = core_id.id;
+            }
+        }
+
+        Ok(core_ids.len() as isize)
+    }).unwrap_or_else(|code| code as isize)
+}
+
+/// This function pins the current thread to core `core` and
+/// returns `CORE_AFFINITY_OK`, or a negative error code.
+#[no_mangle]
+pub extern "C" fn core_affinity_set_for_current(core: usize) -> c_int {
+    catch(|| {
+        set_for_current_checked(CoreId { id: core }).map_err(|err| error_code(&err))?;
+        Ok(CORE_AFFINITY_OK)
+    }).unwrap_or_else(|code| code)
+}
+
+/// This function returns a description of the error code `code`,
+/// as a static NUL-terminated string the caller must not free.
+///
+/// Unknown codes are described as an unknown error, so the
+/// result is never null.
+#[no_mangle]
+pub extern "C" fn core_affinity_error_message(code: c_int) -> *const c_char {
+    let message: &'static [u8] = match code {
+        CORE_AFFINITY_OK => b"success\0",
+        CORE_AFFINITY_ERROR_PERMISSION_DENIED => b"permission denied\0",
+        CORE_AFFINITY_ERROR_INVALID_CORE => b"invalid core\0",
+        CORE_AFFINITY_ERROR_UNSUPPORTED => b"operation not supported\0",
+        CORE_AFFINITY_ERROR_EMPTY_SET => b"empty set of cores\0",
+        CORE_AFFINITY_ERROR_NOT_FOUND => b"no such thread or process\0",
+        CORE_AFFINITY_ERROR_NOT_ALLOWED => b"core not allowed\0",
+        CORE_AFFINITY_ERROR_NOT_EFFECTIVE => b"affinity not in effect\0",
+        CORE_AFFINITY_ERROR_OTHER => b"operating system error\0",
+        CORE_AFFINITY_ERROR_NULL_POINTER => b"null pointer\0",
+        CORE_AFFINITY_ERROR_PANIC => b"internal panic\0",
+        CORE_AFFINITY_ERROR_INVALID_PRIORITY => b"invalid priority\0",
+        CORE_AFFINITY_ERROR_TOO_FEW_CORES => b"too few cores\0",
+        _ => b"unknown error\0",
+    };
+
+    message.as_ptr() as *const c_char
+}
+
+// The code C callers see for `err`.
+fn error_code(err: &Error) -> c_int {
+    match err.kind() {
+        ErrorKind::PermissionDenied => CORE_AFFINITY_ERROR_PERMISSION_DENIED,
+        ErrorKind::InvalidCore => CORE_AFFINITY_ERROR_INVALID_CORE,
+        ErrorKind::Unsupported => CORE_AFFINITY_ERROR_UNSUPPORTED,
+        ErrorKind::EmptySet => CORE_AFFINITY_ERROR_EMPTY_SET,
+        ErrorKind::NotFound => CORE_AFFINITY_ERROR_NOT_FOUND,
+        ErrorKind::NotAllowed => CORE_AFFINITY_ERROR_NOT_ALLOWED,
+        ErrorKind::NotEffective => CORE_AFFINITY_ERROR_NOT_EFFECTIVE,
+        ErrorKind::Other => CORE_AFFINITY_ERROR_OTHER,
+        ErrorKind::InvalidPriority => CORE_AFFINITY_ERROR_INVALID_PRIORITY,
+        ErrorKind::TooFewCores => CORE_AFFINITY_ERROR_TOO_FEW_CORES,
+    }
+}
+
+// Runs `f`, turning a panic into `CORE_AFFINITY_ERROR_PANIC`
+// so it never crosses the boundary.
+fn catch<F, T>(f: F) -> Result<T, c_int>
+    where F: FnOnce() -> Result<T, c_int> + UnwindSafe
+{
+    panic::catch_unwind(f).unwrap_or(Err(CORE_AFFINITY_ERROR_PANIC))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    #[test]
+    fn test_error_code() {
+        let kinds = [
+            ErrorKind::PermissionDenied,
+            ErrorKind::InvalidCore,
+            ErrorKind::Unsupported,
+            ErrorKind::EmptySet,
+            ErrorKind::NotFound,
+            ErrorKind::NotAllowed,
+            ErrorKind::NotEffective,
+            ErrorKind::Other,
+        ];
+
+        // The messages match the ones of the Rust errors.
+        for (i, &kind) in kinds.iter().enumerate() {
+            let code = error_code(&Error::new(kind));
+            assert_eq!(code, -(i as c_int) - 1);
+
+            let message = unsafe { CStr::from_ptr(core_affinity_error_message(code)) };
+            assert_eq!(message.to_str().unwrap(), kind.to_string());
+        }
+
+        // Kinds added after the C-specific codes come after them.
+        for &(kind, expected) in &[(ErrorKind::InvalidPriority, CORE_AFFINITY_ERROR_INVALID_PRIORITY),
+                                   (ErrorKind::TooFewCores, CORE_AFFINITY_ERROR_TOO_FEW_CORES)] {
+            let code = error_code(&Error::new(kind));
+            assert_eq!(code, expected);
+
+            let message = unsafe { CStr::from_ptr(core_affinity_error_message(code)) };
+            assert_eq!(message.to_str().unwrap(), kind.to_string());
+        }
+
+        let message = unsafe { CStr::from_ptr(core_affinity_error_message(42)) };
+        assert_eq!(message.to_str().unwrap(), "unknown error");
+    }
+
+    #[test]
+    fn test_catch() {
+        assert_eq!(catch(|| Ok(1)), Ok(1));
+        assert_eq!(catch(|| -> Result<(), c_int> { panic!("boom") }), Err(CORE_AFFINITY_ERROR_PANIC));
+    }
+}
@@ -0,0 +1,237 @@
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+use std::mem;
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+use std::sync::atomic::{AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+use std::sync::{Mutex, Once};
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use libc::{cpu_set_t, sched_setaffinity};
+#[cfg(target_os = "freebsd")]
+use libc::{CPU_LEVEL_CPUSET, CPU_LEVEL_WHICH, CPU_WHICH_TID, cpuset_getaffinity, cpuset_setaffinity, cpuset_t};
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+use libc::{CPU_SET, CPU_SETSIZE, pthread_atfork};
+
+use super::{CpuSet, Error, ErrorKind};
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+use super::invalidate;
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+use raw::errno_location;
+
+/// What the child of a `fork` does with the affinity mask it
+/// inherits from the thread that forked, once `at_fork_reset`
+/// has been called.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ForkPolicy {
+    /// Keeps the inherited mask.
+    Inherit,
+    /// Widens the mask to every core the process may use, as
+    /// `clear_for_current` does.
+    #[default]
+    Reset,
+    /// Confines the child to these cores.
+    Set(CpuSet),
+}
+
+/// This function makes the child of every later `fork` apply
+/// the fork policy, which is `ForkPolicy::Reset` until changed
+/// by `set_fork_policy`.
+///
+/// The handler is registered with `pthread_atfork` the first
+/// time; later calls return the same result without adding a
+/// second one. It runs in the child before `fork` returns
+/// there, and only uses async-signal-safe system calls, so it
+/// is safe in the child of a multithreaded process. It does
+/// not run for `posix_spawn`, which `std::process::Command`
+/// may use; see `CommandAffinityExt` for those. Errors in the
+/// child leave it with the inherited mask.
+///
+/// Fails with `ErrorKind::Unsupported` on platforms without
+/// affinity masks or without `fork`, such as macOS and Windows.
+pub fn at_fork_reset() -> Result<(), Error> {
+    imp::at_fork_reset()
+}
+
+/// This function sets what the child of a `fork` does with
+/// its affinity mask, once `at_fork_reset` has been called.
+///
+/// It may be called before or after `at_fork_reset`, and from
+/// any thread. A `fork` racing with the call applies either
+/// the old or the new policy.
+///
+/// Fails with `ErrorKind::EmptySet` for an empty set, with
+/// `ErrorKind::InvalidCore` for a core beyond `CPU_SETSIZE`,
+/// and with `ErrorKind::Unsupported` where `at_fork_reset`
+/// does.
+///
+/// # Arguments
+///
+/// * policy - what the child should do
+pub fn set_fork_policy(policy: ForkPolicy) -> Result<(), Error> {
+    if let ForkPolicy::Set(ref set) = policy {
+        if set.is_empty() {
+            return Err(Error::new(ErrorKind::EmptySet));
+        }
+    }
+
+    imp::set_fork_policy(&policy)
+}
+
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+mod imp {
+    use super::*;
+
+    const WORDS: usize = CPU_SETSIZE as usize / 64;
+
+    const INHERIT: u8 = 0;
+    const RESET: u8 = 1;
+    const SET: u8 = 2;
+
+    // A policy in a form the child can read without locks.
+    struct Slot {
+        mode: AtomicU8,
+        words: [AtomicU64; WORDS],
+    }
+
+    impl Slot {
+        const fn new(mode: u8) -> Slot {
+            Slot { mode: AtomicU8::new(mode), words: [const { AtomicU64::new(0) }; WORDS] }
+        }
+    }
+
+    // Writers fill the slot that is not active and then switch
+    // over, so a `fork` in the middle of a write leaves the
+    // child with a complete slot.
+    static SLOTS: [Slot; 2] = [Slot::new(RESET), Slot::new(RESET)];
+    static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+    static WRITER: Mutex<()> = Mutex::new(());
+
+    static REGISTER: Once = Once::new();
+    // The error code returned by `pthread_atfork`.
+    static REGISTERED: AtomicI32 = AtomicI32::new(0);
+
+    pub fn at_fork_reset() -> Result<(), Error> {
+        REGISTER.call_once(|| {
+            let code = unsafe { pthread_atfork(None, None, Some(child)) };
+            REGISTERED.store(code, Ordering::Release);
+        });
+
+        match REGISTERED.load(Ordering::Acquire) {
+            0 => Ok(()),
+            code => Err(Error::from_os_error(code)),
+        }
+    }
+
+    pub fn set_fork_policy(policy: &ForkPolicy) -> Result<(), Error> {
+        let mut words = [0u64; WORDS];
+
+        let mode = match *policy {
+            ForkPolicy::Inherit => INHERIT,
+            ForkPolicy::Reset => RESET,
+            ForkPolicy::Set(ref set) => {
+                for core_id in set {
+                    if core_id.id >= CPU_SETSIZE as usize {
+                        return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+                    }
+
+                    words[core_id.id / 64] |= 1 << (core_id.id % 64);
+                }
+
+                SET
+            },
+        };
+
+        let _writer = WRITER.lock().unwrap_or_else(|err| err.into_inner());
+        let next = 1 - ACTIVE.load(Ordering::Relaxed);
+        let slot = &SLOTS[next];
+
+        slot.mode.store(mode, Ordering::Relaxed);
+        for (word, &value) in slot.words.iter().zip(&words) {
+            word.store(value, Ordering::Relaxed);
+        }
+
+        ACTIVE.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    extern "C" fn child() {
+        let slot = &SLOTS[ACTIVE.load(Ordering::Acquire)];
+
+        unsafe {
+            let errno = errno_location();
+            let saved = *errno;
+
+            let pinned = match slot.mode.load(Ordering::Relaxed) {
+                RESET => reset(),
+                SET => apply(slot),
+                _ => false,
+            };
+
+            // The thread-local cache of the core came along from
+            // the parent.
+            if pinned {
+                let _ = invalidate(Ok(()));
+            }
+
+            *errno = saved;
+        }
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    unsafe fn reset() -> bool {
+        // The kernel drops the cores outside the cpuset of the
+        // process.
+        let mut set = mem::zeroed::<cpu_set_t>();
+        for id in 0..CPU_SETSIZE as usize {
+            CPU_SET(id, &mut set);
+        }
+
+        sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &set) == 0
+    }
+
+    #[cfg(target_os = "freebsd")]
+    unsafe fn reset() -> bool {
+        // A thread may widen its mask up to its cpuset.
+        let mut set = mem::zeroed::<cpuset_t>();
+
+        cpuset_getaffinity(CPU_LEVEL_CPUSET, CPU_WHICH_TID, -1, mem::size_of::<cpuset_t>(), &mut set) == 0
+            && cpuset_setaffinity(CPU_LEVEL_WHICH, CPU_WHICH_TID, -1, mem::size_of::<cpuset_t>(), &set) == 0
+    }
+
+    unsafe fn apply(slot: &Slot) -> bool {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let mut set = mem::zeroed::<cpu_set_t>();
+        #[cfg(target_os = "freebsd")]
+        let mut set = mem::zeroed::<cpuset_t>();
+
+        for (i, word) in slot.words.iter().enumerate() {
+            let value = word.load(Ordering::Relaxed);
+
+            for bit in 0..64 {
+                if value & (1 << bit) != 0 {
+                    CPU_SET(i * 64 + bit, &mut set);
+                }
+            }
+        }
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let res = sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &set);
+        #[cfg(target_os = "freebsd")]
+        let res = cpuset_setaffinity(CPU_LEVEL_WHICH, CPU_WHICH_TID, -1, mem::size_of::<cpuset_t>(), &set);
+
+        res == 0
+    }
+}
+
+#[cfg(not(any(target_os = "android", target_os = "linux", target_os = "freebsd")))]
+mod imp {
+    use super::*;
+
+    pub fn at_fork_reset() -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+
+    pub fn set_fork_policy(_policy: &ForkPolicy) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+}
@@ -0,0 +1,770 @@
+use std::mem;
+use std::os::raw::c_void;
+use std::os::unix::process::CommandExt;
+use std::os::unix::thread::JoinHandleExt;
+use std::process::Command;
+use std::ptr;
+use std::thread::JoinHandle;
+
+use libc::{
+    c_int, c_long, cpuset_getaffinity, cpuset_setaffinity, cpuset_t, id_t, pthread_getaffinity_np,
+    pthread_setaffinity_np, sched_getcpu, size_t, sysctlbyname, CPU_ISSET, CPU_LEVEL_CPUSET, CPU_LEVEL_ROOT,
+    CPU_LEVEL_WHICH, CPU_SET, CPU_SETSIZE, CPU_WHICH_PID, CPU_WHICH_TID,
+};
+
+use super::{CacheLevel, Capabilities, CoreId, CoreIds, CoreKind, CpuTimes, Error, ErrorKind, KindSource, NumaNode};
+use super::CoreState;
+use super::numa_node_of;
+use super::StableCoreId;
+
+pub fn core_ids() -> Result<CoreIds, Error> {
+    get_affinity_mask().map(|set| to_core_id_iter(&set))
+}
+
+pub fn get_affinity_for_current() -> Result<Vec<CoreId>, Error> {
+    // `core_ids` already reads the mask of the current thread.
+    core_ids().map(Iterator::collect)
+}
+
+pub fn get_all_core_ids() -> Option<Vec<CoreId>> {
+    let mut set = new_cpu_set();
+
+    // The root set of the current process contains every
+    // core available to the system.
+    let result = unsafe {
+        cpuset_getaffinity(
+            CPU_LEVEL_ROOT,
+            CPU_WHICH_PID,
+            -1, // -1 == current process
+            mem::size_of::<cpuset_t>(),
+            &mut set,
+        )
+    };
+
+    if result == 0 {
+        Some(
+            (0..CPU_SETSIZE as usize)
+                .filter(|&i| unsafe { CPU_ISSET(i, &set) })
+                .map(|id| CoreId { id })
+                .collect(),
+        )
+    } else {
+        None
+    }
+}
+
+pub fn core_ids_by_state(state: CoreState) -> Option<Vec<CoreId>> {
+    // Only the online cores are known.
+    get_all_core_ids().map(|online| if state == CoreState::Offline { Vec::new() } else { online })
+}
+
+pub fn set_for_current(core_id: CoreId) -> Result<(), Error> {
+    // Turn `core_id` into a `libc::cpuset_t` with only
+    // one core active.
+    let set = to_cpu_set(&[core_id])?;
+
+    set_affinity_mask(&set).map_err(|err| err.with_core_id(core_id))
+}
+
+pub fn set_for_current_set(core_ids: &[CoreId]) -> Result<(), Error> {
+    set_affinity_mask(&to_cpu_set(core_ids)?)
+}
+
+pub fn set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId) -> Result<(), Error> {
+    let thread = handle.as_pthread_t();
+    let set = to_cpu_set(&[core_id])?;
+
+    // Unlike `cpuset_setaffinity`, this returns the error
+    // number instead of setting `errno`.
+    let res = unsafe { pthread_setaffinity_np(thread, mem::size_of::<cpuset_t>(), &set) };
+
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(Error::from_os_error(res).with_core_id(core_id))
+    }
+}
+
+pub fn get_for_thread<T>(handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    let thread = handle.as_pthread_t();
+    let mut set = new_cpu_set();
+
+    let res = unsafe { pthread_getaffinity_np(thread, mem::size_of::<cpuset_t>(), &mut set) };
+
+    if res == 0 {
+        Ok(to_core_ids(&set))
+    } else {
+        Err(Error::from_os_error(res))
+    }
+}
+
+pub fn set_for_process(pid: u32, core_ids: &[CoreId]) -> Result<(), Error> {
+    let set = to_cpu_set(core_ids)?;
+
+    // `CPU_WHICH_PID` applies the mask to every thread of the process.
+    let res = unsafe {
+        cpuset_setaffinity(
+            CPU_LEVEL_WHICH,
+            CPU_WHICH_PID,
+            pid as id_t,
+            mem::size_of::<cpuset_t>(),
+            &set,
+        )
+    };
+
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn get_for_process(pid: u32) -> Result<Vec<CoreId>, Error> {
+    let mut set = new_cpu_set();
+
+    let res = unsafe {
+        cpuset_getaffinity(
+            CPU_LEVEL_WHICH,
+            CPU_WHICH_PID,
+            pid as id_t,
+            mem::size_of::<cpuset_t>(),
+            &mut set,
+        )
+    };
+
+    if res == 0 {
+        Ok(to_core_ids(&set))
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn thread_core_ids() -> Result<CoreIds, Error> {
+    core_ids()
+}
+
+pub fn process_core_ids() -> Result<CoreIds, Error> {
+    let mut set = new_cpu_set();
+
+    let res = unsafe {
+        cpuset_getaffinity(
+            CPU_LEVEL_WHICH,
+            CPU_WHICH_PID,
+            -1, // -1 == current process
+            mem::size_of::<cpuset_t>(),
+            &mut set,
+        )
+    };
+
+    if res == 0 {
+        Ok(to_core_id_iter(&set))
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn set_for_command(command: &mut Command, core_ids: &[CoreId]) {
+    // Build the mask up front: the closure runs in the child
+    // between `fork` and `exec`, where it must not allocate.
+    let set = if core_ids.is_empty() {
+        Err(Error::new(ErrorKind::EmptySet))
+    } else {
+        to_cpu_set(core_ids)
+    };
+
+    unsafe {
+        command.pre_exec(move || {
+            set.and_then(|set| set_affinity_mask(&set))
+                .map_err(Error::into_simple_io_error)
+        });
+    }
+}
+
+pub fn current_core() -> Result<CoreId, Error> {
+    let cpu = unsafe { sched_getcpu() };
+
+    if cpu >= 0 {
+        Ok(CoreId { id: cpu as usize })
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn current_location() -> Result<(CoreId, NumaNode), Error> {
+    // There is no call that returns both, so the node comes
+    // from the table of `numa_node_of`.
+    let core_id = current_core()?;
+
+    match numa_node_of(core_id) {
+        Some(node) => Ok((core_id, node)),
+        None => Err(Error::new(ErrorKind::Unsupported)),
+    }
+}
+
+pub fn set_preferred_for_current(_core_id: CoreId) -> Result<(), Error> {
+    // FreeBSD has no ideal processor, and pinning the thread
+    // instead would be much stronger than a preference.
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn capabilities() -> Capabilities {
+    let mask = get_affinity_mask();
+
+    Capabilities {
+        // Reapplying the current mask changes nothing, but
+        // fails where a jail or sandbox forbids the call.
+        hard_pinning: mask.as_ref().is_ok_and(|set| set_affinity_mask(set).is_ok()),
+        soft_affinity: false,
+        can_query_affinity: mask.is_ok(),
+        max_cores_representable: CPU_SETSIZE as usize,
+    }
+}
+
+pub fn clear_for_current() -> Result<(), Error> {
+    let mut set = new_cpu_set();
+
+    // A thread may widen its mask up to the cpuset it
+    // belongs to, which is the mask of the process.
+    let result = unsafe {
+        cpuset_getaffinity(
+            CPU_LEVEL_CPUSET,
+            CPU_WHICH_TID,
+            -1, // -1 == current thread
+            mem::size_of::<cpuset_t>(),
+            &mut set,
+        )
+    };
+
+    if result != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    set_affinity_mask(&set)
+}
+
+// Selects the cores of a memory domain in `cpuset_getaffinity`.
+// See <sys/cpuset.h>.
+const CPU_WHICH_DOMAIN: c_int = 6;
+
+pub fn dies() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn clusters() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn stable_core_ids() -> Option<Vec<(CoreId, StableCoreId)>> {
+    None
+}
+
+pub fn cache_info(_core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    None
+}
+
+pub fn core_kinds() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    None
+}
+
+pub fn max_frequency_khz(core_id: CoreId) -> Option<u64> {
+    // Only the first core of a package may list the levels.
+    let name = format!("dev.cpu.{}.freq_levels\0", core_id.id);
+
+    sysctl_string(name.as_bytes()).and_then(|levels| parse_freq_levels(&levels))
+}
+
+// Parses the highest of levels such as `3600/95000 3400/85000`,
+// each a frequency in MHz and a power in mW.
+fn parse_freq_levels(levels: &str) -> Option<u64> {
+    levels.split_whitespace()
+        .filter_map(|level| level.split('/').next()?.parse::<u64>().ok())
+        .max()
+        .map(|mhz| mhz * 1000)
+}
+
+pub fn core_rankings() -> Option<Vec<(CoreId, u64)>> {
+    None
+}
+
+pub fn current_frequency_khz(_core_id: CoreId) -> Option<u64> {
+    None
+}
+
+pub fn cpu_times() -> Option<Vec<(CoreId, CpuTimes)>> {
+    let online = get_all_core_ids()?;
+    let bytes = sysctl_bytes(b"kern.cp_times\0")?;
+    let ticks: Vec<u64> = bytes.chunks_exact(mem::size_of::<c_long>())
+        .map(|chunk| {
+            let mut value = [0; mem::size_of::<c_long>()];
+            value.copy_from_slice(chunk);
+            c_long::from_ne_bytes(value) as u64
+        })
+        .collect();
+
+    Some(parse_cp_times(&ticks).into_iter().filter(|(core_id, _)| online.contains(core_id)).collect())
+}
+
+// Splits the ticks of `kern.cp_times` by core. Every core up to
+// the highest has user, nice, system, interrupt and idle ticks,
+// see <sys/resource.h>, including cores that are absent.
+fn parse_cp_times(ticks: &[u64]) -> Vec<(CoreId, CpuTimes)> {
+    ticks.chunks_exact(CPUSTATES)
+        .enumerate()
+        .map(|(id, states)| {
+            let times = CpuTimes { user: states[0] + states[1], system: states[2] + states[3], idle: states[4] };
+            (CoreId { id }, times)
+        })
+        .collect()
+}
+
+const CPUSTATES: usize = 5;
+
+pub fn capacity(_core_id: CoreId) -> Option<u32> {
+    None
+}
+
+pub fn numa_nodes() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    let domains = sysctl_int(b"vm.ndomains\0").filter(|&count| count > 1).unwrap_or(1);
+    let mut nodes = Vec::new();
+
+    for domain in 0..domains {
+        let mut set = new_cpu_set();
+
+        let result = unsafe {
+            cpuset_getaffinity(
+                CPU_LEVEL_WHICH,
+                CPU_WHICH_DOMAIN,
+                domain as id_t,
+                mem::size_of::<cpuset_t>(),
+                &mut set,
+            )
+        };
+
+        if result == 0 {
+            nodes.push((NumaNode { id: domain as usize }, to_core_ids(&set)));
+        }
+    }
+
+    // Kernels without NUMA support have one domain they may
+    // not answer for.
+    if nodes.is_empty() {
+        return get_all_core_ids().map(|core_ids| vec![(NumaNode { id: 0 }, core_ids)]);
+    }
+
+    Some(nodes)
+}
+
+pub fn core_siblings() -> Option<Vec<Vec<CoreId>>> {
+    let groups = topology_spec()?;
+    let mut siblings = Vec::new();
+
+    walk(&groups, &mut |group| {
+        if group.smt {
+            siblings.push(group.cpus.clone());
+        }
+    });
+
+    Some(siblings)
+}
+
+pub fn cache_groups(level: usize) -> Option<Vec<Vec<CoreId>>> {
+    let mut groups = Vec::new();
+
+    walk(&topology_spec()?, &mut |group| {
+        if group.cache_level == level {
+            groups.push(group.cpus.clone());
+        }
+    });
+
+    if groups.is_empty() { None } else { Some(groups) }
+}
+
+pub fn packages() -> Option<Vec<(usize, Vec<CoreId>)>> {
+    Some(find_packages(&topology_spec()?))
+}
+
+// A group of cores in the topology that the scheduler exports
+// as XML in `kern.sched.topology_spec`.
+#[derive(Debug, Default, PartialEq)]
+struct Group {
+    // The level of the cache shared by the cores, 0 for none.
+    cache_level: usize,
+    cpus: Vec<CoreId>,
+    // Whether the cores are the threads of one physical core.
+    smt: bool,
+    children: Vec<Group>,
+}
+
+fn topology_spec() -> Option<Vec<Group>> {
+    sysctl_string(b"kern.sched.topology_spec\0").map(|spec| parse_topology_spec(&spec))
+}
+
+fn walk<F: FnMut(&Group)>(groups: &[Group], f: &mut F) {
+    for group in groups {
+        f(group);
+        walk(&group.children, f);
+    }
+}
+
+// The packages share no cache with each other, so a root
+// group without a shared cache spans several of them, and its
+// children are the packages. Otherwise there is just one.
+fn find_packages(groups: &[Group]) -> Vec<(usize, Vec<CoreId>)> {
+    let packages = match *groups {
+        [ref root] if root.cache_level == 0 && !root.children.is_empty() => &root.children[..],
+        _ => groups,
+    };
+
+    packages.iter().enumerate().map(|(id, group)| (id, group.cpus.clone())).collect()
+}
+
+// Parses the groups of the topology. Each `<group>` lists its
+// cores in `<cpu>`, followed by `<flags>`, which mark the
+// threads of one physical core with `THREAD` or `SMT`, and by
+// the groups it is made of in `<children>`.
+fn parse_topology_spec(spec: &str) -> Vec<Group> {
+    // The groups being parsed, below a root that collects the
+    // outermost ones.
+    let mut stack = vec![Group::default()];
+    let mut rest = spec;
+
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        if tag.starts_with("group") {
+            stack.push(Group {
+                cache_level: attribute(tag, "cache-level").unwrap_or(0),
+                ..Group::default()
+            });
+        } else if tag == "/group" && stack.len() > 1 {
+            let group = stack.pop().unwrap();
+            stack.last_mut().unwrap().children.push(group);
+        } else if tag.starts_with("cpu ") {
+            let list = &rest[..rest.find("</cpu>").unwrap_or(rest.len())];
+
+            stack.last_mut().unwrap().cpus = list
+                .split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .map(|id| CoreId { id })
+                .collect();
+        } else if tag == "flag name=\"SMT\"" || tag == "flag name=\"THREAD\"" {
+            stack.last_mut().unwrap().smt = true;
+        }
+    }
+
+    stack.swap_remove(0).children
+}
+
+fn attribute(tag: &str, name: &str) -> Option<usize> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = start + tag[start..].find('"')?;
+
+    tag[start..end].parse().ok()
+}
+
+// Reads an integer sysctl. `name` must be NUL-terminated.
+fn sysctl_int(name: &[u8]) -> Option<c_int> {
+    let mut value: c_int = 0;
+    let mut size = mem::size_of::<c_int>() as size_t;
+
+    let res = unsafe {
+        sysctlbyname(
+            name.as_ptr() as *const _,
+            &mut value as *mut c_int as *mut c_void,
+            &mut size,
+            ptr::null(),
+            0,
+        )
+    };
+
+    if res == 0 {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+// Reads a string sysctl. `name` must be NUL-terminated.
+fn sysctl_string(name: &[u8]) -> Option<String> {
+    let buf = sysctl_bytes(name)?;
+
+    String::from_utf8(buf).ok().map(|s| s.trim_end_matches('\0').to_owned())
+}
+
+// Reads a sysctl of any size. `name` must be NUL-terminated.
+fn sysctl_bytes(name: &[u8]) -> Option<Vec<u8>> {
+    let mut size: size_t = 0;
+
+    let res = unsafe {
+        sysctlbyname(name.as_ptr() as *const _, ptr::null_mut(), &mut size, ptr::null(), 0)
+    };
+
+    if res != 0 {
+        return None;
+    }
+
+    let mut buf = vec![0u8; size];
+
+    let res = unsafe {
+        sysctlbyname(
+            name.as_ptr() as *const _,
+            buf.as_mut_ptr() as *mut c_void,
+            &mut size,
+            ptr::null(),
+            0,
+        )
+    };
+
+    if res != 0 {
+        return None;
+    }
+
+    buf.truncate(size);
+    Some(buf)
+}
+
+fn set_affinity_mask(set: &cpuset_t) -> Result<(), Error> {
+    // Set the current thread's core affinity.
+    let res = unsafe {
+        // FreeBSD's sched_setaffinity currently operates on process id,
+        // therefore using cpuset_setaffinity instead.
+        cpuset_setaffinity(
+            CPU_LEVEL_WHICH,
+            CPU_WHICH_TID,
+            -1, // -1 == current thread
+            mem::size_of::<cpuset_t>(),
+            set,
+        )
+    };
+
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+fn get_affinity_mask() -> Result<cpuset_t, Error> {
+    let mut set = new_cpu_set();
+
+    // Try to get current core affinity mask.
+    let result = unsafe {
+        // FreeBSD's sched_getaffinity currently operates on process id,
+        // therefore using cpuset_getaffinity instead.
+        cpuset_getaffinity(
+            CPU_LEVEL_WHICH,
+            CPU_WHICH_TID,
+            -1, // -1 == current thread
+            mem::size_of::<cpuset_t>(),
+            &mut set,
+        )
+    };
+
+    if result == 0 {
+        Ok(set)
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+fn new_cpu_set() -> cpuset_t {
+    unsafe { mem::zeroed::<cpuset_t>() }
+}
+
+// Turn `core_ids` into a `libc::cpuset_t` with
+// every listed core active.
+fn to_cpu_set(core_ids: &[CoreId]) -> Result<cpuset_t, Error> {
+    let mut set = new_cpu_set();
+
+    for &core_id in core_ids {
+        if core_id.id >= CPU_SETSIZE as usize {
+            return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+        }
+
+        unsafe { CPU_SET(core_id.id, &mut set) };
+    }
+
+    Ok(set)
+}
+
+// Collect the cores active in `set`, in ascending order.
+fn to_core_id_iter(set: &cpuset_t) -> CoreIds {
+    let mut core_ids = CoreIds::new();
+
+    for id in (0..CPU_SETSIZE as usize).filter(|&i| unsafe { CPU_ISSET(i, set) }) {
+        core_ids.insert(id);
+    }
+
+    core_ids
+}
+
+fn to_core_ids(set: &cpuset_t) -> Vec<CoreId> {
+    (0..CPU_SETSIZE as usize)
+        .filter(|&i| unsafe { CPU_ISSET(i, set) })
+        .map(|id| CoreId { id })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use num_cpus;
+
+    use super::*;
+    use super::super::{get_core_ids, testing};
+
+    #[test]
+    fn test_freebsd_parse_topology_spec() {
+        let spec = r#"<groups>
+ <group level="1" cache-level="3">
+  <cpu count="4" mask="f,0,0,0">0, 1, 2, 3</cpu>
+  <children>
+   <group level="2" cache-level="2">
+    <cpu count="2" mask="3,0,0,0">0, 1</cpu>
+    <flags><flag name="THREAD">THREAD group</flag><flag name="SMT">SMT group</flag></flags>
+   </group>
+   <group level="2" cache-level="2">
+    <cpu count="2" mask="c,0,0,0">2, 3</cpu>
+    <flags><flag name="THREAD">THREAD group</flag><flag name="SMT">SMT group</flag></flags>
+   </group>
+  </children>
+ </group>
+</groups>"#;
+
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        let groups = parse_topology_spec(spec);
+        let mut siblings = Vec::new();
+        walk(&groups, &mut |group| {
+            if group.smt {
+                siblings.push(group.cpus.clone())
+            }
+        });
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].cache_level, 3);
+        assert_eq!(groups[0].cpus, ids(&[0, 1, 2, 3]));
+        assert_eq!(siblings, vec![ids(&[0, 1]), ids(&[2, 3])]);
+        assert_eq!(find_packages(&groups), vec![(0, ids(&[0, 1, 2, 3]))]);
+
+        assert!(parse_topology_spec("<groups></groups>").is_empty());
+    }
+
+    #[test]
+    fn test_freebsd_parse_cp_times() {
+        let ticks = [10, 1, 20, 2, 300, 40, 0, 50, 5, 600, 7];
+        let times = parse_cp_times(&ticks);
+
+        // The ticks past the last whole core are left out.
+        assert_eq!(times, vec![
+            (CoreId { id: 0 }, CpuTimes { user: 11, system: 22, idle: 300 }),
+            (CoreId { id: 1 }, CpuTimes { user: 40, system: 55, idle: 600 }),
+        ]);
+    }
+
+    #[test]
+    fn test_freebsd_parse_freq_levels() {
+        assert_eq!(parse_freq_levels("3600/95000 3400/85000 800/15000"), Some(3_600_000));
+        assert_eq!(parse_freq_levels(""), None);
+    }
+
+    #[test]
+    fn test_freebsd_find_packages() {
+        // Two packages, which share no cache.
+        let spec = r#"<groups>
+ <group level="1" cache-level="0">
+  <cpu count="4" mask="f,0,0,0">0, 1, 2, 3</cpu>
+  <children>
+   <group level="2" cache-level="3">
+    <cpu count="2" mask="3,0,0,0">0, 1</cpu>
+   </group>
+   <group level="2" cache-level="3">
+    <cpu count="2" mask="c,0,0,0">2, 3</cpu>
+   </group>
+  </children>
+ </group>
+</groups>"#;
+
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        assert_eq!(find_packages(&parse_topology_spec(spec)), vec![(0, ids(&[0, 1])), (1, ids(&[2, 3]))]);
+    }
+
+    #[test]
+    fn test_freebsd_get_affinity_mask() {
+        match get_affinity_mask() {
+            Ok(_) => {}
+            Err(_) => {
+                panic!();
+            }
+        }
+    }
+
+    #[test]
+    fn test_freebsd_core_ids() {
+        match core_ids() {
+            Ok(core_ids) => {
+                assert_eq!(core_ids.len(), num_cpus::get());
+            }
+            Err(_) => {
+                panic!();
+            }
+        }
+    }
+
+    #[test]
+    fn test_freebsd_get_all_core_ids() {
+        match get_all_core_ids() {
+            Some(set) => {
+                assert!(set.len() >= num_cpus::get());
+            }
+            None => {
+                panic!();
+            }
+        }
+    }
+
+    #[test]
+    fn test_freebsd_get_all_core_ids_when_pinned() {
+        testing::check_get_all_core_ids_when_pinned();
+    }
+
+    #[test]
+    fn test_freebsd_set_for_current_set() {
+        testing::check_set_for_current_set();
+    }
+
+    #[test]
+    fn test_freebsd_set_for_thread() {
+        testing::check_set_for_thread();
+    }
+
+    #[test]
+    fn test_freebsd_set_for_process() {
+        use std::process::Command;
+
+        let id = *get_core_ids().unwrap().last().unwrap();
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+
+        let res = set_for_process(child.id(), &[id]);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_freebsd_clear_for_current() {
+        testing::check_clear_for_current();
+    }
+
+    #[test]
+    fn test_freebsd_set_for_current() {
+        testing::check_set_for_current();
+    }
+}
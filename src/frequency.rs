@@ -0,0 +1,285 @@
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{CoreId, allowed_for_process, core_rankings_helper, get_all_core_ids_helper, max_frequency_khz_helper};
+use super::{current_frequency_khz_helper, is_enabled, set_for_current_checked};
+
+/// This function returns the highest frequency of `core_id` in
+/// kHz, including any turbo boost, or `None` for a core that is
+/// not online or where the frequency is not known.
+///
+/// Linux reads `cpufreq/cpuinfo_max_freq` in sysfs, Windows
+/// asks `CallNtPowerInformation` and FreeBSD reads
+/// `dev.cpu.N.freq_levels`. macOS reads `hw.cpufrequency_max`,
+/// which Intel Macs have for all cores alike and Apple Silicon
+/// does not have at all.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn max_frequency_khz(core_id: CoreId) -> Option<u64> {
+    if !get_all_core_ids_helper()?.contains(&core_id) {
+        return None;
+    }
+
+    max_khz(core_id)
+}
+
+/// This function returns the online cores by their highest
+/// frequency, from the fastest, such as for picking a core for
+/// work bound by a single thread.
+///
+/// Cores with the same frequency stay in ID order. Cores whose
+/// frequency is not known are left out, and where no core has
+/// one, this returns `None`.
+pub fn cores_by_max_frequency() -> Option<Vec<CoreId>> {
+    let frequencies: Vec<_> = get_all_core_ids_helper()?.into_iter()
+        .filter_map(|core_id| max_khz(core_id).map(|khz| (core_id, khz)))
+        .collect();
+
+    if frequencies.is_empty() { None } else { Some(rank(frequencies)) }
+}
+
+/// This function returns the online cores in the order the
+/// platform prefers them for a single busy thread, from the
+/// favored core that boosts highest, such as for latency
+/// critical work.
+///
+/// Intel Turbo Boost Max 3.0 and AMD Preferred Cores mark
+/// these cores. Linux reads the rankings of `amd_pstate` or of
+/// ACPI CPPC, or the frequencies of `intel_pstate`, and Windows
+/// the scheduling and efficiency classes of
+/// `GetSystemCpuSetInformation`. Cores of the same rank stay in
+/// ID order. Where there is no ranking, or every core has the
+/// same rank, this returns `None` rather than plain ID order.
+pub fn favored_cores() -> Option<Vec<CoreId>> {
+    let online = get_all_core_ids_helper()?;
+    let rankings: Vec<_> = core_rankings_helper()?.into_iter()
+        .filter(|(core_id, _)| online.contains(core_id))
+        .collect();
+
+    let first = rankings.first()?.1;
+
+    if rankings.iter().all(|&(_, rank)| rank == first) {
+        return None;
+    }
+
+    Some(rank(rankings))
+}
+
+/// This function returns the frequency `core_id` runs at right
+/// now in kHz, or `None` for a core that is not online or where
+/// the frequency is not known.
+///
+/// Linux reads `cpufreq/scaling_cur_freq` in sysfs and Windows
+/// asks `CallNtPowerInformation`. Many governors let idle cores
+/// drop to their lowest frequency, so an idle core may look
+/// throttled when it is only resting; `sample_busy` measures
+/// the cores under load instead.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn current_frequency_khz(core_id: CoreId) -> Option<u64> {
+    if !get_all_core_ids_helper()?.contains(&core_id) {
+        return None;
+    }
+
+    current_khz(core_id)
+}
+
+/// This function returns up to `n` online cores that run
+/// closest to their highest frequency right now, from the
+/// least throttled, such as for keeping a long running encoder
+/// off cores that have heated up.
+///
+/// Cores are ranked by their `current_frequency_khz` as a share
+/// of their `max_frequency_khz`, and cores with the same share
+/// stay in ID order. Cores without both frequencies are left
+/// out, and where no core has them, this returns `None`. The
+/// caveat of `current_frequency_khz` about idle cores applies;
+/// `least_throttled_cores_under_load` avoids it.
+///
+/// # Arguments
+///
+/// * n - the most cores to return
+pub fn least_throttled_cores(n: usize) -> Option<Vec<CoreId>> {
+    let frequencies: Vec<_> = get_all_core_ids_helper()?.into_iter()
+        .filter_map(|core_id| current_khz(core_id).map(|khz| (core_id, khz)))
+        .collect();
+
+    least_throttled(&frequencies, n)
+}
+
+/// This function is `least_throttled_cores`, but measures each
+/// core with `sample_busy` first, so that a core is not mistaken
+/// for throttled because it was idle.
+///
+/// This takes about `duration` for every core the process may
+/// run on.
+///
+/// # Arguments
+///
+/// * n - the most cores to return
+/// * duration - how long to load each core before measuring
+pub fn least_throttled_cores_under_load(n: usize, duration: Duration) -> Option<Vec<CoreId>> {
+    least_throttled(&sample_busy(duration)?, n)
+}
+
+/// This function loads each core the process may run on for
+/// `duration` and returns the frequency in kHz it runs at by
+/// then, in ID order.
+///
+/// The cores are loaded one after the other, by a thread pinned
+/// to the core spinning for `duration`, so that a governor that
+/// lowers the frequency of idle cores has raised it by the time
+/// it is read. Cores that can not be pinned or whose frequency
+/// is not known are left out, and where no core is left, this
+/// returns `None`. It also returns `None` while pinning is
+/// turned off, since a core can not be loaded on its own then.
+///
+/// # Arguments
+///
+/// * duration - how long to load each core before measuring
+pub fn sample_busy(duration: Duration) -> Option<Vec<(CoreId, u64)>> {
+    if !is_enabled() {
+        return None;
+    }
+
+    let online = get_all_core_ids_helper()?;
+    let allowed = allowed_for_process().ok()?;
+
+    let frequencies: Vec<_> = allowed.iter()
+        .filter(|core_id| online.contains(core_id))
+        .filter_map(|core_id| {
+            thread::spawn(move || {
+                set_for_current_checked(core_id).ok()?;
+                spin(duration);
+                current_khz(core_id).map(|khz| (core_id, khz))
+            }).join().ok()?
+        })
+        .collect();
+
+    if frequencies.is_empty() { None } else { Some(frequencies) }
+}
+
+// The highest frequency the platform reports for `core_id`,
+// which is to be online, leaving out the 0 of an unknown one.
+pub(crate) fn max_khz(core_id: CoreId) -> Option<u64> {
+    max_frequency_khz_helper(core_id).filter(|&khz| khz > 0)
+}
+
+// The current frequency, like `max_khz`.
+fn current_khz(core_id: CoreId) -> Option<u64> {
+    current_frequency_khz_helper(core_id).filter(|&khz| khz > 0)
+}
+
+// Keeps the current thread busy for `duration`.
+fn spin(duration: Duration) {
+    let start = Instant::now();
+
+    while start.elapsed() < duration {
+        hint::spin_loop();
+    }
+}
+
+// Takes up to `n` of the cores of `frequencies`, which are all
+// online, by how close their current frequency is to their
+// highest.
+fn least_throttled(frequencies: &[(CoreId, u64)], n: usize) -> Option<Vec<CoreId>> {
+    let ratios: Vec<_> = frequencies.iter()
+        .filter_map(|&(core_id, khz)| max_khz(core_id).map(|max| (core_id, khz, max)))
+        .collect();
+
+    if ratios.is_empty() {
+        return None;
+    }
+
+    let mut core_ids = rank_by_throttling(&ratios);
+    core_ids.truncate(n);
+
+    Some(core_ids)
+}
+
+// Orders cores with their current and highest frequencies by
+// the share of the highest they run at, from the largest. A
+// core may run above its highest when boosting.
+fn rank_by_throttling(frequencies: &[(CoreId, u64, u64)]) -> Vec<CoreId> {
+    rank(frequencies.iter().map(|&(core_id, current, max)| (core_id, current * 1_000_000 / max.max(1))).collect())
+}
+
+// Orders the cores by a number that is higher on better cores,
+// keeping cores with the same number in ID order.
+pub(crate) fn rank(mut values: Vec<(CoreId, u64)>) -> Vec<CoreId> {
+    values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    values.into_iter().map(|(core_id, _)| core_id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_all_core_ids;
+    use test_util::ids;
+
+    #[test]
+    fn test_rank() {
+        let values = ids(&[3, 0, 2, 1]).into_iter().zip(vec![4000, 3000, 4000, 5000]).collect();
+
+        assert_eq!(rank(values), ids(&[1, 2, 3, 0]));
+        assert!(rank(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_rank_by_throttling() {
+        // A laptop whose first cores have heated up. Core 3 runs
+        // slower than core 0 but nearer to its own highest.
+        let frequencies = [
+            (CoreId { id: 0 }, 2_400_000, 4_800_000),
+            (CoreId { id: 1 }, 1_200_000, 4_800_000),
+            (CoreId { id: 2 }, 4_800_000, 4_800_000),
+            (CoreId { id: 3 }, 2_100_000, 3_000_000),
+            (CoreId { id: 4 }, 3_000_000, 3_000_000),
+        ];
+
+        assert_eq!(rank_by_throttling(&frequencies), ids(&[2, 4, 3, 0, 1]));
+        assert!(rank_by_throttling(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_least_throttled_cores() {
+        let cores = match least_throttled_cores(2) {
+            Some(cores) => cores,
+            None => return,
+        };
+
+        assert!(!cores.is_empty() && cores.len() <= 2);
+        assert!(cores.iter().all(|core_id| get_all_core_ids().unwrap().contains(core_id)));
+        assert_eq!(current_frequency_khz(CoreId { id: usize::MAX }), None);
+    }
+
+    #[test]
+    fn test_cores_by_max_frequency() {
+        let cores = match cores_by_max_frequency() {
+            Some(cores) => cores,
+            None => return,
+        };
+
+        let frequencies: Vec<_> = cores.iter().map(|&core_id| max_frequency_khz(core_id).unwrap()).collect();
+        assert!(frequencies.windows(2).all(|pair| pair[0] >= pair[1]));
+        assert!(cores.iter().all(|core_id| get_all_core_ids().unwrap().contains(core_id)));
+
+        assert_eq!(max_frequency_khz(CoreId { id: usize::MAX }), None);
+    }
+
+    #[test]
+    fn test_favored_cores() {
+        if let Some(mut cores) = favored_cores() {
+            cores.sort();
+            cores.dedup();
+
+            assert!(cores.len() > 1);
+            assert!(cores.iter().all(|core_id| get_all_core_ids().unwrap().contains(core_id)));
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! `hwloc` support, enabled by the `hwloc` feature.
+//!
+//! hwloc numbers the processors in a cpuset by their OS index,
+//! which is the core ID on Linux, Android, FreeBSD and macOS.
+//! Windows differs: hwloc lays the masks of all processor
+//! groups end to end, leaving a gap after every group with
+//! fewer processors than mask bits, while core IDs count the
+//! processors of all groups without gaps. The conversions
+//! translate between the two.
+
+use std::convert::TryFrom;
+use std::os::raw::c_int;
+
+use hwloc2::{self, TopologyObject};
+
+use super::{CpuSet, Error, ErrorKind, max_core_id, record, try_set_for_current_set};
+#[cfg(not(all(not(feature = "mock"), target_os = "windows")))]
+use super::CoreId;
+#[cfg(all(not(feature = "mock"), target_os = "windows"))]
+use windows::{from_hwloc_index, to_hwloc_index};
+
+impl CpuSet {
+    /// Converts an hwloc cpuset, such as the `cpuset` of a
+    /// topology object, to a `CpuSet`.
+    ///
+    /// Bits that stand for no core are dropped. An infinite
+    /// set, such as `hwloc2::CpuSet::full()`, is cut off after
+    /// the highest core online, or left empty if the cores
+    /// can not be listed.
+    ///
+    /// # Arguments
+    ///
+    /// * set - the hwloc cpuset to convert
+    pub fn from_hwloc(set: &hwloc2::CpuSet) -> CpuSet {
+        // hwloc reports no last bit for infinite sets.
+        let limit = if set.last() < 0 && !set.is_empty() {
+            max_core_id().and_then(|core_id| to_hwloc_index(core_id).ok())
+        }
+        else {
+            Some(usize::MAX)
+        };
+
+        let indexes = set.clone().into_iter().map(|index| index as usize);
+
+        indexes.take_while(|&index| limit.is_some_and(|limit| index <= limit))
+            .filter_map(from_hwloc_index)
+            .collect()
+    }
+
+    /// Converts the set to an hwloc cpuset.
+    ///
+    /// Fails with `ErrorKind::InvalidCore` for a core hwloc can
+    /// not represent, such as an offline core on Windows.
+    pub fn to_hwloc(&self) -> Result<hwloc2::CpuSet, Error> {
+        let mut set = hwloc2::CpuSet::new();
+
+        for core_id in self {
+            // hwloc takes the indexes as `unsigned`, but returns
+            // them as `int`.
+            let index = to_hwloc_index(core_id)?;
+            let index = c_int::try_from(index)
+                .map_err(|_| Error::new(ErrorKind::InvalidCore).with_core_id(core_id))?;
+
+            set.set(index as u32);
+        }
+
+        Ok(set)
+    }
+}
+
+/// This function tries to confine the current thread to the
+/// cores of an hwloc topology object, such as a package or an
+/// L3 cache.
+///
+/// This is `set_for_current_core_set` with the `cpuset` of the
+/// object, converted by `CpuSet::from_hwloc`. Fails with
+/// `ErrorKind::EmptySet` if the object has no cores, as an I/O
+/// device does.
+///
+/// # Arguments
+///
+/// * object - the object whose cores to allow
+pub fn set_for_current_to_hwloc(object: &TopologyObject) -> Result<(), Error> {
+    let set = object.cpuset().map(|set| CpuSet::from_hwloc(&set)).unwrap_or_default();
+
+    record(try_set_for_current_set(&set.to_vec()))
+}
+
+#[cfg(not(all(not(feature = "mock"), target_os = "windows")))]
+fn to_hwloc_index(core_id: CoreId) -> Result<usize, Error> {
+    Ok(core_id.id)
+}
+
+#[cfg(not(all(not(feature = "mock"), target_os = "windows")))]
+fn from_hwloc_index(index: usize) -> Option<CoreId> {
+    Some(CoreId { id: index })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{CoreId, get_all_core_set};
+
+    #[test]
+    fn test_hwloc_round_trip() {
+        // Every online core maps to one bit and back.
+        let online = get_all_core_set().unwrap();
+        let set = online.to_hwloc().unwrap();
+
+        assert_eq!(set.weight() as usize, online.len());
+        assert_eq!(CpuSet::from_hwloc(&set), online);
+
+        let empty = CpuSet::new().to_hwloc().unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(CpuSet::from_hwloc(&empty), CpuSet::new());
+    }
+
+    // Windows renumbers, which the round trip covers.
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn test_hwloc_bit_numbering() {
+        let indexes = [0, 5, 63, 64, 129];
+        let set: CpuSet = indexes.iter().map(|&id| CoreId { id }).collect();
+
+        let hwloc: Vec<u32> = set.to_hwloc().unwrap().into_iter().collect();
+        assert_eq!(hwloc, indexes.iter().map(|&id| id as u32).collect::<Vec<_>>());
+
+        let hwloc: hwloc2::CpuSet = indexes.iter().map(|&id| id as u32).collect();
+        assert_eq!(CpuSet::from_hwloc(&hwloc), set);
+    }
+
+    #[test]
+    fn test_hwloc_full() {
+        let max = max_core_id().unwrap();
+        let full = CpuSet::from_hwloc(&hwloc2::CpuSet::full());
+
+        let online = get_all_core_set().unwrap();
+        assert_eq!(&full & &online, online);
+        assert_eq!(full.iter().last(), Some(max));
+    }
+
+    #[test]
+    fn test_to_hwloc_invalid_core() {
+        let core_id = CoreId { id: c_int::MAX as usize + 1 };
+        let err = CpuSet::from(vec![core_id]).to_hwloc().unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.core_id(), Some(core_id));
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+use std::thread::{self, JoinHandle};
+
+use super::{CoreId, Error};
+use registry;
+#[cfg(feature = "tracing")]
+use super::{format_cpulist, get_affinity_for_current_helper, get_for_thread_helper};
+#[cfg(feature = "metrics")]
+use metrics;
+
+// The hooks around every pin. Without the `tracing` and
+// `metrics` features they only run `set` and check whether
+// the registry is on.
+
+// Runs `set`, which pins the current thread to `core_id`, and
+// reports the outcome.
+#[inline]
+pub(crate) fn pin_current<F>(core_id: CoreId, set: F) -> Result<(), Error>
+    where F: FnOnce() -> Result<(), Error>
+{
+    #[cfg(feature = "tracing")]
+    let previous = previous_mask(get_affinity_for_current_helper);
+
+    let result = set();
+
+    #[cfg(feature = "tracing")]
+    emit(thread::current().id(), core_id, previous, &result);
+    #[cfg(feature = "metrics")]
+    metrics::count(&result, true);
+
+    registry::note(&thread::current(), &[core_id], &result);
+    result
+}
+
+// Runs `set`, which pins the thread behind `handle` to
+// `core_id`, and reports the outcome.
+#[inline]
+pub(crate) fn pin_thread<T, F>(handle: &JoinHandle<T>, core_id: CoreId, set: F) -> Result<(), Error>
+    where F: FnOnce() -> Result<(), Error>
+{
+    #[cfg(feature = "tracing")]
+    let previous = previous_mask(|| get_for_thread_helper(handle));
+
+    let result = set();
+
+    #[cfg(feature = "tracing")]
+    emit(handle.thread().id(), core_id, previous, &result);
+    #[cfg(feature = "metrics")]
+    metrics::count(&result, false);
+
+    registry::note(handle.thread(), &[core_id], &result);
+    result
+}
+
+// Runs `set`, which confines the current thread to `core_ids`,
+// and records it in the registry.
+#[inline]
+pub(crate) fn pin_current_set<F>(core_ids: &[CoreId], set: F) -> Result<(), Error>
+    where F: FnOnce() -> Result<(), Error>
+{
+    let result = set();
+
+    registry::note(&thread::current(), core_ids, &result);
+    result
+}
+
+// Reports that the pin of `what` failed with `err` and the
+// thread runs unpinned, as a warning. Without the `tracing`
+// feature nothing is reported here; `__pin_function` prints to
+// stderr instead.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn unpinned(what: fmt::Arguments, err: &Error) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        result = %err,
+        os_error = err.raw_os_error(),
+        "failed to pin {}, running unpinned",
+        what
+    );
+}
+
+// The mask before the call, as a cpulist, or `None` where it
+// can not be read. Nobody listening means nothing to read.
+#[cfg(feature = "tracing")]
+fn previous_mask<F>(get: F) -> Option<String>
+    where F: FnOnce() -> Result<Vec<CoreId>, Error>
+{
+    if tracing::enabled!(tracing::Level::WARN) {
+        get().ok().map(|core_ids| format_cpulist(&core_ids))
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn emit(thread: thread::ThreadId, core_id: CoreId, previous: Option<String>, result: &Result<(), Error>) {
+    match *result {
+        Ok(()) => {
+            tracing::debug!(
+                thread = ?thread,
+                core = core_id.id,
+                previous = previous.as_deref(),
+                result = "ok",
+                "pinned thread"
+            );
+        },
+        Err(ref err) => {
+            tracing::warn!(
+                thread = ?thread,
+                core = core_id.id,
+                previous = previous.as_deref(),
+                result = %err,
+                os_error = err.raw_os_error(),
+                "failed to pin thread"
+            );
+        },
+    }
+}
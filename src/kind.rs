@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+
+use super::{CoreId, CpuSet, Error, ErrorKind, allowed_for_process, get_all_core_ids_helper, record};
+use frequency::rank;
+use super::{capacity_helper, core_kinds_helper, try_set_for_current_set};
+
+/// The kind of a core on a hybrid CPU, such as the P-cores and
+/// E-cores of Intel Alder Lake or Apple Silicon.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CoreKind {
+    /// A core built for speed. On a CPU whose cores are all
+    /// alike, every core is one.
+    Performance,
+    /// A core built for low power, which is slower.
+    Efficiency,
+    /// A core the platform gives no kind for.
+    Unknown,
+}
+
+/// Where the kinds of the cores come from, as returned by
+/// `core_kind_source`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum KindSource {
+    /// The operating system tells the kind of every core.
+    Reported,
+    /// The kinds are guessed by grouping the cores by their
+    /// highest frequency, which a CPU that boosts some of its
+    /// cores higher than others can fool.
+    Heuristic,
+}
+
+/// This function returns the kind of `core_id`, or `None` for a
+/// core that is not online or where the kinds are not known.
+///
+/// See `cores_by_kind` for where the kinds come from.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn kind_of(core_id: CoreId) -> Option<CoreKind> {
+    let (kinds, _) = core_kinds()?;
+
+    kinds.into_iter().find(|&(other, _)| other == core_id).map(|(_, kind)| kind)
+}
+
+/// This function returns the online cores of every kind, in
+/// ascending order. Kinds without cores are left out.
+///
+/// The kinds are reported by Intel hybrid CPUs on Linux in
+/// `/sys/devices/cpu_core` and `/sys/devices/cpu_atom`, on ARM
+/// by the `cpu_capacity` of `capacity_of`, by Windows in the
+/// `EfficiencyClass` of `GetSystemCpuSetInformation` and by
+/// macOS in `hw.perflevel1.logicalcpu`. Linux otherwise guesses
+/// them from `cpuinfo_max_freq`, which `core_kind_source` tells
+/// apart. Other platforms return `None`.
+pub fn cores_by_kind() -> Option<BTreeMap<CoreKind, Vec<CoreId>>> {
+    let (kinds, _) = core_kinds()?;
+    let mut by_kind: BTreeMap<CoreKind, Vec<CoreId>> = BTreeMap::new();
+
+    for (core_id, kind) in kinds {
+        by_kind.entry(kind).or_default().push(core_id);
+    }
+
+    for core_ids in by_kind.values_mut() {
+        core_ids.sort();
+    }
+
+    Some(by_kind)
+}
+
+/// This function returns whether the kinds of `kind_of` and
+/// `cores_by_kind` are reported by the operating system or
+/// guessed, or `None` where they are not known.
+pub fn core_kind_source() -> Option<KindSource> {
+    core_kinds().map(|(_, source)| source)
+}
+
+/// This function confines the current thread to the cores of
+/// `kind`, such as keeping background work on the efficiency
+/// cores, and returns the cores it may now run on.
+///
+/// Only the cores in the affinity mask of the process are
+/// used, as with `set_for_current_excluding`. On a machine
+/// without efficiency cores, or where the kinds are not known,
+/// `CoreKind::Performance` means every core, and
+/// `CoreKind::Efficiency` fails with `ErrorKind::Unsupported`
+/// rather than leaving the thread nowhere to run. Where the
+/// kinds are only guessed from the frequencies, as shown by
+/// `KindSource::Heuristic`, the guessed efficiency cores may
+/// just be performance cores that boost less, so
+/// `CoreKind::Efficiency` fails with `ErrorKind::Unsupported`
+/// there too. It fails with `ErrorKind::EmptySet` for a kind
+/// without cores, and with `ErrorKind::NotAllowed` if none of
+/// the cores of `kind` is allowed.
+///
+/// # Arguments
+///
+/// * kind - the kind of core to run on
+pub fn set_for_current_kind(kind: CoreKind) -> Result<Vec<CoreId>, Error> {
+    let allowed = record(allowed_for_process())?;
+
+    if kind == CoreKind::Efficiency && core_kind_source() == Some(KindSource::Heuristic) {
+        return record(Err(Error::new(ErrorKind::Unsupported)));
+    }
+
+    let by_kind = cores_by_kind().filter(|by_kind| by_kind.contains_key(&CoreKind::Efficiency));
+    let cores = match (by_kind, kind) {
+        (Some(mut by_kind), _) => by_kind.remove(&kind).ok_or_else(|| Error::new(ErrorKind::EmptySet)),
+        (None, CoreKind::Performance) => Ok(allowed.to_vec()),
+        (None, CoreKind::Efficiency) => Err(Error::new(ErrorKind::Unsupported)),
+        (None, CoreKind::Unknown) => Err(Error::new(ErrorKind::EmptySet)),
+    };
+
+    let core_ids = match (CpuSet::from(record(cores)?) & allowed).to_vec() {
+        ref core_ids if core_ids.is_empty() => return record(Err(Error::new(ErrorKind::NotAllowed))),
+        core_ids => core_ids,
+    };
+
+    record(try_set_for_current_set(&core_ids))?;
+
+    Ok(core_ids)
+}
+
+/// This function is `set_for_current_kind` with
+/// `CoreKind::Performance`, such as for a render loop.
+pub fn pin_to_performance_cores() -> Result<Vec<CoreId>, Error> {
+    set_for_current_kind(CoreKind::Performance)
+}
+
+/// This function is `set_for_current_kind` with
+/// `CoreKind::Efficiency`, such as for background compression.
+pub fn pin_to_efficiency_cores() -> Result<Vec<CoreId>, Error> {
+    set_for_current_kind(CoreKind::Efficiency)
+}
+
+/// This function returns the capacity of `core_id`, which ARM
+/// kernels on Linux publish in `cpu_capacity` to tell big,
+/// mid and little cores apart: the fastest core has 1024, and
+/// the others less in proportion to their speed.
+///
+/// Returns `None` for a core that is not online, and on other
+/// platforms and devices that lack the file.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn capacity_of(core_id: CoreId) -> Option<u32> {
+    if !get_all_core_ids_helper()?.contains(&core_id) {
+        return None;
+    }
+
+    capacity_helper(core_id)
+}
+
+/// This function returns the online cores by their capacity,
+/// from the fastest, keeping cores of the same capacity in ID
+/// order. Cores without a capacity are left out, and where no
+/// core has one, this returns `None`.
+pub fn cores_by_capacity() -> Option<Vec<CoreId>> {
+    let capacities: Vec<_> = get_all_core_ids_helper()?.into_iter()
+        .filter_map(|core_id| capacity_of(core_id).map(|capacity| (core_id, capacity as u64)))
+        .collect();
+
+    if capacities.is_empty() { None } else { Some(rank(capacities)) }
+}
+
+// The kind of every online core. Cores the platform leaves out
+// are of an unknown kind.
+fn core_kinds() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    let (kinds, source) = core_kinds_helper()?;
+    let online = get_all_core_ids_helper()?;
+
+    let kinds = online.into_iter().map(|core_id| {
+        let kind = kinds.iter().find(|&&(other, _)| other == core_id).map(|&(_, kind)| kind);
+        (core_id, kind.unwrap_or(CoreKind::Unknown))
+    }).collect();
+
+    Some((kinds, source))
+}
+
+// Tells the kinds of cores apart by a number that is higher
+// on faster cores, such as a capacity or an efficiency class.
+// Cores in the upper half of the range are performance cores,
+// so a phone with prime, big and little cores has only its
+// little cores as efficiency cores. If every core has the same
+// number, they are all performance cores.
+#[cfg_attr(any(feature = "mock", not(any(target_os = "android", target_os = "linux", target_os = "windows"))),
+           allow(dead_code))]
+pub(crate) fn classify(values: &[(CoreId, u64)]) -> Vec<(CoreId, CoreKind)> {
+    let max = values.iter().map(|&(_, value)| value).max().unwrap_or(0);
+    let min = values.iter().map(|&(_, value)| value).min().unwrap_or(0);
+
+    values.iter().map(|&(core_id, value)| {
+        let kind = if value * 2 >= max + min { CoreKind::Performance } else { CoreKind::Efficiency };
+        (core_id, kind)
+    }).collect()
+}
+
+// Like `classify`, but for the maximum frequencies, which only
+// hint at the kinds. Favored cores, such as those of Turbo
+// Boost Max 3.0 or Preferred Cores, boost a few hundred MHz
+// above the others, so a spread below a quarter of the lowest
+// frequency is one kind of core.
+#[cfg_attr(any(feature = "mock", not(any(target_os = "android", target_os = "linux"))), allow(dead_code))]
+pub(crate) fn classify_frequencies(frequencies: &[(CoreId, u64)]) -> Vec<(CoreId, CoreKind)> {
+    let max = frequencies.iter().map(|&(_, khz)| khz).max().unwrap_or(0);
+    let min = frequencies.iter().map(|&(_, khz)| khz).min().unwrap_or(0);
+
+    if max * 4 < min * 5 {
+        return frequencies.iter().map(|&(core_id, _)| (core_id, CoreKind::Performance)).collect();
+    }
+
+    classify(frequencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_all_core_ids;
+    use test_util::ids;
+
+    #[test]
+    fn test_classify() {
+        let values = |values: &[u64]| ids(&[0, 1, 2, 3]).into_iter().zip(values.iter().cloned()).collect::<Vec<_>>();
+        let kinds = |values: &[(CoreId, u64)]| classify(values).into_iter().map(|(_, kind)| kind).collect::<Vec<_>>();
+
+        use self::CoreKind::{Efficiency, Performance};
+
+        // The capacities of a phone with little, big and prime
+        // cores.
+        assert_eq!(kinds(&values(&[325, 325, 870, 1024])), vec![Efficiency, Efficiency, Performance, Performance]);
+        // The efficiency classes of an Alder Lake.
+        assert_eq!(kinds(&values(&[1, 1, 0, 0])), vec![Performance, Performance, Efficiency, Efficiency]);
+        assert_eq!(kinds(&values(&[3600, 3600, 3600, 3600])), vec![Performance; 4]);
+        assert!(classify(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_classify_frequencies() {
+        let values = |values: &[u64]| ids(&[0, 1, 2, 3]).into_iter().zip(values.iter().cloned()).collect::<Vec<_>>();
+        let kinds = |values: &[(CoreId, u64)]| {
+            classify_frequencies(values).into_iter().map(|(_, kind)| kind).collect::<Vec<_>>()
+        };
+
+        use self::CoreKind::{Efficiency, Performance};
+
+        // Two favored cores of a desktop boost 200 MHz higher.
+        assert_eq!(kinds(&values(&[4_900_000, 4_700_000, 4_900_000, 4_700_000])), vec![Performance; 4]);
+        // The P- and E-cores of an Alder Lake.
+        assert_eq!(kinds(&values(&[4_900_000, 4_900_000, 3_800_000, 3_800_000])),
+                   vec![Performance, Performance, Efficiency, Efficiency]);
+        assert!(classify_frequencies(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_set_for_current_kind() {
+        use std::thread;
+
+        use super::super::{clear_for_current, get_affinity_for_current};
+
+        thread::spawn(|| {
+            let core_ids = match set_for_current_kind(CoreKind::Performance) {
+                Err(ref err) if err.kind() == ErrorKind::NotAllowed => return,
+                result => result.unwrap(),
+            };
+
+            assert!(!core_ids.is_empty());
+
+            if let Some(mask) = get_affinity_for_current() {
+                assert_eq!(mask, core_ids);
+            }
+
+            assert!(clear_for_current());
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_cores_by_kind() {
+        let by_kind = match cores_by_kind() {
+            Some(by_kind) => by_kind,
+            None => return,
+        };
+
+        let mut all: Vec<_> = by_kind.values().flatten().cloned().collect();
+        all.sort();
+        assert_eq!(all, get_all_core_ids().unwrap());
+
+        for (&kind, core_ids) in &by_kind {
+            assert!(!core_ids.is_empty());
+            assert!(core_ids.iter().all(|&core_id| kind_of(core_id) == Some(kind)));
+        }
+
+        assert!(core_kind_source().is_some());
+        assert_eq!(kind_of(CoreId { id: usize::MAX }), None);
+    }
+}
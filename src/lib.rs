@@ -36,557 +36,963 @@
 ))]
 extern crate libc;
 
-#[cfg_attr(all(not(test), not(target_os = "macos")), allow(unused_extern_crates))]
+#[cfg(test)]
 extern crate num_cpus;
 
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+#[cfg(feature = "macros")]
+extern crate core_affinity_macros;
+
+#[cfg(feature = "crossbeam")]
+extern crate crossbeam_utils;
+
+#[cfg(feature = "hwloc")]
+extern crate hwloc2;
+
+#[cfg(all(feature = "nix", any(target_os = "android", target_os = "linux")))]
+extern crate nix;
+
+// Renamed so the modules can keep the names.
+#[cfg(feature = "rayon")]
+extern crate rayon as rayon_crate;
+
+#[cfg(feature = "tokio")]
+extern crate tokio as tokio_crate;
+
+#[cfg(not(unix))]
+use std::io;
+#[cfg(not(unix))]
+use std::process::Child;
+use std::convert::TryFrom;
+use std::fmt;
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+
+mod allocator;
+mod backend;
+mod cache;
+mod capabilities;
+mod command;
+mod core_ids;
+mod core_state;
+mod cpulist;
+#[cfg(feature = "crossbeam")]
+mod crossbeam;
+mod cpuset;
+mod current;
+mod diagnose;
+mod distance;
+mod enabled;
+mod error;
+#[cfg(feature = "hwloc")]
+mod hwloc;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod fork;
+mod frequency;
+mod instrument;
+mod kind;
+mod load;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(all(feature = "nix", any(target_os = "android", target_os = "linux")))]
+mod nix_sched;
+mod numa;
+mod order;
+mod package;
+mod parse;
+mod physical;
+mod pin_state;
+mod policy;
+mod pool;
+mod priority;
+mod query;
+#[cfg(feature = "rayon")]
+pub mod rayon;
+mod registry;
+mod report;
+mod reservation;
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd"))]
+pub mod raw;
+mod scoped;
+mod shared_cache;
+mod spawn;
+mod stable;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+mod topology;
+#[cfg(feature = "serde")]
+mod serialize;
+mod watch;
+
+pub use allocator::{CoreAllocator, NumaGranularity, Placement, distribute, distribute_with};
+pub use allocator::{distribute_within_cache_clusters, distribute_within_dies};
+pub use backend::{Backend, DefaultBackend, set_backend};
+pub use cache::{cached_core_ids, refresh_core_ids};
+pub use capabilities::{Capabilities, capabilities};
+pub use command::CommandAffinityExt;
+pub use core_ids::CoreIds;
+pub use core_state::{CoreState, get_core_ids_by_state, is_online};
+pub use cpulist::{format_cpulist, parse_cpulist};
+pub use cpuset::{CpuSet, CpuSetIter};
+#[cfg(feature = "crossbeam")]
+pub use crossbeam::CrossbeamScopeExt;
+pub use current::current_core_fast;
+pub use diagnose::{PinDiagnostic, diagnose};
+pub use distance::distance;
+pub use enabled::{is_enabled, set_enabled};
+pub use error::{Error, ErrorKind, last_error};
+pub use fork::{ForkPolicy, at_fork_reset, set_fork_policy};
+pub use frequency::{cores_by_max_frequency, favored_cores, max_frequency_khz};
+pub use frequency::{current_frequency_khz, least_throttled_cores, least_throttled_cores_under_load, sample_busy};
+#[cfg(feature = "hwloc")]
+pub use hwloc::set_for_current_to_hwloc;
+pub use kind::{CoreKind, KindSource, core_kind_source, cores_by_kind, kind_of, set_for_current_kind};
+pub use kind::{capacity_of, cores_by_capacity, pin_to_efficiency_cores, pin_to_performance_cores};
+pub use load::{CoreLoad, CpuTimes, LoadSampler, PlatformSampler, least_loaded_cores, least_loaded_cores_with};
+pub use load::utilization_snapshot;
+#[cfg(feature = "metrics")]
+pub use metrics::{MetricsSnapshot, metrics_snapshot};
+#[cfg(all(feature = "nix", any(target_os = "android", target_os = "linux")))]
+pub use nix_sched::{from_nix_cpu_set, to_nix_cpu_set};
+pub use numa::{NumaNode, cores_for_numa_node, current_location, current_numa_node, numa_node_of, numa_nodes};
+pub use numa::set_for_current_numa_node;
+pub use order::{Order, get_core_ids_ordered};
+pub use package::{ClusterId, DieId, PackageId, die_of, dies, package_of, packages};
+pub use package::{cluster_of, clusters};
+pub use parse::{ParseError, ParseErrorKind};
+pub use physical::{PhysicalCore, PhysicalCoreId, get_physical_cores, pin_to_physical_core, smt_active};
+pub use physical::{colocate_pair, colocate_pair_on, smt_siblings, spread_across_physical_cores};
+pub use physical::{PairScope, colocate_pair_within};
+pub use pin_state::{PinState, is_pinned_for_current};
+pub use policy::{PinPolicy, set_for_current_first_available, set_for_current_with};
+pub use pool::PinnedPool;
+pub use priority::{Priority, PriorityFallback, set_for_current_with_priority, set_for_current_with_priority_or};
+pub use query::CoreQuery;
+pub use registry::{Assignment, AssignmentTable, dump_assignments, is_registry_enabled, set_registry_enabled};
+pub use report::{AffinityReport, report};
+pub use reservation::{AlreadyReserved, CoreReservation, is_reserved, reserve_core, reserve_specific};
+pub use reservation::{reserved_core_ids, spawn_reserved};
+pub use scoped::{AffinityGuard, pin_scoped, with_affinity};
+#[doc(hidden)]
+pub use scoped::__pin_function;
+pub use shared_cache::{CacheClusterId, cache_cluster_of, cache_clusters, l2_groups, l3_domains};
+pub use shared_cache::{CacheLevel, CacheType, cache_info};
+#[cfg(feature = "macros")]
+pub use core_affinity_macros::pinned;
+pub use spawn::{BuilderExt, ScopeExt, run_per_core, run_per_core_skipping, spawn_pinned};
+pub use stable::StableCoreId;
+pub use topology::{CoreTopology, Topology};
+pub use watch::{DEFAULT_WATCH_INTERVAL, TopologyChange, WatchHandle, watch_topology, watch_topology_every};
+
+use backend::installed_backend;
+use current::{invalidate, single_core, track};
+use enabled::unless_disabled;
+use error::record;
+use instrument::{pin_current, pin_current_set, pin_thread};
+
 /// This function tries to retrieve information
 /// on all the "cores" on which the current thread 
 /// is allowed to run.
+///
+/// The result depends on the current thread's affinity
+/// mask, so it shrinks once the thread has been pinned.
+/// Use `get_all_core_ids` to enumerate every online core.
 pub fn get_core_ids() -> Option<Vec<CoreId>> {
-    get_core_ids_helper()
+    try_get_core_ids().ok()
 }
 
-/// This function tries to pin the current
-/// thread to the specified core.
-///
-/// # Arguments
+/// This function tries to retrieve information
+/// on all the "cores" on which the current thread
+/// is allowed to run, reporting why it failed if
+/// it did.
+pub fn try_get_core_ids() -> Result<Vec<CoreId>, Error> {
+    record(core_ids_helper()).map(Iterator::collect)
+}
+
+/// This function is like `try_get_core_ids`, but fills
+/// `buf` instead of returning a new `Vec`, and returns the
+/// number of cores.
 ///
-/// * core_id - ID of the core to pin
-pub fn set_for_current(core_id: CoreId) -> bool {
-    set_for_current_helper(core_id)
+/// `buf` is cleared first. It only grows if it can not
+/// hold every core, so reserving `max_core_id()` plus one
+/// entries up front avoids allocating here. On error,
+/// `buf` is left empty.
+pub fn get_core_ids_into(buf: &mut Vec<CoreId>) -> Result<usize, Error> {
+    buf.clear();
+    buf.extend(record(core_ids_helper())?);
+
+    Ok(buf.len())
 }
 
-/// This represents a CPU core.
-#[repr(transparent)]
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct CoreId {
-    pub id: usize,
+/// This function is like `get_core_ids`, but returns
+/// an iterator instead of a `Vec`.
+///
+/// The iterator walks the affinity mask in place, so
+/// this does not allocate. Use it to count or visit
+/// the cores once, such as on every thread startup.
+pub fn core_ids() -> Option<CoreIds> {
+    record(core_ids_helper()).ok()
 }
 
-// Linux Section
+/// This function returns the number of "cores" on which
+/// the current thread is allowed to run.
+///
+/// It counts the bits of the affinity mask without building
+/// a list, so it equals `get_core_ids().len()` but does not
+/// allocate.
+///
+/// This usually matches `std::thread::available_parallelism`,
+/// which also honours the affinity mask. The two differ when
+/// the process runs under a CPU quota, such as the cgroup
+/// limits set by container runtimes: std rounds the quota
+/// down to a number of cores, while this function still
+/// reports every core the thread may be scheduled on.
+pub fn core_count() -> Option<usize> {
+    core_ids().map(|core_ids| core_ids.len())
+}
 
-#[cfg(any(target_os = "android", target_os = "linux"))]
-#[inline]
-fn get_core_ids_helper() -> Option<Vec<CoreId>> {
-    linux::get_core_ids()
+/// This function returns the number of "cores" that are
+/// online on the system, regardless of the affinity mask
+/// of the current thread or any CPU quota.
+pub fn system_core_count() -> Option<usize> {
+    get_all_core_ids().map(|core_ids| core_ids.len())
 }
 
-#[cfg(any(target_os = "android", target_os = "linux"))]
-#[inline]
-fn set_for_current_helper(core_id: CoreId) -> bool {
-    linux::set_for_current(core_id)
+/// This function tries to retrieve information
+/// on all the "cores" that are online on the system,
+/// regardless of the current thread's affinity mask.
+pub fn get_all_core_ids() -> Option<Vec<CoreId>> {
+    get_all_core_ids_helper()
 }
 
-#[cfg(any(target_os = "android", target_os = "linux"))]
-mod linux {
-    use std::mem;
+/// This function is like `get_core_ids`, but
+/// returns the cores as a `CpuSet`.
+pub fn get_core_set() -> Option<CpuSet> {
+    core_ids().map(|core_ids| core_ids.collect())
+}
 
-    use libc::{CPU_ISSET, CPU_SET, CPU_SETSIZE, cpu_set_t, sched_getaffinity, sched_setaffinity};
+/// This function is like `get_all_core_ids`, but
+/// returns the cores as a `CpuSet`.
+pub fn get_all_core_set() -> Option<CpuSet> {
+    get_all_core_ids().map(CpuSet::from)
+}
 
-    use super::CoreId;
+/// This function returns the highest ID of all the
+/// "cores" that are online on the system.
+pub fn max_core_id() -> Option<CoreId> {
+    get_all_core_ids().and_then(|core_ids| core_ids.into_iter().max())
+}
 
-    pub fn get_core_ids() -> Option<Vec<CoreId>> {
-        if let Some(full_set) = get_affinity_mask() {
-            let mut core_ids: Vec<CoreId> = Vec::new();
+/// This function tries to retrieve the "cores" on
+/// which the current thread may be scheduled right now.
+///
+/// The result reflects any earlier call to `set_for_current`.
+/// It returns `None` on platforms where the affinity of a
+/// thread can not be read back, such as macOS.
+pub fn get_affinity_for_current() -> Option<Vec<CoreId>> {
+    record(get_affinity_for_current_helper()).ok()
+}
 
-            for i in 0..CPU_SETSIZE as usize {
-                if unsafe { CPU_ISSET(i, &full_set) } {
-                    core_ids.push(CoreId{ id: i });
-                }
-            }
+/// This function is like `get_affinity_for_current`,
+/// but returns the cores as a `CpuSet`.
+pub fn get_affinity_set_for_current() -> Option<CpuSet> {
+    get_affinity_for_current().map(CpuSet::from)
+}
 
-            Some(core_ids)
-        }
-        else {
-            None
-        }
-    }
+/// This function tries to pin the current
+/// thread to the specified core.
+///
+/// It fails for IDs the platform can not represent and
+/// for cores that are not online. Use `CoreId::try_new`
+/// to check an ID up front.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+pub fn set_for_current(core_id: CoreId) -> bool {
+    set_for_current_checked(core_id).is_ok()
+}
 
-    pub fn set_for_current(core_id: CoreId) -> bool {
-        // Turn `core_id` into a `libc::cpu_set_t` with only
-        // one core active.
-        let mut set = new_cpu_set();
+/// This function tries to pin the current
+/// thread to the specified core, reporting
+/// why it failed if it did.
+///
+/// IDs the platform can not represent fail with
+/// `ErrorKind::InvalidCore` instead of panicking.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+pub fn set_for_current_checked(core_id: CoreId) -> Result<(), Error> {
+    record(unless_disabled(|| {
+        pin_current(core_id, || track(set_for_current_helper(core_id), Some(core_id)))
+    }))
+}
 
-        unsafe { CPU_SET(core_id.id, &mut set) };
+/// This function tries to pin the current thread to the
+/// specified core and then checks that the pin took effect.
+///
+/// The affinity mask is read back after setting it. Where it
+/// can not be read, the core the thread runs on is sampled
+/// once instead. Fails with `ErrorKind::NotEffective` if the
+/// thread is not confined to `core_id`, as on macOS, where
+/// pinning is only a hint to the scheduler.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+pub fn set_for_current_verified(core_id: CoreId) -> Result<(), Error> {
+    set_for_current_checked(core_id)?;
 
-        // Set the current thread's core affinity.
-        let res = unsafe {
-            sched_setaffinity(0, // Defaults to current thread
-                              mem::size_of::<cpu_set_t>(),
-                              &set)
-        };
-        res == 0
+    if !is_enabled() {
+        return Ok(());
     }
 
-    fn get_affinity_mask() -> Option<cpu_set_t> {
-        let mut set = new_cpu_set();
-
-        // Try to get current core affinity mask.
-        let result = unsafe {
-            sched_getaffinity(0, // Defaults to current thread
-                              mem::size_of::<cpu_set_t>(),
-                              &mut set)
-        };
+    let confined = match get_affinity_for_current_helper() {
+        Ok(core_ids) => core_ids == [core_id],
+        Err(ref err) if err.kind() == ErrorKind::Unsupported => {
+            // Give the scheduler a chance to move the thread.
+            thread::yield_now();
+            current_core_helper().ok() == Some(core_id)
+        },
+        Err(err) => return record(Err(err)),
+    };
 
-        if result == 0 {
-            Some(set)
-        }
-        else {
-            None
-        }
+    if confined {
+        Ok(())
     }
-
-    fn new_cpu_set() -> cpu_set_t {
-        unsafe { mem::zeroed::<cpu_set_t>() }
+    else {
+        record(Err(Error::new(ErrorKind::NotEffective).with_core_id(core_id)))
     }
+}
 
-    #[cfg(test)]
-    mod tests {
-        use num_cpus;
+/// This function tries to confine the current
+/// thread to the specified set of cores.
+///
+/// Duplicate IDs are ignored. It fails if `core_ids`
+/// is empty or contains a core that can not be
+/// represented by the platform.
+///
+/// # Arguments
+///
+/// * core_ids - IDs of the cores to allow
+pub fn set_for_current_set(core_ids: &[CoreId]) -> bool {
+    record(try_set_for_current_set(core_ids)).is_ok()
+}
 
-        use super::*;
+// `set_for_current_set`, reporting why it failed.
+fn try_set_for_current_set(core_ids: &[CoreId]) -> Result<(), Error> {
+    if core_ids.is_empty() {
+        Err(Error::new(ErrorKind::EmptySet))
+    }
+    else {
+        unless_disabled(|| {
+            pin_current_set(core_ids, || track(set_for_current_set_helper(core_ids), single_core(core_ids)))
+        })
+    }
+}
 
-        #[test]
-        fn test_linux_get_affinity_mask() {
-            match get_affinity_mask() {
-                Some(_) => {},
-                None => { assert!(false); },
-            }
-        }
+/// This function is like `set_for_current_set`, but
+/// takes the cores as a `CpuSet`.
+///
+/// # Arguments
+///
+/// * set - the cores to allow
+pub fn set_for_current_core_set(set: &CpuSet) -> bool {
+    set_for_current_set(&set.to_vec())
+}
 
-        #[test]
-        fn test_linux_get_core_ids() {
-            match get_core_ids() {
-                Some(set) => {
-                    assert_eq!(set.len(), num_cpus::get());
-                },
-                None => { assert!(false); },
-            }
-        }
+/// This function tries to confine the current thread to
+/// every core the process may run on, except `excluded`.
+///
+/// The base is the affinity mask of the process, not the
+/// list of online cores, so restrictions such as a container
+/// cpuset still apply. Where the process mask can not be
+/// read, the mask of the current thread is used instead. It
+/// fails with `ErrorKind::EmptySet`, leaving the affinity
+/// unchanged, if no core would be left.
+///
+/// # Arguments
+///
+/// * excluded - IDs of the cores to keep the thread off
+pub fn set_for_current_excluding(excluded: &[CoreId]) -> Result<(), Error> {
+    let allowed = record(allowed_for_process())?;
+    let core_ids = (allowed - CpuSet::from(excluded)).to_vec();
 
-        #[test]
-        fn test_linux_set_for_current() {
-            let ids = get_core_ids().unwrap();
+    let res = if core_ids.is_empty() {
+        Err(Error::new(ErrorKind::EmptySet))
+    }
+    else {
+        unless_disabled(|| {
+            pin_current_set(&core_ids, || track(set_for_current_set_helper(&core_ids), single_core(&core_ids)))
+        })
+    };
 
-            assert!(ids.len() > 0);
+    record(res)
+}
 
-            let res = set_for_current(ids[0]);
-            assert_eq!(res, true);
+// The cores the process may run on, or those of the current
+// thread where the process mask can not be read.
+fn allowed_for_process() -> Result<CpuSet, Error> {
+    match get_for_process_helper(std::process::id()) {
+        Ok(core_ids) => Ok(core_ids.into()),
+        Err(ref err) if err.kind() == ErrorKind::Unsupported => Ok(core_ids_helper()?.collect()),
+        Err(err) => Err(err),
+    }
+}
 
-            // Ensure that the system pinned the current thread
-            // to the specified core.
-            let mut core_mask = new_cpu_set();
-            unsafe { CPU_SET(ids[0].id, &mut core_mask) };
+/// This function asks the scheduler to prefer running the
+/// current thread on the specified core.
+///
+/// Unlike `set_for_current`, this does not restrict the
+/// thread: it may still run on any core of its affinity
+/// mask, and moves off the preferred core when that is busy.
+///
+/// * Windows: sets the ideal processor of the thread.
+/// * macOS: sets the affinity tag, just like `set_for_current`,
+///   since tags are only hints there as well.
+/// * Linux, Android and FreeBSD have no such hint. It fails
+///   with `ErrorKind::Unsupported` instead of pinning.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to prefer
+pub fn set_preferred_for_current(core_id: CoreId) -> Result<(), Error> {
+    record(unless_disabled(|| set_preferred_for_current_helper(core_id)))
+}
 
-            let new_mask = get_affinity_mask().unwrap();
+/// This function tries to pin the thread behind
+/// `handle` to the specified core.
+///
+/// It fails if the thread has already finished running.
+///
+/// # Arguments
+///
+/// * handle - handle of the thread to pin
+/// * core_id - ID of the core to pin
+pub fn set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId) -> bool {
+    record(invalidate(try_set_for_thread(handle, core_id, None))).is_ok()
+}
 
-            let mut is_equal = true;
+/// This function pins each thread of `assignments` to the core
+/// it is paired with, such as threads started by a framework
+/// before the layout of the cores is known.
+///
+/// Every assignment is tried, and the results are returned in
+/// the same order: a thread that can not be pinned does not stop
+/// the others. The online cores are read once for the whole
+/// batch, and a core that is not online fails with
+/// `ErrorKind::InvalidCore` without touching its thread.
+/// Otherwise each assignment behaves like `set_for_thread`.
+///
+/// # Arguments
+///
+/// * assignments - the threads to pin, each with its core
+pub fn pin_threads<T>(assignments: &[(&JoinHandle<T>, CoreId)]) -> Vec<Result<(), Error>> {
+    let online = get_all_core_ids_helper().map(CpuSet::from);
 
-            for i in 0..CPU_SETSIZE as usize {
-                let is_set1 = unsafe {
-                    CPU_ISSET(i, &core_mask)
-                };
-                let is_set2 = unsafe {
-                    CPU_ISSET(i, &new_mask)
-                };
+    assignments.iter()
+        .map(|&(handle, core_id)| record(invalidate(try_set_for_thread(handle, core_id, online.as_ref()))))
+        .collect()
+}
 
-                if is_set1 != is_set2 {
-                    is_equal = false;
-                }
-            }
+// `set_for_thread`, reporting why it failed. Cores missing
+// from `online` are rejected without a system call.
+fn try_set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId, online: Option<&CpuSet>) -> Result<(), Error> {
+    // A finished thread may already be gone, so its
+    // native handle must not be used anymore.
+    if handle.is_finished() {
+        return Err(Error::new(ErrorKind::NotFound).with_core_id(core_id));
+    }
 
-            assert!(is_equal);
+    unless_disabled(|| pin_thread(handle, core_id, || {
+        match online {
+            Some(online) if !online.contains(core_id) => {
+                Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id))
+            },
+            _ => set_for_thread_helper(handle, core_id),
         }
-     }
+    }))
 }
 
-// Windows Section
-
-#[cfg(target_os = "windows")]
-#[inline]
-fn get_core_ids_helper() -> Option<Vec<CoreId>> {
-    windows::get_core_ids()
-}
+/// This function returns the set of cores the thread
+/// behind `handle` may run on.
+///
+/// It returns `None` if the thread has already finished
+/// running or on platforms that can not report it.
+///
+/// # Arguments
+///
+/// * handle - handle of the thread to inspect
+pub fn get_for_thread<T>(handle: &JoinHandle<T>) -> Option<Vec<CoreId>> {
+    let res = if handle.is_finished() {
+        Err(Error::new(ErrorKind::NotFound))
+    }
+    else {
+        get_for_thread_helper(handle)
+    };
 
-#[cfg(target_os = "windows")]
-#[inline]
-fn set_for_current_helper(core_id: CoreId) -> bool {
-    windows::set_for_current(core_id)
+    record(res).ok()
 }
 
-#[cfg(target_os = "windows")]
-extern crate winapi;
+/// This function tries to restrict every thread of the
+/// process `pid` to the specified set of cores.
+///
+/// The calling process may pass its own ID. Fails with
+/// `ErrorKind::PermissionDenied` if the caller may not change
+/// the affinity of the process.
+///
+/// # Arguments
+///
+/// * pid - ID of the process to restrict
+/// * core_ids - IDs of the cores the process may run on
+pub fn set_for_process(pid: u32, core_ids: &[CoreId]) -> Result<(), Error> {
+    let res = if core_ids.is_empty() {
+        Err(Error::new(ErrorKind::EmptySet))
+    }
+    else {
+        unless_disabled(|| set_for_process_helper(pid, core_ids))
+    };
 
-#[cfg(target_os = "windows")]
-mod windows {
-    use winapi::shared::basetsd::{DWORD_PTR, PDWORD_PTR};
-    use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentThread};
-    use winapi::um::winbase::{GetProcessAffinityMask, SetThreadAffinityMask};
+    record(invalidate(res))
+}
 
-    use super::CoreId;
+/// This function returns the set of cores the process
+/// `pid` may run on.
+///
+/// On Linux every thread has a mask of its own, so this is
+/// the union of the masks of all threads of the process:
+/// pinning the main thread does not shrink it.
+///
+/// Fails with `ErrorKind::NotFound` if the process has
+/// already exited.
+///
+/// # Arguments
+///
+/// * pid - ID of the process to inspect
+pub fn get_for_process(pid: u32) -> Result<Vec<CoreId>, Error> {
+    record(get_for_process_helper(pid))
+}
 
-    pub fn get_core_ids() -> Option<Vec<CoreId>> {
-        if let Some(mask) = get_affinity_mask() {
-            // Find all active cores in the bitmask.
-            let mut core_ids: Vec<CoreId> = Vec::new();
+/// This function returns the core the current thread
+/// is running on.
+///
+/// The value is only advisory: unless the thread is pinned
+/// to a single core, it may be moved to another core right
+/// after this call returns.
+pub fn current_core() -> Option<CoreId> {
+    record(current_core_helper()).ok()
+}
 
-            for i in 0..64 as u64 {
-                let test_mask = 1 << i;
+/// This function tries to undo any pinning of
+/// the current thread, allowing it to run on all cores.
+///
+/// If the process itself is restricted to fewer cores
+/// than the system has, the thread is restored to the
+/// cores of the process instead.
+pub fn clear_for_current() -> bool {
+    record(unless_disabled(|| track(clear_for_current_helper(), None))).is_ok()
+}
 
-                if (mask & test_mask) == test_mask {
-                    core_ids.push(CoreId { id: i as usize });
-                }
-            }
+/// This represents a CPU core.
+///
+/// It displays as the bare core number, which `parse`
+/// reads back. The alternate form `{:#}` and `Debug`
+/// both render it as `CoreId(3)`.
+#[repr(transparent)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CoreId {
+    pub id: usize,
+}
 
-            Some(core_ids)
+impl fmt::Display for CoreId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(f, "CoreId({})", self.id)
         }
         else {
-            None
+            write!(f, "{}", self.id)
         }
     }
+}
 
-    pub fn set_for_current(core_id: CoreId) -> bool {
-        // Convert `CoreId` back into mask.
-        let mask: u64 = 1 << core_id.id;
-
-        // Set core affinity for current thread.
-        let res = unsafe {
-            SetThreadAffinityMask(
-                GetCurrentThread(),
-                mask as DWORD_PTR
-            )
-        };
-        res != 0
+// Keep lists of cores in log lines readable.
+impl fmt::Debug for CoreId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CoreId({})", self.id)
     }
+}
 
-    fn get_affinity_mask() -> Option<u64> {
-        let mut system_mask: usize = 0;
-        let mut process_mask: usize = 0;
-
-        let res = unsafe {
-            GetProcessAffinityMask(
-                GetCurrentProcess(),
-                &mut process_mask as PDWORD_PTR,
-                &mut system_mask as PDWORD_PTR
-            )
-        };
-
-        // Successfully retrieved affinity mask
-        if res != 0 {
-            Some(process_mask as u64)
-        }
-        // Failed to retrieve affinity mask
-        else {
-            None
-        }
+impl From<CoreId> for usize {
+    fn from(core_id: CoreId) -> usize {
+        core_id.id
     }
+}
 
-    #[cfg(test)]
-    mod tests {
-        use num_cpus;
-
-        use super::*;
-
-        #[test]
-        fn test_windows_get_core_ids() {
-            match get_core_ids() {
-                Some(set) => {
-                    assert_eq!(set.len(), num_cpus::get());
-                },
-                None => { assert!(false); },
-            }
+impl CoreId {
+    /// Creates a `CoreId`, checking that the
+    /// core is online on the system.
+    ///
+    /// # Arguments
+    ///
+    /// * id - index of the core
+    pub fn try_new(id: usize) -> Result<CoreId, Error> {
+        let core_id = CoreId { id };
+
+        match get_all_core_ids() {
+            Some(ref core_ids) if core_ids.contains(&core_id) => Ok(core_id),
+            Some(_) => Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id)),
+            None => Err(Error::new(ErrorKind::Unsupported)),
         }
+    }
+}
 
-        #[test]
-        fn test_windows_set_for_current() {
-            let ids = get_core_ids().unwrap();
-
-            assert!(ids.len() > 0);
+impl TryFrom<usize> for CoreId {
+    type Error = Error;
 
-            assert_ne!(set_for_current(ids[0]), 0);
-        }
+    fn try_from(id: usize) -> Result<CoreId, Error> {
+        CoreId::try_new(id)
     }
 }
 
-// MacOS Section
+// Backend Dispatch
+//
+// Every call goes to the backend passed to `set_backend` if
+// there is one, and to the `platform` module otherwise.
 
-#[cfg(target_os = "macos")]
 #[inline]
-fn get_core_ids_helper() -> Option<Vec<CoreId>> {
-    macos::get_core_ids()
+fn core_ids_helper() -> Result<CoreIds, Error> {
+    match installed_backend() {
+        Some(backend) => backend.core_ids(),
+        None => platform::core_ids(),
+    }
 }
 
-#[cfg(target_os = "macos")]
 #[inline]
-fn set_for_current_helper(core_id: CoreId) -> bool {
-    macos::set_for_current(core_id)
+fn get_all_core_ids_helper() -> Option<Vec<CoreId>> {
+    match installed_backend() {
+        Some(backend) => backend.get_all_core_ids(),
+        None => platform::get_all_core_ids(),
+    }
 }
 
-#[cfg(target_os = "macos")]
-mod macos {
-    use std::mem;
-
-    use libc::{c_int, c_uint, c_void, pthread_self};
-
-    use num_cpus;
-
-    use super::CoreId;
-
-    type kern_return_t = c_int;
-    type integer_t = c_int;
-    type natural_t = c_uint;
-    type thread_t = c_uint;
-    type thread_policy_flavor_t = natural_t;
-    type mach_msg_type_number_t = natural_t;
-
-    #[repr(C)]
-    struct thread_affinity_policy_data_t {
-        affinity_tag: integer_t,
+#[inline]
+fn get_affinity_for_current_helper() -> Result<Vec<CoreId>, Error> {
+    match installed_backend() {
+        Some(backend) => backend.get_affinity_for_current(),
+        None => platform::get_affinity_for_current(),
     }
+}
 
-    type thread_policy_t = *mut thread_affinity_policy_data_t;
+#[inline]
+fn set_for_current_helper(core_id: CoreId) -> Result<(), Error> {
+    match installed_backend() {
+        Some(backend) => backend.set_for_current(core_id),
+        None => platform::set_for_current(core_id),
+    }
+}
 
-    const THREAD_AFFINITY_POLICY: thread_policy_flavor_t = 4;
+#[inline]
+fn set_for_current_set_helper(core_ids: &[CoreId]) -> Result<(), Error> {
+    match installed_backend() {
+        Some(backend) => backend.set_for_current_set(core_ids),
+        None => platform::set_for_current_set(core_ids),
+    }
+}
 
-    extern {
-        fn thread_policy_set(
-            thread: thread_t,
-            flavor: thread_policy_flavor_t,
-            policy_info: thread_policy_t,
-            count: mach_msg_type_number_t,
-        ) -> kern_return_t;
+#[inline]
+fn set_for_thread_helper<T>(handle: &JoinHandle<T>, core_id: CoreId) -> Result<(), Error> {
+    match installed_backend() {
+        Some(backend) => backend.set_for_thread(handle.thread(), core_id),
+        None => platform::set_for_thread(handle, core_id),
     }
+}
 
-    pub fn get_core_ids() -> Option<Vec<CoreId>> {
-        Some((0..(num_cpus::get())).into_iter()
-             .map(|n| CoreId { id: n as usize })
-             .collect::<Vec<_>>())
+#[inline]
+fn get_for_thread_helper<T>(handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    match installed_backend() {
+        Some(backend) => backend.get_for_thread(handle.thread()),
+        None => platform::get_for_thread(handle),
     }
+}
 
-    pub fn set_for_current(core_id: CoreId) -> bool {
-        let THREAD_AFFINITY_POLICY_COUNT: mach_msg_type_number_t =
-            mem::size_of::<thread_affinity_policy_data_t>() as mach_msg_type_number_t /
-            mem::size_of::<integer_t>() as mach_msg_type_number_t;
+#[inline]
+fn set_for_process_helper(pid: u32, core_ids: &[CoreId]) -> Result<(), Error> {
+    match installed_backend() {
+        Some(backend) => backend.set_for_process(pid, core_ids),
+        None => platform::set_for_process(pid, core_ids),
+    }
+}
 
-        let mut info = thread_affinity_policy_data_t {
-            affinity_tag: core_id.id as integer_t,
-        };
+#[inline]
+fn get_for_process_helper(pid: u32) -> Result<Vec<CoreId>, Error> {
+    match installed_backend() {
+        Some(backend) => backend.get_for_process(pid),
+        None => platform::get_for_process(pid),
+    }
+}
 
-        let res = unsafe {
-            thread_policy_set(
-                pthread_self() as thread_t,
-                THREAD_AFFINITY_POLICY,
-                &mut info as thread_policy_t,
-                THREAD_AFFINITY_POLICY_COUNT
-            )
-        };
-        res == 0
+#[inline]
+fn current_core_helper() -> Result<CoreId, Error> {
+    match installed_backend() {
+        Some(backend) => backend.current_core(),
+        None => platform::current_core(),
     }
+}
 
-    #[cfg(test)]
-    mod tests {
-        use num_cpus;
+#[inline]
+fn set_preferred_for_current_helper(core_id: CoreId) -> Result<(), Error> {
+    match installed_backend() {
+        Some(backend) => backend.set_preferred_for_current(core_id),
+        None => platform::set_preferred_for_current(core_id),
+    }
+}
 
-        use super::*;
+#[inline]
+fn thread_core_ids_helper() -> Result<CoreIds, Error> {
+    match installed_backend() {
+        Some(backend) => backend.thread_core_ids(),
+        None => platform::thread_core_ids(),
+    }
+}
 
-        #[test]
-        fn test_macos_get_core_ids() {
-            match get_core_ids() {
-                Some(set) => {
-                    assert_eq!(set.len(), num_cpus::get());
-                },
-                None => { assert!(false); },
-            }
-        }
+#[inline]
+fn process_core_ids_helper() -> Result<CoreIds, Error> {
+    match installed_backend() {
+        Some(backend) => backend.process_core_ids(),
+        None => platform::process_core_ids(),
+    }
+}
 
-        #[test]
-        fn test_macos_set_for_current() {
-            let ids = get_core_ids().unwrap();
-            assert!(ids.len() > 0);
-            assert!(set_for_current(ids[0]))
-        }
+#[inline]
+fn capabilities_helper() -> Capabilities {
+    match installed_backend() {
+        Some(backend) => backend.capabilities(),
+        None => platform::capabilities(),
     }
 }
 
+#[inline]
+fn clear_for_current_helper() -> Result<(), Error> {
+    match installed_backend() {
+        Some(backend) => backend.clear_for_current(),
+        None => platform::clear_for_current(),
+    }
+}
 
-// FreeBSD Section
+#[cfg(unix)]
+#[inline]
+fn set_for_command_helper(command: &mut Command, core_ids: &[CoreId]) {
+    match installed_backend() {
+        Some(backend) => backend.set_for_command(command, core_ids),
+        None => platform::set_for_command(command, core_ids),
+    }
+}
 
-#[cfg(target_os = "freebsd")]
+#[cfg(not(unix))]
 #[inline]
-fn get_core_ids_helper() -> Option<Vec<CoreId>> {
-    freebsd::get_core_ids()
+fn spawn_with_affinity_helper(command: &mut Command, core_ids: &[CoreId]) -> io::Result<Child> {
+    match installed_backend() {
+        Some(backend) => backend.spawn_with_affinity(command, core_ids),
+        None => platform::spawn_with_affinity(command, core_ids),
+    }
 }
 
-#[cfg(target_os = "freebsd")]
 #[inline]
-fn set_for_current_helper(core_id: CoreId) -> bool {
-    freebsd::set_for_current(core_id)
+fn core_siblings_helper() -> Option<Vec<Vec<CoreId>>> {
+    match installed_backend() {
+        Some(backend) => backend.core_siblings(),
+        None => platform::core_siblings(),
+    }
 }
 
-#[cfg(target_os = "freebsd")]
-mod freebsd {
-    use std::mem;
+#[inline]
+fn numa_nodes_helper() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    match installed_backend() {
+        Some(backend) => backend.numa_nodes(),
+        None => platform::numa_nodes(),
+    }
+}
 
-    use libc::{
-        cpuset_getaffinity, cpuset_setaffinity, cpuset_t, CPU_ISSET,
-        CPU_LEVEL_WHICH, CPU_SET, CPU_SETSIZE, CPU_WHICH_TID,
-    };
+#[inline]
+fn current_location_helper() -> Result<(CoreId, NumaNode), Error> {
+    match installed_backend() {
+        Some(backend) => backend.current_location(),
+        None => platform::current_location(),
+    }
+}
 
-    use super::CoreId;
+#[inline]
+fn packages_helper() -> Option<Vec<(usize, Vec<CoreId>)>> {
+    match installed_backend() {
+        Some(backend) => backend.packages(),
+        None => platform::packages(),
+    }
+}
 
-    pub fn get_core_ids() -> Option<Vec<CoreId>> {
-        if let Some(full_set) = get_affinity_mask() {
-            let mut core_ids: Vec<CoreId> = Vec::new();
+#[inline]
+fn dies_helper() -> Option<Vec<Vec<CoreId>>> {
+    match installed_backend() {
+        Some(backend) => backend.dies(),
+        None => platform::dies(),
+    }
+}
 
-            for i in 0..CPU_SETSIZE as usize {
-                if unsafe { CPU_ISSET(i, &full_set) } {
-                    core_ids.push(CoreId { id: i });
-                }
-            }
+#[inline]
+fn clusters_helper() -> Option<Vec<Vec<CoreId>>> {
+    match installed_backend() {
+        Some(backend) => backend.clusters(),
+        None => platform::clusters(),
+    }
+}
 
-            Some(core_ids)
-        } else {
-            None
-        }
+#[inline]
+fn cache_groups_helper(level: usize) -> Option<Vec<Vec<CoreId>>> {
+    match installed_backend() {
+        Some(backend) => backend.cache_groups(level),
+        None => platform::cache_groups(level),
     }
+}
 
-    pub fn set_for_current(core_id: CoreId) -> bool {
-        // Turn `core_id` into a `libc::cpuset_t` with only
-        // one core active.
-        let mut set = new_cpu_set();
-
-        unsafe { CPU_SET(core_id.id, &mut set) };
-
-        // Set the current thread's core affinity.
-        let res = unsafe {
-            // FreeBSD's sched_setaffinity currently operates on process id,
-            // therefore using cpuset_setaffinity instead.
-            cpuset_setaffinity(
-                CPU_LEVEL_WHICH,
-                CPU_WHICH_TID,
-                -1, // -1 == current thread
-                mem::size_of::<cpuset_t>(),
-                &set,
-            )
-        };
-        res == 0
-    }
-
-    fn get_affinity_mask() -> Option<cpuset_t> {
-        let mut set = new_cpu_set();
-
-        // Try to get current core affinity mask.
-        let result = unsafe {
-            // FreeBSD's sched_getaffinity currently operates on process id,
-            // therefore using cpuset_getaffinity instead.
-            cpuset_getaffinity(
-                CPU_LEVEL_WHICH,
-                CPU_WHICH_TID,
-                -1, // -1 == current thread
-                mem::size_of::<cpuset_t>(),
-                &mut set,
-            )
-        };
-
-        if result == 0 {
-            Some(set)
-        } else {
-            None
-        }
+#[inline]
+fn cache_info_helper(core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    match installed_backend() {
+        Some(backend) => backend.cache_info(core_id),
+        None => platform::cache_info(core_id),
     }
+}
 
-    fn new_cpu_set() -> cpuset_t {
-        unsafe { mem::zeroed::<cpuset_t>() }
+#[inline]
+fn core_kinds_helper() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    match installed_backend() {
+        Some(backend) => backend.core_kinds(),
+        None => platform::core_kinds(),
     }
+}
 
-    #[cfg(test)]
-    mod tests {
-        use num_cpus;
+#[inline]
+fn max_frequency_khz_helper(core_id: CoreId) -> Option<u64> {
+    match installed_backend() {
+        Some(backend) => backend.max_frequency_khz(core_id),
+        None => platform::max_frequency_khz(core_id),
+    }
+}
 
-        use super::*;
+#[inline]
+fn core_rankings_helper() -> Option<Vec<(CoreId, u64)>> {
+    match installed_backend() {
+        Some(backend) => backend.core_rankings(),
+        None => platform::core_rankings(),
+    }
+}
 
-        #[test]
-        fn test_freebsd_get_affinity_mask() {
-            match get_affinity_mask() {
-                Some(_) => {}
-                None => {
-                    assert!(false);
-                }
-            }
-        }
+#[inline]
+fn capacity_helper(core_id: CoreId) -> Option<u32> {
+    match installed_backend() {
+        Some(backend) => backend.capacity(core_id),
+        None => platform::capacity(core_id),
+    }
+}
 
-        #[test]
-        fn test_freebsd_get_core_ids() {
-            match get_core_ids() {
-                Some(set) => {
-                    assert_eq!(set.len(), num_cpus::get());
-                }
-                None => {
-                    assert!(false);
-                }
-            }
-        }
+#[inline]
+fn current_frequency_khz_helper(core_id: CoreId) -> Option<u64> {
+    match installed_backend() {
+        Some(backend) => backend.current_frequency_khz(core_id),
+        None => platform::current_frequency_khz(core_id),
+    }
+}
 
-        #[test]
-        fn test_freebsd_set_for_current() {
-            let ids = get_core_ids().unwrap();
+#[inline]
+fn cpu_times_helper() -> Option<Vec<(CoreId, CpuTimes)>> {
+    match installed_backend() {
+        Some(backend) => backend.cpu_times(),
+        None => platform::cpu_times(),
+    }
+}
 
-            assert!(ids.len() > 0);
+#[inline]
+fn core_ids_by_state_helper(state: CoreState) -> Option<Vec<CoreId>> {
+    match installed_backend() {
+        Some(backend) => backend.core_ids_by_state(state),
+        None => platform::core_ids_by_state(state),
+    }
+}
 
-            let res = set_for_current(ids[0]);
-            assert_eq!(res, true);
+#[inline]
+fn stable_core_ids_helper() -> Option<Vec<(CoreId, StableCoreId)>> {
+    match installed_backend() {
+        Some(backend) => backend.stable_core_ids(),
+        None => platform::stable_core_ids(),
+    }
+}
 
-            // Ensure that the system pinned the current thread
-            // to the specified core.
-            let mut core_mask = new_cpu_set();
-            unsafe { CPU_SET(ids[0].id, &mut core_mask) };
+// Platform Selection
+//
+// Each platform module offers the same set of functions, and
+// exactly one of them is brought in as `platform`.
 
-            let new_mask = get_affinity_mask().unwrap();
+#[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux")))]
+mod linux;
+#[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux")))]
+use linux as platform;
 
-            let mut is_equal = true;
+#[cfg(target_os = "windows")]
+extern crate winapi;
 
-            for i in 0..CPU_SETSIZE as usize {
-                let is_set1 = unsafe { CPU_ISSET(i, &core_mask) };
-                let is_set2 = unsafe { CPU_ISSET(i, &new_mask) };
+#[cfg(all(not(feature = "mock"), target_os = "windows"))]
+mod windows;
+#[cfg(all(not(feature = "mock"), target_os = "windows"))]
+use windows as platform;
 
-                if is_set1 != is_set2 {
-                    is_equal = false;
-                }
-            }
+#[cfg(all(not(feature = "mock"), target_os = "macos"))]
+mod macos;
+#[cfg(all(not(feature = "mock"), target_os = "macos"))]
+use macos as platform;
 
-            assert!(is_equal);
-        }
-    }
-}
+#[cfg(all(not(feature = "mock"), target_os = "freebsd"))]
+mod freebsd;
+#[cfg(all(not(feature = "mock"), target_os = "freebsd"))]
+use freebsd as platform;
 
-// Stub Section
+#[cfg(feature = "mock")]
+use mock as platform;
 
-#[cfg(not(any(
+#[cfg(all(not(feature = "mock"), not(any(
     target_os = "linux",
     target_os = "android",
     target_os = "windows",
     target_os = "macos",
     target_os = "freebsd"
-)))]
-#[inline]
-fn get_core_ids_helper() -> Option<Vec<CoreId>> {
-    None
-}
-
-#[cfg(not(any(
+))))]
+mod stub;
+#[cfg(all(not(feature = "mock"), not(any(
     target_os = "linux",
     target_os = "android",
     target_os = "windows",
     target_os = "macos",
     target_os = "freebsd"
-)))]
-#[inline]
-fn set_for_current_helper(_core_id: CoreId) -> bool {
-    false
-}
+))))]
+use stub as platform;
+
+#[cfg(test)]
+mod testing;
+#[cfg(test)]
+mod test_util;
 
 #[cfg(test)]
 mod tests {
+    use std::thread;
+
     use num_cpus;
 
     use super::*;
@@ -598,19 +1004,413 @@ mod tests {
     // }
 
     #[test]
+    #[cfg(not(feature = "mock"))]
     fn test_get_core_ids() {
         match get_core_ids() {
             Some(set) => {
                 assert_eq!(set.len(), num_cpus::get());
             },
-            None => { assert!(false); },
+            None => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_get_all_core_ids() {
+        match get_all_core_ids() {
+            Some(set) => {
+                assert!(set.len() >= num_cpus::get());
+            },
+            None => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_try_get_core_ids() {
+        assert_eq!(try_get_core_ids().ok(), get_core_ids());
+    }
+
+    #[test]
+    fn test_get_core_ids_into() {
+        let mut buf = vec![CoreId { id: 12345 }];
+        buf.reserve(max_core_id().unwrap().id + 1);
+        let capacity = buf.capacity();
+        let ptr = buf.as_ptr();
+
+        assert_eq!(get_core_ids_into(&mut buf).unwrap(), buf.len());
+        assert_eq!(Some(buf.clone()), get_core_ids());
+
+        // Refilling a buffer that is large enough does not reallocate.
+        get_core_ids_into(&mut buf).unwrap();
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(buf.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_set_for_current_excluding() {
+        let all = get_core_ids().unwrap();
+
+        thread::spawn(move || {
+            // Excluding every core leaves the mask alone.
+            let err = set_for_current_excluding(&all).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::EmptySet);
+
+            #[cfg(not(target_os = "macos"))]
+            {
+                assert_eq!(get_affinity_for_current(), Some(all.clone()));
+
+                set_for_current_excluding(&[CoreId { id: 100_000 }]).unwrap();
+                assert_eq!(get_affinity_for_current(), Some(all.clone()));
+
+                if all.len() > 1 {
+                    let last = *all.last().unwrap();
+
+                    // Pinning first does not shrink the base.
+                    assert!(set_for_current(all[0]));
+
+                    set_for_current_excluding(&[last]).unwrap();
+                    assert_eq!(get_affinity_for_current(), Some(all[..all.len() - 1].to_vec()));
+                }
+            }
+        }).join().unwrap();
+    }
+
+    #[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux", target_os = "freebsd")))]
+    #[test]
+    fn test_set_preferred_for_current_unsupported() {
+        let id = get_core_ids().unwrap()[0];
+        let before = get_affinity_for_current();
+
+        let err = set_preferred_for_current(id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert_eq!(get_affinity_for_current(), before);
+    }
+
+    #[test]
+    fn test_core_count() {
+        assert_eq!(core_count(), get_core_ids().map(|ids| ids.len()));
+        assert!(core_count().unwrap() <= system_core_count().unwrap());
+
+        #[cfg(not(target_os = "macos"))]
+        {
+            let id = *get_core_ids().unwrap().last().unwrap();
+
+            thread::spawn(move || {
+                assert!(set_for_current(id));
+                assert_eq!(core_count(), Some(1));
+                assert_eq!(core_count(), get_core_ids().map(|ids| ids.len()));
+            }).join().unwrap();
         }
     }
 
+    #[test]
+    fn test_core_ids() {
+        let ids = get_core_ids().unwrap();
+        let iter = core_ids().unwrap();
+
+        assert_eq!(iter.len(), ids.len());
+        assert_eq!(iter.clone().collect::<Vec<_>>(), ids);
+        assert_eq!(iter.rev().collect::<Vec<_>>(), ids.iter().rev().cloned().collect::<Vec<_>>());
+    }
+
     #[test]
     fn test_set_for_current() {
         let ids = get_core_ids().unwrap();
-        assert!(ids.len() > 0);
+        assert!(!ids.is_empty());
         assert!(set_for_current(ids[0]))
     }
+
+    #[test]
+    fn test_set_for_current_set() {
+        let ids = get_core_ids().unwrap();
+        assert!(!ids.is_empty());
+        assert!(set_for_current_set(&ids[..1]));
+        assert!(!set_for_current_set(&[]));
+    }
+
+    #[test]
+    fn test_core_sets() {
+        assert_eq!(get_core_set().map(Vec::from), get_core_ids());
+        assert_eq!(get_all_core_set().map(Vec::from), get_all_core_ids());
+        assert!(!set_for_current_core_set(&CpuSet::new()));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd"))]
+    fn test_set_for_current_core_set() {
+        thread::spawn(|| {
+            let set = get_core_set().unwrap();
+
+            assert!(set_for_current_core_set(&set));
+            assert_eq!(get_affinity_set_for_current(), Some(set));
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_set_for_current_checked() {
+        let ids = get_core_ids().unwrap();
+        assert!(!ids.is_empty());
+        assert_eq!(set_for_current_checked(ids[0]), Ok(()));
+    }
+
+    #[test]
+    fn test_set_for_current_checked_invalid_core() {
+        let id = CoreId { id: usize::MAX };
+        let err = set_for_current_checked(id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.core_id(), Some(id));
+        assert!(!set_for_current(id));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd"))]
+    fn test_set_for_current_verified() {
+        thread::spawn(|| {
+            let id = *get_core_ids().unwrap().last().unwrap();
+
+            assert_eq!(set_for_current_verified(id), Ok(()));
+            assert_eq!(get_affinity_for_current(), Some(vec![id]));
+        }).join().unwrap();
+    }
+
+    #[test]
+    #[cfg(all(not(feature = "mock"), target_os = "macos"))]
+    fn test_set_for_current_verified_hint() {
+        thread::spawn(|| {
+            let id = get_core_ids().unwrap()[0];
+
+            let err = set_for_current_verified(id).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotEffective);
+            assert_eq!(err.core_id(), Some(id));
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_set_for_current_verified_invalid_core() {
+        let err = set_for_current_verified(CoreId { id: usize::MAX }).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+    }
+
+    #[test]
+    fn test_max_core_id() {
+        let all = get_all_core_ids().unwrap();
+        assert_eq!(max_core_id(), all.last().cloned());
+    }
+
+    #[test]
+    fn test_core_id_try_new() {
+        let max = max_core_id().unwrap();
+
+        assert_eq!(CoreId::try_new(max.id), Ok(max));
+        assert_eq!(CoreId::try_from(max.id), Ok(max));
+
+        let err = CoreId::try_new(max.id + 1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.core_id(), Some(CoreId { id: max.id + 1 }));
+    }
+
+    #[test]
+    fn test_setters_out_of_range() {
+        let id = CoreId { id: usize::MAX };
+
+        assert!(!set_for_current(id));
+        assert!(!set_for_current_set(&[id]));
+        assert!(set_for_process(std::process::id(), &[id]).is_err());
+
+        let handle = thread::spawn(|| thread::sleep(std::time::Duration::from_millis(50)));
+        assert!(!set_for_thread(&handle, id));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_core_id_formatting() {
+        let core_id = CoreId { id: 3 };
+
+        assert_eq!(format!("{}", core_id), "3");
+        assert_eq!(format!("{:#}", core_id), "CoreId(3)");
+        assert_eq!(format!("{:?}", vec![core_id, CoreId { id: 4 }]), "[CoreId(3), CoreId(4)]");
+        assert_eq!(usize::from(core_id), 3);
+    }
+
+    #[test]
+    fn test_last_error() {
+        let ids = get_core_ids().unwrap();
+
+        thread::spawn(move || {
+            assert!(last_error().is_none());
+
+            assert!(!set_for_current(CoreId { id: usize::MAX }));
+            assert!(last_error().is_some());
+
+            // The error is per-thread.
+            thread::spawn(|| assert!(last_error().is_none())).join().unwrap();
+
+            // The next successful call clears it.
+            assert!(set_for_current(ids[0]));
+            assert!(last_error().is_none());
+
+            assert!(!set_for_current_set(&[]));
+            assert!(last_error().is_some());
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_set_for_thread() {
+        use std::sync::mpsc;
+
+        let id = get_core_ids().unwrap()[0];
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            rx.recv().unwrap();
+        });
+
+        assert!(set_for_thread(&handle, id));
+        tx.send(()).unwrap();
+
+        handle.join().unwrap();
+    }
+
+    // `get_affinity_for_current` is not supported on macOS.
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_pin_threads() {
+        use std::sync::mpsc;
+
+        let ids = get_core_ids().unwrap();
+        let cores: Vec<CoreId> = (0..3).map(|i| ids[i % ids.len()]).collect();
+
+        // Each thread waits until it is pinned, then reports
+        // its own mask.
+        let (masks_tx, masks_rx) = mpsc::channel();
+        let threads: Vec<_> = (0..3).map(|i| {
+            let (go_tx, go_rx) = mpsc::channel::<()>();
+            let masks_tx = masks_tx.clone();
+
+            let handle = thread::spawn(move || {
+                go_rx.recv().unwrap();
+                masks_tx.send((i, get_affinity_for_current())).unwrap();
+            });
+
+            (handle, go_tx)
+        }).collect();
+
+        let assignments: Vec<_> = threads.iter()
+            .zip(&cores)
+            .map(|((handle, _), &core_id)| (handle, core_id))
+            .collect();
+        assert_eq!(pin_threads(&assignments), vec![Ok(()); 3]);
+
+        for (_, go_tx) in &threads {
+            go_tx.send(()).unwrap();
+        }
+
+        for _ in 0..3 {
+            let (i, mask) = masks_rx.recv().unwrap();
+            assert_eq!(mask, Some(vec![cores[i]]));
+        }
+
+        for (handle, _) in threads {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pin_threads_partial() {
+        use std::sync::mpsc;
+
+        let id = get_core_ids().unwrap()[0];
+        let offline = CoreId { id: get_all_core_ids().unwrap().last().unwrap().id + 1 };
+
+        let finished = thread::spawn(|| {});
+        while !finished.is_finished() {
+            thread::yield_now();
+        }
+
+        let (tx, rx) = mpsc::channel::<()>();
+        let running = thread::spawn(move || rx.recv().unwrap());
+
+        let results = pin_threads(&[(&finished, id), (&running, offline), (&running, id)]);
+        assert_eq!(results[0].unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(results[1].unwrap_err().kind(), ErrorKind::InvalidCore);
+        assert_eq!(results[1].unwrap_err().core_id(), Some(offline));
+        assert_eq!(results[2], Ok(()));
+
+        tx.send(()).unwrap();
+        running.join().unwrap();
+        finished.join().unwrap();
+    }
+
+    #[test]
+    fn test_set_for_thread_finished() {
+        let id = get_core_ids().unwrap()[0];
+        let handle = thread::spawn(|| {});
+
+        while !handle.is_finished() {
+            thread::yield_now();
+        }
+
+        assert!(!set_for_thread(&handle, id));
+        assert!(last_error().is_some());
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd"))]
+    fn test_get_for_thread() {
+        use std::sync::mpsc;
+
+        let id = *get_core_ids().unwrap().last().unwrap();
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            rx.recv().unwrap();
+        });
+
+        assert!(set_for_thread(&handle, id));
+        assert_eq!(get_for_thread(&handle), Some(vec![id]));
+
+        tx.send(()).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_for_thread_finished() {
+        let handle = thread::spawn(|| {});
+
+        while !handle.is_finished() {
+            thread::yield_now();
+        }
+
+        assert_eq!(get_for_thread(&handle), None);
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_set_for_process_empty_set() {
+        let err = set_for_process(std::process::id(), &[]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::EmptySet);
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd"))]
+    fn test_current_core() {
+        thread::spawn(|| {
+            let id = *get_core_ids().unwrap().last().unwrap();
+            assert!(set_for_current(id));
+
+            // Give the scheduler a chance to move the thread.
+            thread::yield_now();
+
+            assert_eq!(current_core(), Some(id));
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_clear_for_current() {
+        let ids = get_core_ids().unwrap();
+        assert!(set_for_current(ids[0]));
+        assert!(clear_for_current());
+    }
 }
@@ -0,0 +1,1492 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::mem;
+use std::os::unix::process::CommandExt;
+use std::os::unix::thread::JoinHandleExt;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::process::{self, Command};
+use std::ptr;
+use std::thread::JoinHandle;
+
+use libc::{CPU_ISSET, CPU_SET, CPU_SETSIZE, cpu_set_t, sched_getaffinity, sched_setaffinity};
+use libc::{_SC_NPROCESSORS_ONLN, ESRCH, SYS_getcpu, c_uint, pid_t, sched_getcpu, syscall, sysconf};
+
+use super::{CacheLevel, CacheType, Capabilities, CoreId, CoreIds, CoreKind, CpuTimes, Error, ErrorKind, KindSource};
+use super::{CoreState, NumaNode};
+use super::parse_cpulist;
+use kind::{classify, classify_frequencies};
+use super::StableCoreId;
+use stable::number_threads;
+
+pub fn core_ids() -> Result<CoreIds, Error> {
+    get_affinity_mask().map(|set| to_core_id_iter(&set))
+}
+
+pub fn get_all_core_ids() -> Option<Vec<CoreId>> {
+    // The kernel lists every online core here, independent
+    // of the affinity mask of the calling thread.
+    if let Ok(list) = fs::read_to_string("/sys/devices/system/cpu/online") {
+        if let Ok(core_ids) = parse_cpulist(&list) {
+            return Some(core_ids);
+        }
+    }
+
+    // Fall back to the number of online processors
+    // if sysfs is not mounted.
+    let count = unsafe { sysconf(_SC_NPROCESSORS_ONLN) };
+
+    if count > 0 {
+        Some((0..count as usize).map(|id| CoreId { id }).collect())
+    }
+    else {
+        None
+    }
+}
+
+pub fn core_ids_by_state(state: CoreState) -> Option<Vec<CoreId>> {
+    match read_core_state(Path::new("/sys/devices/system/cpu"), state) {
+        Some(core_ids) => Some(core_ids),
+        // Without sysfs, only the online cores are known.
+        None if state == CoreState::Online => get_all_core_ids(),
+        None => None,
+    }
+}
+
+// Reads the cores in `state` from a sysfs tree at `root`,
+// normally `/sys/devices/system/cpu`.
+fn read_core_state(root: &Path, state: CoreState) -> Option<Vec<CoreId>> {
+    let name = match state {
+        CoreState::Online => "online",
+        CoreState::Offline => "offline",
+        CoreState::Present => "present",
+        CoreState::Possible => "possible",
+    };
+
+    parse_cpulist(&fs::read_to_string(root.join(name)).ok()?).ok()
+}
+
+pub fn get_affinity_for_current() -> Result<Vec<CoreId>, Error> {
+    // `core_ids` already reads the mask of the current thread.
+    core_ids().map(Iterator::collect)
+}
+
+pub fn set_for_current(core_id: CoreId) -> Result<(), Error> {
+    // Turn `core_id` into a `libc::cpu_set_t` with only
+    // one core active.
+    let set = to_cpu_set(&[core_id])?;
+
+    set_affinity_mask(&set).map_err(|err| err.with_core_id(core_id))
+}
+
+pub fn set_for_current_set(core_ids: &[CoreId]) -> Result<(), Error> {
+    set_affinity_mask(&to_cpu_set(core_ids)?)
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId) -> Result<(), Error> {
+    use libc::pthread_setaffinity_np;
+
+    let set = to_cpu_set(&[core_id])?;
+
+    // Unlike `sched_setaffinity`, this returns the error
+    // number instead of setting `errno`.
+    let res = unsafe {
+        pthread_setaffinity_np(handle.as_pthread_t(),
+                               mem::size_of::<cpu_set_t>(),
+                               &set)
+    };
+
+    if res == 0 {
+        Ok(())
+    }
+    else {
+        Err(Error::from_os_error(res).with_core_id(core_id))
+    }
+}
+
+#[cfg(target_os = "android")]
+pub fn set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId) -> Result<(), Error> {
+    use libc::pthread_gettid_np;
+
+    let set = to_cpu_set(&[core_id])?;
+
+    // Bionic has no `pthread_setaffinity_np`, so set
+    // the affinity of the underlying kernel thread.
+    let res = unsafe {
+        sched_setaffinity(pthread_gettid_np(handle.as_pthread_t()),
+                          mem::size_of::<cpu_set_t>(),
+                          &set)
+    };
+
+    if res == 0 {
+        Ok(())
+    }
+    else {
+        Err(Error::last_os_error().with_core_id(core_id))
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_for_thread<T>(handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    use libc::pthread_getaffinity_np;
+
+    let mut set = new_cpu_set();
+
+    let res = unsafe {
+        pthread_getaffinity_np(handle.as_pthread_t(),
+                               mem::size_of::<cpu_set_t>(),
+                               &mut set)
+    };
+
+    if res == 0 {
+        Ok(to_core_ids(&set))
+    }
+    else {
+        Err(Error::from_os_error(res))
+    }
+}
+
+#[cfg(target_os = "android")]
+pub fn get_for_thread<T>(handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    use libc::pthread_gettid_np;
+
+    let mut set = new_cpu_set();
+
+    let res = unsafe {
+        sched_getaffinity(pthread_gettid_np(handle.as_pthread_t()),
+                          mem::size_of::<cpu_set_t>(),
+                          &mut set)
+    };
+
+    if res == 0 {
+        Ok(to_core_ids(&set))
+    }
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn set_for_process(pid: u32, core_ids: &[CoreId]) -> Result<(), Error> {
+    let set = to_cpu_set(core_ids)?;
+
+    // The affinity mask belongs to each thread, so start with
+    // the main thread and then visit every other task.
+    set_affinity_mask_for(pid as pid_t, &set)?;
+
+    let tasks = match fs::read_dir(format!("/proc/{}/task", pid)) {
+        Ok(tasks) => tasks,
+        // Without procfs only the main thread can be reached.
+        Err(_) => return Ok(()),
+    };
+
+    for task in tasks.flatten() {
+        let tid = match task.file_name().to_str().and_then(|name| name.parse().ok()) {
+            Some(tid) => tid,
+            None => continue,
+        };
+
+        match set_affinity_mask_for(tid, &set) {
+            Ok(()) => {},
+            // The thread exited while iterating; nothing to do.
+            Err(ref err) if err.raw_os_error() == Some(ESRCH) => {},
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn get_for_process(pid: u32) -> Result<Vec<CoreId>, Error> {
+    process_affinity_mask(pid).map(|set| to_core_ids(&set))
+}
+
+pub fn thread_core_ids() -> Result<CoreIds, Error> {
+    core_ids()
+}
+
+pub fn process_core_ids() -> Result<CoreIds, Error> {
+    process_affinity_mask(process::id()).map(|set| to_core_id_iter(&set))
+}
+
+// The cores any thread of the process `pid` may run on. Each
+// thread has a mask of its own, so a main thread pinned to one
+// core does not stand for the others.
+fn process_affinity_mask(pid: u32) -> Result<cpu_set_t, Error> {
+    let mut set = get_affinity_mask_for(pid as pid_t)?;
+
+    let tasks = match fs::read_dir(format!("/proc/{}/task", pid)) {
+        Ok(tasks) => tasks,
+        // Without procfs only the main thread can be reached.
+        Err(_) => return Ok(set),
+    };
+
+    for task in tasks.flatten() {
+        let tid = match task.file_name().to_str().and_then(|name| name.parse().ok()) {
+            Some(tid) => tid,
+            None => continue,
+        };
+
+        // A thread that exited while iterating has no mask.
+        if let Ok(mask) = get_affinity_mask_for(tid) {
+            for i in (0..CPU_SETSIZE as usize).filter(|&i| unsafe { CPU_ISSET(i, &mask) }) {
+                unsafe { CPU_SET(i, &mut set) };
+            }
+        }
+    }
+
+    Ok(set)
+}
+
+pub fn set_for_command(command: &mut Command, core_ids: &[CoreId]) {
+    // Build the mask up front: the closure runs in the child
+    // between `fork` and `exec`, where it must not allocate.
+    let set = if core_ids.is_empty() {
+        Err(Error::new(ErrorKind::EmptySet))
+    }
+    else {
+        to_cpu_set(core_ids)
+    };
+
+    unsafe {
+        command.pre_exec(move || {
+            set.and_then(|set| set_affinity_mask(&set))
+                .map_err(Error::into_simple_io_error)
+        });
+    }
+}
+
+pub fn current_core() -> Result<CoreId, Error> {
+    let cpu = unsafe { sched_getcpu() };
+
+    if cpu >= 0 {
+        Ok(CoreId { id: cpu as usize })
+    }
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn current_location() -> Result<(CoreId, NumaNode), Error> {
+    let mut cpu: c_uint = 0;
+    let mut node: c_uint = 0;
+
+    // glibc only wraps `getcpu` since 2.29.
+    let res = unsafe { syscall(SYS_getcpu, &mut cpu, &mut node, ptr::null_mut::<c_void>()) };
+
+    if res == 0 {
+        Ok((CoreId { id: cpu as usize }, NumaNode { id: node as usize }))
+    }
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+pub fn set_preferred_for_current(_core_id: CoreId) -> Result<(), Error> {
+    // Linux has no ideal processor, and pinning the thread
+    // instead would be much stronger than a preference.
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn capabilities() -> Capabilities {
+    let mask = get_affinity_mask();
+
+    Capabilities {
+        // Reapplying the current mask changes nothing, but
+        // fails where a sandbox forbids the call.
+        hard_pinning: mask.as_ref().is_ok_and(|set| set_affinity_mask(set).is_ok()),
+        soft_affinity: false,
+        can_query_affinity: mask.is_ok(),
+        max_cores_representable: CPU_SETSIZE as usize,
+    }
+}
+
+pub fn clear_for_current() -> Result<(), Error> {
+    // Allow every core. The kernel drops the cores outside
+    // the cpuset of the process, so this widens the mask as
+    // far as the thread is permitted to go.
+    let mut set = new_cpu_set();
+
+    for i in 0..CPU_SETSIZE as usize {
+        unsafe { CPU_SET(i, &mut set) };
+    }
+
+    set_affinity_mask(&set)
+}
+
+pub fn core_siblings() -> Option<Vec<Vec<CoreId>>> {
+    let online = get_all_core_ids()?;
+
+    read_siblings(Path::new("/sys/devices/system/cpu"), &online)
+}
+
+// Reads which of `core_ids` share a physical core from a sysfs
+// tree at `root`, normally `/sys/devices/system/cpu`, leaving
+// out siblings that are not among them, such as offline ones.
+// Returns `None` if none of them has any topology.
+fn read_siblings(root: &Path, core_ids: &[CoreId]) -> Option<Vec<Vec<CoreId>>> {
+    let mut groups = Vec::new();
+    // Cores that do not list their siblings are matched up by
+    // their package and core IDs instead.
+    let mut by_id: BTreeMap<(usize, usize), Vec<CoreId>> = BTreeMap::new();
+
+    for &core_id in core_ids {
+        let dir = root.join(format!("cpu{}/topology", core_id.id));
+        let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+        let read_id = |name: &str| read(name).and_then(|s| s.trim().parse::<usize>().ok());
+
+        if let Some(siblings) = read("thread_siblings_list").and_then(|list| parse_cpulist(&list).ok()) {
+            groups.push(siblings.into_iter().filter(|core_id| core_ids.contains(core_id)).collect());
+        }
+        else if let (Some(package), Some(core)) = (read_id("physical_package_id"), read_id("core_id")) {
+            by_id.entry((package, core)).or_default().push(core_id);
+        }
+    }
+
+    groups.extend(by_id.into_values());
+
+    if groups.is_empty() { None } else { Some(groups) }
+}
+
+pub fn packages() -> Option<Vec<(usize, Vec<CoreId>)>> {
+    let online = get_all_core_ids()?;
+
+    read_packages(Path::new("/sys/devices/system/cpu"), &online)
+}
+
+// Groups `core_ids` by the `physical_package_id` each of them
+// reads from a sysfs tree at `root`, normally
+// `/sys/devices/system/cpu`. Returns `None` if any of them has
+// no package, as some platforms report -1.
+fn read_packages(root: &Path, core_ids: &[CoreId]) -> Option<Vec<(usize, Vec<CoreId>)>> {
+    let mut packages: BTreeMap<usize, Vec<CoreId>> = BTreeMap::new();
+
+    for &core_id in core_ids {
+        let path = root.join(format!("cpu{}/topology/physical_package_id", core_id.id));
+        let package = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+
+        packages.entry(package).or_default().push(core_id);
+    }
+
+    if packages.is_empty() { None } else { Some(packages.into_iter().collect()) }
+}
+
+pub fn dies() -> Option<Vec<Vec<CoreId>>> {
+    let online = get_all_core_ids()?;
+
+    read_dies(Path::new("/sys/devices/system/cpu"), &online)
+}
+
+// Groups `core_ids` by their package and `die_id` in a sysfs
+// tree at `root`, in that order. Returns `None` if any of them
+// does not list both.
+fn read_dies(root: &Path, core_ids: &[CoreId]) -> Option<Vec<Vec<CoreId>>> {
+    let mut dies: BTreeMap<(usize, usize), Vec<CoreId>> = BTreeMap::new();
+
+    for &core_id in core_ids {
+        let dir = root.join(format!("cpu{}/topology", core_id.id));
+        let read_id = |name: &str| fs::read_to_string(dir.join(name)).ok()?.trim().parse::<usize>().ok();
+
+        dies.entry((read_id("physical_package_id")?, read_id("die_id")?)).or_default().push(core_id);
+    }
+
+    if dies.is_empty() { None } else { Some(dies.into_values().collect()) }
+}
+
+pub fn clusters() -> Option<Vec<Vec<CoreId>>> {
+    let online = get_all_core_ids()?;
+
+    read_clusters(Path::new("/sys/devices/system/cpu"), &online)
+}
+
+// Groups `core_ids` by their package and `cluster_id` in a
+// sysfs tree at `root`. Kernels before 5.16 have no
+// `cluster_id`, but on ARM they list the cores of the cluster
+// in `core_siblings_list`, so that list is the fallback.
+fn read_clusters(root: &Path, core_ids: &[CoreId]) -> Option<Vec<Vec<CoreId>>> {
+    if core_ids.is_empty() {
+        return None;
+    }
+
+    let read = |core_id: CoreId, name: &str| {
+        fs::read_to_string(root.join(format!("cpu{}/topology/{}", core_id.id, name))).ok()
+    };
+    // Cores outside any cluster report -1.
+    let read_id = |core_id: CoreId, name: &str| read(core_id, name)?.trim().parse::<usize>().ok();
+
+    let by_id = core_ids.iter()
+        .map(|&core_id| Some(((read_id(core_id, "physical_package_id")?, read_id(core_id, "cluster_id")?), core_id)))
+        .collect::<Option<Vec<_>>>();
+
+    if let Some(by_id) = by_id {
+        let mut clusters: BTreeMap<(usize, usize), Vec<CoreId>> = BTreeMap::new();
+
+        for (key, core_id) in by_id {
+            clusters.entry(key).or_default().push(core_id);
+        }
+
+        return Some(clusters.into_values().collect());
+    }
+
+    let mut siblings = core_ids.iter()
+        .map(|&core_id| {
+            let list = parse_cpulist(&read(core_id, "core_siblings_list")?).ok()?;
+            Some(list.into_iter().filter(|core_id| core_ids.contains(core_id)).collect::<Vec<_>>())
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    siblings.sort();
+    siblings.dedup();
+
+    Some(siblings)
+}
+
+pub fn stable_core_ids() -> Option<Vec<(CoreId, StableCoreId)>> {
+    let root = Path::new("/sys/devices/system/cpu");
+    let online = get_all_core_ids()?;
+    let present = read_core_state(root, CoreState::Present).unwrap_or_default();
+
+    read_stable_core_ids(root, &present, &online)
+}
+
+// Reads the `physical_package_id`, `die_id` and `core_id` of
+// the `present` and `online` cores from a sysfs tree at `root`
+// and numbers the threads of each physical core among all of
+// them, returning the online ones. Kernels before 5.2 have no
+// `die_id`, which then counts as die 0. Returns `None` if an
+// online core lacks a package or core ID.
+fn read_stable_core_ids(root: &Path, present: &[CoreId], online: &[CoreId]) -> Option<Vec<(CoreId, StableCoreId)>> {
+    if online.is_empty() {
+        return None;
+    }
+
+    let read = |core_id: CoreId, name: &str| {
+        fs::read_to_string(root.join(format!("cpu{}/topology/{}", core_id.id, name))).ok()
+    };
+    let read_id = |core_id: CoreId, name: &str| read(core_id, name)?.trim().parse::<usize>().ok();
+
+    let mut all = present.to_vec();
+    all.extend(online);
+    all.sort();
+    all.dedup();
+
+    let mut ids = Vec::new();
+    let mut unplaced = Vec::new();
+
+    for core_id in all {
+        match (read_id(core_id, "physical_package_id"), read_id(core_id, "core_id")) {
+            (Some(package), Some(core)) => ids.push((core_id, (package, read_id(core_id, "die_id").unwrap_or(0), core))),
+            _ if online.contains(&core_id) => return None,
+            _ => unplaced.push(core_id),
+        }
+    }
+
+    // The kernel removes the topology of offline cores, but may
+    // still list them as siblings of the online ones.
+    let mut placed = Vec::new();
+
+    for &(core_id, id) in &ids {
+        if let Some(siblings) = read(core_id, "thread_siblings_list").and_then(|list| parse_cpulist(&list).ok()) {
+            unplaced.retain(|&sibling| {
+                let listed = siblings.contains(&sibling);
+
+                if listed {
+                    placed.push((sibling, id));
+                }
+
+                !listed
+            });
+        }
+    }
+
+    ids.extend(placed);
+
+    let numbered = number_threads(ids);
+    let mut widths: BTreeMap<(usize, usize, usize), usize> = BTreeMap::new();
+
+    for (_, id) in &numbered {
+        *widths.entry((id.package, id.die, id.core)).or_default() += 1;
+    }
+
+    // A core that is still unplaced may be missing from any
+    // physical core with fewer threads than the widest, so the
+    // threads of those are left out rather than misnumbered.
+    let widest = widths.values().cloned().max().unwrap_or(0);
+
+    Some(numbered.into_iter().filter(|&(core_id, id)| {
+        online.contains(&core_id) && (unplaced.is_empty() || widths[&(id.package, id.die, id.core)] == widest)
+    }).collect())
+}
+
+pub fn cache_groups(level: usize) -> Option<Vec<Vec<CoreId>>> {
+    let online = get_all_core_ids()?;
+
+    read_cache_groups(Path::new("/sys/devices/system/cpu"), &online, level)
+}
+
+// Reads which of `core_ids` share their data or unified cache
+// of `level` from the `cache/indexN` directories of a sysfs
+// tree at `root`, leaving out cores that are not among them.
+// Returns `None` if none of them has such a cache.
+fn read_cache_groups(root: &Path, core_ids: &[CoreId], level: usize) -> Option<Vec<Vec<CoreId>>> {
+    let mut groups = Vec::new();
+
+    for &core_id in core_ids {
+        let entries = match fs::read_dir(root.join(format!("cpu{}/cache", core_id.id))) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        let shared = entries.filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_str().is_some_and(|name| name.starts_with("index")))
+            .find_map(|entry| {
+                let read = |name: &str| fs::read_to_string(entry.path().join(name)).ok();
+
+                if read("level")?.trim().parse::<usize>().ok()? != level || read("type")?.trim() == "Instruction" {
+                    return None;
+                }
+
+                parse_cpulist(&read("shared_cpu_list")?).ok()
+            })
+            .map(|shared| shared.into_iter().filter(|core_id| core_ids.contains(core_id)).collect());
+
+        groups.extend(shared);
+    }
+
+    if groups.is_empty() { None } else { Some(groups) }
+}
+
+pub fn cache_info(core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    read_cache_info(Path::new("/sys/devices/system/cpu"), core_id)
+}
+
+// Reads the caches of `core_id` from the `cache/indexN`
+// directories of a sysfs tree at `root`.
+fn read_cache_info(root: &Path, core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    let entries = fs::read_dir(root.join(format!("cpu{}/cache", core_id.id))).ok()?;
+
+    let caches: Vec<_> = entries.filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_str().is_some_and(|name| name.starts_with("index")))
+        .filter_map(|entry| {
+            let read = |name: &str| fs::read_to_string(entry.path().join(name)).ok();
+            // Some platforms report 0 for what they do not know.
+            let read_number = |name: &str| read(name)?.trim().parse::<usize>().ok().filter(|&n| n > 0);
+
+            let cache_type = match read("type")?.trim() {
+                "Unified" => CacheType::Unified,
+                "Instruction" => CacheType::Instruction,
+                "Data" => CacheType::Data,
+                _ => return None,
+            };
+
+            Some(CacheLevel {
+                level: read_number("level")?,
+                cache_type,
+                size: read("size").and_then(|size| parse_cache_size(size.trim())),
+                line_size: read_number("coherency_line_size"),
+                associativity: read_number("ways_of_associativity"),
+            })
+        })
+        .collect();
+
+    if caches.is_empty() { None } else { Some(caches) }
+}
+
+// Parses a size such as `32K`.
+fn parse_cache_size(size: &str) -> Option<usize> {
+    let (number, unit) = match size.char_indices().find(|&(_, c)| !c.is_ascii_digit()) {
+        Some((index, _)) => size.split_at(index),
+        None => (size, ""),
+    };
+
+    let unit = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return None,
+    };
+
+    number.parse::<usize>().ok().filter(|&n| n > 0)?.checked_mul(unit)
+}
+
+pub fn core_kinds() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    let online = get_all_core_ids()?;
+
+    read_core_kinds(Path::new("/sys/devices"), &online)
+}
+
+// Reads the kinds of `core_ids` from a sysfs tree at `root`,
+// normally `/sys/devices`. Intel hybrid CPUs have a PMU for
+// each kind that lists its cores. ARM kernels instead give
+// every core a `cpu_capacity`, which the scheduler goes by.
+// Otherwise the kinds are guessed from the highest frequency
+// of every core in `cpufreq`.
+fn read_core_kinds(root: &Path, core_ids: &[CoreId]) -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    let read_list = |name: &str| parse_cpulist(&fs::read_to_string(root.join(name)).ok()?).ok();
+
+    if let Some(performance) = read_list("cpu_core/cpus") {
+        let efficiency = read_list("cpu_atom/cpus").unwrap_or_default();
+
+        let kinds = core_ids.iter().map(|&core_id| {
+            let kind = if performance.contains(&core_id) {
+                CoreKind::Performance
+            }
+            else if efficiency.contains(&core_id) {
+                CoreKind::Efficiency
+            }
+            else {
+                CoreKind::Unknown
+            };
+
+            (core_id, kind)
+        }).collect();
+
+        return Some((kinds, KindSource::Reported));
+    }
+
+    if core_ids.is_empty() {
+        return None;
+    }
+
+    // Every core needs a number for the kinds to mean anything.
+    let cpu = root.join("system/cpu");
+    let capacities = core_ids.iter()
+        .map(|&core_id| read_capacity(&cpu, core_id).map(|capacity| (core_id, capacity as u64)))
+        .collect::<Option<Vec<_>>>();
+
+    if let Some(capacities) = capacities {
+        return Some((classify(&capacities), KindSource::Reported));
+    }
+
+    let frequencies = core_ids.iter()
+        .map(|&core_id| read_cpufreq(&cpu, core_id, "cpuinfo_max_freq").map(|khz| (core_id, khz)))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((classify_frequencies(&frequencies), KindSource::Heuristic))
+}
+
+pub fn capacity(core_id: CoreId) -> Option<u32> {
+    read_capacity(Path::new("/sys/devices/system/cpu"), core_id)
+}
+
+// Reads the `cpu_capacity` of `core_id` from a sysfs tree at
+// `root`, normally `/sys/devices/system/cpu`.
+fn read_capacity(root: &Path, core_id: CoreId) -> Option<u32> {
+    let path = root.join(format!("cpu{}/cpu_capacity", core_id.id));
+
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn max_frequency_khz(core_id: CoreId) -> Option<u64> {
+    read_cpufreq(Path::new("/sys/devices/system/cpu"), core_id, "cpuinfo_max_freq")
+}
+
+pub fn current_frequency_khz(core_id: CoreId) -> Option<u64> {
+    read_cpufreq(Path::new("/sys/devices/system/cpu"), core_id, "scaling_cur_freq")
+}
+
+// Reads a number in kHz from the `cpufreq` directory of
+// `core_id` in a sysfs tree at `root`.
+fn read_cpufreq(root: &Path, core_id: CoreId, name: &str) -> Option<u64> {
+    let path = root.join(format!("cpu{}/cpufreq/{}", core_id.id, name));
+
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+pub fn cpu_times() -> Option<Vec<(CoreId, CpuTimes)>> {
+    parse_proc_stat(&fs::read_to_string("/proc/stat").ok()?)
+}
+
+// Reads the times of every online core from the `cpuN` lines
+// of `/proc/stat`: user, nice, system, idle, iowait, irq,
+// softirq and steal, in clock ticks. Older kernels have fewer
+// columns. Time stolen by the hypervisor counts as neither
+// busy nor idle.
+fn parse_proc_stat(stat: &str) -> Option<Vec<(CoreId, CpuTimes)>> {
+    let times: Vec<_> = stat.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let id = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            let values: Vec<u64> = fields.map(|field| field.parse().ok()).collect::<Option<_>>()?;
+            let value = |index: usize| values.get(index).cloned().unwrap_or(0);
+
+            if values.len() < 4 {
+                return None;
+            }
+
+            let times = CpuTimes {
+                user: value(0) + value(1),
+                system: value(2) + value(5) + value(6),
+                idle: value(3) + value(4),
+            };
+
+            Some((CoreId { id }, times))
+        })
+        .collect();
+
+    if times.is_empty() { None } else { Some(times) }
+}
+
+pub fn core_rankings() -> Option<Vec<(CoreId, u64)>> {
+    let online = get_all_core_ids()?;
+
+    read_core_rankings(Path::new("/sys/devices/system/cpu"), &online)
+}
+
+// Reads how much the platform prefers each of `core_ids` from
+// a sysfs tree at `root`. `amd_pstate` ranks the preferred
+// cores of AMD CPUs, newer kernels directly. Intel CPUs with
+// Turbo Boost Max 3.0 give their favored cores a higher
+// `highest_perf` in ACPI CPPC, which the ITMT scheduler goes
+// by, and under `intel_pstate` also a higher
+// `cpuinfo_max_freq`. Every core must have the same source.
+fn read_core_rankings(root: &Path, core_ids: &[CoreId]) -> Option<Vec<(CoreId, u64)>> {
+    let read_all = |name: &str| -> Option<Vec<(CoreId, u64)>> {
+        core_ids.iter().map(|&core_id| {
+            let path = root.join(format!("cpu{}/{}", core_id.id, name));
+            Some((core_id, fs::read_to_string(path).ok()?.trim().parse().ok()?))
+        }).collect()
+    };
+
+    let intel_pstate = || {
+        let driver = core_ids.first().and_then(|core_id| read_cpufreq_string(root, *core_id, "scaling_driver"));
+
+        if driver.as_deref() == Some("intel_pstate") { read_all("cpufreq/cpuinfo_max_freq") } else { None }
+    };
+
+    read_all("cpufreq/amd_pstate_prefcore_ranking")
+        .or_else(|| read_all("cpufreq/amd_pstate_highest_perf"))
+        .or_else(|| read_all("acpi_cppc/highest_perf"))
+        .or_else(intel_pstate)
+        .filter(|rankings| !rankings.is_empty())
+}
+
+fn read_cpufreq_string(root: &Path, core_id: CoreId, name: &str) -> Option<String> {
+    let path = root.join(format!("cpu{}/cpufreq/{}", core_id.id, name));
+
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+pub fn numa_nodes() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    let online = get_all_core_ids()?;
+
+    match read_numa_nodes(Path::new("/sys/devices/system/node")) {
+        // Some architectures list the offline cores of a node
+        // too.
+        Some(mut nodes) => {
+            for (_, core_ids) in &mut nodes {
+                core_ids.retain(|core_id| online.contains(core_id));
+            }
+
+            Some(nodes)
+        },
+        // Kernels built without NUMA support list no nodes, but
+        // the machine is still a single node.
+        None => Some(vec![(NumaNode { id: 0 }, online)]),
+    }
+}
+
+// Reads the `nodeN` directories of a sysfs tree at `root`,
+// normally `/sys/devices/system/node`, with the cores each of
+// them lists.
+fn read_numa_nodes(root: &Path) -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    let nodes: Vec<_> = fs::read_dir(root).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let id = entry.file_name().to_str()?.strip_prefix("node")?.parse().ok()?;
+            // Nodes with memory but no cores have an empty list.
+            let list = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+
+            Some((NumaNode { id }, parse_cpulist(&list).ok()?))
+        })
+        .collect();
+
+    if nodes.is_empty() { None } else { Some(nodes) }
+}
+
+fn set_affinity_mask(set: &cpu_set_t) -> Result<(), Error> {
+    // Set the current thread's core affinity.
+    set_affinity_mask_for(0, set) // Defaults to current thread
+}
+
+fn set_affinity_mask_for(tid: pid_t, set: &cpu_set_t) -> Result<(), Error> {
+    let res = unsafe {
+        sched_setaffinity(tid,
+                          mem::size_of::<cpu_set_t>(),
+                          set)
+    };
+
+    if res == 0 {
+        Ok(())
+    }
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+fn get_affinity_mask() -> Result<cpu_set_t, Error> {
+    // Try to get current core affinity mask.
+    get_affinity_mask_for(0) // Defaults to current thread
+}
+
+fn get_affinity_mask_for(tid: pid_t) -> Result<cpu_set_t, Error> {
+    let mut set = new_cpu_set();
+
+    let result = unsafe {
+        sched_getaffinity(tid,
+                          mem::size_of::<cpu_set_t>(),
+                          &mut set)
+    };
+
+    if result == 0 {
+        Ok(set)
+    }
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+fn new_cpu_set() -> cpu_set_t {
+    unsafe { mem::zeroed::<cpu_set_t>() }
+}
+
+// Turn `core_ids` into a `libc::cpu_set_t` with
+// every listed core active.
+fn to_cpu_set(core_ids: &[CoreId]) -> Result<cpu_set_t, Error> {
+    let mut set = new_cpu_set();
+
+    for &core_id in core_ids {
+        if core_id.id >= CPU_SETSIZE as usize {
+            return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+        }
+
+        unsafe { CPU_SET(core_id.id, &mut set) };
+    }
+
+    Ok(set)
+}
+
+// Collect the cores active in `set`, in ascending order.
+fn to_core_id_iter(set: &cpu_set_t) -> CoreIds {
+    let mut core_ids = CoreIds::new();
+
+    for id in (0..CPU_SETSIZE as usize).filter(|&i| unsafe { CPU_ISSET(i, set) }) {
+        core_ids.insert(id);
+    }
+
+    core_ids
+}
+
+fn to_core_ids(set: &cpu_set_t) -> Vec<CoreId> {
+    (0..CPU_SETSIZE as usize)
+        .filter(|&i| unsafe { CPU_ISSET(i, set) })
+        .map(|id| CoreId { id })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use num_cpus;
+
+    use super::*;
+    use super::super::{get_core_ids, testing};
+    use test_util::SysfsFixture;
+
+    #[test]
+    fn test_linux_get_all_core_ids() {
+        match get_all_core_ids() {
+            Some(set) => {
+                assert!(set.len() >= num_cpus::get());
+            },
+            None => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_linux_read_siblings() {
+        let root = SysfsFixture::new("siblings");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        root.file("cpu0/topology/thread_siblings_list", "0,2");
+        root.file("cpu2/topology/thread_siblings_list", "0,2");
+
+        // Without the lists, the IDs still tell the siblings.
+        for &id in &[1, 3] {
+            root.file(format!("cpu{}/topology/physical_package_id", id), 0);
+            root.file(format!("cpu{}/topology/core_id", id), 1);
+        }
+
+        let groups = read_siblings(&root, &ids(&[0, 1, 2, 3])).unwrap();
+        assert_eq!(groups, vec![ids(&[0, 2]), ids(&[0, 2]), ids(&[1, 3])]);
+
+        // With core 2 offline, core 0 has no sibling left.
+        let groups = read_siblings(&root, &ids(&[0, 1, 3])).unwrap();
+        assert_eq!(groups, vec![ids(&[0]), ids(&[1, 3])]);
+
+        assert_eq!(read_siblings(&root, &ids(&[7])), None);
+    }
+
+    #[test]
+    fn test_linux_read_packages() {
+        let root = SysfsFixture::new("packages");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        for &(id, package) in &[(0, 1), (1, 3), (2, 1), (3, -1)] {
+            root.file(format!("cpu{}/topology/physical_package_id", id), package);
+        }
+
+        let packages = read_packages(&root, &ids(&[0, 1, 2])).unwrap();
+        assert_eq!(packages, vec![(1, ids(&[0, 2])), (3, ids(&[1]))]);
+
+        // An unknown package leaves them all unknown.
+        assert_eq!(read_packages(&root, &ids(&[0, 3])), None);
+        assert_eq!(read_packages(&root, &ids(&[7])), None);
+    }
+
+    #[test]
+    fn test_linux_read_dies() {
+        let root = SysfsFixture::new("dies");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        // One package of 2 dies, whose cores alternate, and a
+        // second package with a single die.
+        for &(id, package, die) in &[(0, 0, 0), (1, 0, 1), (2, 0, 0), (3, 0, 1), (4, 1, 0)] {
+            root.file(format!("cpu{}/topology/physical_package_id", id), package);
+            root.file(format!("cpu{}/topology/die_id", id), die);
+        }
+
+        // Kernels before 5.2 have no `die_id`.
+        root.file("cpu5/topology/physical_package_id", 1);
+
+        let dies = read_dies(&root, &ids(&[0, 1, 2, 3, 4])).unwrap();
+        assert_eq!(dies, vec![ids(&[0, 2]), ids(&[1, 3]), ids(&[4])]);
+
+        assert_eq!(read_dies(&root, &ids(&[4, 5])), None);
+    }
+
+    // Writes the `topology` files of a captured sysfs tree to
+    // `root`: one line of every core, with the file names first.
+    fn write_topology(root: &SysfsFixture, names: &[&str], cores: &[&[&str]]) {
+        for (id, values) in cores.iter().enumerate() {
+            for (name, value) in names.iter().zip(values.iter()) {
+                root.file(format!("cpu{}/topology/{}", id, name), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_linux_read_clusters_phone() {
+        // A Snapdragon 888 on a 5.4 kernel: 4 little, 3 big and
+        // 1 prime core, and no `cluster_id` yet.
+        let root = SysfsFixture::new("clusters-phone");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        let names = ["physical_package_id", "core_id", "core_siblings_list"];
+
+        write_topology(&root, &names, &[
+            &["0", "0", "0-3"], &["0", "1", "0-3"], &["0", "2", "0-3"], &["0", "3", "0-3"],
+            &["1", "0", "4-6"], &["1", "1", "4-6"], &["1", "2", "4-6"],
+            &["2", "0", "7"],
+        ]);
+
+        let clusters = read_clusters(&root, &ids(&[0, 1, 2, 3, 4, 5, 6, 7])).unwrap();
+        assert_eq!(clusters, vec![ids(&[0, 1, 2, 3]), ids(&[4, 5, 6]), ids(&[7])]);
+    }
+
+    #[test]
+    fn test_linux_read_clusters_server() {
+        // The first 8 of the 80 cores of an Ampere Altra on a 6.1
+        // kernel, with the cluster in `cluster_id` and the whole
+        // package in `core_siblings_list`.
+        let root = SysfsFixture::new("clusters-server");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        let names = ["physical_package_id", "cluster_id", "core_id", "core_siblings_list", "cluster_cpus_list"];
+
+        write_topology(&root, &names, &[
+            &["0", "0", "0", "0-79", "0-1"], &["0", "0", "1", "0-79", "0-1"],
+            &["0", "256", "2", "0-79", "2-3"], &["0", "256", "3", "0-79", "2-3"],
+            &["0", "512", "4", "0-79", "4-5"], &["0", "512", "5", "0-79", "4-5"],
+            &["0", "768", "6", "0-79", "6-7"], &["0", "768", "7", "0-79", "6-7"],
+        ]);
+
+        let clusters = read_clusters(&root, &ids(&[0, 1, 2, 3, 4, 5, 6, 7])).unwrap();
+        assert_eq!(clusters, vec![ids(&[0, 1]), ids(&[2, 3]), ids(&[4, 5]), ids(&[6, 7])]);
+
+        // Without a cluster, the package is all there is.
+        root.file("cpu0/topology/cluster_id", -1);
+        assert_eq!(read_clusters(&root, &ids(&[0, 1, 2])).unwrap(), vec![ids(&[0, 1, 2])]);
+
+        assert_eq!(read_clusters(&root, &ids(&[80])), None);
+    }
+
+    // Writes the caches of a captured sysfs tree to `root`: the
+    // level, the type and the `shared_cpu_list` of every cache
+    // of every core.
+    fn write_caches(root: &SysfsFixture, cores: &[Vec<(usize, &str, String)>]) {
+        for (id, caches) in cores.iter().enumerate() {
+            for (index, &(level, kind, ref shared)) in caches.iter().enumerate() {
+                let dir = format!("cpu{}/cache/index{}", id, index);
+                root.file(format!("{}/level", dir), level);
+                root.file(format!("{}/type", dir), kind);
+                root.file(format!("{}/shared_cpu_list", dir), shared);
+            }
+        }
+    }
+
+    // The caches of a Zen 3 part with `ccds` CCDs of 8 cores,
+    // each with 2 threads numbered like Linux does, core by
+    // core for the first threads and then for the second.
+    fn zen3_caches(ccds: usize) -> Vec<Vec<(usize, &'static str, String)>> {
+        let cores = ccds * 8;
+
+        (0..cores * 2).map(|id| {
+            let core = id % cores;
+            let ccd = core / 8;
+            let siblings = format!("{},{}", core, core + cores);
+            let l3 = format!("{}-{},{}-{}", ccd * 8, ccd * 8 + 7, cores + ccd * 8, cores + ccd * 8 + 7);
+
+            vec![(1, "Data", siblings.clone()), (1, "Instruction", siblings.clone()),
+                 (2, "Unified", siblings), (3, "Unified", l3)]
+        }).collect()
+    }
+
+    #[test]
+    fn test_linux_read_cache_groups_5950x() {
+        let root = SysfsFixture::new("caches-5950x");
+        let all = (0..32).map(|id| CoreId { id }).collect::<Vec<_>>();
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        write_caches(&root, &zen3_caches(2));
+
+        let mut groups = read_cache_groups(&root, &all, 3).unwrap();
+        groups.sort();
+        groups.dedup();
+
+        assert_eq!(groups, vec![
+            ids(&[0, 1, 2, 3, 4, 5, 6, 7, 16, 17, 18, 19, 20, 21, 22, 23]),
+            ids(&[8, 9, 10, 11, 12, 13, 14, 15, 24, 25, 26, 27, 28, 29, 30, 31]),
+        ]);
+
+        // The instruction cache is left out.
+        let groups = read_cache_groups(&root, &ids(&[0, 16]), 1).unwrap();
+        assert_eq!(groups, vec![ids(&[0, 16]), ids(&[0, 16])]);
+
+        assert_eq!(read_cache_groups(&root, &all, 4), None);
+        assert_eq!(read_cache_groups(&root, &ids(&[32]), 3), None);
+    }
+
+    #[test]
+    fn test_linux_read_cache_groups_single_ccd() {
+        let root = SysfsFixture::new("caches-5800x");
+        let all = (0..16).map(|id| CoreId { id }).collect::<Vec<_>>();
+
+        write_caches(&root, &zen3_caches(1));
+
+        let mut groups = read_cache_groups(&root, &all, 3).unwrap();
+        groups.dedup();
+        assert_eq!(groups, vec![all.clone()]);
+    }
+
+    #[test]
+    fn test_linux_read_cache_info() {
+        let root = SysfsFixture::new("cache-info");
+
+        for &(index, level, kind, size, line, ways) in &[
+            (0, "1", "Data", "48K", "64", "12"),
+            (1, "1", "Instruction", "32K", "64", "8"),
+            (2, "2", "Unified", "1280K", "64", "10"),
+            // Some platforms leave out what they do not know.
+            (3, "3", "Unified", "30M", "0", ""),
+        ] {
+            for &(name, value) in &[("level", level), ("type", kind), ("size", size),
+                                    ("coherency_line_size", line), ("ways_of_associativity", ways)] {
+                root.file(format!("cpu0/cache/index{}/{}", index, name), value);
+            }
+        }
+
+        let mut caches = read_cache_info(&root, CoreId { id: 0 }).unwrap();
+        caches.sort_by_key(|cache| (cache.level, cache.cache_type));
+
+        assert_eq!(caches[0].cache_type, CacheType::Instruction);
+        assert_eq!(caches[1].size, Some(48 << 10));
+        assert_eq!(caches[1].associativity, Some(12));
+        assert_eq!(caches[2], CacheLevel {
+            level: 2,
+            cache_type: CacheType::Unified,
+            size: Some(1280 << 10),
+            line_size: Some(64),
+            associativity: Some(10),
+        });
+        assert_eq!(caches[3].size, Some(30 << 20));
+        assert_eq!(caches[3].line_size, None);
+        assert_eq!(caches[3].associativity, None);
+
+        assert_eq!(read_cache_info(&root, CoreId { id: 1 }), None);
+        assert_eq!(parse_cache_size("512"), Some(512));
+        assert_eq!(parse_cache_size("4T"), None);
+    }
+
+    #[test]
+    fn test_linux_read_core_kinds() {
+        let root = SysfsFixture::new("kinds");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        use self::CoreKind::{Efficiency, Performance};
+
+        assert_eq!(read_core_kinds(&root, &ids(&[0])), None);
+
+        // The highest frequencies of a big.LITTLE phone.
+        for &(id, freq) in &[(0, "1800000"), (1, "1800000"), (2, "2420000"), (3, "2840000")] {
+            root.file(format!("system/cpu/cpu{}/cpufreq/cpuinfo_max_freq", id), freq);
+        }
+
+        let (kinds, source) = read_core_kinds(&root, &ids(&[0, 1, 2, 3])).unwrap();
+        assert_eq!(source, KindSource::Heuristic);
+        assert_eq!(kinds, ids(&[0, 1, 2, 3]).into_iter().zip(vec![Efficiency, Efficiency, Performance, Performance]).collect::<Vec<_>>());
+
+        // The capacities win over the frequencies.
+        for &(id, capacity) in &[(0, "1024"), (1, "1024"), (2, "1024"), (3, "1024")] {
+            root.file(format!("system/cpu/cpu{}/cpu_capacity", id), capacity);
+        }
+
+        let (kinds, source) = read_core_kinds(&root, &ids(&[0, 1, 2, 3])).unwrap();
+        assert_eq!(source, KindSource::Reported);
+        assert!(kinds.iter().all(|&(_, kind)| kind == Performance));
+
+        // An Alder Lake reports the kinds, with its 8 P-cores
+        // numbered first, 2 threads each, and its 8 E-cores
+        // after them.
+        root.file("cpu_core/cpus", "0-15");
+        root.file("cpu_atom/cpus", "16-23");
+
+        let (kinds, source) = read_core_kinds(&root, &ids(&[0, 15, 16, 23, 24])).unwrap();
+        assert_eq!(source, KindSource::Reported);
+        assert_eq!(kinds, ids(&[0, 15, 16, 23, 24]).into_iter()
+                   .zip(vec![Performance, Performance, Efficiency, Efficiency, CoreKind::Unknown])
+                   .collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_linux_read_cpufreq() {
+        let root = SysfsFixture::new("cpufreq");
+
+        root.file("cpu0/cpufreq/cpuinfo_max_freq", 4950000);
+
+        assert_eq!(read_cpufreq(&root, CoreId { id: 0 }, "cpuinfo_max_freq"), Some(4_950_000));
+        assert_eq!(read_cpufreq(&root, CoreId { id: 1 }, "cpuinfo_max_freq"), None);
+    }
+
+    #[test]
+    fn test_linux_read_core_rankings_amd() {
+        // A 7950X under `amd_pstate`, whose cores 2 and 5 boost
+        // highest.
+        let root = SysfsFixture::new("rankings-amd");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        for &(id, perf) in &[(0, "166"), (1, "171"), (2, "196"), (3, "176"), (4, "181"), (5, "196")] {
+            root.file(format!("cpu{}/cpufreq/scaling_driver", id), "amd-pstate-epp");
+            root.file(format!("cpu{}/cpufreq/amd_pstate_highest_perf", id), perf);
+        }
+
+        let rankings = read_core_rankings(&root, &ids(&[0, 1, 2, 3, 4, 5])).unwrap();
+        assert_eq!(rankings[2], (CoreId { id: 2 }, 196));
+        assert_eq!(rankings[0], (CoreId { id: 0 }, 166));
+
+        // Newer kernels rank them directly.
+        for &(id, rank) in &[(0, "166"), (1, "171"), (2, "236"), (3, "176"), (4, "181"), (5, "231")] {
+            root.file(format!("cpu{}/cpufreq/amd_pstate_prefcore_ranking", id), rank);
+        }
+
+        let rankings = read_core_rankings(&root, &ids(&[2, 5])).unwrap();
+        assert_eq!(rankings, vec![(CoreId { id: 2 }, 236), (CoreId { id: 5 }, 231)]);
+    }
+
+    #[test]
+    fn test_linux_read_core_rankings_intel() {
+        // A 12900K under `intel_pstate`, with two favored P-cores.
+        let root = SysfsFixture::new("rankings-intel");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        for &(id, freq) in &[(0, "4900000"), (1, "4900000"), (2, "5200000"), (3, "5200000"), (4, "3900000")] {
+            root.file(format!("cpu{}/cpufreq/scaling_driver", id), "intel_pstate");
+            root.file(format!("cpu{}/cpufreq/cpuinfo_max_freq", id), freq);
+        }
+
+        let rankings = read_core_rankings(&root, &ids(&[0, 2, 4])).unwrap();
+        assert_eq!(rankings, vec![(CoreId { id: 0 }, 4_900_000), (CoreId { id: 2 }, 5_200_000), (CoreId { id: 4 }, 3_900_000)]);
+
+        // CPPC has the ranking ITMT uses.
+        for &(id, perf) in &[(0, "68"), (1, "68"), (2, "70"), (3, "70"), (4, "39")] {
+            root.file(format!("cpu{}/acpi_cppc/highest_perf", id), perf);
+        }
+
+        let rankings = read_core_rankings(&root, &ids(&[1, 2])).unwrap();
+        assert_eq!(rankings, vec![(CoreId { id: 1 }, 68), (CoreId { id: 2 }, 70)]);
+
+        // Other drivers do not mark the favored cores in their
+        // frequencies.
+        assert_eq!(read_core_rankings(&root, &ids(&[7])), None);
+    }
+
+    #[test]
+    fn test_linux_parse_proc_stat() {
+        let stat = "cpu  4705 356 584 3699176 23 23 0 0 0 0\n\
+                    cpu0 1393280 32966 572056 13343292 6130 0 17875 0 23933 0\n\
+                    cpu2 1335394 33357 585377 13407210 6025 0 11180 12 0 0\n\
+                    cpu3 100 0 50 1000\n\
+                    intr 114930548 113199788 3 0 5 263 0 4 [...]\n\
+                    ctxt 1990473\n";
+
+        let times = parse_proc_stat(stat).unwrap();
+
+        // The total of all cores is left out, and so are cores
+        // that are offline.
+        assert_eq!(times, vec![
+            (CoreId { id: 0 }, CpuTimes { user: 1_426_246, system: 589_931, idle: 13_349_422 }),
+            (CoreId { id: 2 }, CpuTimes { user: 1_368_751, system: 596_557, idle: 13_413_235 }),
+            (CoreId { id: 3 }, CpuTimes { user: 100, system: 50, idle: 1000 }),
+        ]);
+
+        assert_eq!(parse_proc_stat("intr 1 2 3\n"), None);
+    }
+
+    #[test]
+    fn test_linux_read_capacity() {
+        // A Snapdragon 8 Gen 1, with 4 little cores, 3 big ones
+        // and a prime core.
+        let root = SysfsFixture::new("capacity");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        let capacities = ["381", "381", "381", "381", "903", "903", "903", "1024"];
+
+        for (id, capacity) in capacities.iter().enumerate() {
+            root.file(format!("system/cpu/cpu{}/cpu_capacity", id), capacity);
+        }
+
+        let cpu = root.join("system/cpu");
+        assert_eq!(read_capacity(&cpu, CoreId { id: 0 }), Some(381));
+        assert_eq!(read_capacity(&cpu, CoreId { id: 7 }), Some(1024));
+        assert_eq!(read_capacity(&cpu, CoreId { id: 8 }), None);
+
+        let (kinds, source) = read_core_kinds(&root, &ids(&[0, 1, 2, 3, 4, 5, 6, 7])).unwrap();
+        let efficiency: Vec<_> = kinds.iter().filter(|&&(_, kind)| kind == CoreKind::Efficiency).map(|&(core_id, _)| core_id).collect();
+
+        assert_eq!(source, KindSource::Reported);
+        assert_eq!(efficiency, ids(&[0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_linux_read_numa_nodes() {
+        let root = SysfsFixture::new("numa");
+
+        for &(node, list) in &[(0, "0-3,8-11"), (1, "4-7,12-15"), (2, "")] {
+            root.file(format!("node{}/cpulist", node), list);
+        }
+
+        root.dir("power");
+        root.file("possible", "0-2");
+
+        let mut nodes = read_numa_nodes(&root).unwrap();
+        nodes.sort();
+
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        assert_eq!(nodes, vec![
+            (NumaNode { id: 0 }, ids(&[0, 1, 2, 3, 8, 9, 10, 11])),
+            (NumaNode { id: 1 }, ids(&[4, 5, 6, 7, 12, 13, 14, 15])),
+            (NumaNode { id: 2 }, vec![]),
+        ]);
+
+        assert_eq!(read_numa_nodes(&root.join("missing")), None);
+    }
+
+    #[test]
+    fn test_linux_read_stable_core_ids() {
+        let root = SysfsFixture::new("stable-ids");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        let stable = |package, die, core| StableCoreId { package, die, core, thread: 0 };
+
+        // Core IDs need not be consecutive, and repeat on every
+        // package.
+        for &(id, package, die, core) in &[(0, "0", Some("0"), "0"), (1, "0", Some("1"), "4"), (2, "1", None, "0"),
+                                           (3, "-1", None, "0")] {
+            root.file(format!("cpu{}/topology/physical_package_id", id), package);
+            root.file(format!("cpu{}/topology/core_id", id), core);
+
+            if let Some(die) = die {
+                root.file(format!("cpu{}/topology/die_id", id), die);
+            }
+        }
+
+        assert_eq!(read_stable_core_ids(&root, &[], &ids(&[0, 1, 2])), Some(vec![
+            (CoreId { id: 0 }, stable(0, 0, 0)),
+            (CoreId { id: 1 }, stable(0, 1, 4)),
+            (CoreId { id: 2 }, stable(1, 0, 0)),
+        ]));
+
+        // A package of -1 is not known.
+        assert_eq!(read_stable_core_ids(&root, &[], &ids(&[0, 3])), None);
+        assert_eq!(read_stable_core_ids(&root, &[], &ids(&[7])), None);
+        assert_eq!(read_stable_core_ids(&root, &[], &[]), None);
+    }
+
+    #[test]
+    fn test_linux_read_stable_core_ids_offline_sibling() {
+        let root = SysfsFixture::new("stable-offline");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        let thread = |core, thread| StableCoreId { package: 0, die: 0, core, thread };
+
+        // Two threads per core, numbered apart, with the first
+        // thread of core 0 offline.
+        for &(id, core, siblings) in &[(0, "0", "0,2"), (1, "1", "1,3"), (2, "0", "0,2"), (3, "1", "1,3")] {
+            root.file(format!("cpu{}/topology/physical_package_id", id), 0);
+            root.file(format!("cpu{}/topology/core_id", id), core);
+            root.file(format!("cpu{}/topology/thread_siblings_list", id), siblings);
+        }
+
+        let present = ids(&[0, 1, 2, 3]);
+        let online = ids(&[1, 2, 3]);
+        let expected = Some(vec![
+            (CoreId { id: 1 }, thread(1, 0)),
+            (CoreId { id: 2 }, thread(0, 1)),
+            (CoreId { id: 3 }, thread(1, 1)),
+        ]);
+
+        // Core 2 stays the second thread, whether the offline
+        // core keeps its topology or is only listed as a sibling.
+        assert_eq!(read_stable_core_ids(&root, &present, &online), expected);
+
+        root.remove("cpu0/topology");
+        assert_eq!(read_stable_core_ids(&root, &present, &online), expected);
+
+        // Without either, core 2 could be either thread.
+        root.file("cpu2/topology/thread_siblings_list", 2);
+        assert_eq!(read_stable_core_ids(&root, &present, &online), Some(vec![
+            (CoreId { id: 1 }, thread(1, 0)),
+            (CoreId { id: 3 }, thread(1, 1)),
+        ]));
+    }
+
+    #[test]
+    fn test_linux_read_core_state() {
+        let root = SysfsFixture::new("core-state");
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        // A virtual machine with room for 16 cores, 8 of them
+        // plugged in and core 6 taken offline.
+        for &(name, list) in &[("online", "0-5,7"), ("offline", "6,8-15"), ("present", "0-7"), ("possible", "0-15")] {
+            root.file(name, list);
+        }
+
+        assert_eq!(read_core_state(&root, CoreState::Online), Some(ids(&[0, 1, 2, 3, 4, 5, 7])));
+        assert_eq!(read_core_state(&root, CoreState::Offline), Some(ids(&[6, 8, 9, 10, 11, 12, 13, 14, 15])));
+        assert_eq!(read_core_state(&root, CoreState::Present), Some(ids(&[0, 1, 2, 3, 4, 5, 6, 7])));
+        assert_eq!(read_core_state(&root, CoreState::Possible), Some((0..16).map(|id| CoreId { id }).collect()));
+
+        // Nothing offline is an empty list.
+        root.file("offline", "");
+        assert_eq!(read_core_state(&root, CoreState::Offline), Some(vec![]));
+
+        assert_eq!(read_core_state(&root.join("missing"), CoreState::Online), None);
+    }
+
+    #[test]
+    fn test_linux_get_all_core_ids_when_pinned() {
+        testing::check_get_all_core_ids_when_pinned();
+    }
+
+    #[test]
+    fn test_linux_set_for_current_set() {
+        testing::check_set_for_current_set();
+    }
+
+    #[test]
+    fn test_linux_set_for_current_set_out_of_range() {
+        let id = CoreId { id: CPU_SETSIZE as usize };
+        let err = set_for_current_set(&[id]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.core_id(), Some(id));
+    }
+
+    #[test]
+    fn test_linux_set_for_current_invalid_core() {
+        // Out of range for `cpu_set_t`.
+        let id = CoreId { id: usize::MAX };
+        let err = set_for_current(id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.core_id(), Some(id));
+
+        // Representable, but not a core of this system.
+        let id = CoreId { id: CPU_SETSIZE as usize - 1 };
+
+        if !get_all_core_ids().unwrap().contains(&id) {
+            let err = set_for_current(id).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidCore);
+            assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        }
+    }
+
+    #[test]
+    fn test_linux_set_for_thread() {
+        testing::check_set_for_thread();
+    }
+
+    #[test]
+    fn test_linux_set_for_process() {
+        use std::process::Command;
+
+        let id = *get_core_ids().unwrap().last().unwrap();
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+
+        let res = set_for_process(child.id(), &[id]);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_linux_set_for_process_not_found() {
+        let id = get_core_ids().unwrap()[0];
+
+        // Larger than the biggest `pid_max` the kernel allows.
+        let err = set_for_process(0x7fff_fff0, &[id]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_linux_get_for_process() {
+        // No other test changes the mask of the main thread.
+        let all = get_for_process(std::process::id()).unwrap();
+        assert!(!all.is_empty());
+
+        let err = get_for_process(0x7fff_fff0).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_linux_clear_for_current() {
+        testing::check_clear_for_current();
+    }
+
+    #[test]
+    fn test_linux_get_affinity_mask() {
+        match get_affinity_mask() {
+            Ok(_) => {},
+            Err(_) => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_linux_core_ids() {
+        match core_ids() {
+            Ok(core_ids) => {
+                assert_eq!(core_ids.len(), num_cpus::get());
+            },
+            Err(_) => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_linux_set_for_current() {
+        testing::check_set_for_current();
+    }
+ }
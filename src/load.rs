@@ -0,0 +1,377 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use super::{CoreId, CpuSet, Error, ErrorKind, allowed_for_process, cpu_times_helper, record};
+
+/// The time a core has spent running user code, running the
+/// kernel and idling, as counted by the platform since boot.
+///
+/// The unit depends on the platform, clock ticks or intervals
+/// of 100 ns, so only the differences between two samples of
+/// the same core mean anything.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CpuTimes {
+    /// User code, including niced processes.
+    pub user: u64,
+    /// The kernel, including interrupts.
+    pub system: u64,
+    /// Idling, including waiting for I/O.
+    pub idle: u64,
+}
+
+impl CpuTimes {
+    // The time since `earlier`, allowing for counters that
+    // wrapped around in between.
+    fn since(&self, earlier: &CpuTimes) -> CpuTimes {
+        CpuTimes {
+            user: elapsed(earlier.user, self.user),
+            system: elapsed(earlier.system, self.system),
+            idle: elapsed(earlier.idle, self.idle),
+        }
+    }
+
+    fn busy(&self) -> u64 {
+        self.user.saturating_add(self.system)
+    }
+
+    fn total(&self) -> u64 {
+        self.busy().saturating_add(self.idle)
+    }
+}
+
+/// How busy a core was between two readings of its times, as
+/// returned by `utilization_snapshot`.
+///
+/// The percentages add up to 100, and are all but `idle` zero
+/// for a core that did not count any time in between. The
+/// times it was read at stay with it, so that a later snapshot
+/// can be compared to it with `since`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CoreLoad {
+    pub core_id: CoreId,
+    /// Whether the process may run on the core.
+    pub allowed: bool,
+    /// The share of the time spent running user code, in percent.
+    pub user: f64,
+    /// The share of the time spent running the kernel, in percent.
+    pub system: f64,
+    /// The share of the time spent idling, in percent.
+    pub idle: f64,
+    times: CpuTimes,
+}
+
+impl CoreLoad {
+    fn new(core_id: CoreId, allowed: bool, times: CpuTimes, earlier: &CpuTimes) -> CoreLoad {
+        let delta = times.since(earlier);
+        let share = |time: u64| match delta.total() {
+            0 => 0.0,
+            total => time as f64 * 100.0 / total as f64,
+        };
+
+        CoreLoad {
+            core_id,
+            allowed,
+            user: share(delta.user),
+            system: share(delta.system),
+            idle: if delta.total() == 0 { 100.0 } else { share(delta.idle) },
+            times,
+        }
+    }
+
+    /// Returns the times of the core this load was read at.
+    pub fn times(&self) -> CpuTimes {
+        self.times
+    }
+
+    /// Returns how busy the core was between `earlier` and this
+    /// snapshot, such as for comparing two snapshots taken in
+    /// different places. `earlier` should be of the same core.
+    ///
+    /// # Arguments
+    ///
+    /// * earlier - the load the core had before
+    pub fn since(&self, earlier: &CoreLoad) -> CoreLoad {
+        CoreLoad::new(self.core_id, self.allowed, self.times, &earlier.times)
+    }
+}
+
+/// Where `least_loaded_cores_with` reads the times of the
+/// cores, so that tests can feed it counters of their own.
+pub trait LoadSampler {
+    /// Returns the times of every online core, in any order.
+    fn sample(&mut self) -> Result<Vec<(CoreId, CpuTimes)>, Error>;
+}
+
+/// The sampler `least_loaded_cores` uses: the counters of the
+/// platform, or of the mock when the `mock` feature is enabled.
+///
+/// Linux reads `/proc/stat`, Windows asks
+/// `NtQuerySystemInformation` for the processors of the group
+/// of the calling thread, FreeBSD reads `kern.cp_times` and
+/// macOS asks `host_processor_info`. Other platforms fail with
+/// `ErrorKind::Unsupported`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PlatformSampler;
+
+impl LoadSampler for PlatformSampler {
+    fn sample(&mut self) -> Result<Vec<(CoreId, CpuTimes)>, Error> {
+        cpu_times_helper().ok_or_else(|| Error::new(ErrorKind::Unsupported))
+    }
+}
+
+/// This function returns up to `n` of the cores the process
+/// may run on that were least busy over the next `window`,
+/// from the idlest, such as for placing an occasional heavy
+/// worker.
+///
+/// The times of the cores are read at the start and at the end
+/// of `window`, which the calling thread sleeps through, so
+/// the measurement neither pins nor loads any core. Cores
+/// equally busy stay in ID order. It fails with
+/// `ErrorKind::Unsupported` where the times can not be read,
+/// and with `ErrorKind::NotAllowed` if none of the cores that
+/// were sampled is allowed.
+///
+/// # Arguments
+///
+/// * n - the most cores to return
+/// * window - how long to measure for
+pub fn least_loaded_cores(n: usize, window: Duration) -> Result<Vec<CoreId>, Error> {
+    least_loaded_cores_with(&mut PlatformSampler, n, window)
+}
+
+/// This function is `least_loaded_cores`, but reads the times
+/// of the cores from `sampler`.
+///
+/// # Arguments
+///
+/// * sampler - where to read the times of the cores
+/// * n - the most cores to return
+/// * window - how long to measure for
+pub fn least_loaded_cores_with<S>(sampler: &mut S, n: usize, window: Duration) -> Result<Vec<CoreId>, Error>
+    where S: LoadSampler + ?Sized
+{
+    let allowed = record(allowed_for_process())?;
+    let before = record(sampler.sample())?;
+
+    thread::sleep(window);
+
+    let after = record(sampler.sample())?;
+
+    let mut loads: Vec<_> = load_between(&before, &after).into_iter()
+        .filter(|&(core_id, _)| allowed.contains(core_id))
+        .collect();
+
+    if loads.is_empty() {
+        return record(Err(Error::new(ErrorKind::NotAllowed)));
+    }
+
+    loads.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+
+    Ok(loads.into_iter().take(n).map(|(core_id, _)| core_id).collect())
+}
+
+/// This function returns how busy every online core has been
+/// since the previous call, in ID order, such as for showing
+/// the load of each core in a monitor.
+///
+/// The first call reports the load since boot. Calls from all
+/// threads share the previous snapshot; `CoreLoad::since`
+/// compares two snapshots independently of that. Cores the
+/// process may not run on are reported too, with `allowed`
+/// unset. The times come from `PlatformSampler`, and it fails
+/// with `ErrorKind::Unsupported` where they can not be read.
+pub fn utilization_snapshot() -> Result<Vec<CoreLoad>, Error> {
+    static PREVIOUS: Mutex<Vec<(CoreId, CpuTimes)>> = Mutex::new(Vec::new());
+
+    let allowed = record(allowed_for_process())?;
+    let times = record(PlatformSampler.sample())?;
+    let mut previous = PREVIOUS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let loads = snapshot(&previous, &times, &allowed);
+    *previous = times;
+
+    Ok(loads)
+}
+
+// The load of every core of `times` since `previous`, or since
+// boot for cores that were not read before.
+fn snapshot(previous: &[(CoreId, CpuTimes)], times: &[(CoreId, CpuTimes)], allowed: &CpuSet) -> Vec<CoreLoad> {
+    let mut loads: Vec<_> = times.iter()
+        .map(|&(core_id, times)| {
+            let earlier = previous.iter()
+                .find(|&&(other, _)| other == core_id)
+                .map(|&(_, earlier)| earlier)
+                .unwrap_or_default();
+
+            CoreLoad::new(core_id, allowed.contains(core_id), times, &earlier)
+        })
+        .collect();
+
+    loads.sort_by_key(|load| load.core_id);
+    loads
+}
+
+// How busy each core of both samples was in between, in parts
+// per million of the time. A core that did not count any time
+// is taken as idle.
+fn load_between(before: &[(CoreId, CpuTimes)], after: &[(CoreId, CpuTimes)]) -> Vec<(CoreId, u64)> {
+    after.iter()
+        .filter_map(|&(core_id, times)| {
+            let &(_, earlier) = before.iter().find(|&&(other, _)| other == core_id)?;
+            let delta = times.since(&earlier);
+
+            let load = match delta.total() {
+                0 => 0,
+                total => (delta.busy() as u128 * 1_000_000 / total as u128) as u64,
+            };
+
+            Some((core_id, load))
+        })
+        .collect()
+}
+
+// The difference of two readings of a counter. A counter lower
+// than before has wrapped around, at 32 bits if the earlier
+// reading fit in them, as the ticks of macOS do, and otherwise
+// at 64 bits.
+fn elapsed(earlier: u64, later: u64) -> u64 {
+    if later >= earlier {
+        later - earlier
+    }
+    else if earlier <= u32::MAX as u64 {
+        later.wrapping_sub(earlier) & u32::MAX as u64
+    }
+    else {
+        later.wrapping_sub(earlier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_all_core_ids;
+
+    // Hands out the samples it was given, one per call.
+    struct Scripted {
+        samples: Vec<Vec<(CoreId, CpuTimes)>>,
+    }
+
+    impl LoadSampler for Scripted {
+        fn sample(&mut self) -> Result<Vec<(CoreId, CpuTimes)>, Error> {
+            if self.samples.is_empty() {
+                Err(Error::new(ErrorKind::Unsupported))
+            }
+            else {
+                Ok(self.samples.remove(0))
+            }
+        }
+    }
+
+    fn times(user: u64, system: u64, idle: u64) -> CpuTimes {
+        CpuTimes { user, system, idle }
+    }
+
+    #[test]
+    fn test_elapsed() {
+        assert_eq!(elapsed(100, 250), 150);
+        assert_eq!(elapsed(u32::MAX as u64 - 9, 5), 15);
+        assert_eq!(elapsed(u64::MAX - 9, 5), 15);
+    }
+
+    #[test]
+    fn test_load_between() {
+        let core = |id| CoreId { id };
+        let before = [(core(0), times(100, 100, 800)), (core(1), times(0, 0, 0)), (core(2), times(5, 5, 5))];
+        let after = [(core(1), times(0, 0, 0)), (core(0), times(400, 200, 1400)), (core(3), times(1, 1, 1))];
+
+        // A core missing from either sample is left out.
+        assert_eq!(load_between(&before, &after), vec![(core(1), 0), (core(0), 400_000)]);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let core = |id| CoreId { id };
+        let allowed = CpuSet::from(vec![core(0), core(2)]);
+        let previous = [(core(0), times(100, 100, 800)), (core(1), times(50, 50, 50))];
+        let current = [(core(2), times(30, 10, 60)), (core(1), times(50, 50, 50)), (core(0), times(250, 150, 1600))];
+
+        let loads = snapshot(&previous, &current, &allowed);
+        let shares: Vec<_> = loads.iter().map(|load| (load.core_id, load.allowed, load.user, load.system, load.idle)).collect();
+
+        // Core 2 was not read before, so its load is since boot,
+        // and core 1 did not count any time.
+        assert_eq!(shares, vec![
+            (core(0), true, 15.0, 5.0, 80.0),
+            (core(1), false, 0.0, 0.0, 100.0),
+            (core(2), true, 30.0, 10.0, 60.0),
+        ]);
+        assert_eq!(loads[2].times(), times(30, 10, 60));
+    }
+
+    #[test]
+    fn test_core_load_since_wrap() {
+        let core_id = CoreId { id: 0 };
+
+        // The 32-bit ticks of macOS wrap around, and so would 64-bit
+        // counters, eventually.
+        let earlier = CoreLoad::new(core_id, true, times(u32::MAX as u64 - 19, 0, u32::MAX as u64 - 9), &CpuTimes::default());
+        let later = CoreLoad::new(core_id, true, times(5, 25, 40), &CpuTimes::default());
+        let load = later.since(&earlier);
+        assert_eq!((load.user, load.system, load.idle), (25.0, 25.0, 50.0));
+
+        let earlier = CoreLoad::new(core_id, true, times(u64::MAX - 49, 1 << 40, 1 << 40), &CpuTimes::default());
+        let later = CoreLoad::new(core_id, true, times(50, (1 << 40) + 100, (1 << 40) + 200), &CpuTimes::default());
+        let load = later.since(&earlier);
+        assert_eq!((load.user, load.system, load.idle), (25.0, 25.0, 50.0));
+    }
+
+    #[test]
+    fn test_utilization_snapshot() {
+        let loads = match utilization_snapshot() {
+            Ok(loads) => loads,
+            Err(_) => return,
+        };
+
+        assert!(loads.windows(2).all(|pair| pair[0].core_id < pair[1].core_id));
+
+        for load in loads {
+            assert!((load.user + load.system + load.idle - 100.0).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn test_least_loaded_cores_with() {
+        let core_ids = allowed_for_process().unwrap().to_vec();
+
+        if core_ids.len() < 2 {
+            return;
+        }
+
+        let (first, second) = (core_ids[0], core_ids[1]);
+        let mut sampler = Scripted {
+            samples: vec![
+                vec![(first, times(0, 0, 0)), (second, times(0, 0, 0))],
+                vec![(first, times(90, 0, 10)), (second, times(10, 10, 80))],
+            ],
+        };
+
+        assert_eq!(least_loaded_cores_with(&mut sampler, 1, Duration::from_millis(0)), Ok(vec![second]));
+
+        // Without samples, there is nothing to go by.
+        let err = least_loaded_cores_with(&mut sampler, 1, Duration::from_millis(0)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_least_loaded_cores() {
+        let cores = match least_loaded_cores(2, Duration::from_millis(10)) {
+            Ok(cores) => cores,
+            Err(_) => return,
+        };
+
+        assert!(!cores.is_empty() && cores.len() <= 2);
+        assert!(cores.iter().all(|core_id| get_all_core_ids().unwrap().contains(core_id)));
+    }
+}
@@ -0,0 +1,487 @@
+use std::mem;
+
+use std::os::unix::process::CommandExt;
+use std::os::unix::thread::JoinHandleExt;
+use std::process::Command;
+use std::ptr;
+use std::thread::{self, JoinHandle};
+
+use libc::{c_int, c_uint, c_void, pthread_mach_thread_np, pthread_self, pthread_t, size_t, sysctlbyname};
+
+use super::{CacheLevel, CacheType, Capabilities, CoreId, CoreIds, CoreKind, CpuTimes, Error, ErrorKind, KindSource};
+use super::{CoreState, NumaNode};
+use super::StableCoreId;
+
+type kern_return_t = c_int;
+type integer_t = c_int;
+type natural_t = c_uint;
+type thread_t = c_uint;
+type thread_policy_flavor_t = natural_t;
+type mach_msg_type_number_t = natural_t;
+type mach_port_t = c_uint;
+type processor_flavor_t = c_int;
+type vm_address_t = usize;
+type vm_size_t = usize;
+
+#[repr(C)]
+struct thread_affinity_policy_data_t {
+    affinity_tag: integer_t,
+}
+
+type thread_policy_t = *mut thread_affinity_policy_data_t;
+
+const THREAD_AFFINITY_POLICY: thread_policy_flavor_t = 4;
+const THREAD_AFFINITY_TAG_NULL: integer_t = 0;
+
+// The ticks of each core, see <mach/processor_info.h> and
+// <mach/machine.h>.
+const PROCESSOR_CPU_LOAD_INFO: processor_flavor_t = 2;
+const CPU_STATE_MAX: usize = 4;
+const CPU_STATE_USER: usize = 0;
+const CPU_STATE_SYSTEM: usize = 1;
+const CPU_STATE_IDLE: usize = 2;
+const CPU_STATE_NICE: usize = 3;
+
+extern {
+    fn thread_policy_set(
+        thread: thread_t,
+        flavor: thread_policy_flavor_t,
+        policy_info: thread_policy_t,
+        count: mach_msg_type_number_t,
+    ) -> kern_return_t;
+
+    static mach_task_self_: mach_port_t;
+
+    fn mach_host_self() -> mach_port_t;
+
+    fn host_processor_info(
+        host: mach_port_t,
+        flavor: processor_flavor_t,
+        processor_count: *mut natural_t,
+        info: *mut *mut integer_t,
+        info_count: *mut mach_msg_type_number_t,
+    ) -> kern_return_t;
+
+    fn vm_deallocate(task: mach_port_t, address: vm_address_t, size: vm_size_t) -> kern_return_t;
+}
+
+pub fn core_ids() -> Result<CoreIds, Error> {
+    // Threads can not be kept off any core, so every
+    // thread may run on all of them.
+    logical_cpu_count().map(CoreIds::range)
+}
+
+pub fn get_all_core_ids() -> Option<Vec<CoreId>> {
+    logical_cpu_count().ok().map(|count| (0..count).map(|id| CoreId { id }).collect())
+}
+
+pub fn core_ids_by_state(state: CoreState) -> Option<Vec<CoreId>> {
+    // Only the online cores are known.
+    get_all_core_ids().map(|online| if state == CoreState::Offline { Vec::new() } else { online })
+}
+
+pub fn get_affinity_for_current() -> Result<Vec<CoreId>, Error> {
+    // Mach only supports setting an affinity tag,
+    // there is no way to read the effective mask back.
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_for_current(core_id: CoreId) -> Result<(), Error> {
+    set_for_pthread(unsafe { pthread_self() }, core_id)
+}
+
+pub fn set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId) -> Result<(), Error> {
+    set_for_pthread(handle.as_pthread_t(), core_id)
+}
+
+fn set_for_pthread(thread: pthread_t, core_id: CoreId) -> Result<(), Error> {
+    // Tag 0 is the null tag, so shift every core up by one.
+    if core_id.id >= integer_t::MAX as usize {
+        return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+    }
+
+    set_affinity_tag(thread, core_id.id as integer_t + 1)
+        .map_err(|err| err.with_core_id(core_id))
+}
+
+pub fn get_for_thread<T>(_handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    // An affinity tag does not name the cores of a thread.
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_for_process(_pid: u32, _core_ids: &[CoreId]) -> Result<(), Error> {
+    // Affinity tags only group the threads of one task.
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn get_for_process(_pid: u32) -> Result<Vec<CoreId>, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_for_command(command: &mut Command, _core_ids: &[CoreId]) {
+    // Affinity tags do not survive `exec`.
+    unsafe {
+        command.pre_exec(|| Err(Error::new(ErrorKind::Unsupported).into_simple_io_error()));
+    }
+}
+
+pub fn current_core() -> Result<CoreId, Error> {
+    // Darwin does not tell a thread which core it runs on.
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_preferred_for_current(core_id: CoreId) -> Result<(), Error> {
+    // An affinity tag already is only a hint to the scheduler.
+    set_for_current(core_id)
+}
+
+pub fn thread_core_ids() -> Result<CoreIds, Error> {
+    // `core_ids` only counts the cores, it can not tell
+    // which of them the thread may use.
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn process_core_ids() -> Result<CoreIds, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn capabilities() -> Capabilities {
+    // Try a tag on a scratch thread, so the tag of the
+    // current thread is left alone.
+    let tags = thread::spawn(|| set_affinity_tag(unsafe { pthread_self() }, 1).is_ok())
+        .join()
+        .unwrap_or(false);
+
+    Capabilities {
+        hard_pinning: false,
+        soft_affinity: tags,
+        can_query_affinity: false,
+        max_cores_representable: if tags { integer_t::MAX as usize } else { 0 },
+    }
+}
+
+pub fn clear_for_current() -> Result<(), Error> {
+    // The null tag removes the thread from any affinity set.
+    set_affinity_tag(unsafe { pthread_self() }, THREAD_AFFINITY_TAG_NULL)
+}
+
+pub fn core_siblings() -> Option<Vec<Vec<CoreId>>> {
+    // Only the counts are known, so the logical cores are taken
+    // to be numbered core by core.
+    let logical = logical_cpu_count().ok()?;
+    let physical = match sysctl_int(b"hw.physicalcpu\0").ok()? {
+        count if count > 0 => count as usize,
+        _ => return None,
+    };
+
+    if logical % physical != 0 {
+        return None;
+    }
+
+    let threads = logical / physical;
+
+    Some((0..physical).map(|core| {
+        (core * threads..(core + 1) * threads).map(|id| CoreId { id }).collect()
+    }).collect())
+}
+
+pub fn current_location() -> Result<(CoreId, NumaNode), Error> {
+    // There is a single node, but `current_core` fails.
+    current_core().map(|core_id| (core_id, NumaNode { id: 0 }))
+}
+
+pub fn packages() -> Option<Vec<(usize, Vec<CoreId>)>> {
+    // Macs have a single package.
+    get_all_core_ids().map(|core_ids| vec![(0, core_ids)])
+}
+
+pub fn dies() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn clusters() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn stable_core_ids() -> Option<Vec<(CoreId, StableCoreId)>> {
+    None
+}
+
+pub fn cache_groups(level: usize) -> Option<Vec<Vec<CoreId>>> {
+    // `hw.cacheconfig` tells how many logical cores share each
+    // level, memory first, so like `core_siblings` the cores
+    // sharing a cache are taken to be numbered together.
+    let mut config = [0u64; 10];
+    let mut size = mem::size_of_val(&config) as size_t;
+
+    let res = unsafe {
+        sysctlbyname(
+            b"hw.cacheconfig\0".as_ptr() as *const _,
+            config.as_mut_ptr() as *mut c_void,
+            &mut size,
+            ptr::null_mut(),
+            0
+        )
+    };
+
+    if res != 0 || level == 0 || level >= size / mem::size_of::<u64>() {
+        return None;
+    }
+
+    let sharing = config[level] as usize;
+    let logical = logical_cpu_count().ok()?;
+
+    if sharing == 0 {
+        return None;
+    }
+
+    Some((0..logical).step_by(sharing).map(|first| {
+        (first..logical.min(first + sharing)).map(|id| CoreId { id }).collect()
+    }).collect())
+}
+
+pub fn cache_info(_core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    let line_size = sysctl_u64(b"hw.cachelinesize\0");
+    let levels: [(usize, CacheType, &[u8]); 4] = [
+        (1, CacheType::Instruction, b"hw.l1icachesize\0"),
+        (1, CacheType::Data, b"hw.l1dcachesize\0"),
+        (2, CacheType::Unified, b"hw.l2cachesize\0"),
+        (3, CacheType::Unified, b"hw.l3cachesize\0"),
+    ];
+
+    // Machines without a level leave its sysctl out.
+    let caches: Vec<_> = levels.iter().filter_map(|&(level, cache_type, name)| {
+        Some(CacheLevel {
+            level,
+            cache_type,
+            size: Some(sysctl_u64(name)?),
+            line_size,
+            associativity: None,
+        })
+    }).collect();
+
+    if caches.is_empty() { None } else { Some(caches) }
+}
+
+pub fn core_kinds() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    let core_ids = get_all_core_ids()?;
+
+    // Performance level 0 is the fastest. Apple Silicon numbers
+    // its efficiency cores first, and Intel Macs have a single
+    // level, or none at all on older releases.
+    let efficiency = match sysctl_int(b"hw.nperflevels\0").unwrap_or(1) {
+        levels if levels > 1 => sysctl_int(b"hw.perflevel1.logicalcpu\0").ok()?.max(0) as usize,
+        _ => 0,
+    };
+
+    let kinds = core_ids.into_iter().map(|core_id| {
+        (core_id, if core_id.id < efficiency { CoreKind::Efficiency } else { CoreKind::Performance })
+    }).collect();
+
+    Some((kinds, KindSource::Reported))
+}
+
+pub fn max_frequency_khz(_core_id: CoreId) -> Option<u64> {
+    sysctl_u64(b"hw.cpufrequency_max\0").map(|hz| hz as u64 / 1000)
+}
+
+pub fn core_rankings() -> Option<Vec<(CoreId, u64)>> {
+    None
+}
+
+pub fn current_frequency_khz(_core_id: CoreId) -> Option<u64> {
+    None
+}
+
+pub fn cpu_times() -> Option<Vec<(CoreId, CpuTimes)>> {
+    let mut count: natural_t = 0;
+    let mut info: *mut integer_t = ptr::null_mut();
+    let mut info_count: mach_msg_type_number_t = 0;
+
+    let res = unsafe {
+        host_processor_info(mach_host_self(), PROCESSOR_CPU_LOAD_INFO, &mut count, &mut info, &mut info_count)
+    };
+
+    if res != 0 {
+        return None;
+    }
+
+    // The ticks are 32-bit counters, which wrap around.
+    let ticks = unsafe { std::slice::from_raw_parts(info as *const natural_t, info_count as usize) };
+    let times = ticks.chunks_exact(CPU_STATE_MAX)
+        .take(count as usize)
+        .enumerate()
+        .map(|(id, states)| {
+            let state = |index: usize| states[index] as u64;
+            let times = CpuTimes {
+                user: state(CPU_STATE_USER) + state(CPU_STATE_NICE),
+                system: state(CPU_STATE_SYSTEM),
+                idle: state(CPU_STATE_IDLE),
+            };
+
+            (CoreId { id }, times)
+        })
+        .collect();
+
+    let size = info_count as usize * mem::size_of::<integer_t>();
+    unsafe { vm_deallocate(mach_task_self_, info as vm_address_t, size) };
+
+    Some(times)
+}
+
+pub fn capacity(_core_id: CoreId) -> Option<u32> {
+    None
+}
+
+pub fn numa_nodes() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    // Macs have uniform memory.
+    get_all_core_ids().map(|core_ids| vec![(NumaNode { id: 0 }, core_ids)])
+}
+
+fn set_affinity_tag(thread: pthread_t, affinity_tag: integer_t) -> Result<(), Error> {
+    let THREAD_AFFINITY_POLICY_COUNT: mach_msg_type_number_t =
+        mem::size_of::<thread_affinity_policy_data_t>() as mach_msg_type_number_t /
+        mem::size_of::<integer_t>() as mach_msg_type_number_t;
+
+    let mut info = thread_affinity_policy_data_t {
+        affinity_tag,
+    };
+
+    let res = unsafe {
+        // Mach expects the thread port, not the pthread handle.
+        thread_policy_set(
+            pthread_mach_thread_np(thread) as thread_t,
+            THREAD_AFFINITY_POLICY,
+            &mut info as thread_policy_t,
+            THREAD_AFFINITY_POLICY_COUNT
+        )
+    };
+
+    if res == 0 {
+        Ok(())
+    }
+    else {
+        Err(Error::from_kern_return(res))
+    }
+}
+
+pub fn set_for_current_set(core_ids: &[CoreId]) -> Result<(), Error> {
+    // An affinity tag names a single core, so only a set
+    // that consists of one distinct core can be honored.
+    if core_ids.iter().all(|&core_id| core_id == core_ids[0]) {
+        set_for_current(core_ids[0])
+    }
+    else {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+}
+
+// Read an integer sysctl. `name` must be NUL-terminated.
+// The number of logical processors available in the current
+// power management mode.
+fn logical_cpu_count() -> Result<usize, Error> {
+    match sysctl_int(b"hw.logicalcpu\0")? {
+        count if count > 0 => Ok(count as usize),
+        _ => Err(Error::new(ErrorKind::Other)),
+    }
+}
+
+fn sysctl_int(name: &[u8]) -> Result<c_int, Error> {
+    let mut value: c_int = 0;
+    let mut size = mem::size_of::<c_int>() as size_t;
+
+    let res = unsafe {
+        sysctlbyname(
+            name.as_ptr() as *const _,
+            &mut value as *mut c_int as *mut c_void,
+            &mut size,
+            ptr::null_mut(),
+            0
+        )
+    };
+
+    if res == 0 {
+        Ok(value)
+    }
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+// Reads a 64-bit sysctl, such as a cache size. Returns `None`
+// if it is missing or 0.
+fn sysctl_u64(name: &[u8]) -> Option<usize> {
+    let mut value: u64 = 0;
+    let mut size = mem::size_of::<u64>() as size_t;
+
+    let res = unsafe {
+        sysctlbyname(
+            name.as_ptr() as *const _,
+            &mut value as *mut u64 as *mut c_void,
+            &mut size,
+            ptr::null_mut(),
+            0
+        )
+    };
+
+    if res == 0 && value > 0 { Some(value as usize) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_cpus;
+
+    use super::*;
+    use super::super::testing;
+
+    #[test]
+    fn test_macos_core_ids() {
+        match core_ids() {
+            Ok(core_ids) => {
+                assert_eq!(core_ids.len(), num_cpus::get());
+            },
+            Err(_) => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_macos_get_all_core_ids() {
+        match get_all_core_ids() {
+            Some(set) => {
+                assert_eq!(set.len(), num_cpus::get());
+            },
+            None => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_macos_get_all_core_ids_when_pinned() {
+        testing::check_get_all_core_ids_when_pinned();
+    }
+
+    #[test]
+    fn test_macos_get_affinity_for_current() {
+        let err = get_affinity_for_current().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_macos_set_for_current_set() {
+        testing::check_set_for_current_set();
+    }
+
+    #[test]
+    fn test_macos_set_for_thread() {
+        testing::check_set_for_thread();
+    }
+
+    #[test]
+    fn test_macos_clear_for_current() {
+        testing::check_clear_for_current();
+    }
+
+    #[test]
+    fn test_macos_set_for_current() {
+        testing::check_set_for_current();
+    }
+}
@@ -0,0 +1,114 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Error, ErrorKind};
+
+// Every kind a failure is counted under, in the order of
+// `MetricsSnapshot::failed_by_kind`.
+const KINDS: [ErrorKind; 8] = [
+    ErrorKind::PermissionDenied,
+    ErrorKind::InvalidCore,
+    ErrorKind::Unsupported,
+    ErrorKind::EmptySet,
+    ErrorKind::NotFound,
+    ErrorKind::NotAllowed,
+    ErrorKind::NotEffective,
+    ErrorKind::Other,
+];
+
+static ATTEMPTED: AtomicU64 = AtomicU64::new(0);
+static SUCCEEDED: AtomicU64 = AtomicU64::new(0);
+static THREADS_PINNED: AtomicU64 = AtomicU64::new(0);
+static FAILED: [AtomicU64; 8] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+thread_local! {
+    // Whether this thread has been counted in `THREADS_PINNED`.
+    static COUNTED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The counters of the `metrics` feature, as returned by
+/// `metrics_snapshot`.
+///
+/// The counters cover `set_for_current` and `set_for_thread`,
+/// including the `_checked` and `_verified` variants, and only
+/// ever grow. Calls made while pinning is turned off are not
+/// counted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MetricsSnapshot {
+    /// The number of pins attempted.
+    pub attempted: u64,
+    /// The number of pins that succeeded.
+    pub succeeded: u64,
+    /// The number of pins that failed, for every kind of
+    /// error, including those that never happened.
+    pub failed_by_kind: Vec<(ErrorKind, u64)>,
+    /// The number of distinct threads that pinned themselves
+    /// successfully at least once. Threads pinned through
+    /// `set_for_thread` are not included.
+    pub threads_pinned: u64,
+}
+
+impl MetricsSnapshot {
+    /// Returns the number of pins that failed.
+    pub fn failed(&self) -> u64 {
+        self.failed_by_kind.iter().map(|&(_, count)| count).sum()
+    }
+
+    /// Returns the number of pins that failed with `kind`.
+    ///
+    /// # Arguments
+    ///
+    /// * kind - the kind of error to look up
+    pub fn failed_with(&self, kind: ErrorKind) -> u64 {
+        self.failed_by_kind.iter()
+            .find(|&&(other, _)| other == kind)
+            .map_or(0, |&(_, count)| count)
+    }
+}
+
+/// This function reads the counters of the `metrics` feature.
+///
+/// The counters are read one by one, so a snapshot taken
+/// while other threads pin may be off by the pins in flight.
+pub fn metrics_snapshot() -> MetricsSnapshot {
+    MetricsSnapshot {
+        attempted: ATTEMPTED.load(Ordering::Relaxed),
+        succeeded: SUCCEEDED.load(Ordering::Relaxed),
+        failed_by_kind: KINDS.iter()
+            .zip(FAILED.iter())
+            .map(|(&kind, count)| (kind, count.load(Ordering::Relaxed)))
+            .collect(),
+        threads_pinned: THREADS_PINNED.load(Ordering::Relaxed),
+    }
+}
+
+// Counts a pin with `result`. `current` tells whether the
+// current thread was the one pinned.
+pub(crate) fn count(result: &Result<(), Error>, current: bool) {
+    ATTEMPTED.fetch_add(1, Ordering::Relaxed);
+
+    match *result {
+        Ok(()) => {
+            SUCCEEDED.fetch_add(1, Ordering::Relaxed);
+
+            if current && !COUNTED.with(|counted| counted.replace(true)) {
+                THREADS_PINNED.fetch_add(1, Ordering::Relaxed);
+            }
+        },
+        Err(ref err) => {
+            if let Some(i) = KINDS.iter().position(|&kind| kind == err.kind()) {
+                FAILED[i].fetch_add(1, Ordering::Relaxed);
+            }
+        },
+    }
+}
@@ -0,0 +1,898 @@
+//! An in-memory backend that replaces the platform calls when
+//! the `mock` feature is enabled, for deterministic tests.
+//!
+//! The mock simulates a machine with `DEFAULT_CORE_COUNT` online
+//! cores, each a physical core of its own, on a single NUMA
+//! node, until told otherwise. Every thread has a mask of its
+//! own, which starts out as the mask of the process, so tests
+//! running in parallel pin their threads without seeing each
+//! other. The settings, such as the online cores or injected
+//! failures, are shared by the whole process; tests that change
+//! them should not run at the same time as tests that rely on
+//! the defaults.
+//!
+//! ```
+//! # #[cfg(feature = "mock")]
+//! # fn main() {
+//! extern crate core_affinity;
+//!
+//! use core_affinity::{CoreId, ErrorKind};
+//! use core_affinity::mock::{self, Target};
+//!
+//! let core_id = CoreId { id: 1 };
+//!
+//! assert!(core_affinity::set_for_current(core_id));
+//! assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core_id]));
+//!
+//! let calls = mock::set_calls();
+//! assert_eq!(calls.len(), 1);
+//! assert_eq!(calls[0].target, Target::Thread(std::thread::current().id()));
+//! assert_eq!(calls[0].core_ids, vec![core_id]);
+//!
+//! // Make pinning to core 3 fail like a missing privilege.
+//! mock::fail_on(CoreId { id: 3 }, ErrorKind::PermissionDenied);
+//! let err = core_affinity::set_for_current_checked(CoreId { id: 3 }).unwrap_err();
+//! assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+//! # mock::reset();
+//! # }
+//! # #[cfg(not(feature = "mock"))]
+//! # fn main() {}
+//! ```
+
+use std::collections::HashMap;
+#[cfg(not(unix))]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::process;
+#[cfg(not(unix))]
+use std::process::Child;
+use std::process::Command;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, JoinHandle, ThreadId};
+
+use super::{CacheLevel, Capabilities, CoreId, CoreIds, CoreKind, CpuSet, CpuTimes, Error, ErrorKind, KindSource};
+use super::{CoreState, NumaNode};
+use super::Priority;
+use super::StableCoreId;
+use stable::{HardwareId, number_threads};
+
+/// The number of cores the mock reports until
+/// `set_core_count` or `set_online` is called.
+pub const DEFAULT_CORE_COUNT: usize = 4;
+
+// The highest core the mock can represent, the capacity of
+// `CoreIds`.
+const MAX_CORES: usize = 1024;
+
+/// What a recorded call tried to change the affinity of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Target {
+    /// A thread, such as the current one.
+    Thread(ThreadId),
+    /// The process with this ID.
+    Process(u32),
+    /// A child process about to be spawned from a `Command`.
+    Command,
+}
+
+/// A call that tried to change an affinity, as returned by
+/// `set_calls`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetCall {
+    /// The thread or process whose affinity was to change.
+    pub target: Target,
+    /// The requested cores, without duplicates, in ascending
+    /// order.
+    pub core_ids: Vec<CoreId>,
+    /// What the call returned.
+    pub result: Result<(), Error>,
+    // The thread that made the call.
+    caller: ThreadId,
+}
+
+struct State {
+    online: CpuSet,
+    present: CpuSet,
+    possible: CpuSet,
+    process: CpuSet,
+    threads: HashMap<ThreadId, CpuSet>,
+    processes: HashMap<u32, CpuSet>,
+    failures: HashMap<CoreId, ErrorKind>,
+    calls: Vec<SetCall>,
+    priorities: HashMap<ThreadId, Priority>,
+    priority_failure: Option<ErrorKind>,
+    siblings: Vec<Vec<CoreId>>,
+    numa_nodes: Option<Vec<Vec<CoreId>>>,
+    packages: Option<Vec<Vec<CoreId>>>,
+    dies: Option<Vec<Vec<CoreId>>>,
+    clusters: Option<Vec<Vec<CoreId>>>,
+    hardware_ids: Option<Vec<(CoreId, HardwareId)>>,
+    caches: HashMap<usize, Vec<Vec<CoreId>>>,
+    cache_info: Option<Vec<CacheLevel>>,
+    core_kinds: Option<(Vec<(CoreId, CoreKind)>, KindSource)>,
+    max_frequencies: HashMap<CoreId, u64>,
+    current_frequencies: HashMap<CoreId, u64>,
+    rankings: Option<Vec<(CoreId, u64)>>,
+    capacities: HashMap<CoreId, u32>,
+    cpu_times: Option<Vec<(CoreId, CpuTimes)>>,
+}
+
+impl State {
+    fn new(online: CpuSet) -> State {
+        State {
+            process: online.clone(),
+            online,
+            present: CpuSet::new(),
+            possible: CpuSet::new(),
+            threads: HashMap::new(),
+            processes: HashMap::new(),
+            failures: HashMap::new(),
+            calls: Vec::new(),
+            priorities: HashMap::new(),
+            priority_failure: None,
+            siblings: Vec::new(),
+            numa_nodes: None,
+            packages: None,
+            dies: None,
+            clusters: None,
+            hardware_ids: None,
+            caches: HashMap::new(),
+            cache_info: None,
+            core_kinds: None,
+            max_frequencies: HashMap::new(),
+            current_frequencies: HashMap::new(),
+            rankings: None,
+            capacities: HashMap::new(),
+            cpu_times: None,
+        }
+    }
+
+    fn thread_mask(&self, thread: ThreadId) -> CpuSet {
+        self.threads.get(&thread).unwrap_or(&self.process).clone()
+    }
+
+    // Checks that a mask of `core_ids` may be applied, the way
+    // the kernel would.
+    fn check(&self, core_ids: &[CoreId], allowed: &CpuSet) -> Result<(), Error> {
+        if core_ids.is_empty() {
+            return Err(Error::new(ErrorKind::EmptySet));
+        }
+
+        for &core_id in core_ids {
+            if let Some(&kind) = self.failures.get(&core_id) {
+                return Err(Error::new(kind).with_core_id(core_id));
+            }
+
+            if !self.online.contains(core_id) {
+                return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+            }
+
+            if !allowed.contains(core_id) {
+                return Err(Error::new(ErrorKind::NotAllowed).with_core_id(core_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_thread(&mut self, thread: ThreadId, core_ids: &[CoreId]) -> Result<(), Error> {
+        let result = self.check(core_ids, &self.process);
+
+        // Only online cores pass the check, so the set is small.
+        if result.is_ok() {
+            self.threads.insert(thread, core_ids.into());
+        }
+
+        self.record(Target::Thread(thread), core_ids, result)
+    }
+
+    fn record(&mut self, target: Target, core_ids: &[CoreId], result: Result<(), Error>) -> Result<(), Error> {
+        let mut core_ids = core_ids.to_vec();
+        core_ids.sort();
+        core_ids.dedup();
+
+        self.calls.push(SetCall {
+            target,
+            core_ids,
+            result,
+            caller: thread::current().id(),
+        });
+
+        result
+    }
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+// Runs `f` on the state, setting up the defaults on first use.
+fn with_state<T, F>(f: F) -> T
+    where F: FnOnce(&mut State) -> T
+{
+    let mut state = lock();
+    f(state.get_or_insert_with(|| State::new(range(DEFAULT_CORE_COUNT))))
+}
+
+// A test that panics while holding the lock must not take the
+// other tests down with it.
+fn lock() -> MutexGuard<'static, Option<State>> {
+    STATE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn range(count: usize) -> CpuSet {
+    (0..count.min(MAX_CORES)).map(|id| CoreId { id }).collect()
+}
+
+fn representable(core_ids: &[CoreId]) -> CpuSet {
+    core_ids.iter().cloned().filter(|core_id| core_id.id < MAX_CORES).collect()
+}
+
+fn to_core_ids(set: &CpuSet) -> CoreIds {
+    let mut core_ids = CoreIds::new();
+
+    for core_id in set.iter() {
+        core_ids.insert(core_id.id);
+    }
+
+    core_ids
+}
+
+/// Restores the defaults: `DEFAULT_CORE_COUNT` online cores
+/// that every thread may use, without simultaneous
+/// multithreading, on one NUMA node, no failures and no
+/// recorded calls.
+pub fn reset() {
+    *lock() = None;
+}
+
+/// Simulates a machine with cores `0` to `count - 1` online,
+/// all of which the process may use.
+///
+/// Threads that were pinned before are unpinned again. The
+/// recorded calls and failures are kept.
+///
+/// # Arguments
+///
+/// * count - the number of online cores
+pub fn set_core_count(count: usize) {
+    set_online(&range(count).to_vec());
+}
+
+/// Simulates a machine with the listed cores online, all of
+/// which the process may use.
+///
+/// Like `set_core_count`, this unpins every thread.
+///
+/// # Arguments
+///
+/// * core_ids - the online cores
+pub fn set_online(core_ids: &[CoreId]) {
+    with_state(|state| {
+        state.online = representable(core_ids);
+        state.process = state.online.clone();
+        state.threads.clear();
+        state.processes.clear();
+    });
+}
+
+/// Simulates cores that are not online: `present` lists cores
+/// in the machine, such as ones taken offline, and `possible`
+/// the cores that could be added on top of those.
+///
+/// Online cores are always present and possible, so only
+/// `get_core_ids_by_state` tells these cores apart. Pinning to
+/// them fails with `ErrorKind::InvalidCore`, as to any core
+/// that is not online.
+///
+/// # Arguments
+///
+/// * present - the cores in the machine
+/// * possible - the cores that could be added
+pub fn set_core_states(present: &[CoreId], possible: &[CoreId]) {
+    with_state(|state| {
+        state.present = representable(present);
+        state.possible = &representable(possible) | &state.present;
+    });
+}
+
+/// Restricts the process to the listed cores, as a container
+/// cpuset would.
+///
+/// Threads are reset to the new mask, and pinning to cores
+/// outside of it fails with `ErrorKind::NotAllowed`. Cores
+/// that are not online are left out.
+///
+/// # Arguments
+///
+/// * core_ids - the cores the process may use
+pub fn set_process_mask(core_ids: &[CoreId]) {
+    with_state(|state| {
+        state.process = &representable(core_ids) & &state.online;
+        state.threads.clear();
+    });
+}
+
+/// Simulates simultaneous multithreading: the cores of each
+/// group are the hardware threads of one physical core.
+///
+/// Online cores that are in no group are physical cores of
+/// their own, and offline cores are left out, so the groups
+/// can describe the whole machine before some of its cores are
+/// taken offline. An empty list turns simultaneous
+/// multithreading off again.
+///
+/// # Arguments
+///
+/// * groups - the logical cores of each physical core
+pub fn set_siblings(groups: &[Vec<CoreId>]) {
+    with_state(|state| state.siblings = groups.to_vec());
+}
+
+/// Simulates a NUMA machine: node `i` has the cores of
+/// `nodes[i]`.
+///
+/// Offline cores are left out, and online cores on none of
+/// the nodes are on none. A node may have no cores, like a
+/// node with only memory.
+///
+/// # Arguments
+///
+/// * nodes - the cores of each node
+pub fn set_numa_nodes(nodes: &[Vec<CoreId>]) {
+    with_state(|state| state.numa_nodes = Some(nodes.to_vec()));
+}
+
+/// Simulates a machine with several packages: package `i` has
+/// the cores of `packages[i]`.
+///
+/// Offline cores are left out, as with `set_numa_nodes`.
+/// Otherwise all the cores are on a single package.
+///
+/// # Arguments
+///
+/// * packages - the cores of each package
+pub fn set_packages(packages: &[Vec<CoreId>]) {
+    with_state(|state| state.packages = Some(packages.to_vec()));
+}
+
+/// Simulates packages made of several dies: die `i` has the
+/// cores of `dies[i]`.
+///
+/// Offline cores are left out, as with `set_numa_nodes`.
+/// Otherwise the dies are not known.
+///
+/// # Arguments
+///
+/// * dies - the cores of each die
+pub fn set_dies(dies: &[Vec<CoreId>]) {
+    with_state(|state| state.dies = Some(dies.to_vec()));
+}
+
+/// Simulates cores grouped into clusters, as on ARM: cluster
+/// `i` has the cores of `clusters[i]`.
+///
+/// Offline cores are left out, as with `set_numa_nodes`.
+/// Otherwise the clusters are not known.
+///
+/// # Arguments
+///
+/// * clusters - the cores of each cluster
+pub fn set_clusters(clusters: &[Vec<CoreId>]) {
+    with_state(|state| state.clusters = Some(clusters.to_vec()));
+}
+
+/// Gives every core of `ids` the package, die and core IDs the
+/// hardware numbers it with, as `CoreId::stable` reads them.
+///
+/// Several logical cores with the same IDs are the threads of
+/// one physical core, numbered among all the listed cores, so
+/// list the offline ones too. Only the online cores are
+/// reported, and until this is called, the IDs are not known.
+///
+/// # Arguments
+///
+/// * ids - the package, die and core IDs of each core
+pub fn set_hardware_ids(ids: &[(CoreId, (usize, usize, usize))]) {
+    with_state(|state| state.hardware_ids = Some(ids.to_vec()));
+}
+
+/// Simulates caches of `level` shared by groups of cores: the
+/// cores of each of `groups` share one.
+///
+/// Offline cores are left out, as with `set_numa_nodes`.
+/// Otherwise the caches of `level` are not known.
+///
+/// # Arguments
+///
+/// * level - the cache level, such as 3 for the L3
+/// * groups - the cores sharing each cache of the level
+pub fn set_cache_groups(level: usize, groups: &[Vec<CoreId>]) {
+    with_state(|state| {
+        state.caches.insert(level, groups.to_vec());
+    });
+}
+
+/// Makes `cache_info` report `caches` for every online core.
+///
+/// # Arguments
+///
+/// * caches - the caches of each core
+pub fn set_cache_info(caches: &[CacheLevel]) {
+    with_state(|state| state.cache_info = Some(caches.to_vec()));
+}
+
+/// Simulates a hybrid CPU whose cores have the kinds of
+/// `kinds`, as told by `source`.
+///
+/// Online cores left out of `kinds` are of an unknown kind.
+/// Otherwise the kinds are not known.
+///
+/// # Arguments
+///
+/// * kinds - the kind of each core
+/// * source - where the kinds come from
+pub fn set_core_kinds(kinds: &[(CoreId, CoreKind)], source: KindSource) {
+    with_state(|state| state.core_kinds = Some((kinds.to_vec(), source)));
+}
+
+/// Makes each core of `frequencies` run at most at its
+/// frequency in kHz. The frequencies of other cores are not
+/// known.
+///
+/// # Arguments
+///
+/// * frequencies - the highest frequency of each core
+pub fn set_max_frequencies(frequencies: &[(CoreId, u64)]) {
+    with_state(|state| state.max_frequencies = frequencies.iter().cloned().collect());
+}
+
+/// Makes each core of `frequencies` run at its frequency in kHz
+/// right now, whether busy or not. The frequencies of other
+/// cores are not known.
+///
+/// # Arguments
+///
+/// * frequencies - the current frequency of each core
+pub fn set_current_frequencies(frequencies: &[(CoreId, u64)]) {
+    with_state(|state| state.current_frequencies = frequencies.iter().cloned().collect());
+}
+
+/// Makes the platform prefer the cores of `rankings` by their
+/// rank, higher first, as for `favored_cores`. Otherwise there
+/// is no ranking.
+///
+/// # Arguments
+///
+/// * rankings - the rank of each core
+pub fn set_core_rankings(rankings: &[(CoreId, u64)]) {
+    with_state(|state| state.rankings = Some(rankings.to_vec()));
+}
+
+/// Gives each core of `capacities` its capacity, as ARM
+/// kernels do, out of 1024 for the fastest. The capacities of
+/// other cores are not known.
+///
+/// # Arguments
+///
+/// * capacities - the capacity of each core
+pub fn set_capacities(capacities: &[(CoreId, u32)]) {
+    with_state(|state| state.capacities = capacities.iter().cloned().collect());
+}
+
+/// Makes the cores of `times` report the times they spent
+/// busy and idle, until the next call. The times of cores that
+/// are not online are not reported, and without a call, the
+/// times are not known.
+///
+/// # Arguments
+///
+/// * times - the times of each core
+pub fn set_cpu_times(times: &[(CoreId, CpuTimes)]) {
+    with_state(|state| state.cpu_times = Some(times.to_vec()));
+}
+
+/// Makes every later attempt to pin to `core_id` fail with
+/// an error of `kind`.
+///
+/// A set of cores fails if it contains `core_id`. Reading
+/// affinities is not affected.
+///
+/// # Arguments
+///
+/// * core_id - the core to reject
+/// * kind - the kind of error to return
+pub fn fail_on(core_id: CoreId, kind: ErrorKind) {
+    with_state(|state| state.failures.insert(core_id, kind));
+}
+
+/// Makes every later attempt to change the priority of a
+/// thread fail with an error of `kind`, such as
+/// `ErrorKind::PermissionDenied` for a process without the
+/// privileges for realtime scheduling.
+///
+/// # Arguments
+///
+/// * kind - the kind of error to return
+pub fn fail_priority(kind: ErrorKind) {
+    with_state(|state| state.priority_failure = Some(kind));
+}
+
+/// Removes the failures injected by `fail_on` and
+/// `fail_priority`.
+pub fn clear_failures() {
+    with_state(|state| {
+        state.failures.clear();
+        state.priority_failure = None;
+    });
+}
+
+/// Returns the priority the current thread was last given,
+/// or `None` if it has not been changed.
+pub fn priority_for_current() -> Option<Priority> {
+    with_state(|state| state.priorities.get(&thread::current().id()).cloned())
+}
+
+/// Returns the calls the current thread made to change an
+/// affinity, oldest first, including the failed ones.
+///
+/// Only the calls of the current thread are returned, so
+/// tests running in parallel do not see each other's.
+pub fn set_calls() -> Vec<SetCall> {
+    let current = thread::current().id();
+
+    with_state(|state| state.calls.iter().filter(|call| call.caller == current).cloned().collect())
+}
+
+/// Returns the calls every thread made to change an affinity,
+/// oldest first.
+pub fn all_set_calls() -> Vec<SetCall> {
+    with_state(|state| state.calls.clone())
+}
+
+/// Forgets the calls the current thread made so far.
+pub fn clear_set_calls() {
+    let current = thread::current().id();
+
+    with_state(|state| state.calls.retain(|call| call.caller != current));
+}
+
+// The backend, called in place of the platform.
+
+pub(crate) fn core_ids() -> Result<CoreIds, Error> {
+    with_state(|state| Ok(to_core_ids(&state.thread_mask(thread::current().id()))))
+}
+
+pub(crate) fn get_all_core_ids() -> Option<Vec<CoreId>> {
+    with_state(|state| Some(state.online.to_vec()))
+}
+
+pub(crate) fn core_ids_by_state(state: CoreState) -> Option<Vec<CoreId>> {
+    with_state(|mock| {
+        let possible = &mock.possible | &mock.online;
+
+        let core_ids = match state {
+            CoreState::Online => mock.online.clone(),
+            CoreState::Offline => &possible - &mock.online,
+            CoreState::Present => &mock.present | &mock.online,
+            CoreState::Possible => possible,
+        };
+
+        Some(core_ids.to_vec())
+    })
+}
+
+pub(crate) fn get_affinity_for_current() -> Result<Vec<CoreId>, Error> {
+    with_state(|state| Ok(state.thread_mask(thread::current().id()).to_vec()))
+}
+
+pub(crate) fn set_for_current(core_id: CoreId) -> Result<(), Error> {
+    set_for_current_set(&[core_id])
+}
+
+pub(crate) fn set_for_current_set(core_ids: &[CoreId]) -> Result<(), Error> {
+    with_state(|state| state.set_thread(thread::current().id(), core_ids))
+}
+
+pub(crate) fn set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId) -> Result<(), Error> {
+    with_state(|state| state.set_thread(handle.thread().id(), &[core_id]))
+}
+
+pub(crate) fn get_for_thread<T>(handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    with_state(|state| Ok(state.thread_mask(handle.thread().id()).to_vec()))
+}
+
+pub(crate) fn set_for_process(pid: u32, core_ids: &[CoreId]) -> Result<(), Error> {
+    with_state(|state| {
+        let result = state.check(core_ids, &state.online);
+
+        if result.is_ok() {
+            if pid == process::id() {
+                // Like the kernel, this restricts every thread.
+                state.process = core_ids.into();
+                state.threads.clear();
+            }
+            else {
+                state.processes.insert(pid, core_ids.into());
+            }
+        }
+
+        state.record(Target::Process(pid), core_ids, result)
+    })
+}
+
+pub(crate) fn get_for_process(pid: u32) -> Result<Vec<CoreId>, Error> {
+    with_state(|state| {
+        if pid == process::id() {
+            Ok(state.process.to_vec())
+        }
+        else {
+            Ok(state.processes.get(&pid).unwrap_or(&state.online).to_vec())
+        }
+    })
+}
+
+// The child is a real process, so it is not restricted, but
+// invalid sets of cores still make the spawn fail.
+#[cfg(unix)]
+pub(crate) fn set_for_command(command: &mut Command, core_ids: &[CoreId]) {
+    let result = with_state(|state| {
+        let result = state.check(core_ids, &state.online);
+        state.record(Target::Command, core_ids, result)
+    });
+
+    unsafe {
+        command.pre_exec(move || result.map_err(Error::into_simple_io_error));
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn spawn_with_affinity(command: &mut Command, core_ids: &[CoreId]) -> io::Result<Child> {
+    with_state(|state| {
+        let result = state.check(core_ids, &state.online);
+        state.record(Target::Command, core_ids, result)
+    })?;
+
+    command.spawn()
+}
+
+pub(crate) fn current_core() -> Result<CoreId, Error> {
+    // Pretend the thread runs on the first core it may use.
+    with_state(|state| {
+        state.thread_mask(thread::current().id())
+            .iter()
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::EmptySet))
+    })
+}
+
+pub(crate) fn current_location() -> Result<(CoreId, NumaNode), Error> {
+    let core_id = current_core()?;
+    let nodes = numa_nodes().unwrap_or_default();
+
+    nodes.into_iter()
+        .find(|(_, core_ids)| core_ids.contains(&core_id))
+        .map(|(node, _)| (core_id, node))
+        .ok_or_else(|| Error::new(ErrorKind::Unsupported))
+}
+
+pub(crate) fn set_preferred_for_current(core_id: CoreId) -> Result<(), Error> {
+    with_state(|state| {
+        if state.online.contains(core_id) {
+            Ok(())
+        }
+        else {
+            Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id))
+        }
+    })
+}
+
+pub(crate) fn set_priority_for_current(priority: Priority) -> Result<(), Error> {
+    with_state(|state| match state.priority_failure {
+        Some(kind) => Err(Error::new(kind)),
+        None => {
+            state.priorities.insert(thread::current().id(), priority);
+            Ok(())
+        },
+    })
+}
+
+pub(crate) fn core_siblings() -> Option<Vec<Vec<CoreId>>> {
+    with_state(|state| Some(state.siblings.clone()))
+}
+
+pub(crate) fn numa_nodes() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    with_state(|state| {
+        let nodes = match state.numa_nodes {
+            Some(ref nodes) => nodes.clone(),
+            None => vec![state.online.to_vec()],
+        };
+
+        Some(nodes.into_iter().enumerate().map(|(id, core_ids)| {
+            (NumaNode { id }, core_ids.into_iter().filter(|&core_id| state.online.contains(core_id)).collect())
+        }).collect())
+    })
+}
+
+pub(crate) fn packages() -> Option<Vec<(usize, Vec<CoreId>)>> {
+    with_state(|state| {
+        let packages = match state.packages {
+            Some(ref packages) => packages.clone(),
+            None => vec![state.online.to_vec()],
+        };
+
+        Some(packages.into_iter().enumerate().map(|(id, core_ids)| {
+            (id, core_ids.into_iter().filter(|&core_id| state.online.contains(core_id)).collect())
+        }).collect())
+    })
+}
+
+pub(crate) fn dies() -> Option<Vec<Vec<CoreId>>> {
+    with_state(|state| state.dies.as_ref().map(|dies| online_groups(state, dies)))
+}
+
+pub(crate) fn stable_core_ids() -> Option<Vec<(CoreId, StableCoreId)>> {
+    with_state(|state| state.hardware_ids.as_ref().map(|ids| {
+        number_threads(ids.clone()).into_iter().filter(|&(core_id, _)| state.online.contains(core_id)).collect()
+    }))
+}
+
+pub(crate) fn clusters() -> Option<Vec<Vec<CoreId>>> {
+    with_state(|state| state.clusters.as_ref().map(|clusters| online_groups(state, clusters)))
+}
+
+pub(crate) fn cache_groups(level: usize) -> Option<Vec<Vec<CoreId>>> {
+    with_state(|state| state.caches.get(&level).map(|groups| online_groups(state, groups)))
+}
+
+pub(crate) fn cache_info(_core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    with_state(|state| state.cache_info.clone())
+}
+
+pub(crate) fn core_kinds() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    with_state(|state| state.core_kinds.clone())
+}
+
+pub(crate) fn max_frequency_khz(core_id: CoreId) -> Option<u64> {
+    with_state(|state| state.max_frequencies.get(&core_id).cloned())
+}
+
+pub(crate) fn current_frequency_khz(core_id: CoreId) -> Option<u64> {
+    with_state(|state| state.current_frequencies.get(&core_id).cloned())
+}
+
+pub(crate) fn core_rankings() -> Option<Vec<(CoreId, u64)>> {
+    with_state(|state| state.rankings.clone())
+}
+
+pub(crate) fn cpu_times() -> Option<Vec<(CoreId, CpuTimes)>> {
+    with_state(|state| state.cpu_times.as_ref().map(|times| {
+        times.iter().cloned().filter(|&(core_id, _)| state.online.contains(core_id)).collect()
+    }))
+}
+
+pub(crate) fn capacity(core_id: CoreId) -> Option<u32> {
+    with_state(|state| state.capacities.get(&core_id).cloned())
+}
+
+fn online_groups(state: &State, groups: &[Vec<CoreId>]) -> Vec<Vec<CoreId>> {
+    groups.iter().map(|core_ids| {
+        core_ids.iter().cloned().filter(|&core_id| state.online.contains(core_id)).collect()
+    }).collect()
+}
+
+pub(crate) fn thread_core_ids() -> Result<CoreIds, Error> {
+    core_ids()
+}
+
+pub(crate) fn process_core_ids() -> Result<CoreIds, Error> {
+    with_state(|state| Ok(to_core_ids(&state.process)))
+}
+
+pub(crate) fn capabilities() -> Capabilities {
+    Capabilities {
+        hard_pinning: true,
+        soft_affinity: true,
+        can_query_affinity: true,
+        max_cores_representable: MAX_CORES,
+    }
+}
+
+pub(crate) fn clear_for_current() -> Result<(), Error> {
+    with_state(|state| {
+        let current = thread::current().id();
+        let process = state.process.to_vec();
+
+        state.threads.remove(&current);
+        state.record(Target::Thread(current), &process, Ok(()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use super::super::{clear_for_current, current_core, get_affinity_for_current, get_core_ids, get_for_thread};
+    use super::super::{set_for_current, set_for_thread, testing};
+
+    // These tests leave the settings alone, since the other
+    // tests of the crate run against the same mock. The
+    // settings are covered in `tests/mock.rs`.
+
+    #[test]
+    fn test_mock_current_thread() {
+        let current = thread::current().id();
+        let core_id = CoreId { id: 2 };
+
+        assert!(set_for_current(core_id));
+        assert_eq!(get_affinity_for_current(), Some(vec![core_id]));
+        assert_eq!(current_core(), Some(core_id));
+
+        // Other threads keep the mask of the process.
+        let other = thread::spawn(|| {
+            assert!(set_calls().is_empty());
+            get_affinity_for_current()
+        }).join().unwrap();
+        assert_eq!(other, Some(range(DEFAULT_CORE_COUNT).to_vec()));
+
+        assert!(clear_for_current());
+        assert_eq!(get_core_ids().unwrap().len(), DEFAULT_CORE_COUNT);
+
+        let calls = set_calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].target, Target::Thread(current));
+        assert_eq!(calls[0].core_ids, vec![core_id]);
+        assert_eq!(calls[0].result, Ok(()));
+        assert_eq!(calls[1].core_ids, range(DEFAULT_CORE_COUNT).to_vec());
+
+        clear_set_calls();
+        assert!(set_calls().is_empty());
+    }
+
+    #[test]
+    fn test_mock_other_thread() {
+        let core_id = CoreId { id: 3 };
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let handle = thread::spawn(move || {
+            rx.recv().unwrap();
+            get_affinity_for_current()
+        });
+        let target = Target::Thread(handle.thread().id());
+
+        assert!(set_for_thread(&handle, core_id));
+        assert_eq!(get_for_thread(&handle), Some(vec![core_id]));
+
+        tx.send(()).unwrap();
+        assert_eq!(handle.join().unwrap(), Some(vec![core_id]));
+
+        // The call is recorded for the thread that made it.
+        assert_eq!(set_calls().last().unwrap().target, target);
+        assert!(all_set_calls().iter().any(|call| call.target == target));
+    }
+    #[test]
+    fn test_mock_set_for_current() {
+        testing::check_set_for_current();
+    }
+
+    #[test]
+    fn test_mock_set_for_current_set() {
+        testing::check_set_for_current_set();
+    }
+
+    #[test]
+    fn test_mock_get_all_core_ids_when_pinned() {
+        testing::check_get_all_core_ids_when_pinned();
+    }
+
+    #[test]
+    fn test_mock_set_for_thread() {
+        testing::check_set_for_thread();
+    }
+
+    #[test]
+    fn test_mock_clear_for_current() {
+        testing::check_clear_for_current();
+    }
+}
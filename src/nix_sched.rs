@@ -0,0 +1,128 @@
+//! `nix` support, enabled by the `nix` feature on Linux and
+//! Android.
+//!
+//! `nix::sched::CpuSet` wraps a `cpu_set_t`, so it holds the
+//! cores below `nix::sched::CpuSet::count()`, usually 1024.
+//! Every such core converts to a `CpuSet`, while the other way
+//! fails for the cores above.
+//!
+//! The orphan rules rule out `From` between `Vec<CoreId>` and
+//! `nix::sched::CpuSet`, so `from_nix_cpu_set` and
+//! `to_nix_cpu_set` stand in for it.
+
+use std::convert::TryFrom;
+
+use nix::sched::CpuSet as NixCpuSet;
+
+use super::{CoreId, CpuSet, Error, ErrorKind};
+
+impl From<&NixCpuSet> for CpuSet {
+    fn from(set: &NixCpuSet) -> CpuSet {
+        (0..NixCpuSet::count())
+            .filter(|&id| set.is_set(id).unwrap_or(false))
+            .map(|id| CoreId { id })
+            .collect()
+    }
+}
+
+impl From<NixCpuSet> for CpuSet {
+    fn from(set: NixCpuSet) -> CpuSet {
+        CpuSet::from(&set)
+    }
+}
+
+impl TryFrom<&CpuSet> for NixCpuSet {
+    type Error = Error;
+
+    /// Fails with `ErrorKind::InvalidCore` for a core at or
+    /// above `nix::sched::CpuSet::count()`.
+    fn try_from(set: &CpuSet) -> Result<NixCpuSet, Error> {
+        let mut nix_set = NixCpuSet::new();
+
+        for core_id in set {
+            nix_set.set(core_id.id).map_err(|_| Error::new(ErrorKind::InvalidCore).with_core_id(core_id))?;
+        }
+
+        Ok(nix_set)
+    }
+}
+
+impl TryFrom<CpuSet> for NixCpuSet {
+    type Error = Error;
+
+    fn try_from(set: CpuSet) -> Result<NixCpuSet, Error> {
+        NixCpuSet::try_from(&set)
+    }
+}
+
+/// This function returns the cores of a `nix::sched::CpuSet`,
+/// such as the one `nix::sched::sched_getaffinity` returns, in
+/// ascending order.
+///
+/// # Arguments
+///
+/// * set - the set to convert
+pub fn from_nix_cpu_set(set: &NixCpuSet) -> Vec<CoreId> {
+    CpuSet::from(set).to_vec()
+}
+
+/// This function puts `core_ids` into a `nix::sched::CpuSet`,
+/// such as for `nix::sched::sched_setaffinity`.
+///
+/// Fails with `ErrorKind::InvalidCore` for a core at or above
+/// `nix::sched::CpuSet::count()`.
+///
+/// # Arguments
+///
+/// * core_ids - the cores to put into the set
+pub fn to_nix_cpu_set(core_ids: &[CoreId]) -> Result<NixCpuSet, Error> {
+    NixCpuSet::try_from(&CpuSet::from(core_ids))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "mock"))]
+    use nix::sched::sched_getaffinity;
+    #[cfg(not(feature = "mock"))]
+    use nix::unistd::Pid;
+
+    use super::*;
+    #[cfg(not(feature = "mock"))]
+    use super::super::get_affinity_for_current;
+
+    #[test]
+    fn test_nix_round_trip() {
+        // Cores in the first, second and last word of the mask.
+        let max = NixCpuSet::count() - 1;
+        let core_ids: Vec<CoreId> = [0, 5, 63, 64, 127, 128, max].iter().map(|&id| CoreId { id }).collect();
+
+        let nix_set = to_nix_cpu_set(&core_ids).unwrap();
+        for id in 0..NixCpuSet::count() {
+            assert_eq!(nix_set.is_set(id).unwrap(), core_ids.contains(&CoreId { id }));
+        }
+
+        assert_eq!(from_nix_cpu_set(&nix_set), core_ids);
+
+        let set = CpuSet::from(core_ids);
+        assert_eq!(CpuSet::from(NixCpuSet::try_from(&set).unwrap()), set);
+        assert_eq!(CpuSet::from(NixCpuSet::new()), CpuSet::new());
+    }
+
+    #[test]
+    fn test_nix_too_large() {
+        let core_id = CoreId { id: NixCpuSet::count() };
+        let err = to_nix_cpu_set(&[CoreId { id: 0 }, core_id]).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.core_id(), Some(core_id));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn test_nix_sched_getaffinity() {
+        // Pid 0 is the calling thread.
+        let nix_set = sched_getaffinity(Pid::from_raw(0)).unwrap();
+
+        assert_eq!(from_nix_cpu_set(&nix_set), get_affinity_for_current().unwrap());
+    }
+}
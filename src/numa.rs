@@ -0,0 +1,273 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use super::{CoreId, CpuSet, Error, ErrorKind, allowed_for_process, numa_nodes_helper, record};
+use super::{current_location_helper, try_set_for_current_set};
+
+/// A NUMA node: a group of cores with the memory closest to
+/// them, as numbered by the operating system.
+///
+/// Node IDs need not be consecutive, and nodes with memory but
+/// no cores are listed too.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NumaNode {
+    pub id: usize,
+}
+
+impl fmt::Display for NumaNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// This function returns the NUMA nodes of the system, in
+/// ascending order.
+///
+/// A machine without NUMA has a single node, which is reported
+/// as node 0 rather than as `None`. Linux lists the nodes in
+/// `/sys/devices/system/node`, Windows asks
+/// `GetNumaHighestNodeNumber` and FreeBSD reads `vm.ndomains`.
+/// macOS always reports a single node. Other platforms return
+/// `None`.
+pub fn numa_nodes() -> Option<Vec<NumaNode>> {
+    node_cores().map(|nodes| nodes.into_iter().map(|(node, _)| node).collect())
+}
+
+/// This function returns the online cores of the NUMA node
+/// `node`, in ascending order, such as for sizing a thread pool
+/// per node.
+///
+/// Every online core is on exactly one node. A node with only
+/// memory has no cores. Returns `None` for a node that does not
+/// exist, and where `numa_nodes` does.
+///
+/// # Arguments
+///
+/// * node - the node to look up
+pub fn cores_for_numa_node(node: NumaNode) -> Option<Vec<CoreId>> {
+    node_cores()?.into_iter().find(|&(other, _)| other == node).map(|(_, core_ids)| core_ids)
+}
+
+/// This function confines the current thread to the cores of
+/// the NUMA node `node`, so that it may run on any of them.
+///
+/// Only the cores in the affinity mask of the process are
+/// used, as with `set_for_current_excluding`. It fails with
+/// `ErrorKind::NotAllowed` if none of the cores of the node is
+/// allowed, with `ErrorKind::EmptySet` for a node without
+/// cores and with `ErrorKind::InvalidCore` for a node that
+/// does not exist, all recording `node` in the error. Where the
+/// nodes are not known, it fails with `ErrorKind::Unsupported`.
+///
+/// # Arguments
+///
+/// * node - the node to run on
+pub fn set_for_current_numa_node(node: NumaNode) -> Result<(), Error> {
+    let allowed = record(allowed_for_process())?;
+    let nodes = record(node_cores().ok_or_else(|| Error::new(ErrorKind::Unsupported)))?;
+
+    let core_ids = match nodes.into_iter().find(|&(other, _)| other == node) {
+        Some((_, ref core_ids)) if core_ids.is_empty() => Err(Error::new(ErrorKind::EmptySet)),
+        Some((_, core_ids)) => match (CpuSet::from(core_ids) & allowed).to_vec() {
+            ref core_ids if core_ids.is_empty() => Err(Error::new(ErrorKind::NotAllowed)),
+            core_ids => Ok(core_ids),
+        },
+        None => Err(Error::new(ErrorKind::InvalidCore)),
+    };
+
+    let core_ids = record(core_ids.map_err(|err| err.with_numa_node(node)))?;
+
+    record(try_set_for_current_set(&core_ids))
+}
+
+/// This function returns the NUMA node of `core_id`, or `None`
+/// for a core that is not online or where the nodes are not
+/// known.
+///
+/// The first call builds a table of the node of every core, so
+/// later calls neither allocate nor query the system. The table
+/// is not updated when cores go on- or offline.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn numa_node_of(core_id: CoreId) -> Option<NumaNode> {
+    static TABLE: OnceLock<Vec<Option<NumaNode>>> = OnceLock::new();
+
+    // The nodes of the mock change between tests.
+    if cfg!(feature = "mock") {
+        return node_table().get(core_id.id).cloned().flatten();
+    }
+
+    TABLE.get_or_init(node_table).get(core_id.id).cloned().flatten()
+}
+
+/// This function returns the NUMA node the current thread is
+/// running on.
+///
+/// Like `current_core`, the answer is only advisory: unless the
+/// thread is confined to the cores of one node, it may move to
+/// another node right after. It is `current_location` without
+/// the core. Returns `None` where the node can not be found.
+pub fn current_numa_node() -> Option<NumaNode> {
+    current_location().map(|(_, node)| node)
+}
+
+/// This function returns the core the current thread is
+/// running on together with its NUMA node, from a single system
+/// call where the platform has one.
+///
+/// Linux asks `getcpu`, and Windows
+/// `GetCurrentProcessorNumberEx` and `GetNumaProcessorNodeEx`.
+/// FreeBSD looks the node up with `numa_node_of`. The answer is
+/// advisory, as for `current_numa_node`, but the core and the
+/// node are read together, so they match. Returns `None` where
+/// `current_core` is not supported, such as on macOS.
+pub fn current_location() -> Option<(CoreId, NumaNode)> {
+    current_location_helper().ok()
+}
+
+// The node of every core, indexed by core.
+fn node_table() -> Vec<Option<NumaNode>> {
+    let mut table = Vec::new();
+
+    for (node, core_ids) in node_cores().unwrap_or_default() {
+        for core_id in core_ids {
+            if table.len() <= core_id.id {
+                table.resize(core_id.id + 1, None);
+            }
+
+            table[core_id.id] = Some(node);
+        }
+    }
+
+    table
+}
+
+// Every node with its online cores, sorted by node.
+pub(crate) fn node_cores() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    let mut nodes = numa_nodes_helper()?;
+
+    for (_, core_ids) in &mut nodes {
+        core_ids.sort();
+        core_ids.dedup();
+    }
+
+    nodes.sort();
+    nodes.dedup_by_key(|&mut (node, _)| node);
+
+    Some(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    use super::super::{clear_for_current, get_affinity_for_current, get_all_core_ids, set_for_current};
+
+    #[test]
+    fn test_numa_nodes() {
+        let nodes = match numa_nodes() {
+            Some(nodes) => nodes,
+            None => return,
+        };
+
+        assert!(!nodes.is_empty());
+        assert!(nodes.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Every core is on one of the nodes.
+        let node_cores = node_cores().unwrap();
+
+        for core_id in get_all_core_ids().unwrap() {
+            assert!(node_cores.iter().any(|(_, core_ids)| core_ids.contains(&core_id)));
+        }
+    }
+
+    #[test]
+    fn test_cores_for_numa_node() {
+        let nodes = match numa_nodes() {
+            Some(nodes) => nodes,
+            None => return,
+        };
+
+        let mut all = CpuSet::new();
+        let mut count = 0;
+
+        for &node in &nodes {
+            let core_ids = cores_for_numa_node(node).unwrap();
+
+            count += core_ids.len();
+            all.extend(core_ids);
+        }
+
+        // The nodes split the cores without overlapping.
+        assert_eq!(all.to_vec(), get_all_core_ids().unwrap());
+        assert_eq!(count, all.len());
+
+        let missing = NumaNode { id: nodes.last().unwrap().id + 1 };
+        assert_eq!(cores_for_numa_node(missing), None);
+    }
+
+    #[test]
+    fn test_numa_node_of() {
+        let nodes = match numa_nodes() {
+            Some(nodes) => nodes,
+            None => return,
+        };
+
+        for node in nodes {
+            for core_id in cores_for_numa_node(node).unwrap() {
+                assert_eq!(numa_node_of(core_id), Some(node));
+            }
+        }
+
+        assert_eq!(numa_node_of(CoreId { id: usize::MAX }), None);
+    }
+
+    #[test]
+    fn test_current_location() {
+        let core_id = match get_all_core_ids().and_then(|core_ids| core_ids.last().cloned()) {
+            Some(core_id) => core_id,
+            None => return,
+        };
+
+        let location = thread::spawn(move || {
+            assert!(set_for_current(core_id));
+            (current_location(), current_numa_node())
+        }).join().unwrap();
+
+        // Not supported on macOS.
+        if let (Some(location), node) = location {
+            assert_eq!(location, (core_id, numa_node_of(core_id).unwrap()));
+            assert_eq!(node, Some(location.1));
+        }
+    }
+
+    #[test]
+    fn test_set_for_current_numa_node() {
+        let nodes = match numa_nodes() {
+            Some(nodes) => nodes,
+            None => return,
+        };
+
+        let node = nodes.into_iter().find(|&node| !cores_for_numa_node(node).unwrap().is_empty()).unwrap();
+        match set_for_current_numa_node(node) {
+            Err(ref err) if err.kind() == ErrorKind::NotAllowed => return,
+            result => result.unwrap(),
+        }
+
+        // The mask reads back as part of the node.
+        if let Some(core_ids) = get_affinity_for_current() {
+            let node_cores = cores_for_numa_node(node).unwrap();
+            assert!(core_ids.iter().all(|core_id| node_cores.contains(core_id)));
+        }
+
+        assert!(clear_for_current());
+
+        let missing = NumaNode { id: usize::MAX };
+        let err = set_for_current_numa_node(missing).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.numa_node(), Some(missing));
+    }
+}
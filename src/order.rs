@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use super::{CoreId, get_core_ids};
+use allocator::interleave;
+use distance::{Groups, L2, L3, NODE, PACKAGE, PHYSICAL, group_table};
+
+/// The order `get_core_ids_ordered` lists the cores in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Order {
+    /// By ID, as `get_core_ids` does.
+    #[default]
+    Numeric,
+    /// Package by package, and within a package NUMA node by
+    /// node, then by ID. Machines that number the cores of
+    /// their sockets in turn list all of socket 0 first.
+    PackageMajor,
+    /// Like `PackageMajor`, but within a package cache cluster
+    /// by cluster, then L2 group by group, then physical core
+    /// by physical core, so that any prefix shares as much
+    /// cache as it can.
+    CacheMajor,
+    /// A logical core of every physical core first, in ID
+    /// order, then a second one of every physical core that has
+    /// more, and so on, so that a prefix avoids SMT siblings as
+    /// long as it can.
+    SmtLast,
+}
+
+/// This function is like `get_core_ids`, but lists the cores
+/// in `order`, so that the first `n` of them are close
+/// together in the machine rather than merely low in number.
+///
+/// The order only depends on the topology and on the affinity
+/// mask of the current thread, so it is the same from one call
+/// to the next until either changes. Parts of the topology the
+/// platform does not report are left out of the order, so
+/// where none is known, every order is `Order::Numeric`.
+///
+/// # Arguments
+///
+/// * order - the order to list the cores in
+pub fn get_core_ids_ordered(order: Order) -> Option<Vec<CoreId>> {
+    let mut core_ids = get_core_ids()?;
+    let table = group_table();
+    let groups = |core_id: CoreId| -> Groups { table.get(core_id.id).cloned().flatten().unwrap_or_default() };
+
+    match order {
+        Order::Numeric => (),
+        Order::PackageMajor => core_ids.sort_by_key(|&core_id| {
+            let groups = groups(core_id);
+            (groups[PACKAGE], groups[NODE], core_id)
+        }),
+        Order::CacheMajor => core_ids.sort_by_key(|&core_id| {
+            let groups = groups(core_id);
+            (groups[PACKAGE], groups[L3], groups[L2], groups[PHYSICAL], core_id)
+        }),
+        Order::SmtLast => {
+            // Cores of unknown physical cores are cores of their
+            // own.
+            let mut physical: BTreeMap<_, Vec<CoreId>> = BTreeMap::new();
+
+            for &core_id in &core_ids {
+                let key = groups(core_id)[PHYSICAL].ok_or(core_id);
+                physical.entry(key).or_default().push(core_id);
+            }
+
+            let mut siblings: Vec<_> = physical.into_values().collect();
+            siblings.sort();
+            core_ids = interleave(siblings);
+        },
+    }
+
+    Some(core_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_core_ids_ordered() {
+        let core_ids = match get_core_ids() {
+            Some(core_ids) => core_ids,
+            None => return,
+        };
+
+        assert_eq!(get_core_ids_ordered(Order::Numeric), Some(core_ids.clone()));
+
+        // Every order lists the same cores, the same way each
+        // time.
+        for &order in &[Order::PackageMajor, Order::CacheMajor, Order::SmtLast] {
+            let mut ordered = get_core_ids_ordered(order).unwrap();
+            assert_eq!(get_core_ids_ordered(order), Some(ordered.clone()));
+
+            ordered.sort();
+            assert_eq!(ordered, core_ids);
+        }
+    }
+}
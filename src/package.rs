@@ -0,0 +1,241 @@
+use std::fmt;
+
+use super::{CoreId, clusters_helper, dies_helper, packages_helper};
+
+/// A package, or socket: one physical chip in the machine.
+///
+/// Packages are numbered `0..n` in the order of the IDs the
+/// operating system gives them, which need not be consecutive.
+/// `raw_id` returns the ID of the operating system.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PackageId {
+    pub id: usize,
+    raw: usize,
+}
+
+impl PackageId {
+    /// Returns the ID the operating system gives the package,
+    /// such as `physical_package_id` on Linux. Windows does not
+    /// number packages, so there it is the same as `id`.
+    pub fn raw_id(&self) -> usize {
+        self.raw
+    }
+}
+
+impl fmt::Display for PackageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// A die: one of the chips that make up a package.
+///
+/// Dies are numbered `0..n` across the whole machine, package
+/// by package, whereas Linux numbers them within each package.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DieId {
+    pub id: usize,
+}
+
+impl fmt::Display for DieId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// A cluster: a group of cores that share an L2 cache, as on
+/// ARM, where the clusters of a big.LITTLE design also differ
+/// in their microarchitecture.
+///
+/// Clusters are numbered `0..n` across the whole machine.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClusterId {
+    pub id: usize,
+}
+
+impl fmt::Display for ClusterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// This function returns the packages of the system with their
+/// online cores, both in ascending order.
+///
+/// Every online core is on exactly one package. The number of
+/// packages need not match the number of NUMA nodes: a package
+/// may be split into several nodes, and a node may have no
+/// cores. Linux reads `physical_package_id` in sysfs, Windows
+/// asks `GetLogicalProcessorInformationEx` and FreeBSD reads
+/// `kern.sched.topology_spec`. macOS always reports a single
+/// package. Other platforms, and Linux systems that do not know
+/// the package of a core, return `None`.
+pub fn packages() -> Option<Vec<(PackageId, Vec<CoreId>)>> {
+    packages_helper().map(number)
+}
+
+/// This function returns the package of `core_id`, or `None`
+/// for a core that is not online or where the packages are not
+/// known.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn package_of(core_id: CoreId) -> Option<PackageId> {
+    packages()?.into_iter().find(|(_, core_ids)| core_ids.contains(&core_id)).map(|(package, _)| package)
+}
+
+/// This function returns the dies of the system with their
+/// online cores, both in ascending order.
+///
+/// Packages with several dies, such as some Xeon and EPYC
+/// parts, are much slower to cross between dies than within
+/// one. Linux reads `die_id` in sysfs, which kernels before 5.2
+/// do not have, and Windows asks
+/// `GetLogicalProcessorInformationEx` for `RelationProcessorDie`,
+/// which Windows 10 before version 2004 fails. Other platforms
+/// return `None`.
+pub fn dies() -> Option<Vec<(DieId, Vec<CoreId>)>> {
+    dies_helper().map(|dies| number_groups(dies, |id| DieId { id }))
+}
+
+/// This function returns the die of `core_id`, or `None` for a
+/// core that is not online or where the dies are not known.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn die_of(core_id: CoreId) -> Option<DieId> {
+    dies()?.into_iter().find(|(_, core_ids)| core_ids.contains(&core_id)).map(|(die, _)| die)
+}
+
+/// This function returns the clusters of the system with their
+/// online cores, both in ascending order.
+///
+/// Linux reads `cluster_id` in sysfs, and on kernels before
+/// 5.16 groups the cores by `core_siblings_list` instead, which
+/// ARM kernels of that time filled with the cluster. Other
+/// platforms return `None`. `CoreAllocator::within_cluster`
+/// keeps a pool of threads on one of them.
+pub fn clusters() -> Option<Vec<(ClusterId, Vec<CoreId>)>> {
+    clusters_helper().map(|clusters| number_groups(clusters, |id| ClusterId { id }))
+}
+
+/// This function returns the cluster of `core_id`, or `None`
+/// for a core that is not online or where the clusters are not
+/// known.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn cluster_of(core_id: CoreId) -> Option<ClusterId> {
+    clusters()?.into_iter().find(|(_, core_ids)| core_ids.contains(&core_id)).map(|(cluster, _)| cluster)
+}
+
+// Numbers groups of cores that are already in order.
+fn number_groups<T, F>(groups: Vec<Vec<CoreId>>, id: F) -> Vec<(T, Vec<CoreId>)>
+    where F: Fn(usize) -> T
+{
+    groups.into_iter().enumerate().map(|(i, mut core_ids)| {
+        core_ids.sort();
+        core_ids.dedup();
+        (id(i), core_ids)
+    }).collect()
+}
+
+// Numbers the packages by their raw IDs, merging any that share
+// one.
+fn number(mut packages: Vec<(usize, Vec<CoreId>)>) -> Vec<(PackageId, Vec<CoreId>)> {
+    packages.sort_by_key(|&(raw, _)| raw);
+
+    let mut numbered: Vec<(PackageId, Vec<CoreId>)> = Vec::new();
+
+    for (raw, core_ids) in packages {
+        match numbered.last_mut() {
+            Some((package, all)) if package.raw == raw => all.extend(core_ids),
+            _ => numbered.push((PackageId { id: numbered.len(), raw }, core_ids)),
+        }
+    }
+
+    for (_, core_ids) in &mut numbered {
+        core_ids.sort();
+        core_ids.dedup();
+    }
+
+    numbered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::get_all_core_ids;
+
+    #[test]
+    fn test_number() {
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+        let packages = number(vec![(4, ids(&[3, 1])), (1, ids(&[0])), (4, ids(&[2]))]);
+
+        assert_eq!(packages, vec![
+            (PackageId { id: 0, raw: 1 }, ids(&[0])),
+            (PackageId { id: 1, raw: 4 }, ids(&[1, 2, 3])),
+        ]);
+        assert_eq!(packages[1].0.raw_id(), 4);
+    }
+
+    #[test]
+    fn test_packages() {
+        let packages = match packages() {
+            Some(packages) => packages,
+            None => return,
+        };
+
+        assert!(!packages.is_empty());
+
+        for (i, (package, _)) in packages.iter().enumerate() {
+            assert_eq!(package.id, i);
+        }
+
+        // Every core is on exactly one package.
+        for core_id in get_all_core_ids().unwrap() {
+            let count = packages.iter().filter(|(_, core_ids)| core_ids.contains(&core_id)).count();
+
+            assert_eq!(count, 1);
+            assert!(package_of(core_id).is_some());
+        }
+
+        assert_eq!(package_of(CoreId { id: usize::MAX }), None);
+    }
+
+    #[test]
+    fn test_dies() {
+        let dies = match dies() {
+            Some(dies) => dies,
+            None => return,
+        };
+
+        for core_id in get_all_core_ids().unwrap() {
+            let count = dies.iter().filter(|(_, core_ids)| core_ids.contains(&core_id)).count();
+
+            assert_eq!(count, 1);
+            assert!(die_of(core_id).is_some());
+        }
+
+        assert_eq!(die_of(CoreId { id: usize::MAX }), None);
+    }
+
+    #[test]
+    fn test_clusters() {
+        let clusters = match clusters() {
+            Some(clusters) => clusters,
+            None => return,
+        };
+
+        for core_id in get_all_core_ids().unwrap() {
+            let count = clusters.iter().filter(|(_, core_ids)| core_ids.contains(&core_id)).count();
+
+            assert_eq!(count, 1);
+            assert!(cluster_of(core_id).is_some());
+        }
+    }
+}
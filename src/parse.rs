@@ -0,0 +1,146 @@
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use super::CoreId;
+
+/// A list specifying the reasons parsing core IDs can fail.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// The input, or a part of it, is empty.
+    Empty,
+    /// The input contains a character that is not a digit.
+    InvalidDigit,
+    /// The input is negative.
+    Negative,
+    /// The number is too large for a `usize`, or a
+    /// range spans too many cores.
+    Overflow,
+    /// A range ends before it starts.
+    InvalidRange,
+    /// A stride is zero or selects more cores than
+    /// its group contains.
+    InvalidStride,
+}
+
+impl ParseErrorKind {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            ParseErrorKind::Empty => "empty core ID",
+            ParseErrorKind::InvalidDigit => "invalid digit",
+            ParseErrorKind::Negative => "negative core ID",
+            ParseErrorKind::Overflow => "core ID too large",
+            ParseErrorKind::InvalidRange => "range ends before it starts",
+            ParseErrorKind::InvalidStride => "invalid stride",
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The error type for parsing core IDs.
+///
+/// It records the byte offset of the offending
+/// character, so callers can point at the mistake.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    offset: usize,
+}
+
+impl ParseError {
+    pub(crate) fn new(kind: ParseErrorKind, offset: usize) -> ParseError {
+        ParseError { kind, offset }
+    }
+
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+
+    /// Returns the byte offset in the input at which parsing failed.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.offset)
+    }
+}
+
+impl error::Error for ParseError {}
+
+// Parse the decimal number in `s`, which starts at byte
+// `offset` of the whole input.
+pub(crate) fn parse_core_id(s: &str, offset: usize) -> Result<CoreId, ParseError> {
+    if s.is_empty() {
+        return Err(ParseError::new(ParseErrorKind::Empty, offset));
+    }
+
+    let mut id: usize = 0;
+
+    for (i, c) in s.char_indices() {
+        let digit = match c.to_digit(10) {
+            Some(digit) => digit as usize,
+            None if c == '-' && i == 0 => {
+                return Err(ParseError::new(ParseErrorKind::Negative, offset));
+            },
+            None => return Err(ParseError::new(ParseErrorKind::InvalidDigit, offset + i)),
+        };
+
+        id = match id.checked_mul(10).and_then(|id| id.checked_add(digit)) {
+            Some(id) => id,
+            None => return Err(ParseError::new(ParseErrorKind::Overflow, offset)),
+        };
+    }
+
+    Ok(CoreId { id })
+}
+
+impl FromStr for CoreId {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<CoreId, ParseError> {
+        parse_core_id(s, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_core_id() {
+        assert_eq!("0".parse(), Ok(CoreId { id: 0 }));
+        assert_eq!("42".parse(), Ok(CoreId { id: 42 }));
+    }
+
+    #[test]
+    fn test_parse_core_id_errors() {
+        let err = |s: &str| s.parse::<CoreId>().unwrap_err();
+
+        assert_eq!(err(""), ParseError::new(ParseErrorKind::Empty, 0));
+        assert_eq!(err("-1"), ParseError::new(ParseErrorKind::Negative, 0));
+        assert_eq!(err("12a"), ParseError::new(ParseErrorKind::InvalidDigit, 2));
+        assert_eq!(err(" 1"), ParseError::new(ParseErrorKind::InvalidDigit, 0));
+        assert_eq!(err("99999999999999999999999"), ParseError::new(ParseErrorKind::Overflow, 0));
+        assert_eq!(err("12a").to_string(), "invalid digit at byte 2");
+    }
+
+    #[test]
+    fn test_parse_display_round_trip() {
+        let ids = (0..1000).chain((0..64).map(|shift| 1usize << shift)).chain(Some(usize::MAX));
+
+        for id in ids {
+            let core_id = CoreId { id };
+            assert_eq!(core_id.to_string().parse(), Ok(core_id));
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use std::fmt;
+#[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux")))]
+use std::fs;
+
+use super::{CoreId, CoreReservation, CpuSet, Error, ErrorKind, allowed_for_process, get_all_core_ids_helper};
+use super::{core_ids_helper, core_siblings_helper, is_reserved, record, reserve_specific, set_for_current_checked};
+use allocator::interleave;
+use shared_cache::{cache_clusters, l2_groups, partition};
+
+/// The ID of a physical core, as numbered by
+/// `get_physical_cores`.
+///
+/// Physical cores are numbered from 0 in the order of their
+/// lowest logical core, so the IDs stay the same as long as
+/// the online cores do. They do not depend on the affinity
+/// mask of the caller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysicalCoreId {
+    pub id: usize,
+}
+
+impl fmt::Display for PhysicalCoreId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// A physical core and the logical cores, or hardware threads,
+/// that run on it, as returned by `get_physical_cores`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PhysicalCore {
+    id: PhysicalCoreId,
+    siblings: Vec<CoreId>,
+}
+
+impl PhysicalCore {
+    /// Returns the ID of the physical core.
+    pub fn id(&self) -> PhysicalCoreId {
+        self.id
+    }
+
+    /// Returns the online logical cores of the physical core, in
+    /// ascending order. There is one for a core without
+    /// simultaneous multithreading, or with it turned off.
+    pub fn siblings(&self) -> &[CoreId] {
+        &self.siblings
+    }
+}
+
+/// This function returns every physical core that has an
+/// online logical core, ordered by ID.
+///
+/// Together, the siblings of the physical cores are exactly
+/// the cores of `get_all_core_ids`. Linux reads the siblings
+/// from `/sys/devices/system/cpu/cpu*/topology`, Windows asks
+/// `GetLogicalProcessorInformationEx` and FreeBSD parses
+/// `kern.sched.topology_spec`. macOS only reports the numbers
+/// of physical and logical cores, so the logical cores are
+/// assumed to be numbered core by core there. Other platforms
+/// return `None`.
+pub fn get_physical_cores() -> Option<Vec<PhysicalCore>> {
+    let online = get_all_core_ids_helper()?;
+    let groups = core_siblings_helper()?;
+
+    Some(number(groups, &online))
+}
+
+/// This function returns the other logical cores on the
+/// physical core of `core_id`, which share its execution units
+/// and caches. The list is empty without simultaneous
+/// multithreading, or with it turned off.
+///
+/// Returns `None` for a core that is not online, and where the
+/// platform does not report the siblings. That includes macOS,
+/// where `get_physical_cores` only infers them.
+///
+/// # Arguments
+///
+/// * core_id - the logical core to look up
+pub fn smt_siblings(core_id: CoreId) -> Option<Vec<CoreId>> {
+    if cfg!(all(not(feature = "mock"), target_os = "macos")) {
+        return None;
+    }
+
+    let cores = get_physical_cores()?;
+    let core = cores.iter().find(|core| core.siblings.contains(&core_id))?;
+
+    Some(core.siblings.iter().cloned().filter(|&sibling| sibling != core_id).collect())
+}
+
+/// This function tells whether simultaneous multithreading,
+/// or hyper-threading, is on: whether any physical core runs
+/// more than one online logical core.
+///
+/// Linux reads `/sys/devices/system/cpu/smt/active`, and other
+/// platforms compare the logical cores to the physical cores
+/// of `get_physical_cores`. Returns `None` where neither is
+/// known, which is not the same as `Some(false)`.
+pub fn smt_active() -> Option<bool> {
+    read_smt_active().or_else(|| {
+        get_physical_cores().map(|cores| cores.iter().any(|core| core.siblings.len() > 1))
+    })
+}
+
+#[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux")))]
+fn read_smt_active() -> Option<bool> {
+    // Kernels without SMT support do not have the file.
+    match fs::read_to_string("/sys/devices/system/cpu/smt/active").ok()?.trim() {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(any(feature = "mock", not(any(target_os = "android", target_os = "linux"))))]
+fn read_smt_active() -> Option<bool> {
+    None
+}
+
+/// This function pins the current thread to one of the logical
+/// cores of the physical core `physical`, and returns that
+/// core.
+///
+/// Of the siblings in the affinity mask of the process, the
+/// first one nobody has reserved with `reserve_core` or
+/// `reserve_specific` is used, or the first one if all of them
+/// are reserved. It fails with `ErrorKind::InvalidCore` for a
+/// physical core that does not exist, with
+/// `ErrorKind::NotAllowed` if none of its siblings is allowed,
+/// both recording `physical` in the error, and with
+/// `ErrorKind::Unsupported` where the physical cores are not
+/// known. Any other error comes from pinning.
+///
+/// # Arguments
+///
+/// * physical - ID of the physical core to pin to
+pub fn pin_to_physical_core(physical: PhysicalCoreId) -> Result<CoreId, Error> {
+    let allowed = record(allowed_for_process())?;
+    let cores = record(get_physical_cores().ok_or_else(|| Error::new(ErrorKind::Unsupported)))?;
+    let chosen = record(pick_sibling(&cores, physical, &allowed, is_reserved))?;
+
+    set_for_current_checked(chosen)?;
+
+    Ok(chosen)
+}
+
+// Picks the sibling of `physical` to pin to, preferring the
+// ones `reserved` says are free.
+fn pick_sibling<F>(cores: &[PhysicalCore], physical: PhysicalCoreId, allowed: &CpuSet, reserved: F)
+                   -> Result<CoreId, Error>
+    where F: Fn(CoreId) -> bool
+{
+    let core = cores.get(physical.id)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidCore).with_physical_core_id(physical))?;
+    let candidates: Vec<CoreId> = core.siblings.iter().cloned().filter(|&core_id| allowed.contains(core_id)).collect();
+
+    candidates.iter()
+        .find(|&&core_id| !reserved(core_id))
+        .or_else(|| candidates.first())
+        .cloned()
+        .ok_or_else(|| Error::new(ErrorKind::NotAllowed).with_physical_core_id(physical))
+}
+
+/// Which cores `colocate_pair_within` pairs up.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PairScope {
+    /// Two logical cores of one physical core, as with
+    /// `colocate_pair`.
+    #[default]
+    SmtSibling,
+    /// Two cores that share an L2 cache, such as the E-cores
+    /// of one module of an Intel hybrid CPU, or SMT siblings
+    /// where every core has its own.
+    L2,
+    /// Two cores of one cache cluster, which share an L3
+    /// cache, such as a CCX of an AMD Zen CPU.
+    CacheCluster,
+}
+
+/// This function reserves two logical cores of one physical
+/// core, for a pair of threads that work closely together,
+/// such as a producer and a consumer sharing a queue. Sharing
+/// a physical core, they share its caches.
+///
+/// The physical cores are tried in ID order, and the first
+/// two siblings in the affinity mask of the process that
+/// nobody has reserved are taken. The reservations keep later
+/// calls from handing out the same cores until they are
+/// dropped; pin a thread to each with `spawn_pinned` or
+/// `set_for_current`.
+///
+/// It fails with `ErrorKind::Unsupported` on machines without
+/// simultaneous multithreading, or with it turned off, and
+/// where the physical cores are not known. It fails with
+/// `ErrorKind::EmptySet` if no physical core has two free
+/// siblings left.
+pub fn colocate_pair() -> Result<(CoreReservation, CoreReservation), Error> {
+    let allowed = record(allowed_for_process())?;
+    let cores = record(smt_cores())?;
+
+    let pair = cores.iter().find_map(|core| reserve_pair(&core.siblings, &allowed));
+
+    record(pair.ok_or_else(|| Error::new(ErrorKind::EmptySet)))
+}
+
+/// This function reserves two cores that are close to each
+/// other in the way `scope` asks for.
+///
+/// `PairScope::SmtSibling` is `colocate_pair`. With
+/// `PairScope::CacheCluster`, the clusters are tried in ID
+/// order and the first two free cores of one are taken, which
+/// fails with `ErrorKind::Unsupported` where the clusters are
+/// not known, and with `ErrorKind::EmptySet` if no cluster has
+/// two free cores left. `PairScope::L2` does the same with the
+/// groups of `l2_groups`.
+///
+/// # Arguments
+///
+/// * scope - what the two cores share
+pub fn colocate_pair_within(scope: PairScope) -> Result<(CoreReservation, CoreReservation), Error> {
+    let groups = match scope {
+        PairScope::SmtSibling => return colocate_pair(),
+        PairScope::L2 => l2_groups(),
+        PairScope::CacheCluster => cache_clusters().map(|clusters| clusters.into_iter().map(|(_, core_ids)| core_ids).collect()),
+    };
+
+    let allowed = record(allowed_for_process())?;
+    let groups: Vec<Vec<CoreId>> = record(groups.ok_or_else(|| Error::new(ErrorKind::Unsupported)))?;
+
+    let pair = groups.iter().find_map(|core_ids| reserve_pair(core_ids, &allowed));
+
+    record(pair.ok_or_else(|| Error::new(ErrorKind::EmptySet)))
+}
+
+/// This function is like `colocate_pair`, but reserves two
+/// siblings of the physical core `physical`.
+///
+/// Besides the errors of `colocate_pair`, it fails with
+/// `ErrorKind::InvalidCore` for a physical core that does not
+/// exist. The errors about the physical core record
+/// `physical`.
+///
+/// # Arguments
+///
+/// * physical - ID of the physical core to reserve two
+///   siblings of
+pub fn colocate_pair_on(physical: PhysicalCoreId) -> Result<(CoreReservation, CoreReservation), Error> {
+    let allowed = record(allowed_for_process())?;
+    let cores = record(smt_cores())?;
+
+    let pair = match cores.get(physical.id) {
+        Some(core) => reserve_pair(&core.siblings, &allowed).ok_or_else(|| Error::new(ErrorKind::EmptySet)),
+        None => Err(Error::new(ErrorKind::InvalidCore)),
+    };
+
+    record(pair.map_err(|err| err.with_physical_core_id(physical)))
+}
+
+// The physical cores, if any of them has more than one
+// sibling.
+fn smt_cores() -> Result<Vec<PhysicalCore>, Error> {
+    match get_physical_cores() {
+        Some(cores) if cores.iter().any(|core| core.siblings.len() > 1) => Ok(cores),
+        _ => Err(Error::new(ErrorKind::Unsupported)),
+    }
+}
+
+// Reserves the first two free cores of `core_ids` in
+// `allowed`. A single free one is given back.
+fn reserve_pair(core_ids: &[CoreId], allowed: &CpuSet) -> Option<(CoreReservation, CoreReservation)> {
+    let mut free = core_ids.iter()
+        .filter(|&&core_id| allowed.contains(core_id))
+        .filter_map(|&core_id| reserve_specific(core_id).ok());
+
+    let first = free.next()?;
+    let second = free.next()?;
+
+    Some((first, second))
+}
+
+/// This function picks `n` cores for as many compute threads,
+/// spreading them over the physical cores so that no two
+/// share one while there are physical cores left.
+///
+/// Only the cores the current thread may run on are used. The
+/// first sibling of every physical core comes first, in ID
+/// order, then the second of every physical core, and so on.
+/// It fails with `ErrorKind::TooFewCores` if `n` is greater
+/// than the number of those cores, however many the machine
+/// has, and with `ErrorKind::Unsupported` where the physical
+/// cores are not known.
+///
+/// # Arguments
+///
+/// * n - the number of cores to pick
+pub fn spread_across_physical_cores(n: usize) -> Result<Vec<CoreId>, Error> {
+    let allowed: CpuSet = record(core_ids_helper())?.collect();
+    let cores = record(get_physical_cores().ok_or_else(|| Error::new(ErrorKind::Unsupported)))?;
+
+    record(spread(&cores, &allowed, n))
+}
+
+fn spread(cores: &[PhysicalCore], allowed: &CpuSet, n: usize) -> Result<Vec<CoreId>, Error> {
+    let siblings = cores.iter()
+        .map(|core| core.siblings.iter().cloned().filter(|&core_id| allowed.contains(core_id)).collect())
+        .collect();
+    let mut order = interleave(siblings);
+
+    if n > order.len() {
+        return Err(Error::new(ErrorKind::TooFewCores));
+    }
+
+    order.truncate(n);
+    Ok(order)
+}
+
+// Numbers the physical cores made up of `groups`, keeping only
+// the cores in `online`. Online cores that are in no group run
+// on a physical core of their own.
+fn number(groups: Vec<Vec<CoreId>>, online: &[CoreId]) -> Vec<PhysicalCore> {
+    partition(groups, online).into_iter()
+        .enumerate()
+        .map(|(id, siblings)| PhysicalCore { id: PhysicalCoreId { id }, siblings })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_all_core_ids;
+    use test_util::ids;
+
+    #[test]
+    fn test_get_physical_cores() {
+        let cores = match get_physical_cores() {
+            Some(cores) => cores,
+            None => return,
+        };
+        let online = get_all_core_ids().unwrap();
+
+        assert_eq!(cores.iter().map(|core| core.siblings().len()).sum::<usize>(), online.len());
+
+        for (index, core) in cores.iter().enumerate() {
+            assert_eq!(core.id(), PhysicalCoreId { id: index });
+            assert!(!core.siblings().is_empty());
+            assert!(core.siblings().iter().all(|core_id| online.contains(core_id)));
+        }
+    }
+
+    #[test]
+    fn test_smt_siblings() {
+        let online = get_all_core_ids().unwrap();
+
+        for &core_id in &online {
+            let siblings = match smt_siblings(core_id) {
+                Some(siblings) => siblings,
+                None => return,
+            };
+
+            assert!(!siblings.contains(&core_id));
+
+            for &sibling in &siblings {
+                assert!(smt_siblings(sibling).unwrap().contains(&core_id));
+            }
+        }
+
+        assert_eq!(smt_siblings(CoreId { id: usize::MAX }), None);
+    }
+
+    #[test]
+    fn test_smt_active() {
+        if let (Some(active), Some(cores)) = (smt_active(), get_physical_cores()) {
+            assert_eq!(active, cores.iter().any(|core| core.siblings().len() > 1));
+        }
+    }
+
+    #[test]
+    fn test_pin_to_physical_core() {
+        let cores = match get_physical_cores() {
+            Some(cores) => cores,
+            None => return,
+        };
+
+        let core_id = pin_to_physical_core(PhysicalCoreId { id: 0 }).unwrap();
+        assert!(cores[0].siblings().contains(&core_id));
+        assert!(super::super::clear_for_current());
+
+        let err = pin_to_physical_core(PhysicalCoreId { id: cores.len() }).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.physical_core_id(), Some(PhysicalCoreId { id: cores.len() }));
+    }
+
+    #[test]
+    fn test_pick_sibling() {
+        let cores = number(vec![ids(&[0, 4]), ids(&[1, 5])], &ids(&[0, 1, 4, 5]));
+        let all: CpuSet = ids(&[0, 1, 4, 5]).into();
+        let first = PhysicalCoreId { id: 0 };
+
+        assert_eq!(pick_sibling(&cores, first, &all, |_| false), Ok(CoreId { id: 0 }));
+
+        // A free sibling beats a reserved one, but a reserved
+        // one is still better than none.
+        assert_eq!(pick_sibling(&cores, first, &all, |core_id| core_id.id == 0), Ok(CoreId { id: 4 }));
+        assert_eq!(pick_sibling(&cores, first, &all, |_| true), Ok(CoreId { id: 0 }));
+
+        let allowed: CpuSet = ids(&[1, 5]).into();
+        let err = pick_sibling(&cores, first, &allowed, |_| false).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotAllowed);
+        assert_eq!(err.physical_core_id(), Some(first));
+    }
+
+    #[test]
+    fn test_spread_across_physical_cores() {
+        let allowed = match (get_physical_cores(), super::super::get_core_ids()) {
+            (Some(_), Some(core_ids)) => core_ids.len(),
+            _ => return,
+        };
+
+        let spread = spread_across_physical_cores(allowed).unwrap();
+        let mut sorted = spread.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), allowed);
+
+        let err = spread_across_physical_cores(allowed + 1).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TooFewCores);
+    }
+
+    #[test]
+    fn test_spread() {
+        let cores = number(vec![ids(&[0, 4]), ids(&[1, 5]), ids(&[2]), ids(&[3, 7])], &ids(&[0, 1, 2, 3, 4, 5, 7]));
+        let allowed: CpuSet = ids(&[0, 1, 2, 3, 4, 5]).into();
+
+        assert_eq!(spread(&cores, &allowed, 2), Ok(ids(&[0, 1])));
+        assert_eq!(spread(&cores, &allowed, 4), Ok(ids(&[0, 1, 2, 3])));
+        assert_eq!(spread(&cores, &allowed, 6), Ok(ids(&[0, 1, 2, 3, 4, 5])));
+        assert_eq!(spread(&cores, &allowed, 0), Ok(vec![]));
+        assert_eq!(spread(&cores, &allowed, 7).unwrap_err().kind(), ErrorKind::TooFewCores);
+    }
+
+    #[test]
+    fn test_number() {
+        // Two-way SMT with the siblings numbered apart, as on
+        // most x86 machines, and core 5 offline.
+        let groups = vec![ids(&[4, 0]), ids(&[1, 5]), ids(&[0, 4]), ids(&[2, 6])];
+        let online = ids(&[0, 1, 2, 3, 4, 6]);
+
+        let cores = number(groups, &online);
+        let siblings: Vec<_> = cores.iter().map(|core| core.siblings().to_vec()).collect();
+
+        assert_eq!(siblings, vec![ids(&[0, 4]), ids(&[1]), ids(&[2, 6]), ids(&[3])]);
+        assert_eq!(cores[3].id(), PhysicalCoreId { id: 3 });
+    }
+}
@@ -0,0 +1,115 @@
+use super::{CoreId, CoreIds, CpuSet, process_core_ids_helper, record, thread_core_ids_helper};
+
+/// How far the current thread is confined, compared to the
+/// process, as returned by `is_pinned_for_current`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PinState {
+    /// The thread may run on every core of the process.
+    Unrestricted,
+    /// The thread may only run on some of the cores of the
+    /// process, listed in ascending order.
+    Restricted(Vec<CoreId>),
+    /// The thread is pinned to a single core, while the
+    /// process may use more.
+    Single(CoreId),
+}
+
+/// This function tells whether the current thread has been
+/// pinned, by comparing its affinity mask to that of the
+/// process.
+///
+/// Use it to avoid pinning a thread again that the host
+/// application already pinned. A process that may only run
+/// on one core reports `Unrestricted` for all of its threads.
+/// It does not allocate unless the thread is restricted to
+/// several cores.
+///
+/// It returns `None` where either mask can not be read, such
+/// as on macOS.
+pub fn is_pinned_for_current() -> Option<PinState> {
+    let thread = record(thread_core_ids_helper()).ok()?;
+    let process = record(process_core_ids_helper()).ok()?;
+
+    Some(compare(thread, process))
+}
+
+// Classifies the mask of a thread against that of its process.
+fn compare(mut thread: CoreIds, mut process: CoreIds) -> PinState {
+    if thread.len() == 1 {
+        let core_id = thread.next().unwrap();
+
+        return if process.len() == 1 && process.next() == Some(core_id) {
+            PinState::Unrestricted
+        }
+        else {
+            PinState::Single(core_id)
+        };
+    }
+
+    // A thread may be allowed more cores than the main thread
+    // that stands for the process, which is not a restriction.
+    let allowed: CpuSet = thread.clone().collect();
+
+    if process.all(|core_id| allowed.contains(core_id)) {
+        PinState::Unrestricted
+    }
+    else {
+        PinState::Restricted(thread.collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn core_ids(ids: &[usize]) -> CoreIds {
+        let mut core_ids = CoreIds::new();
+
+        for &id in ids {
+            core_ids.insert(id);
+        }
+
+        core_ids
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(compare(core_ids(&[0, 1, 2, 3]), core_ids(&[0, 1, 2, 3])), PinState::Unrestricted);
+        assert_eq!(compare(core_ids(&[0, 1, 2, 3]), core_ids(&[1, 2])), PinState::Unrestricted);
+        assert_eq!(compare(core_ids(&[2]), core_ids(&[2])), PinState::Unrestricted);
+        assert_eq!(compare(core_ids(&[2]), core_ids(&[0, 1, 2, 3])), PinState::Single(CoreId { id: 2 }));
+        assert_eq!(compare(core_ids(&[1, 3]), core_ids(&[0, 1, 2, 3])),
+                   PinState::Restricted(vec![CoreId { id: 1 }, CoreId { id: 3 }]));
+    }
+
+    #[test]
+    #[cfg(any(
+        target_os = "android",
+        target_os = "linux",
+        target_os = "windows",
+        target_os = "freebsd"
+    ))]
+    fn test_is_pinned_for_current() {
+        use std::thread;
+
+        use super::super::{get_core_ids, get_for_process, set_for_current};
+
+        thread::spawn(|| {
+            assert_eq!(is_pinned_for_current(), Some(PinState::Unrestricted));
+
+            let ids = get_core_ids().unwrap();
+            let id = *ids.last().unwrap();
+            assert!(set_for_current(id));
+
+            let process = get_for_process(std::process::id()).unwrap();
+            let expected = if process == [id] { PinState::Unrestricted } else { PinState::Single(id) };
+            assert_eq!(is_pinned_for_current(), Some(expected));
+        }).join().unwrap();
+    }
+
+    #[test]
+    #[cfg(all(not(feature = "mock"), target_os = "macos"))]
+    fn test_is_pinned_for_current_macos() {
+        assert_eq!(is_pinned_for_current(), None);
+    }
+}
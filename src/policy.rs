@@ -0,0 +1,200 @@
+use super::{CoreId, CpuSet, Error, ErrorKind, allowed_for_process, record, set_for_current_checked};
+use topology::{CoreInfo, core_info};
+
+/// What `set_for_current_with` does when the requested core is
+/// not in the affinity mask of the process.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PinPolicy {
+    /// Fails with `ErrorKind::NotAllowed`.
+    #[default]
+    Strict,
+    /// Pins to the allowed core closest to the requested one,
+    /// preferring cores of the same NUMA node where the
+    /// topology is known, and otherwise the nearest ID.
+    NearestAllowed,
+}
+
+/// This function tries to pin the current thread to the
+/// specified core, handling a core outside the affinity mask
+/// of the process as `policy` says.
+///
+/// The mask of the process is the one `set_for_current_excluding`
+/// uses, so restrictions such as a container cpuset count
+/// against the core. On success it returns the core the thread
+/// is pinned to now, which differs from `core_id` if a nearby
+/// core was used instead.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+/// * policy - what to do if that core is not allowed
+pub fn set_for_current_with(core_id: CoreId, policy: PinPolicy) -> Result<CoreId, Error> {
+    let allowed = record(allowed_for_process())?;
+    let effective = record(resolve(core_id, &allowed, policy, core_info))?;
+
+    set_for_current_checked(effective)?;
+
+    Ok(effective)
+}
+
+// Picks the core to pin to for `core_id`, looking up the
+// topology with `info`.
+fn resolve<F>(core_id: CoreId, allowed: &CpuSet, policy: PinPolicy, info: F) -> Result<CoreId, Error>
+    where F: Fn(&[CoreId]) -> Vec<CoreInfo>
+{
+    if allowed.contains(core_id) {
+        return Ok(core_id);
+    }
+
+    if policy == PinPolicy::Strict || allowed.is_empty() {
+        return Err(Error::new(ErrorKind::NotAllowed).with_core_id(core_id));
+    }
+
+    let node = info(&[core_id])[0].node;
+    let nearest = info(&allowed.to_vec()).into_iter()
+        .min_by_key(|other| {
+            let other_node = node.is_some() && other.node != node;
+            (other_node, other.core_id.id.abs_diff(core_id.id), other.core_id)
+        })
+        .map(|other| other.core_id);
+
+    nearest.ok_or_else(|| Error::new(ErrorKind::NotAllowed).with_core_id(core_id))
+}
+
+/// This function pins the current thread to the first of
+/// `candidates` that is in the affinity mask of the process,
+/// and returns that core.
+///
+/// It fails with `ErrorKind::NotAllowed` if none of them is,
+/// and with `ErrorKind::EmptySet` if `candidates` is empty.
+/// Any other error comes from pinning to the chosen core,
+/// in which case the later candidates are not tried.
+///
+/// # Arguments
+///
+/// * candidates - IDs of the cores to try, in order of preference
+pub fn set_for_current_first_available(candidates: &[CoreId]) -> Result<CoreId, Error> {
+    let allowed = record(allowed_for_process())?;
+    let chosen = record(first_allowed(candidates, &allowed))?;
+
+    set_for_current_checked(chosen)?;
+
+    Ok(chosen)
+}
+
+fn first_allowed(candidates: &[CoreId], allowed: &CpuSet) -> Result<CoreId, Error> {
+    if candidates.is_empty() {
+        return Err(Error::new(ErrorKind::EmptySet));
+    }
+
+    match candidates.iter().find(|&&core_id| allowed.contains(core_id)) {
+        Some(&core_id) => Ok(core_id),
+        None => Err(Error::new(ErrorKind::NotAllowed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_core_ids;
+
+    fn set(ids: &[usize]) -> CpuSet {
+        ids.iter().map(|&id| CoreId { id }).collect()
+    }
+
+    // No topology at all.
+    fn unknown(core_ids: &[CoreId]) -> Vec<CoreInfo> {
+        core_ids.iter().map(|&core_id| CoreInfo::unknown(core_id)).collect()
+    }
+
+    // Cores 0-7 on node 0 and 8-15 on node 1.
+    fn two_nodes(core_ids: &[CoreId]) -> Vec<CoreInfo> {
+        core_ids.iter().map(|&core_id| {
+            CoreInfo { core_id, package: None, die: None, node: Some(core_id.id / 8).filter(|&node| node < 2) }
+        }).collect()
+    }
+
+    #[test]
+    fn test_resolve_strict() {
+        let allowed = set(&[0, 1, 2, 3]);
+
+        assert_eq!(resolve(CoreId { id: 2 }, &allowed, PinPolicy::Strict, unknown), Ok(CoreId { id: 2 }));
+
+        let err = resolve(CoreId { id: 12 }, &allowed, PinPolicy::Strict, unknown).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotAllowed);
+        assert_eq!(err.core_id(), Some(CoreId { id: 12 }));
+    }
+
+    #[test]
+    fn test_resolve_nearest_allowed() {
+        let allowed = set(&[0, 1, 2, 3]);
+        let nearest = |id| resolve(CoreId { id }, &allowed, PinPolicy::NearestAllowed, unknown);
+
+        assert_eq!(nearest(12), Ok(CoreId { id: 3 }));
+        assert_eq!(nearest(1), Ok(CoreId { id: 1 }));
+
+        // Ties go to the lower core.
+        assert_eq!(resolve(CoreId { id: 5 }, &set(&[3, 7]), PinPolicy::NearestAllowed, unknown),
+                   Ok(CoreId { id: 3 }));
+
+        assert!(resolve(CoreId { id: 5 }, &CpuSet::new(), PinPolicy::NearestAllowed, unknown).is_err());
+    }
+
+    #[test]
+    fn test_resolve_nearest_allowed_same_node() {
+        // Core 7 is closer by ID, but 12 is on the same node as 9.
+        let allowed = set(&[0, 7, 12, 13]);
+        let core_id = resolve(CoreId { id: 9 }, &allowed, PinPolicy::NearestAllowed, two_nodes);
+
+        assert_eq!(core_id, Ok(CoreId { id: 12 }));
+    }
+
+    #[test]
+    fn test_first_allowed() {
+        let allowed = set(&[0, 1, 2, 3]);
+        let ids = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<Vec<_>>();
+
+        assert_eq!(first_allowed(&ids(&[12, 2, 1]), &allowed), Ok(CoreId { id: 2 }));
+        assert_eq!(first_allowed(&ids(&[3]), &allowed), Ok(CoreId { id: 3 }));
+        assert_eq!(first_allowed(&ids(&[12, 13]), &allowed).unwrap_err().kind(), ErrorKind::NotAllowed);
+        assert_eq!(first_allowed(&[], &allowed).unwrap_err().kind(), ErrorKind::EmptySet);
+    }
+
+    #[test]
+    fn test_set_for_current_first_available() {
+        use std::thread;
+
+        let last = *get_core_ids().unwrap().last().unwrap();
+
+        thread::spawn(move || {
+            let candidates = [CoreId { id: 100_000 }, last, CoreId { id: 0 }];
+            assert_eq!(set_for_current_first_available(&candidates), Ok(last));
+
+            #[cfg(not(target_os = "macos"))]
+            assert_eq!(super::super::get_affinity_for_current(), Some(vec![last]));
+
+            let err = set_for_current_first_available(&[CoreId { id: 100_000 }]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotAllowed);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_set_for_current_with() {
+        use std::thread;
+
+        let ids = get_core_ids().unwrap();
+        let last = *ids.last().unwrap();
+
+        thread::spawn(move || {
+            assert_eq!(set_for_current_with(last, PinPolicy::Strict), Ok(last));
+
+            let far = CoreId { id: 100_000 };
+            assert_eq!(set_for_current_with(far, PinPolicy::Strict).unwrap_err().kind(),
+                       ErrorKind::NotAllowed);
+            assert_eq!(set_for_current_with(far, PinPolicy::NearestAllowed), Ok(last));
+
+            #[cfg(not(target_os = "macos"))]
+            assert_eq!(super::super::get_affinity_for_current(), Some(vec![last]));
+        }).join().unwrap();
+    }
+}
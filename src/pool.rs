@@ -0,0 +1,261 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::thread::JoinHandle;
+
+use super::{CoreId, Error, ErrorKind, spawn_pinned};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A pool of long-lived worker threads, each pinned to one core.
+///
+/// Jobs sent to a worker run one at a time, in the order they
+/// were submitted. Dropping the pool lets every worker finish
+/// the jobs already queued and then joins it.
+///
+/// A job that panics takes its worker down with it. Later
+/// submissions to that worker fail with `ErrorKind::NotFound`;
+/// jobs that were still queued behind the panicking one are
+/// dropped.
+#[derive(Debug)]
+pub struct PinnedPool {
+    workers: Vec<Worker>,
+    // The worker `execute_any` tries first.
+    next: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Worker {
+    core_id: CoreId,
+    sender: Option<Sender<Job>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PinnedPool {
+    /// Creates a pool with one worker for each of `core_ids`.
+    ///
+    /// Duplicate cores get a single worker. Fails if the list
+    /// is empty or if any worker can not be pinned, in which
+    /// case the workers started so far are shut down again.
+    pub fn new(core_ids: &[CoreId]) -> io::Result<PinnedPool> {
+        if core_ids.is_empty() {
+            return Err(Error::new(ErrorKind::EmptySet).into());
+        }
+
+        let mut pool = PinnedPool::empty();
+
+        for &core_id in core_ids {
+            pool.add_worker(core_id)?;
+        }
+
+        Ok(pool)
+    }
+
+    /// Creates a pool like `new`, but leaves out the cores
+    /// whose worker can not be pinned.
+    ///
+    /// The pool may end up without any workers; check
+    /// `core_ids` to see which cores it covers.
+    pub fn new_lenient(core_ids: &[CoreId]) -> PinnedPool {
+        let mut pool = PinnedPool::empty();
+
+        for &core_id in core_ids {
+            let _ = pool.add_worker(core_id);
+        }
+
+        pool
+    }
+
+    /// Returns the cores that have a worker, in the order they
+    /// were passed to the constructor.
+    pub fn core_ids(&self) -> Vec<CoreId> {
+        self.workers.iter().map(|worker| worker.core_id).collect()
+    }
+
+    /// Runs `f` on the worker pinned to `core_id`.
+    ///
+    /// Fails with `ErrorKind::InvalidCore` if the pool has no
+    /// worker for `core_id`, and with `ErrorKind::NotFound` if
+    /// that worker has died.
+    pub fn execute_on<F>(&self, core_id: CoreId, f: F) -> Result<(), Error>
+        where F: FnOnce() + Send + 'static
+    {
+        match self.workers.iter().find(|worker| worker.core_id == core_id) {
+            Some(worker) => worker.send(Box::new(f)).map_err(|_| worker.died()),
+            None => Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id)),
+        }
+    }
+
+    /// Runs `f` on one of the workers, taking turns between them.
+    ///
+    /// Workers that have died are skipped. Fails with
+    /// `ErrorKind::NotFound` if none is left.
+    pub fn execute_any<F>(&self, f: F) -> Result<(), Error>
+        where F: FnOnce() + Send + 'static
+    {
+        let count = self.workers.len();
+        let first = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut job: Job = Box::new(f);
+
+        for i in 0..count {
+            match self.workers[(first + i) % count].send(job) {
+                Ok(()) => return Ok(()),
+                Err(rejected) => job = rejected,
+            }
+        }
+
+        Err(Error::new(ErrorKind::NotFound))
+    }
+
+    fn empty() -> PinnedPool {
+        PinnedPool { workers: Vec::new(), next: AtomicUsize::new(0) }
+    }
+
+    fn add_worker(&mut self, core_id: CoreId) -> io::Result<()> {
+        if self.workers.iter().any(|worker| worker.core_id == core_id) {
+            return Ok(());
+        }
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+
+        let handle = spawn_pinned(core_id, move || {
+            for job in receiver {
+                job();
+            }
+        })?;
+
+        self.workers.push(Worker { core_id, sender: Some(sender), handle: Some(handle) });
+
+        Ok(())
+    }
+}
+
+impl Worker {
+    // Queues `job`, handing it back if the worker has died.
+    fn send(&self, job: Job) -> Result<(), Job> {
+        match self.sender {
+            Some(ref sender) => sender.send(job).map_err(|err| err.0),
+            None => Err(job),
+        }
+    }
+
+    fn died(&self) -> Error {
+        Error::new(ErrorKind::NotFound).with_core_id(self.core_id)
+    }
+}
+
+impl Drop for PinnedPool {
+    fn drop(&mut self) {
+        // Close every queue first, so the workers finish in parallel.
+        for worker in &mut self.workers {
+            worker.sender = None;
+        }
+
+        for worker in &mut self.workers {
+            if let Some(handle) = worker.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use super::super::get_core_ids;
+
+    #[test]
+    fn test_pinned_pool_execute_on() {
+        let ids = get_core_ids().unwrap();
+        let pool = PinnedPool::new(&ids).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        assert_eq!(pool.core_ids(), ids);
+
+        for &id in &ids {
+            let tx = tx.clone();
+            pool.execute_on(id, move || tx.send((id, super::super::current_core())).unwrap()).unwrap();
+        }
+
+        for _ in &ids {
+            let (id, core_id) = rx.recv().unwrap();
+
+            // `current_core` is not supported on macOS.
+            assert!(core_id.is_none() || core_id == Some(id));
+        }
+    }
+
+    #[test]
+    fn test_pinned_pool_ordering() {
+        let id = get_core_ids().unwrap()[0];
+        let pool = PinnedPool::new(&[id, id]).unwrap();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        assert_eq!(pool.core_ids(), vec![id]);
+
+        for i in 0..100 {
+            let seen = seen.clone();
+            pool.execute_on(id, move || seen.lock().unwrap().push(i)).unwrap();
+        }
+
+        // Dropping the pool runs the queued jobs before joining.
+        drop(pool);
+        assert_eq!(*seen.lock().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pinned_pool_execute_any() {
+        let ids = get_core_ids().unwrap();
+        let pool = PinnedPool::new(&ids).unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..50 {
+            let count = count.clone();
+            pool.execute_any(move || { count.fetch_add(1, Ordering::SeqCst); }).unwrap();
+        }
+
+        drop(pool);
+        assert_eq!(count.load(Ordering::SeqCst), 50);
+    }
+
+    #[test]
+    fn test_pinned_pool_errors() {
+        let id = get_core_ids().unwrap()[0];
+        let invalid = CoreId { id: usize::MAX };
+
+        assert!(PinnedPool::new(&[]).is_err());
+        assert!(PinnedPool::new(&[id, invalid]).is_err());
+
+        let pool = PinnedPool::new_lenient(&[id, invalid]);
+        assert_eq!(pool.core_ids(), vec![id]);
+        assert_eq!(pool.execute_on(invalid, || ()).unwrap_err().kind(), ErrorKind::InvalidCore);
+
+        assert_eq!(PinnedPool::new_lenient(&[invalid]).execute_any(|| ()).unwrap_err().kind(),
+                   ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_pinned_pool_dead_worker() {
+        let id = get_core_ids().unwrap()[0];
+        let pool = PinnedPool::new(&[id]).unwrap();
+
+        pool.execute_on(id, || panic!("job panicked")).unwrap();
+
+        // Wait for the worker to go down.
+        let mut result = Ok(());
+        for _ in 0..1000 {
+            result = pool.execute_on(id, || ());
+            if result.is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(pool.execute_any(|| ()).unwrap_err().kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,364 @@
+use super::{CoreId, Error, ErrorKind, get_affinity_for_current_helper, record, set_for_current_checked};
+use super::try_set_for_current_set;
+
+/// A scheduling priority for a thread, as understood by
+/// `set_for_current_with_priority`.
+///
+/// The levels map to what each platform offers:
+///
+/// | Priority      | Linux, Android | macOS                        | Windows                         |
+/// |---------------|----------------|------------------------------|---------------------------------|
+/// | `Lowest`      | nice 19        | `QOS_CLASS_BACKGROUND`       | `THREAD_PRIORITY_LOWEST`        |
+/// | `Low`         | nice 10        | `QOS_CLASS_UTILITY`          | `THREAD_PRIORITY_BELOW_NORMAL`  |
+/// | `Normal`      | nice 0         | `QOS_CLASS_DEFAULT`          | `THREAD_PRIORITY_NORMAL`        |
+/// | `High`        | nice -10       | `QOS_CLASS_USER_INITIATED`   | `THREAD_PRIORITY_ABOVE_NORMAL`  |
+/// | `Highest`     | nice -20       | `QOS_CLASS_USER_INTERACTIVE` | `THREAD_PRIORITY_HIGHEST`       |
+/// | `Realtime(n)` | `SCHED_FIFO` n | `SCHED_FIFO` n               | `THREAD_PRIORITY_TIME_CRITICAL` |
+///
+/// On Linux and Android the nice value belongs to the thread
+/// alone. Lowering it, as `High` and `Highest` do, or returning
+/// to `Normal` after `Low`, needs `CAP_SYS_NICE` or a high
+/// enough `RLIMIT_NICE`; without either it fails with
+/// `ErrorKind::PermissionDenied`. FreeBSD only supports
+/// `Realtime`.
+///
+/// # Realtime
+///
+/// A realtime thread runs until it blocks or yields, ahead of
+/// every thread that is not realtime, so one that spins can
+/// starve the rest of the core it is pinned to. On Linux and
+/// Android it needs `CAP_SYS_NICE` or an `RLIMIT_RTPRIO` of at
+/// least the level, and on FreeBSD root. Unprivileged threads
+/// get `ErrorKind::PermissionDenied`; pick
+/// `PriorityFallback::IgnoreDenied` to run on at the priority
+/// they had instead. On Windows, the level is ignored and the
+/// priority class of the process still applies.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// The lowest priority short of idle.
+    Lowest,
+    /// Below the default.
+    Low,
+    /// The default priority.
+    Normal,
+    /// Above the default.
+    High,
+    /// The highest priority without realtime scheduling.
+    Highest,
+    /// Realtime first-in, first-out scheduling at a level from
+    /// 1 to 99, where higher levels run first. The range may be
+    /// narrower on some platforms.
+    Realtime(u8),
+}
+
+/// What `set_for_current_with_priority_or` does when the thread
+/// was pinned, but its priority could not be changed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PriorityFallback {
+    /// Keeps the thread pinned and returns the error.
+    #[default]
+    KeepPin,
+    /// Restores the affinity the thread had before and returns
+    /// the error.
+    Revert,
+    /// Keeps the thread pinned at the priority it had, and
+    /// succeeds, if the priority was denied for lack of
+    /// privileges. Other errors are returned as with `KeepPin`.
+    IgnoreDenied,
+}
+
+/// This function pins the current thread to the specified core
+/// and then changes its scheduling priority.
+///
+/// A thread pinned this way keeps the pin if the priority can
+/// not be changed, as with `PriorityFallback::KeepPin`. The
+/// error tells which step failed: `Error::is_priority_error`
+/// is true if pinning succeeded and the priority did not.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+/// * priority - the priority to switch to
+pub fn set_for_current_with_priority(core_id: CoreId, priority: Priority) -> Result<(), Error> {
+    set_for_current_with_priority_or(core_id, priority, PriorityFallback::KeepPin)
+}
+
+/// This function pins the current thread to the specified core
+/// and then changes its scheduling priority, handling a failure
+/// of the second step as `fallback` says.
+///
+/// A failure to pin is returned as by `set_for_current_checked`,
+/// without touching the priority. A `Realtime` level outside 1
+/// to 99 fails with `ErrorKind::InvalidPriority` before either
+/// step.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+/// * priority - the priority to switch to
+/// * fallback - what to do if only pinning succeeded
+pub fn set_for_current_with_priority_or(core_id: CoreId, priority: Priority, fallback: PriorityFallback) -> Result<(), Error> {
+    if let Priority::Realtime(level) = priority {
+        if level == 0 || level > 99 {
+            return record(Err(Error::new(ErrorKind::InvalidPriority).in_priority()));
+        }
+    }
+
+    let previous = match fallback {
+        PriorityFallback::Revert => Some(record(get_affinity_for_current_helper())?),
+        _ => None,
+    };
+
+    set_for_current_checked(core_id)?;
+
+    let err = match imp::set_priority(priority) {
+        Ok(()) => return record(Ok(())),
+        Err(err) => err.in_priority(),
+    };
+
+    match fallback {
+        PriorityFallback::IgnoreDenied if err.kind() == ErrorKind::PermissionDenied => return record(Ok(())),
+        PriorityFallback::Revert => {
+            // The error of the priority matters more than one
+            // from restoring the mask.
+            let _ = try_set_for_current_set(&previous.unwrap_or_default());
+        },
+        _ => (),
+    }
+
+    record(Err(err))
+}
+
+#[cfg(feature = "mock")]
+mod imp {
+    use super::*;
+    use mock;
+
+    pub fn set_priority(priority: Priority) -> Result<(), Error> {
+        mock::set_priority_for_current(priority)
+    }
+}
+
+#[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+mod imp {
+    use std::mem;
+
+    use libc::{SCHED_FIFO, pthread_self, pthread_setschedparam, sched_get_priority_max, sched_get_priority_min};
+    use libc::{c_int, sched_param};
+
+    use super::*;
+
+    pub fn set_priority(priority: Priority) -> Result<(), Error> {
+        match priority {
+            Priority::Realtime(level) => set_realtime(level as c_int),
+            _ => set_level(priority),
+        }
+    }
+
+    fn set_realtime(level: c_int) -> Result<(), Error> {
+        unsafe {
+            if level < sched_get_priority_min(SCHED_FIFO) || level > sched_get_priority_max(SCHED_FIFO) {
+                return Err(Error::new(ErrorKind::InvalidPriority));
+            }
+
+            let mut param = mem::zeroed::<sched_param>();
+            param.sched_priority = level;
+
+            match pthread_setschedparam(pthread_self(), SCHED_FIFO, &param) {
+                0 => Ok(()),
+                code => Err(Error::from_os_error(code)),
+            }
+        }
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    fn set_level(priority: Priority) -> Result<(), Error> {
+        use libc::{PRIO_PROCESS, SCHED_NORMAL, SCHED_RR, gettid, id_t, pthread_getschedparam, setpriority};
+
+        let nice = match priority {
+            Priority::Lowest => 19,
+            Priority::Low => 10,
+            Priority::High => -10,
+            Priority::Highest => -20,
+            _ => 0,
+        };
+
+        unsafe {
+            // The nice value only applies outside realtime
+            // scheduling.
+            let thread = pthread_self();
+            let mut policy = 0;
+            let mut param = mem::zeroed::<sched_param>();
+
+            if pthread_getschedparam(thread, &mut policy, &mut param) == 0 && (policy == SCHED_FIFO || policy == SCHED_RR) {
+                param.sched_priority = 0;
+
+                let code = pthread_setschedparam(thread, SCHED_NORMAL, &param);
+                if code != 0 {
+                    return Err(Error::from_os_error(code));
+                }
+            }
+
+            // Linux keeps a nice value per thread, which
+            // `PRIO_PROCESS` sets given a thread ID.
+            if setpriority(PRIO_PROCESS, gettid() as id_t, nice) != 0 {
+                return Err(Error::last_os_error());
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "macos")]
+    fn set_level(priority: Priority) -> Result<(), Error> {
+        use libc::{pthread_set_qos_class_self_np, qos_class_t};
+
+        let class = match priority {
+            Priority::Lowest => qos_class_t::QOS_CLASS_BACKGROUND,
+            Priority::Low => qos_class_t::QOS_CLASS_UTILITY,
+            Priority::High => qos_class_t::QOS_CLASS_USER_INITIATED,
+            Priority::Highest => qos_class_t::QOS_CLASS_USER_INTERACTIVE,
+            _ => qos_class_t::QOS_CLASS_DEFAULT,
+        };
+
+        match unsafe { pthread_set_qos_class_self_np(class, 0) } {
+            0 => Ok(()),
+            code => Err(Error::from_os_error(code)),
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    fn set_level(_priority: Priority) -> Result<(), Error> {
+        // FreeBSD keeps nice values per process.
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+}
+
+#[cfg(all(not(feature = "mock"), target_os = "windows"))]
+mod imp {
+    use std::os::raw::c_int;
+
+    use winapi::um::processthreadsapi::{GetCurrentThread, SetThreadPriority};
+    use winapi::um::winbase::{THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_BELOW_NORMAL, THREAD_PRIORITY_HIGHEST};
+    use winapi::um::winbase::{THREAD_PRIORITY_LOWEST, THREAD_PRIORITY_NORMAL, THREAD_PRIORITY_TIME_CRITICAL};
+
+    use super::*;
+
+    pub fn set_priority(priority: Priority) -> Result<(), Error> {
+        let level = match priority {
+            Priority::Lowest => THREAD_PRIORITY_LOWEST,
+            Priority::Low => THREAD_PRIORITY_BELOW_NORMAL,
+            Priority::Normal => THREAD_PRIORITY_NORMAL,
+            Priority::High => THREAD_PRIORITY_ABOVE_NORMAL,
+            Priority::Highest => THREAD_PRIORITY_HIGHEST,
+            Priority::Realtime(_) => THREAD_PRIORITY_TIME_CRITICAL,
+        };
+
+        // The levels are negative numbers stored as `DWORD`.
+        if unsafe { SetThreadPriority(GetCurrentThread(), level as c_int) } == 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(not(feature = "mock"), not(any(
+    target_os = "android",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "windows"
+))))]
+mod imp {
+    use super::*;
+
+    pub fn set_priority(_priority: Priority) -> Result<(), Error> {
+        Err(Error::new(ErrorKind::Unsupported))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use super::super::{get_affinity_for_current, get_core_ids};
+
+    // Every test changes a thread of its own, which keeps what
+    // it was given after the test.
+    fn on_thread<F>(f: F)
+        where F: FnOnce() + Send + 'static
+    {
+        thread::spawn(f).join().unwrap();
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    #[test]
+    fn test_set_for_current_with_priority() {
+        on_thread(|| {
+            let id = get_core_ids().unwrap()[0];
+
+            // Anyone may lower the priority of their threads.
+            set_for_current_with_priority(id, Priority::Low).unwrap();
+            assert_eq!(get_affinity_for_current(), Some(vec![id]));
+        });
+    }
+
+    #[test]
+    fn test_set_for_current_with_priority_realtime() {
+        on_thread(|| {
+            let id = get_core_ids().unwrap()[0];
+
+            // Privileged runs get realtime, the others an error
+            // for the second step, with the pin kept.
+            if let Err(err) = set_for_current_with_priority(id, Priority::Realtime(1)) {
+                assert!(err.is_priority_error());
+                assert!(err.kind() == ErrorKind::PermissionDenied || err.kind() == ErrorKind::Unsupported);
+            }
+
+            assert_eq!(get_affinity_for_current(), Some(vec![id]));
+        });
+    }
+
+    #[test]
+    fn test_set_for_current_with_priority_ignore_denied() {
+        on_thread(|| {
+            let id = get_core_ids().unwrap()[0];
+
+            match set_for_current_with_priority_or(id, Priority::Realtime(1), PriorityFallback::IgnoreDenied) {
+                Ok(()) => (),
+                Err(err) => assert_eq!(err.kind(), ErrorKind::Unsupported),
+            }
+
+            assert_eq!(get_affinity_for_current(), Some(vec![id]));
+        });
+    }
+
+    #[test]
+    fn test_set_for_current_with_priority_invalid() {
+        on_thread(|| {
+            let before = get_affinity_for_current();
+
+            for &level in &[0, 100] {
+                let err = set_for_current_with_priority(CoreId { id: 0 }, Priority::Realtime(level)).unwrap_err();
+
+                assert_eq!(err.kind(), ErrorKind::InvalidPriority);
+                assert!(err.is_priority_error());
+            }
+
+            // Neither step ran.
+            assert_eq!(get_affinity_for_current(), before);
+        });
+    }
+
+    #[test]
+    fn test_set_for_current_with_priority_invalid_core() {
+        on_thread(|| {
+            let err = set_for_current_with_priority(CoreId { id: usize::MAX }, Priority::Normal).unwrap_err();
+
+            assert_eq!(err.kind(), ErrorKind::InvalidCore);
+            assert!(!err.is_priority_error());
+        });
+    }
+}
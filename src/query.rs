@@ -0,0 +1,194 @@
+use super::{CoreId, CoreKind, CpuSet, Error, ErrorKind, NumaNode, allowed_for_process, get_all_core_ids_helper};
+use super::{cores_by_kind, get_physical_cores, record};
+use numa::node_cores;
+
+/// A builder that picks cores by ANDing filters, such as the
+/// allowed performance cores of NUMA node 0 with one logical
+/// core per physical core.
+///
+/// ```
+/// use core_affinity::{CoreKind, CoreQuery};
+///
+/// let query = CoreQuery::new().allowed().online().kind(CoreKind::Performance);
+///
+/// match query.one_per_physical_core().collect() {
+///     Ok(core_ids) => println!("{:?}", core_ids),
+///     Err(err) => println!("{}", err),
+/// }
+/// ```
+///
+/// The query starts from every core the system reports and
+/// applies the filters when `collect` runs, in the order they
+/// were added, except for `one_per_physical_core`, which thins
+/// out what the others leave. A filter the platform can not
+/// evaluate fails the query with `ErrorKind::Unsupported`
+/// instead of matching everything, and the error names the
+/// filter in `Error::filter`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CoreQuery {
+    filters: Vec<Filter>,
+    one_per_physical_core: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Filter {
+    Allowed,
+    Online,
+    Kind(CoreKind),
+    NumaNode(NumaNode),
+}
+
+impl CoreQuery {
+    /// Creates a query without filters, which matches every core
+    /// the system reports.
+    pub fn new() -> CoreQuery {
+        CoreQuery::default()
+    }
+
+    /// Keeps the cores in the affinity mask of the process.
+    pub fn allowed(mut self) -> CoreQuery {
+        self.filters.push(Filter::Allowed);
+        self
+    }
+
+    /// Keeps the cores that are online.
+    pub fn online(mut self) -> CoreQuery {
+        self.filters.push(Filter::Online);
+        self
+    }
+
+    /// Keeps the cores of `kind`, as told by `kind_of`.
+    ///
+    /// # Arguments
+    ///
+    /// * kind - the kind of cores to keep
+    pub fn kind(mut self, kind: CoreKind) -> CoreQuery {
+        self.filters.push(Filter::Kind(kind));
+        self
+    }
+
+    /// Keeps the cores of the NUMA node `node`. A node that does
+    /// not exist fails the query with `ErrorKind::InvalidCore`,
+    /// recording `node` in the error.
+    ///
+    /// # Arguments
+    ///
+    /// * node - the node whose cores to keep
+    pub fn numa_node(mut self, node: NumaNode) -> CoreQuery {
+        self.filters.push(Filter::NumaNode(node));
+        self
+    }
+
+    /// Keeps only the lowest of the cores left on every physical
+    /// core, so that no two of them are SMT siblings.
+    pub fn one_per_physical_core(mut self) -> CoreQuery {
+        self.one_per_physical_core = true;
+        self
+    }
+
+    /// Runs the query and returns the matching cores in
+    /// ascending order, which may be none.
+    pub fn collect(&self) -> Result<Vec<CoreId>, Error> {
+        record(self.run())
+    }
+
+    fn run(&self) -> Result<Vec<CoreId>, Error> {
+        let online = get_all_core_ids_helper().ok_or_else(|| Error::new(ErrorKind::Unsupported))?;
+        let mut core_ids: CpuSet = online.iter().cloned().collect();
+
+        for filter in &self.filters {
+            core_ids = &core_ids & &filter.core_ids(&online)?;
+        }
+
+        if !self.one_per_physical_core {
+            return Ok(core_ids.to_vec());
+        }
+
+        let physical = get_physical_cores()
+            .ok_or_else(|| Error::new(ErrorKind::Unsupported).with_filter("one_per_physical_core"))?;
+
+        // The physical cores are sorted, and so are their siblings.
+        Ok(physical.iter()
+            .filter_map(|core| core.siblings().iter().cloned().find(|&core_id| core_ids.contains(core_id)))
+            .collect())
+    }
+}
+
+impl Filter {
+    // The cores the filter keeps.
+    fn core_ids(&self, online: &[CoreId]) -> Result<CpuSet, Error> {
+        match *self {
+            Filter::Allowed => allowed_for_process().map_err(|err| err.with_filter("allowed")),
+            Filter::Online => Ok(online.iter().cloned().collect()),
+            Filter::Kind(kind) => {
+                let kinds = cores_by_kind().ok_or_else(|| Error::new(ErrorKind::Unsupported).with_filter("kind"))?;
+
+                Ok(kinds.get(&kind).cloned().unwrap_or_default().into())
+            },
+            Filter::NumaNode(node) => {
+                let nodes = node_cores().ok_or_else(|| Error::new(ErrorKind::Unsupported).with_filter("numa_node"))?;
+
+                match nodes.into_iter().find(|&(other, _)| other == node) {
+                    Some((_, core_ids)) => Ok(core_ids.into()),
+                    None => Err(Error::new(ErrorKind::InvalidCore).with_filter("numa_node").with_numa_node(node)),
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{get_all_core_ids, get_core_ids};
+
+    #[test]
+    fn test_core_query_online() {
+        let online = match get_all_core_ids() {
+            Some(online) => online,
+            None => return,
+        };
+
+        assert_eq!(CoreQuery::new().collect(), Ok(online.clone()));
+        assert_eq!(CoreQuery::new().online().collect(), Ok(online));
+    }
+
+    #[test]
+    fn test_core_query_allowed() {
+        let core_ids = match CoreQuery::new().allowed().collect() {
+            Ok(core_ids) => core_ids,
+            Err(_) => return,
+        };
+
+        // The thread of the test is not pinned, so its mask is
+        // that of the process.
+        assert_eq!(Some(core_ids), get_core_ids());
+    }
+
+    #[test]
+    fn test_core_query_one_per_physical_core() {
+        let physical = match get_physical_cores() {
+            Some(physical) => physical,
+            None => return,
+        };
+
+        let core_ids = CoreQuery::new().one_per_physical_core().collect().unwrap();
+        assert_eq!(core_ids.len(), physical.len());
+    }
+
+    #[test]
+    fn test_core_query_unsupported() {
+        // Each filter either works or names itself.
+        let queries = [
+            ("kind", CoreQuery::new().kind(CoreKind::Performance)),
+            ("numa_node", CoreQuery::new().numa_node(NumaNode { id: 0 })),
+            ("one_per_physical_core", CoreQuery::new().one_per_physical_core()),
+        ];
+
+        for (name, query) in queries.iter() {
+            if let Err(err) = query.collect() {
+                assert_eq!(err.filter(), Some(*name));
+            }
+        }
+    }
+}
@@ -0,0 +1,424 @@
+//! Conversions between `CpuSet` and the affinity types of
+//! the platform, for passing sets to and from C libraries.
+//!
+//! * Linux and Android: `cpu_set_t` from `<sched.h>`, as taken
+//!   by `sched_setaffinity` and `pthread_setaffinity_np`.
+//! * FreeBSD: `cpuset_t` from `<sys/cpuset.h>`, as taken by
+//!   `cpuset_setaffinity`.
+//! * Windows: `GROUP_AFFINITY` from `<winnt.h>`, one for each
+//!   processor group, as taken by `SetThreadGroupAffinity`.
+//!   Its `Mask` field is the `KAFFINITY` of that group.
+//!
+//! Every bit survives the conversion, including cores beyond
+//! the first 64. Cores that do not fit into the platform type
+//! fail with `ErrorKind::InvalidCore`.
+//!
+//! `set_for_current_raw` pins the current thread without going
+//! through the rest of the crate, for code such as signal
+//! handlers that may not allocate or take locks.
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub use libc::cpu_set_t;
+#[cfg(target_os = "freebsd")]
+pub use libc::cpuset_t;
+#[cfg(target_os = "windows")]
+pub use winapi::um::winnt::GROUP_AFFINITY;
+
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+use std::mem;
+#[cfg(target_os = "windows")]
+use std::ptr;
+
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+use libc::{CPU_ISSET, CPU_SET, CPU_SETSIZE, EINVAL, c_int};
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use libc::sched_setaffinity;
+#[cfg(target_os = "freebsd")]
+use libc::{CPU_LEVEL_WHICH, CPU_WHICH_TID, cpuset_setaffinity};
+
+use super::{CoreId, CpuSet, Error, ErrorKind};
+use current;
+
+/// Converts `set` into a `cpu_set_t`.
+///
+/// Fails if a core is beyond `CPU_SETSIZE`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub fn to_cpu_set_t(set: &CpuSet) -> Result<cpu_set_t, Error> {
+    let mut raw = unsafe { mem::zeroed::<cpu_set_t>() };
+
+    for core_id in set {
+        if core_id.id >= CPU_SETSIZE as usize {
+            return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+        }
+
+        unsafe { CPU_SET(core_id.id, &mut raw) };
+    }
+
+    Ok(raw)
+}
+
+/// Converts a `cpu_set_t` into a `CpuSet`.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub fn from_cpu_set_t(raw: &cpu_set_t) -> CpuSet {
+    (0..CPU_SETSIZE as usize)
+        .filter(|&id| unsafe { CPU_ISSET(id, raw) })
+        .map(|id| CoreId { id })
+        .collect()
+}
+
+/// Converts `set` into a `cpuset_t`.
+///
+/// Fails if a core is beyond `CPU_SETSIZE`.
+#[cfg(target_os = "freebsd")]
+pub fn to_cpuset_t(set: &CpuSet) -> Result<cpuset_t, Error> {
+    let mut raw = unsafe { mem::zeroed::<cpuset_t>() };
+
+    for core_id in set {
+        if core_id.id >= CPU_SETSIZE as usize {
+            return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+        }
+
+        unsafe { CPU_SET(core_id.id, &mut raw) };
+    }
+
+    Ok(raw)
+}
+
+/// Converts a `cpuset_t` into a `CpuSet`.
+#[cfg(target_os = "freebsd")]
+pub fn from_cpuset_t(raw: &cpuset_t) -> CpuSet {
+    (0..CPU_SETSIZE as usize)
+        .filter(|&id| unsafe { CPU_ISSET(id, raw) })
+        .map(|id| CoreId { id })
+        .collect()
+}
+
+/// This function pins the current thread to `core` with a
+/// single system call, returning 0 on success and the raw OS
+/// error code otherwise, such as `EINVAL` for a core that is
+/// offline or beyond `CPU_SETSIZE`.
+///
+/// Nothing is allocated, locked or kept in thread-local
+/// storage, and `errno` is left as it was. The call is
+/// async-signal-safe where the system call is: on Linux and
+/// Android it is `sched_setaffinity`, on FreeBSD
+/// `cpuset_setaffinity`, and on Windows, which has no signal
+/// handlers, `SetThreadGroupAffinity`.
+///
+/// The rest of the crate does not learn of the pin, so
+/// `CORE_AFFINITY_DISABLED`, the registry and the `tracing`
+/// and `metrics` features all pass it by. On success the cached
+/// core of `current_core_fast` is forgotten, so that it asks
+/// the system again.
+///
+/// # Safety
+///
+/// The call itself is sound. It is `unsafe` since it bypasses
+/// the checks of `set_for_current`, and since its guarantees
+/// are only as good as those of the system call.
+///
+/// # Arguments
+///
+/// * core - ID of the core to pin
+pub unsafe fn set_for_current_raw(core: usize) -> i32 {
+    let code = pin_raw(core);
+
+    if code == 0 {
+        current::forget_all();
+    }
+
+    code
+}
+
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+unsafe fn pin_raw(core: usize) -> c_int {
+    if core >= CPU_SETSIZE as usize {
+        return EINVAL;
+    }
+
+    let errno = errno_location();
+    let saved = *errno;
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    let res = {
+        let mut set = mem::zeroed::<cpu_set_t>();
+        CPU_SET(core, &mut set);
+
+        // Pid 0 is the calling thread.
+        sched_setaffinity(0, mem::size_of::<cpu_set_t>(), &set)
+    };
+
+    #[cfg(target_os = "freebsd")]
+    let res = {
+        let mut set = mem::zeroed::<cpuset_t>();
+        CPU_SET(core, &mut set);
+
+        // -1 is the calling thread.
+        cpuset_setaffinity(CPU_LEVEL_WHICH, CPU_WHICH_TID, -1, mem::size_of::<cpuset_t>(), &set)
+    };
+
+    let code = if res == 0 { 0 } else { *errno };
+
+    *errno = saved;
+    code
+}
+
+#[cfg(target_os = "windows")]
+unsafe fn pin_raw(core: usize) -> i32 {
+    use std::io;
+
+    use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+    use winapi::um::processthreadsapi::GetCurrentThread;
+    use winapi::um::processtopologyapi::SetThreadGroupAffinity;
+    use winapi::um::winbase::{GetActiveProcessorCount, GetActiveProcessorGroupCount};
+
+    // Walk the groups as `group_sizes` does, without the `Vec`.
+    let mut first = 0;
+
+    for group in 0..GetActiveProcessorGroupCount() {
+        let size = GetActiveProcessorCount(group) as usize;
+
+        if core < first + size {
+            let mut affinity: GROUP_AFFINITY = mem::zeroed();
+            affinity.Mask = 1 << (core - first);
+            affinity.Group = group;
+
+            if SetThreadGroupAffinity(GetCurrentThread(), &affinity, ptr::null_mut()) != 0 {
+                return 0;
+            }
+
+            return io::Error::last_os_error().raw_os_error().unwrap_or(ERROR_INVALID_PARAMETER as i32);
+        }
+
+        first += size;
+    }
+
+    ERROR_INVALID_PARAMETER as i32
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub(crate) unsafe fn errno_location() -> *mut c_int {
+    #[cfg(target_os = "linux")]
+    return libc::__errno_location();
+    #[cfg(target_os = "android")]
+    return libc::__errno();
+}
+
+#[cfg(target_os = "freebsd")]
+pub(crate) unsafe fn errno_location() -> *mut c_int {
+    libc::__error()
+}
+
+/// Converts `set` into one `GROUP_AFFINITY` for every
+/// processor group that has a core in the set.
+///
+/// Core IDs are numbered across the processor groups of the
+/// system, in the same way as everywhere else in this crate.
+/// Fails if a core is beyond the last processor group.
+#[cfg(target_os = "windows")]
+pub fn to_group_affinity(set: &CpuSet) -> Result<Vec<GROUP_AFFINITY>, Error> {
+    split_groups(set, &group_sizes())
+}
+
+/// Converts `GROUP_AFFINITY` values, such as those returned
+/// by `to_group_affinity`, back into a `CpuSet`.
+#[cfg(target_os = "windows")]
+pub fn from_group_affinity(groups: &[GROUP_AFFINITY]) -> CpuSet {
+    join_groups(groups, &group_sizes())
+}
+
+// The number of active processors in every group.
+#[cfg(target_os = "windows")]
+fn group_sizes() -> Vec<usize> {
+    use winapi::um::winbase::{GetActiveProcessorCount, GetActiveProcessorGroupCount};
+
+    let count = unsafe { GetActiveProcessorGroupCount() };
+
+    (0..count)
+        .map(|group| unsafe { GetActiveProcessorCount(group) } as usize)
+        .collect()
+}
+
+#[cfg(target_os = "windows")]
+fn split_groups(set: &CpuSet, sizes: &[usize]) -> Result<Vec<GROUP_AFFINITY>, Error> {
+    let mut groups: Vec<GROUP_AFFINITY> = Vec::new();
+    let mut first = 0;
+
+    for (group, &size) in sizes.iter().enumerate() {
+        let mut mask: usize = 0;
+
+        for id in 0..size {
+            if set.contains(CoreId { id: first + id }) {
+                mask |= 1 << id;
+            }
+        }
+
+        if mask != 0 {
+            let mut affinity: GROUP_AFFINITY = unsafe { mem::zeroed() };
+            affinity.Mask = mask;
+            affinity.Group = group as u16;
+            groups.push(affinity);
+        }
+
+        first += size;
+    }
+
+    // Anything left over does not belong to any group.
+    match set.iter().find(|core_id| core_id.id >= first) {
+        Some(core_id) => Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id)),
+        None => Ok(groups),
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn join_groups(groups: &[GROUP_AFFINITY], sizes: &[usize]) -> CpuSet {
+    let mut set = CpuSet::new();
+
+    for affinity in groups {
+        let first: usize = sizes.iter().take(affinity.Group as usize).sum();
+        let mask = affinity.Mask as u64;
+
+        set.extend((0..64)
+                   .filter(|i| mask & (1 << i) != 0)
+                   .map(|i| CoreId { id: first + i }));
+    }
+
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cores on both sides of every 64-bit word boundary.
+    #[cfg(any(target_os = "android", target_os = "linux", target_os = "freebsd"))]
+    fn wide_set() -> CpuSet {
+        [0, 1, 63, 64, 127, 128, 500, 1023].iter().map(|&id| CoreId { id }).collect()
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn test_cpu_set_t_round_trip() {
+        let set = wide_set();
+        let raw = to_cpu_set_t(&set).unwrap();
+
+        assert!(unsafe { CPU_ISSET(1023, &raw) });
+        assert_eq!(from_cpu_set_t(&raw), set);
+
+        let err = to_cpu_set_t(&CpuSet::from(vec![CoreId { id: 1024 }])).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+    }
+
+    #[cfg(all(not(feature = "mock"), target_os = "linux"))]
+    #[test]
+    fn test_set_for_current_raw_in_signal_handler() {
+        use std::ptr;
+        use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+        use std::thread;
+
+        use libc::{SIGUSR1, c_int, pthread_kill, pthread_self, sigaction, sigemptyset};
+
+        use super::super::get_affinity_for_current;
+
+        static CORE: AtomicUsize = AtomicUsize::new(0);
+        static RESULT: AtomicI32 = AtomicI32::new(-1);
+
+        extern "C" fn handler(_signal: c_int) {
+            let code = unsafe { set_for_current_raw(CORE.load(Ordering::SeqCst)) };
+            RESULT.store(code, Ordering::SeqCst);
+        }
+
+        let core_id = *get_affinity_for_current().unwrap().last().unwrap();
+        CORE.store(core_id.id, Ordering::SeqCst);
+
+        thread::spawn(move || unsafe {
+            let mut action: sigaction = mem::zeroed();
+            action.sa_sigaction = handler as extern "C" fn(c_int) as usize;
+            sigemptyset(&mut action.sa_mask);
+
+            let mut previous: sigaction = mem::zeroed();
+            assert_eq!(sigaction(SIGUSR1, &action, &mut previous), 0);
+
+            // A signal sent to the calling thread is handled
+            // before `pthread_kill` returns.
+            assert_eq!(pthread_kill(pthread_self(), SIGUSR1), 0);
+            assert_eq!(sigaction(SIGUSR1, &previous, ptr::null_mut()), 0);
+
+            assert_eq!(RESULT.load(Ordering::SeqCst), 0);
+            assert_eq!(get_affinity_for_current().unwrap(), vec![core_id]);
+        }).join().unwrap();
+    }
+
+    #[cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux")))]
+    #[test]
+    fn test_set_for_current_raw_forgets_current_core() {
+        use std::thread;
+
+        use super::super::{current_core_fast, get_affinity_for_current, set_for_current};
+
+        let core_ids = get_affinity_for_current().unwrap();
+        let (first, last) = (core_ids[0], *core_ids.last().unwrap());
+
+        thread::spawn(move || {
+            assert!(set_for_current(first));
+            assert_eq!(current_core_fast(), Some(first));
+
+            assert_eq!(unsafe { set_for_current_raw(last.id) }, 0);
+            assert_eq!(current_core_fast(), Some(last));
+        }).join().unwrap();
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    fn test_set_for_current_raw_errors() {
+        let errno = unsafe { errno_location() };
+
+        unsafe { *errno = 42 };
+        assert_eq!(unsafe { set_for_current_raw(CPU_SETSIZE as usize) }, EINVAL);
+        assert_eq!(unsafe { set_for_current_raw(usize::MAX) }, EINVAL);
+
+        // A core that is not online fails in the kernel, and
+        // leaves `errno` alone.
+        if let Some(max) = super::super::max_core_id() {
+            if max.id + 1 < CPU_SETSIZE as usize {
+                assert_eq!(unsafe { set_for_current_raw(max.id + 1) }, EINVAL);
+            }
+        }
+
+        assert_eq!(unsafe { *errno }, 42);
+    }
+
+    #[cfg(target_os = "freebsd")]
+    #[test]
+    fn test_cpuset_t_round_trip() {
+        let set: CpuSet = wide_set().iter().filter(|id| id.id < CPU_SETSIZE as usize).collect();
+        let raw = to_cpuset_t(&set).unwrap();
+
+        assert_eq!(from_cpuset_t(&raw), set);
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_group_affinity_round_trip() {
+        // Two full groups and a partial one, as on a 136-core system.
+        let sizes = [64, 64, 8];
+        let set: CpuSet = [0, 63, 64, 127, 128, 135].iter().map(|&id| CoreId { id }).collect();
+
+        let groups = split_groups(&set, &sizes).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[2].Group, 2);
+        assert_eq!(groups[2].Mask, 0x81);
+        assert_eq!(join_groups(&groups, &sizes), set);
+
+        let err = split_groups(&CpuSet::from(vec![CoreId { id: 136 }]), &sizes).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+    }
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn test_group_affinity_system() {
+        let set = super::super::get_core_set().unwrap();
+
+        assert_eq!(from_group_affinity(&to_group_affinity(&set).unwrap()), set);
+    }
+}
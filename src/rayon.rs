@@ -0,0 +1,155 @@
+//! `rayon` support, enabled by the `rayon` feature.
+//!
+//! `pinned_pool_builder` returns a `rayon::ThreadPoolBuilder`
+//! with one worker per core, where worker `i` pins itself to
+//! `cores[i]` as it starts. The list is used as given, so it
+//! may be sparse, such as the result of `get_core_ids` under
+//! `taskset`, without shifting the workers onto cores they
+//! may not use.
+//!
+//! Pinning happens in the `start_handler` of the builder. A
+//! later call to `start_handler` on the returned builder
+//! replaces it, and the workers stay unpinned.
+
+use std::sync::Arc;
+
+use rayon_crate::{ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+
+use super::{CoreId, Error, ErrorKind, set_for_current_checked};
+use instrument::unpinned;
+
+/// What a worker does when it can not be pinned to its core.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OnPinFailure {
+    /// Panics in the worker. rayon hands the panic to the
+    /// `panic_handler` of the pool, and aborts the process if
+    /// there is none.
+    Panic,
+    /// Keeps the worker unpinned, with a warning through
+    /// `tracing` if that feature is on.
+    #[default]
+    Log,
+    /// Keeps the worker unpinned without a word.
+    Ignore,
+}
+
+/// This function returns a builder for a pool with one worker
+/// per entry of `cores`, where worker `i` pins itself to
+/// `cores[i]`, warning as `OnPinFailure::Log` if that fails.
+///
+/// The index of a worker is `rayon::current_thread_index()`.
+/// Duplicate cores get one worker each. With an empty list,
+/// rayon picks the number of workers, which all fail to pin
+/// with `ErrorKind::EmptySet`.
+///
+/// # Arguments
+///
+/// * cores - the core of each worker
+pub fn pinned_pool_builder(cores: &[CoreId]) -> ThreadPoolBuilder {
+    pinned_pool_builder_with(cores, OnPinFailure::default())
+}
+
+/// This function returns a builder like `pinned_pool_builder`,
+/// but with `on_failure` deciding what a worker does when it
+/// can not be pinned.
+///
+/// # Arguments
+///
+/// * cores - the core of each worker
+/// * on_failure - what to do when pinning fails
+pub fn pinned_pool_builder_with(cores: &[CoreId], on_failure: OnPinFailure) -> ThreadPoolBuilder {
+    let cores: Arc<[CoreId]> = cores.into();
+
+    ThreadPoolBuilder::new()
+        .num_threads(cores.len())
+        .start_handler(move |index| pin_worker(index, cores.get(index).cloned(), on_failure))
+}
+
+/// This function builds a pool of workers pinned as by
+/// `pinned_pool_builder_with`.
+///
+/// # Arguments
+///
+/// * cores - the core of each worker
+/// * on_failure - what to do when pinning fails
+pub fn pinned_pool(cores: &[CoreId], on_failure: OnPinFailure) -> Result<ThreadPool, ThreadPoolBuildError> {
+    pinned_pool_builder_with(cores, on_failure).build()
+}
+
+fn pin_worker(index: usize, core_id: Option<CoreId>, on_failure: OnPinFailure) {
+    let result = match core_id {
+        Some(core_id) => set_for_current_checked(core_id),
+        None => Err(Error::new(ErrorKind::EmptySet)),
+    };
+
+    let err = match result {
+        Ok(()) => return,
+        Err(err) => err,
+    };
+
+    let core = core_id.map_or_else(|| "no core".to_string(), |core_id| format!("core {}", core_id.id));
+
+    match on_failure {
+        OnPinFailure::Panic => panic!("failed to pin rayon worker {} to {}: {}", index, core, err),
+        OnPinFailure::Log => {
+            unpinned(format_args!("rayon worker {} to {}", index, core), &err);
+        },
+        OnPinFailure::Ignore => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    // `current_core` is not supported on macOS.
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_pinned_pool() {
+        use super::super::{current_core, get_core_ids};
+
+        // Every core twice, so the workers outnumber the cores.
+        let ids = get_core_ids().unwrap();
+        let cores: Vec<CoreId> = ids.iter().chain(&ids).cloned().collect();
+
+        let pool = pinned_pool(&cores, OnPinFailure::Panic).unwrap();
+        assert_eq!(pool.current_num_threads(), cores.len());
+
+        let placed = pool.broadcast(|ctx| (ctx.index(), current_core()));
+
+        for (index, core_id) in placed {
+            assert_eq!(core_id, Some(cores[index]));
+        }
+    }
+
+    #[test]
+    fn test_pinned_pool_failure() {
+        let cores = [CoreId { id: usize::MAX }];
+
+        // The worker starts unpinned and still runs jobs.
+        for &on_failure in &[OnPinFailure::Log, OnPinFailure::Ignore] {
+            let pool = pinned_pool(&cores, on_failure).unwrap();
+            assert_eq!(pool.install(|| 1 + 1), 2);
+        }
+
+        let panics = Arc::new(Mutex::new(Vec::new()));
+        let sink = panics.clone();
+
+        let pool = pinned_pool_builder_with(&cores, OnPinFailure::Panic)
+            .panic_handler(move |payload| {
+                let message = payload.downcast_ref::<String>().cloned().unwrap_or_default();
+                sink.lock().unwrap().push(message);
+            })
+            .build()
+            .unwrap();
+
+        // Startup goes on once the handler returns.
+        assert_eq!(pool.install(|| 1 + 1), 2);
+
+        let panics = panics.lock().unwrap();
+        assert_eq!(panics.len(), 1);
+        assert!(panics[0].starts_with(&format!("failed to pin rayon worker 0 to core {}", usize::MAX)));
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::{Thread, ThreadId};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{CoreId, Error, format_cpulist};
+
+// The most threads remembered at once. Past that, the thread
+// pinned longest ago is forgotten.
+const MAX_THREADS: usize = 1024;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ASSIGNMENTS: Mutex<Option<HashMap<ThreadId, Assignment>>> = Mutex::new(None);
+
+/// The latest pin of one thread, as returned by
+/// `dump_assignments`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Assignment {
+    /// The thread that was pinned.
+    pub thread_id: ThreadId,
+    /// The name of the thread, if it has one.
+    pub thread_name: Option<String>,
+    /// The cores the thread was pinned to.
+    pub core_ids: Vec<CoreId>,
+    /// When the pin was made.
+    pub time: SystemTime,
+    /// Whether the pin succeeded, and why not if it did not.
+    pub result: Result<(), Error>,
+}
+
+/// A list of assignments, displayed as a table with one row
+/// per thread.
+///
+/// ```
+/// extern crate core_affinity;
+///
+/// use core_affinity::AssignmentTable;
+///
+/// println!("{}", AssignmentTable(&core_affinity::dump_assignments()));
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct AssignmentTable<'a>(pub &'a [Assignment]);
+
+impl<'a> fmt::Display for AssignmentTable<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<14} {:<16} {:<12} {:<18} RESULT", "THREAD", "NAME", "CORES", "TIME")?;
+
+        for assignment in self.0 {
+            let time = assignment.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let time = format!("{}.{:03}", time.as_secs(), time.subsec_millis());
+
+            write!(f, "{:<14} {:<16} {:<12} {:<18} ",
+                   format!("{:?}", assignment.thread_id),
+                   assignment.thread_name.as_deref().unwrap_or("-"),
+                   format_cpulist(&assignment.core_ids),
+                   time)?;
+
+            match assignment.result {
+                Ok(()) => writeln!(f, "ok")?,
+                Err(ref err) => writeln!(f, "{}", err)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// This function turns the registry of pins on or off.
+///
+/// While it is on, every call to `set_for_current`,
+/// `set_for_current_set`, `set_for_current_excluding` and
+/// `set_for_thread` is remembered, as is the restore when an
+/// `AffinityGuard` is dropped, keeping only the latest one of
+/// each thread. It is off by
+/// default. Turning it off keeps what was recorded so far.
+///
+/// # Arguments
+///
+/// * enabled - whether pins should be recorded
+pub fn set_registry_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Release);
+}
+
+/// This function returns whether pins are being recorded.
+pub fn is_registry_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// This function returns the latest pin of every thread
+/// recorded while the registry was on, oldest first.
+///
+/// Threads that have exited are kept until the registry
+/// fills up. Use `AssignmentTable` to print the list.
+pub fn dump_assignments() -> Vec<Assignment> {
+    let assignments = ASSIGNMENTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut list: Vec<Assignment> = assignments.iter().flat_map(|map| map.values().cloned()).collect();
+
+    list.sort_by_key(|assignment| assignment.time);
+    list
+}
+
+// Records that `thread` was pinned to `core_ids`.
+pub(crate) fn note(thread: &Thread, core_ids: &[CoreId], result: &Result<(), Error>) {
+    if !is_registry_enabled() {
+        return;
+    }
+
+    let assignment = Assignment {
+        thread_id: thread.id(),
+        thread_name: thread.name().map(String::from),
+        core_ids: core_ids.to_vec(),
+        time: SystemTime::now(),
+        result: *result,
+    };
+
+    let mut assignments = ASSIGNMENTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let map = assignments.get_or_insert_with(HashMap::new);
+
+    if map.len() >= MAX_THREADS && !map.contains_key(&assignment.thread_id) {
+        let oldest = map.values().min_by_key(|assignment| assignment.time).map(|oldest| oldest.thread_id);
+
+        if let Some(thread_id) = oldest {
+            map.remove(&thread_id);
+        }
+    }
+
+    map.insert(assignment.thread_id, assignment);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use super::super::{ErrorKind, get_core_ids, pin_scoped, set_for_current, set_for_current_excluding};
+
+    #[test]
+    fn test_dump_assignments() {
+        let ids = get_core_ids().unwrap();
+
+        set_registry_enabled(true);
+        assert!(is_registry_enabled());
+
+        let names = ["registry-a", "registry-b", "registry-c"];
+        let threads: Vec<ThreadId> = names.iter().enumerate().map(|(i, name)| {
+            let id = ids[i % ids.len()];
+
+            thread::Builder::new().name(name.to_string()).spawn(move || {
+                assert!(set_for_current(id));
+                thread::current().id()
+            }).unwrap().join().unwrap()
+        }).collect();
+
+        // Only ours, since other tests may pin at the same time.
+        let dump: Vec<Assignment> = dump_assignments().into_iter()
+            .filter(|assignment| threads.contains(&assignment.thread_id))
+            .collect();
+        assert_eq!(dump.len(), 3);
+
+        for (i, &thread_id) in threads.iter().enumerate() {
+            let assignment = dump.iter().find(|assignment| assignment.thread_id == thread_id).unwrap();
+            assert_eq!(assignment.thread_name.as_deref(), Some(names[i]));
+            assert_eq!(assignment.core_ids, vec![ids[i % ids.len()]]);
+            assert_eq!(assignment.result, Ok(()));
+        }
+
+        let table = AssignmentTable(&dump).to_string();
+        assert_eq!(table.lines().count(), 4);
+        assert!(table.starts_with("THREAD"));
+        assert!(names.iter().all(|name| table.contains(name)));
+    }
+
+    #[test]
+    fn test_dump_assignments_excluding() {
+        let ids = get_core_ids().unwrap();
+        let first = ids[0];
+
+        // A single core can not be excluded.
+        if ids.len() < 2 {
+            return;
+        }
+
+        set_registry_enabled(true);
+
+        let (thread_id, excluded, restored) = thread::spawn(move || {
+            assert_eq!(set_for_current_excluding(&[first]), Ok(()));
+            let excluded = dump_assignments().into_iter().find(|assignment| assignment.thread_id == thread::current().id());
+
+            drop(pin_scoped(first));
+            let restored = dump_assignments().into_iter().find(|assignment| assignment.thread_id == thread::current().id());
+
+            (thread::current().id(), excluded.unwrap(), restored.unwrap())
+        }).join().unwrap();
+
+        assert_eq!(excluded.thread_id, thread_id);
+        assert!(!excluded.core_ids.contains(&first));
+
+        // The guard put back the mask it found.
+        assert_eq!(restored.core_ids, excluded.core_ids);
+    }
+
+    #[test]
+    fn test_assignment_table() {
+        let assignment = Assignment {
+            thread_id: thread::current().id(),
+            thread_name: None,
+            core_ids: vec![CoreId { id: 1 }, CoreId { id: 2 }],
+            time: UNIX_EPOCH + std::time::Duration::from_millis(1500),
+            result: Err(Error::new(ErrorKind::InvalidCore)),
+        };
+
+        let table = AssignmentTable(&[assignment]).to_string();
+        let row = table.lines().nth(1).unwrap();
+
+        assert!(row.contains(" -  "));
+        assert!(row.contains("1-2"));
+        assert!(row.contains("1.500"));
+        assert!(row.ends_with("invalid core"));
+    }
+}
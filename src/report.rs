@@ -0,0 +1,125 @@
+use std::fmt;
+use std::process;
+
+use super::{CpuSet, get_affinity_for_current_helper, get_all_core_ids_helper, get_for_process_helper};
+
+/// A snapshot of the affinity masks that apply to the current
+/// thread, as returned by `report`.
+///
+/// It displays as a few lines meant for bug reports: each mask,
+/// or "unavailable" where it could not be read, followed by a
+/// line for every mask that is narrower than the one above it,
+/// such as "thread restricted to 1 of 32 cores".
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AffinityReport {
+    /// The cores the current thread may run on.
+    pub thread: Option<CpuSet>,
+    /// The cores the process may run on.
+    pub process: Option<CpuSet>,
+    /// The cores that are online on the system.
+    pub online: Option<CpuSet>,
+}
+
+/// This function gathers the affinity masks of the current
+/// thread and process and the online cores of the system.
+///
+/// It never fails: a mask that can not be read is left out
+/// of the report. It does not touch `last_error` either, so
+/// it can be called while handling another error.
+pub fn report() -> AffinityReport {
+    AffinityReport {
+        thread: get_affinity_for_current_helper().ok().map(CpuSet::from),
+        process: get_for_process_helper(process::id()).ok().map(CpuSet::from),
+        online: get_all_core_ids_helper().map(CpuSet::from),
+    }
+}
+
+impl fmt::Display for AffinityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_mask(f, "thread mask", &self.thread)?;
+        write_mask(f, "process mask", &self.process)?;
+        write_mask(f, "online cores", &self.online)?;
+
+        // Compare the thread against the process where both are
+        // known, and only fall back to the online cores otherwise.
+        let outer = self.process.as_ref().or(self.online.as_ref());
+        write_restricted(f, "thread", self.thread.as_ref(), outer)?;
+        write_restricted(f, "process", self.process.as_ref(), self.online.as_ref())
+    }
+}
+
+fn write_mask(f: &mut fmt::Formatter, name: &str, mask: &Option<CpuSet>) -> fmt::Result {
+    match *mask {
+        Some(ref set) => writeln!(f, "{}: {} ({})", name, set, cores(set.len())),
+        None => writeln!(f, "{}: unavailable", name),
+    }
+}
+
+fn write_restricted(f: &mut fmt::Formatter, name: &str, inner: Option<&CpuSet>,
+                    outer: Option<&CpuSet>) -> fmt::Result {
+    match (inner, outer) {
+        (Some(inner), Some(outer)) if inner.len() < outer.len() => {
+            writeln!(f, "{} restricted to {} of {}", name, inner.len(), cores(outer.len()))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn cores(count: usize) -> String {
+    if count == 1 {
+        "1 core".to_string()
+    }
+    else {
+        format!("{} cores", count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_affinity_report_display() {
+        let report = AffinityReport {
+            thread: Some("3".parse().unwrap()),
+            process: Some("0-7".parse().unwrap()),
+            online: Some("0-31".parse().unwrap()),
+        };
+
+        assert_eq!(report.to_string(),
+                   "thread mask: 3 (1 core)\n\
+                    process mask: 0-7 (8 cores)\n\
+                    online cores: 0-31 (32 cores)\n\
+                    thread restricted to 1 of 8 cores\n\
+                    process restricted to 8 of 32 cores\n");
+
+        let report = AffinityReport { thread: None, process: None, online: Some("0-3".parse().unwrap()) };
+
+        assert_eq!(report.to_string(),
+                   "thread mask: unavailable\n\
+                    process mask: unavailable\n\
+                    online cores: 0-3 (4 cores)\n");
+    }
+
+    #[test]
+    fn test_report() {
+        assert!(report().online.is_some());
+
+        #[cfg(not(target_os = "macos"))]
+        {
+            use std::thread;
+
+            use super::super::{get_core_ids, set_for_current};
+
+            let id = *get_core_ids().unwrap().last().unwrap();
+
+            let text = thread::spawn(move || {
+                assert!(set_for_current(id));
+                report().to_string()
+            }).join().unwrap();
+
+            assert!(text.starts_with(&format!("thread mask: {} (1 core)\n", id)));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use super::{CoreId, Error, ErrorKind, allowed_for_process, spawn_pinned};
+
+// The cores reserved in this process.
+static RESERVED: Mutex<Vec<CoreId>> = Mutex::new(Vec::new());
+
+/// A core set aside for one user in this process.
+///
+/// Reservations are only bookkeeping: they do not pin any
+/// thread, and only keep out the code that asks for a
+/// reservation too, such as a `CoreAllocator` built with
+/// `skipping_reserved`. Dropping the reservation gives the
+/// core back.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CoreReservation {
+    core_id: CoreId,
+}
+
+impl CoreReservation {
+    /// Returns the reserved core.
+    pub fn core_id(&self) -> CoreId {
+        self.core_id
+    }
+}
+
+impl Drop for CoreReservation {
+    fn drop(&mut self) {
+        lock().retain(|&core_id| core_id != self.core_id);
+    }
+}
+
+/// The error returned by `reserve_specific` for a core that
+/// is reserved already.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AlreadyReserved {
+    core_id: CoreId,
+}
+
+impl AlreadyReserved {
+    /// Returns the core that was asked for.
+    pub fn core_id(&self) -> CoreId {
+        self.core_id
+    }
+}
+
+impl fmt::Display for AlreadyReserved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "core {}: already reserved", self.core_id.id)
+    }
+}
+
+impl error::Error for AlreadyReserved {}
+
+/// This function reserves a core the process may run on
+/// that nobody has reserved yet.
+///
+/// The cores are tried in ID order. Returns `None` if every
+/// core is reserved or the cores can not be listed.
+pub fn reserve_core() -> Option<CoreReservation> {
+    let allowed = allowed_for_process().ok()?;
+    let mut reserved = lock();
+
+    let core_id = allowed.iter().find(|core_id| !reserved.contains(core_id))?;
+    reserved.push(core_id);
+
+    Some(CoreReservation { core_id })
+}
+
+/// This function reserves `core_id`, failing if it is
+/// reserved already.
+///
+/// The core is not checked against the system, so IDs of
+/// cores that are offline can be reserved too.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to reserve
+pub fn reserve_specific(core_id: CoreId) -> Result<CoreReservation, AlreadyReserved> {
+    let mut reserved = lock();
+
+    if reserved.contains(&core_id) {
+        return Err(AlreadyReserved { core_id });
+    }
+
+    reserved.push(core_id);
+
+    Ok(CoreReservation { core_id })
+}
+
+/// This function returns whether `core_id` is reserved.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to look up
+pub fn is_reserved(core_id: CoreId) -> bool {
+    lock().contains(&core_id)
+}
+
+/// This function returns the reserved cores, sorted by ID.
+pub fn reserved_core_ids() -> Vec<CoreId> {
+    let mut core_ids = lock().clone();
+
+    core_ids.sort();
+    core_ids
+}
+
+/// This function reserves a free core, and spawns a thread
+/// pinned to it that holds the reservation until it exits.
+///
+/// Fails with `ErrorKind::EmptySet` if no core is free, and
+/// like `spawn_pinned` otherwise, in which case the core is
+/// given back.
+///
+/// # Arguments
+///
+/// * f - closure to run on the new thread
+pub fn spawn_reserved<F, T>(f: F) -> io::Result<JoinHandle<T>>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let reservation = reserve_core().ok_or_else(|| Error::new(ErrorKind::EmptySet))?;
+
+    spawn_pinned(reservation.core_id(), move || {
+        let _reservation = reservation;
+        f()
+    })
+}
+
+fn lock() -> MutexGuard<'static, Vec<CoreId>> {
+    // The list stays consistent even if a holder panicked.
+    RESERVED.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use super::*;
+    use super::super::get_affinity_for_current;
+
+    // The registry is shared by every test, so the tests that
+    // do not need real cores use IDs no machine has.
+
+    #[test]
+    fn test_reserve_specific() {
+        let id = CoreId { id: 100_000 };
+
+        let reservation = reserve_specific(id).unwrap();
+        assert_eq!(reservation.core_id(), id);
+        assert!(is_reserved(id));
+
+        let err = reserve_specific(id).unwrap_err();
+        assert_eq!(err.core_id(), id);
+        assert_eq!(err.to_string(), "core 100000: already reserved");
+
+        drop(reservation);
+        assert!(!is_reserved(id));
+        assert!(reserve_specific(id).is_ok());
+    }
+
+    #[test]
+    fn test_reserve_specific_race() {
+        let id = CoreId { id: 100_001 };
+        let barrier = Arc::new(Barrier::new(8));
+
+        let handles: Vec<_> = (0..8).map(|_| {
+            let barrier = barrier.clone();
+
+            thread::spawn(move || {
+                barrier.wait();
+                // Hold on to the reservation until every thread tried.
+                let res = reserve_specific(id);
+                barrier.wait();
+                res.is_ok()
+            })
+        }).collect();
+
+        let won = handles.into_iter().map(|handle| handle.join().unwrap());
+        assert_eq!(won.filter(|&won| won).count(), 1);
+        assert!(!is_reserved(id));
+    }
+
+    #[test]
+    fn test_reserve_core_race() {
+        let free = allowed_for_process().unwrap().len();
+        let threads = free + 2;
+        let barrier = Arc::new(Barrier::new(threads));
+
+        let handles: Vec<_> = (0..threads).map(|_| {
+            let barrier = barrier.clone();
+
+            thread::spawn(move || {
+                barrier.wait();
+                let reservation = reserve_core();
+                let core_id = reservation.as_ref().map(CoreReservation::core_id);
+                barrier.wait();
+                core_id
+            })
+        }).collect();
+
+        let mut won: Vec<CoreId> = handles.into_iter().filter_map(|handle| handle.join().unwrap()).collect();
+        assert_eq!(won.len(), free);
+
+        // Every core went to exactly one thread.
+        won.sort();
+        won.dedup();
+        assert_eq!(won.len(), free);
+
+        // With the reservations gone, a reserved thread can start.
+        let core_id = spawn_reserved(|| {
+            let core_ids = get_affinity_for_current().unwrap();
+            assert!(is_reserved(core_ids[0]));
+            core_ids
+        }).unwrap().join().unwrap();
+        assert_eq!(core_id.len(), 1);
+        assert!(!is_reserved(core_id[0]));
+    }
+}
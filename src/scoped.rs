@@ -0,0 +1,196 @@
+use std::marker::PhantomData;
+
+use super::{
+    clear_for_current_helper, get_affinity_for_current_helper, set_for_current_checked,
+    set_for_current_set_helper, single_core, track, CoreId, Error, ErrorKind,
+};
+use enabled::unless_disabled;
+use instrument::{pin_current_set, unpinned};
+
+/// Restores the previous affinity of the current thread when dropped.
+///
+/// Guards are created by `pin_scoped`. Nested guards restore
+/// in reverse order of creation, and the restore also happens
+/// while unwinding from a panic.
+///
+/// On macOS the previous affinity tag can not be read, so
+/// dropping a guard resets the thread to the null tag instead.
+#[must_use = "the previous affinity is restored as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct AffinityGuard {
+    previous: Option<Vec<CoreId>>,
+    // The guard restores the thread that created it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for AffinityGuard {
+    fn drop(&mut self) {
+        let _ = unless_disabled(|| match self.previous {
+            Some(ref core_ids) => {
+                pin_current_set(core_ids, || track(set_for_current_set_helper(core_ids), single_core(core_ids)))
+            },
+            None => track(clear_for_current_helper(), None),
+        });
+    }
+}
+
+/// This function tries to pin the current thread to the
+/// specified core until the returned guard is dropped.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+pub fn pin_scoped(core_id: CoreId) -> Option<AffinityGuard> {
+    pin_guarded(core_id).ok()
+}
+
+// Called by the code `#[pinned]` expands to, at the start of
+// `function`.
+#[doc(hidden)]
+pub fn __pin_function(function: &str, id: usize, strict: bool) -> Option<AffinityGuard> {
+    match pin_guarded(CoreId { id }) {
+        Ok(guard) => Some(guard),
+        Err(err) if strict => panic!("failed to pin `{}` to core {}: {}", function, id, err),
+        Err(err) => {
+            // Without `tracing` the warning goes to stderr, since
+            // nothing else would tell of the failure.
+            #[cfg(not(feature = "tracing"))]
+            eprintln!("core_affinity: failed to pin `{}` to core {}: {}, running unpinned", function, id, err);
+            unpinned(format_args!("`{}` to core {}", function, id), &err);
+            None
+        },
+    }
+}
+
+/// This function runs `f` with the current thread pinned
+/// to the specified core, restoring the previous affinity
+/// afterwards.
+///
+/// The affinity is restored even if `f` panics, in which
+/// case the panic continues to unwind. If pinning fails,
+/// `f` is not run and the error is returned instead.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin
+/// * f - closure to run while pinned
+pub fn with_affinity<T, F>(core_id: CoreId, f: F) -> Result<T, Error>
+    where F: FnOnce() -> T
+{
+    let _guard = pin_guarded(core_id)?;
+    Ok(f())
+}
+
+fn pin_guarded(core_id: CoreId) -> Result<AffinityGuard, Error> {
+    let previous = match get_affinity_for_current_helper() {
+        Ok(core_ids) => Some(core_ids),
+        Err(ref err) if err.kind() == ErrorKind::Unsupported => None,
+        Err(err) => return Err(err),
+    };
+
+    set_for_current_checked(core_id)?;
+
+    Ok(AffinityGuard {
+        previous,
+        _not_send: PhantomData,
+    })
+}
+
+#[cfg(test)]
+#[cfg(any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd"))]
+mod tests {
+    use std::panic;
+    use std::thread;
+
+    use super::*;
+    use super::super::get_affinity_for_current;
+
+    #[test]
+    fn test_pin_scoped() {
+        thread::spawn(|| {
+            let before = get_affinity_for_current().unwrap();
+
+            {
+                let _guard = pin_scoped(before[0]).unwrap();
+                assert_eq!(get_affinity_for_current().unwrap(), vec![before[0]]);
+            }
+
+            assert_eq!(get_affinity_for_current().unwrap(), before);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_pin_scoped_nested() {
+        thread::spawn(|| {
+            let before = get_affinity_for_current().unwrap();
+            let first = before[0];
+            let second = *before.last().unwrap();
+
+            let outer = pin_scoped(first).unwrap();
+            let inner = pin_scoped(second).unwrap();
+            assert_eq!(get_affinity_for_current().unwrap(), vec![second]);
+
+            drop(inner);
+            assert_eq!(get_affinity_for_current().unwrap(), vec![first]);
+
+            drop(outer);
+            assert_eq!(get_affinity_for_current().unwrap(), before);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_pin_scoped_panic() {
+        thread::spawn(|| {
+            let before = get_affinity_for_current().unwrap();
+
+            let res = panic::catch_unwind(|| {
+                let _guard = pin_scoped(before[0]).unwrap();
+                panic!("unwinding through the guard");
+            });
+
+            assert!(res.is_err());
+            assert_eq!(get_affinity_for_current().unwrap(), before);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_pin_scoped_invalid_core() {
+        assert!(pin_scoped(CoreId { id: usize::MAX }).is_none());
+    }
+
+    #[test]
+    fn test_with_affinity() {
+        thread::spawn(|| {
+            let before = get_affinity_for_current().unwrap();
+
+            let inside = with_affinity(before[0], || get_affinity_for_current().unwrap());
+
+            assert_eq!(inside, Ok(vec![before[0]]));
+            assert_eq!(get_affinity_for_current().unwrap(), before);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_with_affinity_panic() {
+        thread::spawn(|| {
+            let before = get_affinity_for_current().unwrap();
+
+            let res = panic::catch_unwind(|| {
+                with_affinity(before[0], || panic!("unwinding out of the closure"))
+            });
+
+            assert!(res.is_err());
+            assert_eq!(get_affinity_for_current().unwrap(), before);
+        }).join().unwrap();
+    }
+
+    #[test]
+    fn test_with_affinity_invalid_core() {
+        let mut ran = false;
+
+        let res = with_affinity(CoreId { id: usize::MAX }, || ran = true);
+
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidCore);
+        assert!(!ran);
+    }
+}
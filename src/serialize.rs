@@ -0,0 +1,169 @@
+//! `serde` support, enabled by the `serde` feature.
+//!
+//! The representations are stable across releases:
+//!
+//! * `CoreId` is a bare unsigned integer, such as `3`.
+//! * `CpuSet` is a sequence of core IDs in ascending order,
+//!   such as `[0, 1, 2, 3]`.
+//! * `StableCoreId` is a sequence of its package, die, core
+//!   and thread, such as `[0, 0, 3, 1]`.
+//! * `Topology` is an object in the versioned schema described
+//!   on `Topology::SCHEMA_VERSION`. It only serializes.
+//!
+//! Deserializing does not check that the cores exist on the
+//! current machine; use `CoreId::try_new` for that.
+
+use std::fmt;
+
+use serde::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeSeq, SerializeStruct, Serializer};
+
+use super::{CoreId, CoreKind, CoreTopology, CpuSet, KindSource, StableCoreId, Topology};
+
+impl Serialize for CoreId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.id as u64)
+    }
+}
+
+impl<'de> Deserialize<'de> for CoreId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CoreId, D::Error> {
+        usize::deserialize(deserializer).map(|id| CoreId { id })
+    }
+}
+
+impl Serialize for CpuSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+
+        for core_id in self {
+            seq.serialize_element(&core_id)?;
+        }
+
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CpuSet, D::Error> {
+        deserializer.deserialize_seq(CpuSetVisitor)
+    }
+}
+
+struct CpuSetVisitor;
+
+impl<'de> Visitor<'de> for CpuSetVisitor {
+    type Value = CpuSet;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of core IDs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<CpuSet, A::Error> {
+        let mut set = CpuSet::new();
+
+        while let Some(core_id) = seq.next_element::<CoreId>()? {
+            set.set(core_id);
+        }
+
+        Ok(set)
+    }
+}
+
+impl Serialize for StableCoreId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        [self.package, self.die, self.core, self.thread].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StableCoreId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StableCoreId, D::Error> {
+        let [package, die, core, thread] = <[usize; 4]>::deserialize(deserializer)?;
+
+        Ok(StableCoreId { package, die, core, thread })
+    }
+}
+
+impl Serialize for Topology {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Topology", 3)?;
+        state.serialize_field("version", &Topology::SCHEMA_VERSION)?;
+        state.serialize_field("kind_source", &self.kind_source().map(|source| match source {
+            KindSource::Reported => "reported",
+            KindSource::Heuristic => "heuristic",
+        }))?;
+        state.serialize_field("cores", self.cores())?;
+        state.end()
+    }
+}
+
+impl Serialize for CoreTopology {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("CoreTopology", 11)?;
+        state.serialize_field("id", &self.core_id)?;
+        state.serialize_field("allowed", &self.allowed)?;
+        state.serialize_field("package", &self.package.map(|package| package.id))?;
+        state.serialize_field("die", &self.die.map(|die| die.id))?;
+        state.serialize_field("cluster", &self.cluster.map(|cluster| cluster.id))?;
+        state.serialize_field("numa_node", &self.numa_node.map(|node| node.id))?;
+        state.serialize_field("physical_core", &self.physical_core.map(|core| core.id))?;
+        state.serialize_field("l2_group", &self.l2_group)?;
+        state.serialize_field("cache_cluster", &self.cache_cluster.map(|cluster| cluster.id))?;
+        state.serialize_field("kind", &self.kind.map(|kind| match kind {
+            CoreKind::Performance => "performance",
+            CoreKind::Efficiency => "efficiency",
+            CoreKind::Unknown => "unknown",
+        }))?;
+        state.serialize_field("max_frequency_khz", &self.max_frequency_khz)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate bincode;
+    extern crate serde_json;
+
+    use super::*;
+
+    #[test]
+    fn test_serde_json_core_id() {
+        let core_id = CoreId { id: 3 };
+
+        assert_eq!(serde_json::to_string(&core_id).unwrap(), "3");
+        assert_eq!(serde_json::from_str::<CoreId>("3").unwrap(), core_id);
+        assert!(serde_json::from_str::<CoreId>("-1").is_err());
+
+        // Cores that do not exist here still deserialize.
+        assert_eq!(serde_json::from_str::<CoreId>("100000").unwrap(), CoreId { id: 100000 });
+    }
+
+    #[test]
+    fn test_serde_json_cpu_set() {
+        let set: CpuSet = "0-2,70".parse().unwrap();
+
+        assert_eq!(serde_json::to_string(&set).unwrap(), "[0,1,2,70]");
+        assert_eq!(serde_json::from_str::<CpuSet>("[70,0,1,2,1]").unwrap(), set);
+    }
+
+    #[test]
+    fn test_serde_json_stable_core_id() {
+        let id = StableCoreId { package: 1, die: 0, core: 12, thread: 1 };
+
+        assert_eq!(serde_json::to_string(&id).unwrap(), "[1,0,12,1]");
+        assert_eq!(serde_json::from_str::<StableCoreId>("[1,0,12,1]").unwrap(), id);
+        assert!(serde_json::from_str::<StableCoreId>("[1,0,12]").is_err());
+    }
+
+    #[test]
+    fn test_bincode_round_trip() {
+        let core_id = CoreId { id: 42 };
+        let set: CpuSet = "0-3,7,200".parse().unwrap();
+
+        let bytes = bincode::serialize(&core_id).unwrap();
+        assert_eq!(bincode::deserialize::<CoreId>(&bytes).unwrap(), core_id);
+
+        let bytes = bincode::serialize(&set).unwrap();
+        assert_eq!(bincode::deserialize::<CpuSet>(&bytes).unwrap(), set);
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+
+use super::{CoreId, CpuSet, cache_groups_helper, cache_info_helper, get_all_core_ids_helper};
+
+// No CPU has caches past an L4.
+const MAX_CACHE_LEVEL: usize = 4;
+
+/// What a cache holds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CacheType {
+    /// Both instructions and data.
+    Unified,
+    /// Instructions only.
+    Instruction,
+    /// Data only.
+    Data,
+}
+
+/// A cache of a core, as returned by `cache_info`.
+///
+/// Numbers the platform does not report are `None` rather than
+/// zero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheLevel {
+    /// The level, 1 for the cache closest to the core.
+    pub level: usize,
+    pub cache_type: CacheType,
+    /// The size in bytes.
+    pub size: Option<usize>,
+    /// The size in bytes of a cache line.
+    pub line_size: Option<usize>,
+    /// The number of ways of the cache.
+    pub associativity: Option<usize>,
+}
+
+/// A cache cluster: the cores that share one L3 cache, such as
+/// a CCX of an AMD Zen CPU.
+///
+/// Threads on one cluster talk to each other much faster than
+/// across clusters. Clusters are numbered `0..n` by their
+/// lowest core, so the IDs stay the same from run to run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CacheClusterId {
+    pub id: usize,
+}
+
+impl fmt::Display for CacheClusterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// This function returns the cache clusters of the system with
+/// their online cores, both in ascending order.
+///
+/// Linux reads `cache/index*/shared_cpu_list` in sysfs,
+/// Windows asks `GetLogicalProcessorInformationEx` for the L3
+/// caches, FreeBSD reads `kern.sched.topology_spec` and macOS
+/// `hw.cacheconfig`. A core
+/// without an L3 cache is a cluster of its own. Returns `None`
+/// where the caches are not known.
+pub fn cache_clusters() -> Option<Vec<(CacheClusterId, Vec<CoreId>)>> {
+    let groups = partition(cache_groups_helper(3)?, &get_all_core_ids_helper()?);
+
+    Some(groups.into_iter().enumerate().map(|(id, core_ids)| (CacheClusterId { id }, core_ids)).collect())
+}
+
+/// This function returns the cache cluster of `core_id`, or
+/// `None` for a core that is not online or where the caches are
+/// not known.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn cache_cluster_of(core_id: CoreId) -> Option<CacheClusterId> {
+    cache_clusters()?.into_iter().find(|(_, core_ids)| core_ids.contains(&core_id)).map(|(cluster, _)| cluster)
+}
+
+/// This function returns the groups of cores that share a
+/// last-level cache, in the order of their lowest core.
+///
+/// The groups split the online cores without overlapping. The
+/// last level is the highest one any core has, so on CPUs
+/// without an L3 cache these are the L2 groups. Linux reads
+/// `cache/index*/shared_cpu_list` in sysfs, Windows asks
+/// `GetLogicalProcessorInformationEx`, FreeBSD reads
+/// `kern.sched.topology_spec` and macOS `hw.cacheconfig`.
+/// Returns `None` where the caches are not known.
+pub fn l3_domains() -> Option<Vec<Vec<CoreId>>> {
+    let groups = (1..=MAX_CACHE_LEVEL).rev().find_map(cache_groups_helper)?;
+
+    Some(partition(groups, &get_all_core_ids_helper()?))
+}
+
+/// This function returns the groups of cores that share an L2
+/// cache, in the order of their lowest core.
+///
+/// The groups split the online cores without overlapping. On
+/// CPUs with a private L2 for every core, each group is one
+/// core, or its SMT siblings, while the E-cores of Intel hybrid
+/// CPUs share an L2 in modules of four, as do the cores of
+/// many ARM clusters. The sources are those of `l3_domains`.
+pub fn l2_groups() -> Option<Vec<Vec<CoreId>>> {
+    Some(partition(cache_groups_helper(2)?, &get_all_core_ids_helper()?))
+}
+
+/// This function returns the caches of `core_id`, ordered by
+/// level and then by type, such as for sizing the buffers of a
+/// worker pinned to it.
+///
+/// Linux reads `cache/index*` in sysfs and Windows asks
+/// `GetLogicalProcessorInformationEx`. macOS reads
+/// `hw.l1dcachesize` and the like, which describe every core
+/// alike and give no associativity. Returns `None` for a core
+/// that is not online, and where the caches are not known.
+///
+/// # Arguments
+///
+/// * core_id - the core to look up
+pub fn cache_info(core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    if !get_all_core_ids_helper()?.contains(&core_id) {
+        return None;
+    }
+
+    let mut caches = cache_info_helper(core_id)?;
+    caches.sort_by_key(|cache| (cache.level, cache.cache_type));
+
+    Some(caches)
+}
+
+// Splits the cores in `online` into the `groups` that list
+// them. Each core goes to the first group that does, and
+// online cores in no group make up a group of their own. The
+// groups are sorted by their lowest core.
+pub(crate) fn partition(groups: Vec<Vec<CoreId>>, online: &[CoreId]) -> Vec<Vec<CoreId>> {
+    let online: CpuSet = online.iter().cloned().collect();
+    let mut seen = CpuSet::new();
+    let mut parts: Vec<Vec<CoreId>> = Vec::new();
+
+    for group in groups {
+        let mut part: Vec<CoreId> = group.into_iter()
+            .filter(|&core_id| online.contains(core_id) && !seen.contains(core_id))
+            .collect();
+
+        part.sort();
+        part.dedup();
+        seen.extend(part.iter().cloned());
+
+        if !part.is_empty() {
+            parts.push(part);
+        }
+    }
+
+    parts.extend((&online - &seen).iter().map(|core_id| vec![core_id]));
+
+    // The parts are sorted, so this orders by the first core.
+    parts.sort();
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_all_core_ids;
+    use test_util::ids;
+
+    #[test]
+    fn test_partition() {
+        let groups = vec![ids(&[4, 5, 6, 7]), ids(&[0, 1, 2, 3]), ids(&[4, 5, 6, 7]), ids(&[3, 9])];
+        let parts = partition(groups, &ids(&[0, 1, 2, 3, 4, 5, 6, 7, 8]));
+
+        assert_eq!(parts, vec![ids(&[0, 1, 2, 3]), ids(&[4, 5, 6, 7]), ids(&[8])]);
+    }
+
+    #[test]
+    fn test_l3_domains() {
+        let domains = match l3_domains() {
+            Some(domains) => domains,
+            None => return,
+        };
+
+        let mut all = domains.concat();
+        all.sort();
+        assert_eq!(all, get_all_core_ids().unwrap());
+        assert!(domains.windows(2).all(|pair| pair[0][0] < pair[1][0]));
+    }
+
+    #[test]
+    fn test_l2_groups() {
+        let groups = match l2_groups() {
+            Some(groups) => groups,
+            None => return,
+        };
+
+        let mut all = groups.concat();
+        all.sort();
+        assert_eq!(all, get_all_core_ids().unwrap());
+    }
+
+    #[test]
+    fn test_cache_info() {
+        let core_ids = get_all_core_ids().unwrap();
+
+        assert_eq!(cache_info(CoreId { id: usize::MAX }), None);
+
+        for core_id in core_ids {
+            let caches = match cache_info(core_id) {
+                Some(caches) => caches,
+                None => return,
+            };
+
+            for cache in &caches {
+                assert!(cache.line_size.is_none_or(usize::is_power_of_two));
+                assert!(cache.size != Some(0));
+            }
+
+            // Caches grow with their level.
+            let sizes: Vec<_> = caches.iter()
+                .filter(|cache| cache.cache_type != CacheType::Instruction)
+                .filter_map(|cache| cache.size)
+                .collect();
+
+            assert!(sizes.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_cache_clusters() {
+        let clusters = match cache_clusters() {
+            Some(clusters) => clusters,
+            None => return,
+        };
+
+        let mut all: Vec<_> = clusters.iter().flat_map(|(_, core_ids)| core_ids.clone()).collect();
+        all.sort();
+        assert_eq!(all, get_all_core_ids().unwrap());
+
+        for (cluster, core_ids) in clusters {
+            assert!(core_ids.iter().all(|&core_id| cache_cluster_of(core_id) == Some(cluster)));
+        }
+    }
+}
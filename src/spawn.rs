@@ -0,0 +1,371 @@
+use std::io;
+use std::panic;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, Builder, JoinHandle, Scope, ScopedJoinHandle};
+
+use super::{CoreId, Error, get_core_ids, set_for_current_checked};
+
+/// Pinned spawning for `std::thread::Builder`.
+pub trait BuilderExt {
+    /// Spawns a new thread like `Builder::spawn`, pinned to
+    /// the specified core before `f` starts to run.
+    ///
+    /// The name, stack size and other settings of the builder
+    /// apply as usual. If pinning fails, the thread exits
+    /// without running `f` and the returned `io::Error` wraps
+    /// the `core_affinity::Error`, which tells it apart from a
+    /// failure to spawn the thread at all:
+    ///
+    /// ```
+    /// extern crate core_affinity;
+    ///
+    /// use std::thread::Builder;
+    ///
+    /// use core_affinity::{BuilderExt, CoreId};
+    ///
+    /// let err = Builder::new()
+    ///     .spawn_on(CoreId { id: usize::MAX }, || ())
+    ///     .unwrap_err();
+    ///
+    /// assert!(err.get_ref().unwrap().is::<core_affinity::Error>());
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * core_id - ID of the core to pin the new thread to
+    /// * f - closure to run on the new thread
+    fn spawn_on<F, T>(self, core_id: CoreId, f: F) -> io::Result<JoinHandle<T>>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static;
+}
+
+impl BuilderExt for Builder {
+    fn spawn_on<F, T>(self, core_id: CoreId, f: F) -> io::Result<JoinHandle<T>>
+        where F: FnOnce() -> T + Send + 'static,
+              T: Send + 'static
+    {
+        spawn_on(self, core_id, f)
+    }
+}
+
+/// This function spawns a new thread that is pinned to the
+/// specified core before `f` starts to run.
+///
+/// Unlike calling `set_for_current` at the top of the closure,
+/// a failure to pin is reported to the caller: the thread
+/// exits without running `f` and the error is returned here.
+///
+/// # Arguments
+///
+/// * core_id - ID of the core to pin the new thread to
+/// * f - closure to run on the new thread
+pub fn spawn_pinned<F, T>(core_id: CoreId, f: F) -> io::Result<JoinHandle<T>>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    spawn_on(Builder::new(), core_id, f)
+}
+
+/// Pinned spawning for `std::thread::Scope`.
+pub trait ScopeExt<'scope> {
+    /// Spawns a scoped thread like `Scope::spawn`, pinned to
+    /// the specified core before `f` starts to run.
+    ///
+    /// `f` may borrow from outside the scope, exactly as with
+    /// `std::thread::scope`. Failures are reported in the same
+    /// way as by `BuilderExt::spawn_on`.
+    ///
+    /// # Arguments
+    ///
+    /// * core_id - ID of the core to pin the new thread to
+    /// * f - closure to run on the new thread
+    fn spawn_on<F, T>(&'scope self, core_id: CoreId, f: F) -> io::Result<ScopedJoinHandle<'scope, T>>
+        where F: FnOnce() -> T + Send + 'scope,
+              T: Send + 'scope;
+}
+
+impl<'scope, 'env> ScopeExt<'scope> for Scope<'scope, 'env> {
+    fn spawn_on<F, T>(&'scope self, core_id: CoreId, f: F) -> io::Result<ScopedJoinHandle<'scope, T>>
+        where F: FnOnce() -> T + Send + 'scope,
+              T: Send + 'scope
+    {
+        let (f, pinned) = pin_first(core_id, f);
+        let handle = Builder::new().spawn_scoped(self, f)?;
+
+        handshake(pinned, handle, |handle| { let _ = handle.join(); })
+    }
+}
+
+/// This function runs `f` once on every core on which the
+/// current thread is allowed to run, each time on its own
+/// thread pinned to that core, and returns the results in
+/// ascending order of core.
+///
+/// Every thread is joined before this returns. If `f`
+/// panics on any of them, the first panic is resumed after
+/// that. It also panics if a thread can not be pinned; use
+/// `run_per_core_skipping` to leave such cores out instead.
+///
+/// # Arguments
+///
+/// * f - closure to run, given the core it is pinned to
+pub fn run_per_core<F, T>(f: F) -> Vec<T>
+    where F: Fn(CoreId) -> T + Sync,
+          T: Send
+{
+    run_on_cores(&f, false).into_iter().map(|(_, value)| value).collect()
+}
+
+/// This function is like `run_per_core`, but skips the cores
+/// whose thread could not be pinned, and returns each result
+/// together with its core.
+///
+/// # Arguments
+///
+/// * f - closure to run, given the core it is pinned to
+pub fn run_per_core_skipping<F, T>(f: F) -> Vec<(CoreId, T)>
+    where F: Fn(CoreId) -> T + Sync,
+          T: Send
+{
+    run_on_cores(&f, true)
+}
+
+fn run_on_cores<F, T>(f: &F, skip_failed: bool) -> Vec<(CoreId, T)>
+    where F: Fn(CoreId) -> T + Sync,
+          T: Send
+{
+    let core_ids = get_core_ids().unwrap_or_default();
+
+    thread::scope(|s| {
+        let handles = core_ids.into_iter()
+            .map(|core_id| (core_id, s.spawn_on(core_id, move || f(core_id))))
+            .collect::<Vec<_>>();
+
+        let mut results = Vec::new();
+        let mut panicked = None;
+        let mut failed = None;
+
+        // Join every thread before reporting anything.
+        for (core_id, handle) in handles {
+            match handle {
+                Ok(handle) => match handle.join() {
+                    Ok(value) => results.push((core_id, value)),
+                    Err(payload) => { panicked.get_or_insert(payload); },
+                },
+                Err(_) if skip_failed => (),
+                Err(err) => { failed.get_or_insert((core_id, err)); },
+            }
+        }
+
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+
+        if let Some((core_id, err)) = failed {
+            panic!("failed to pin a thread to core {}: {}", core_id, err);
+        }
+
+        results
+    })
+}
+
+fn spawn_on<F, T>(builder: Builder, core_id: CoreId, f: F) -> io::Result<JoinHandle<T>>
+    where F: FnOnce() -> T + Send + 'static,
+          T: Send + 'static
+{
+    let (f, pinned) = pin_first(core_id, f);
+    let handle = builder.spawn(f)?;
+
+    handshake(pinned, handle, |handle| { let _ = handle.join(); })
+}
+
+// Wraps `f` so the new thread pins itself first and reports
+// the outcome through the returned receiver.
+pub(crate) fn pin_first<F, T>(core_id: CoreId, f: F) -> (impl FnOnce() -> T, Receiver<Result<(), Error>>)
+    where F: FnOnce() -> T
+{
+    let (tx, rx) = mpsc::sync_channel(1);
+
+    let f = move || {
+        let pinned = set_for_current_checked(core_id);
+        let failed = pinned.is_err();
+        let _ = tx.send(pinned);
+
+        if failed {
+            // Unwind without running the panic hook; the
+            // spawner reports the error instead.
+            panic::resume_unwind(Box::new(core_id));
+        }
+
+        f()
+    };
+
+    (f, rx)
+}
+
+// Waits until the new thread tried to pin itself, and joins
+// it if that failed.
+pub(crate) fn handshake<H, J>(pinned: Receiver<Result<(), Error>>, handle: H, join: J) -> io::Result<H>
+    where J: FnOnce(H)
+{
+    match pinned.recv() {
+        Ok(Ok(())) => Ok(handle),
+        Ok(Err(err)) => {
+            join(handle);
+            Err(pin_error(err))
+        },
+        Err(_) => {
+            join(handle);
+            Err(io::Error::other("pinned thread exited before pinning"))
+        },
+    }
+}
+
+// Keeps `err` as the payload, even for OS errors, so callers
+// can tell a failure to pin from a failure to spawn.
+fn pin_error(err: Error) -> io::Error {
+    io::Error::new(io::Error::from(err).kind(), err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_spawn_pinned() {
+        use super::super::current_core;
+
+        for id in get_core_ids().unwrap() {
+            let handle = spawn_pinned(id, current_core).unwrap();
+
+            assert_eq!(handle.join().unwrap(), Some(id));
+        }
+    }
+
+    #[test]
+    fn test_spawn_pinned_invalid_core() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+
+        let err = spawn_pinned(CoreId { id: usize::MAX }, move || flag.store(true, Ordering::SeqCst))
+            .unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_builder_spawn_on() {
+        use std::hint::black_box;
+
+        let id = *get_core_ids().unwrap().last().unwrap();
+
+        let handle = Builder::new()
+            .name("pinned-worker".to_string())
+            .stack_size(16 << 20)
+            .spawn_on(id, move || {
+                // More than the default stack of 2 MiB.
+                let buf = [1u8; 4 << 20];
+                let sum: usize = black_box(&buf).iter().map(|&b| b as usize).sum();
+
+                (thread::current().name().map(String::from), sum, super::super::current_core())
+            })
+            .unwrap();
+
+        let (name, sum, core_id) = handle.join().unwrap();
+        assert_eq!(name.as_deref(), Some("pinned-worker"));
+        assert_eq!(sum, 4 << 20);
+        // `current_core` is not supported on macOS.
+        assert!(core_id.is_none() || core_id == Some(id));
+    }
+
+    #[test]
+    fn test_scope_spawn_on() {
+        let ids = get_core_ids().unwrap();
+        let data: Vec<u64> = (0..1000).collect();
+        let chunk_size = data.len().div_ceil(ids.len());
+
+        let results = thread::scope(|s| {
+            let handles = ids.iter().zip(data.chunks(chunk_size)).map(|(&id, chunk)| {
+                s.spawn_on(id, move || (id, chunk.iter().sum::<u64>(), super::super::current_core()))
+                    .unwrap()
+            }).collect::<Vec<_>>();
+
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect::<Vec<_>>()
+        });
+
+        for &(id, _, core_id) in &results {
+            // `current_core` is not supported on macOS.
+            assert!(core_id.is_none() || core_id == Some(id));
+        }
+
+        assert_eq!(results.iter().map(|r| r.1).sum::<u64>(), data.iter().sum::<u64>());
+    }
+
+    #[test]
+    fn test_scope_spawn_on_invalid_core() {
+        let ran = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let err = s.spawn_on(CoreId { id: usize::MAX }, || ran.store(true, Ordering::SeqCst))
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        });
+
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_run_per_core() {
+        let ids = get_core_ids().unwrap();
+
+        assert_eq!(run_per_core(|core_id| core_id), ids);
+        assert_eq!(run_per_core_skipping(|core_id| core_id.id),
+                   ids.iter().map(|&id| (id, id.id)).collect::<Vec<_>>());
+
+        #[cfg(not(target_os = "macos"))]
+        {
+            let seen = run_per_core(|_| super::super::current_core());
+            assert_eq!(seen, ids.iter().map(|&id| Some(id)).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_run_per_core_panic() {
+        let ids = get_core_ids().unwrap();
+        let last = *ids.last().unwrap();
+        let finished = AtomicUsize::new(0);
+
+        let payload = panic::catch_unwind(|| {
+            run_per_core(|core_id| {
+                if core_id == last {
+                    panic!("worker panicked");
+                }
+
+                finished.fetch_add(1, Ordering::SeqCst);
+            })
+        }).unwrap_err();
+
+        // The other workers still ran to completion.
+        assert_eq!(finished.load(Ordering::SeqCst), ids.len() - 1);
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"worker panicked"));
+    }
+
+    #[test]
+    fn test_builder_spawn_on_invalid_core() {
+        let err = Builder::new()
+            .name("never-runs".to_string())
+            .spawn_on(CoreId { id: usize::MAX }, || ())
+            .unwrap_err();
+
+        let inner = err.get_ref().and_then(|inner| inner.downcast_ref::<Error>()).unwrap();
+        assert_eq!(inner.kind(), super::super::ErrorKind::InvalidCore);
+    }
+}
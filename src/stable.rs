@@ -0,0 +1,150 @@
+#[cfg(any(feature = "mock", target_os = "android", target_os = "linux"))]
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{CoreId, stable_core_ids_helper};
+
+// The IDs the hardware gives the package, die and physical
+// core of a logical core, in that order.
+#[cfg(any(feature = "mock", target_os = "android", target_os = "linux"))]
+pub(crate) type HardwareId = (usize, usize, usize);
+
+/// An ID of a logical core that does not change when the
+/// operating system numbers the cores differently.
+///
+/// A `CoreId` is only an index: when core 3 goes offline and
+/// comes back, or a virtual machine numbers its vCPUs anew
+/// after a migration, the same index may end up on another
+/// core. A `StableCoreId` is made of the IDs the hardware gives
+/// the package, die and physical core instead, with the place
+/// of the logical core among the threads of its physical core.
+/// Save these in a pinning plan and `resolve` them again after
+/// the topology changed.
+///
+/// The threads of a physical core are counted in the order of
+/// their `CoreId`s among all its present threads, online or
+/// not, so a thread going offline does not renumber the others.
+/// Where the platform no longer tells which physical core an
+/// offline thread is on, the threads of the physical cores that
+/// may have lost one get no `StableCoreId`, rather than one
+/// that could later name another thread.
+///
+/// It displays as the four IDs separated by colons, such as
+/// `0:0:3:1`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StableCoreId {
+    /// The ID the hardware gives the package, such as
+    /// `physical_package_id` on Linux.
+    pub package: usize,
+    /// The ID of the die within the package, or 0 where the
+    /// dies are not known.
+    pub die: usize,
+    /// The ID of the physical core within its package, such as
+    /// `core_id` on Linux. These need not be consecutive.
+    pub core: usize,
+    /// The place of the logical core among the threads of its
+    /// physical core, from 0.
+    pub thread: usize,
+}
+
+impl fmt::Display for StableCoreId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.package, self.die, self.core, self.thread)
+    }
+}
+
+impl StableCoreId {
+    /// Returns the online core with this ID, as numbered now.
+    ///
+    /// Returns `None` if no online core has it, such as when
+    /// its physical core was removed or has fewer threads
+    /// online, and where `CoreId::stable` returns `None`.
+    pub fn resolve(&self) -> Option<CoreId> {
+        stable_core_ids_helper()?.into_iter().find(|&(_, id)| id == *self).map(|(core_id, _)| core_id)
+    }
+}
+
+impl CoreId {
+    /// Returns the `StableCoreId` of the core, to find it again
+    /// after the cores are numbered differently.
+    ///
+    /// Linux reads the IDs from `/sys/devices/system/cpu`. Other
+    /// platforms do not report them, so there, as for a core
+    /// that is not online, this returns `None`.
+    pub fn stable(&self) -> Option<StableCoreId> {
+        stable_core_ids_helper()?.into_iter().find(|&(core_id, _)| core_id == *self).map(|(_, id)| id)
+    }
+}
+
+// Numbers the threads of each physical core in the order of
+// their cores. The platforms pass every present core, online
+// or not, and leave out the offline ones afterwards.
+#[cfg(any(feature = "mock", target_os = "android", target_os = "linux"))]
+pub(crate) fn number_threads(mut ids: Vec<(CoreId, HardwareId)>) -> Vec<(CoreId, StableCoreId)> {
+    ids.sort();
+    ids.dedup_by_key(|&mut (core_id, _)| core_id);
+
+    let mut threads: HashMap<HardwareId, usize> = HashMap::new();
+
+    ids.into_iter().map(|(core_id, (package, die, core))| {
+        let thread = threads.entry((package, die, core)).or_insert(0);
+        let id = StableCoreId { package, die, core, thread: *thread };
+        *thread += 1;
+
+        (core_id, id)
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::get_all_core_ids;
+
+    #[test]
+    #[cfg(any(feature = "mock", target_os = "android", target_os = "linux"))]
+    fn test_number_threads() {
+        let core = |id| CoreId { id };
+        let stable = |package, die, core, thread| StableCoreId { package, die, core, thread };
+
+        // Two threads per core, numbered apart.
+        let ids = number_threads(vec![(core(2), (0, 0, 0)), (core(0), (0, 0, 0)), (core(1), (0, 0, 4)), (core(3), (0, 0, 4)),
+                              (core(4), (1, 0, 0))]);
+
+        assert_eq!(ids, vec![
+            (core(0), stable(0, 0, 0, 0)),
+            (core(1), stable(0, 0, 4, 0)),
+            (core(2), stable(0, 0, 0, 1)),
+            (core(3), stable(0, 0, 4, 1)),
+            (core(4), stable(1, 0, 0, 0)),
+        ]);
+    }
+
+    #[test]
+    fn test_stable_core_id_display() {
+        let id = StableCoreId { package: 1, die: 0, core: 12, thread: 1 };
+
+        assert_eq!(id.to_string(), "1:0:12:1");
+    }
+
+    #[test]
+    fn test_stable() {
+        let core_ids = match get_all_core_ids() {
+            Some(core_ids) => core_ids,
+            None => return,
+        };
+
+        // Every online core resolves back to itself.
+        let mut seen = Vec::new();
+
+        for core_id in core_ids {
+            if let Some(id) = core_id.stable() {
+                assert_eq!(id.resolve(), Some(core_id));
+                assert!(!seen.contains(&id));
+                seen.push(id);
+            }
+        }
+
+        assert_eq!(CoreId { id: usize::MAX }.stable(), None);
+        assert_eq!(StableCoreId { package: usize::MAX, die: 0, core: 0, thread: 0 }.resolve(), None);
+    }
+}
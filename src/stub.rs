@@ -0,0 +1,210 @@
+#[cfg(not(unix))]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+#[cfg(not(unix))]
+use std::process::Child;
+use std::process::Command;
+use std::thread::JoinHandle;
+
+use super::{CacheLevel, Capabilities, CoreId, CoreIds, CoreKind, CpuTimes, Error, ErrorKind, KindSource, NumaNode};
+use super::CoreState;
+use super::StableCoreId;
+
+// Platforms without any affinity support. Every call fails
+// with `ErrorKind::Unsupported`, so callers can fall back to
+// running unpinned.
+
+pub fn core_ids() -> Result<CoreIds, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn get_all_core_ids() -> Option<Vec<CoreId>> {
+    None
+}
+
+pub fn core_ids_by_state(_state: CoreState) -> Option<Vec<CoreId>> {
+    None
+}
+
+pub fn get_affinity_for_current() -> Result<Vec<CoreId>, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_for_current(_core_id: CoreId) -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_for_current_set(_core_ids: &[CoreId]) -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_for_thread<T>(_handle: &JoinHandle<T>, _core_id: CoreId) -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn get_for_thread<T>(_handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_for_process(_pid: u32, _core_ids: &[CoreId]) -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn get_for_process(_pid: u32) -> Result<Vec<CoreId>, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+#[cfg(unix)]
+pub fn set_for_command(command: &mut Command, _core_ids: &[CoreId]) {
+    unsafe {
+        command.pre_exec(|| Err(Error::new(ErrorKind::Unsupported).into_simple_io_error()));
+    }
+}
+
+#[cfg(not(unix))]
+pub fn spawn_with_affinity(_command: &mut Command, _core_ids: &[CoreId]) -> io::Result<Child> {
+    Err(Error::new(ErrorKind::Unsupported).into())
+}
+
+pub fn current_core() -> Result<CoreId, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn set_preferred_for_current(_core_id: CoreId) -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn thread_core_ids() -> Result<CoreIds, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn process_core_ids() -> Result<CoreIds, Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        hard_pinning: false,
+        soft_affinity: false,
+        can_query_affinity: false,
+        max_cores_representable: 0,
+    }
+}
+
+pub fn clear_for_current() -> Result<(), Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+pub fn core_siblings() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn packages() -> Option<Vec<(usize, Vec<CoreId>)>> {
+    None
+}
+
+pub fn dies() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn clusters() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn stable_core_ids() -> Option<Vec<(CoreId, StableCoreId)>> {
+    None
+}
+
+pub fn cache_groups(_level: usize) -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn cache_info(_core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    None
+}
+
+pub fn core_kinds() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    None
+}
+
+pub fn max_frequency_khz(_core_id: CoreId) -> Option<u64> {
+    None
+}
+
+pub fn core_rankings() -> Option<Vec<(CoreId, u64)>> {
+    None
+}
+
+pub fn current_frequency_khz(_core_id: CoreId) -> Option<u64> {
+    None
+}
+
+pub fn cpu_times() -> Option<Vec<(CoreId, CpuTimes)>> {
+    None
+}
+
+pub fn capacity(_core_id: CoreId) -> Option<u32> {
+    None
+}
+
+pub fn numa_nodes() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    None
+}
+
+pub fn current_location() -> Result<(CoreId, NumaNode), Error> {
+    Err(Error::new(ErrorKind::Unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing;
+
+    #[test]
+    fn test_stub_core_ids() {
+        assert_eq!(core_ids().unwrap_err().kind(), ErrorKind::Unsupported);
+        assert_eq!(get_all_core_ids(), None);
+        assert_eq!(core_ids_by_state(CoreState::Possible), None);
+        assert_eq!(core_siblings(), None);
+        assert_eq!(packages(), None);
+        assert_eq!(dies(), None);
+        assert_eq!(clusters(), None);
+        assert_eq!(stable_core_ids(), None);
+        assert_eq!(cache_groups(3), None);
+        assert_eq!(cache_info(CoreId { id: 0 }), None);
+        assert_eq!(core_kinds(), None);
+        assert_eq!(max_frequency_khz(CoreId { id: 0 }), None);
+        assert_eq!(core_rankings(), None);
+        assert_eq!(capacity(CoreId { id: 0 }), None);
+        assert_eq!(current_frequency_khz(CoreId { id: 0 }), None);
+        assert_eq!(cpu_times(), None);
+        assert_eq!(numa_nodes(), None);
+        assert_eq!(capabilities(), Capabilities::default());
+    }
+
+    #[test]
+    fn test_stub_set_for_current() {
+        testing::check_set_for_current();
+    }
+
+    #[test]
+    fn test_stub_set_for_current_set() {
+        testing::check_set_for_current_set();
+    }
+
+    #[test]
+    fn test_stub_get_all_core_ids_when_pinned() {
+        testing::check_get_all_core_ids_when_pinned();
+    }
+
+    #[test]
+    fn test_stub_set_for_thread() {
+        testing::check_set_for_thread();
+    }
+
+    #[test]
+    fn test_stub_clear_for_current() {
+        testing::check_clear_for_current();
+    }
+}
@@ -0,0 +1,86 @@
+// Helpers shared by the unit tests of several modules.
+
+use super::CoreId;
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub use self::sysfs::SysfsFixture;
+
+// The cores with the IDs `ids`, in that order.
+pub fn ids(ids: &[usize]) -> Vec<CoreId> {
+    ids.iter().map(|&id| CoreId { id }).collect()
+}
+
+// A sysfs tree for the tests of the modules that read one, in
+// a temporary directory that is removed again when the fixture
+// is dropped, even if the test fails.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+mod sysfs {
+    use std::env;
+    use std::fmt::Display;
+    use std::fs;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    pub struct SysfsFixture {
+        root: PathBuf,
+    }
+
+    impl SysfsFixture {
+        // Creates an empty tree, named after `name` and the process
+        // so that tests running at the same time get one each.
+        pub fn new(name: &str) -> SysfsFixture {
+            let root = env::temp_dir().join(format!("core_affinity-{}-{}", name, process::id()));
+
+            // A tree left behind by a run that was killed.
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+
+            SysfsFixture { root }
+        }
+
+        // Writes `contents` and a newline to `path` in the tree, as
+        // the kernel does, creating the directories on the way.
+        pub fn file<P, C>(&self, path: P, contents: C) -> &SysfsFixture
+            where P: AsRef<Path>, C: Display
+        {
+            let path = self.root.join(path);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("{}\n", contents)).unwrap();
+
+            self
+        }
+
+        // Creates the directory `path` in the tree.
+        #[cfg_attr(feature = "mock", allow(dead_code))]
+        pub fn dir<P: AsRef<Path>>(&self, path: P) -> &SysfsFixture {
+            fs::create_dir_all(self.root.join(path)).unwrap();
+
+            self
+        }
+
+        // Removes `path` and everything below it from the tree.
+        #[cfg_attr(feature = "mock", allow(dead_code))]
+        pub fn remove<P: AsRef<Path>>(&self, path: P) -> &SysfsFixture {
+            fs::remove_dir_all(self.root.join(path)).unwrap();
+
+            self
+        }
+    }
+
+    // The root of the tree, to pass where a sysfs path is read.
+    impl Deref for SysfsFixture {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.root
+        }
+    }
+
+    impl Drop for SysfsFixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+// Checks shared by the tests of every platform module. They
+// run against `platform`, and ask `capabilities` what to
+// expect: a platform that can not pin must fail with
+// `ErrorKind::Unsupported`, and one that can not read a mask
+// back is only checked for accepting the call.
+
+use std::fmt::Debug;
+use std::sync::mpsc;
+use std::thread;
+
+use super::{Capabilities, CoreId, Error, ErrorKind};
+use super::platform;
+
+pub fn check_set_for_current() {
+    let (caps, ids) = match pinnable() {
+        Some(pinnable) => pinnable,
+        None => {
+            assert_unsupported(platform::set_for_current(CoreId { id: 0 }));
+            return;
+        },
+    };
+
+    thread::spawn(move || {
+        assert!(platform::set_for_current(ids[0]).is_ok());
+        check_read_back(caps, &[ids[0]]);
+    }).join().unwrap();
+}
+
+pub fn check_set_for_current_set() {
+    let (caps, ids) = match pinnable() {
+        Some(pinnable) => pinnable,
+        None => {
+            assert_unsupported(platform::set_for_current_set(&[CoreId { id: 0 }]));
+            return;
+        },
+    };
+
+    if ids.len() < 2 {
+        return;
+    }
+
+    thread::spawn(move || {
+        // Duplicates must not matter.
+        let res = platform::set_for_current_set(&[ids[0], ids[1], ids[0]]);
+
+        if caps.hard_pinning {
+            assert!(res.is_ok());
+            check_read_back(caps, &[ids[0], ids[1]]);
+        }
+        else {
+            // A hint can only name a single core.
+            assert_unsupported(res);
+        }
+    }).join().unwrap();
+}
+
+pub fn check_get_all_core_ids_when_pinned() {
+    let (caps, ids) = match pinnable() {
+        Some(pinnable) => pinnable,
+        None => {
+            assert_eq!(platform::get_all_core_ids(), None);
+            return;
+        },
+    };
+    let all_ids = platform::get_all_core_ids().unwrap();
+
+    thread::spawn(move || {
+        assert!(platform::set_for_current(ids[0]).is_ok());
+
+        // Only the pinned core remains in the affinity mask,
+        // but every online core is still reported.
+        check_read_back(caps, &[ids[0]]);
+        assert_eq!(platform::get_all_core_ids().unwrap(), all_ids);
+    }).join().unwrap();
+}
+
+pub fn check_set_for_thread() {
+    let (tx, rx) = mpsc::channel::<()>();
+
+    let handle = thread::spawn(move || {
+        // Wait until the parent has pinned this thread.
+        rx.recv().unwrap();
+        platform::get_affinity_for_current()
+    });
+
+    let (caps, ids) = match pinnable() {
+        Some(pinnable) => pinnable,
+        None => {
+            assert_unsupported(platform::set_for_thread(&handle, CoreId { id: 0 }));
+            tx.send(()).unwrap();
+            handle.join().unwrap().unwrap_err();
+            return;
+        },
+    };
+    let id = *ids.last().unwrap();
+
+    assert!(platform::set_for_thread(&handle, id).is_ok());
+
+    if caps.can_query_affinity {
+        assert_eq!(platform::get_for_thread(&handle), Ok(vec![id]));
+    }
+    else {
+        assert_unsupported(platform::get_for_thread(&handle));
+    }
+
+    tx.send(()).unwrap();
+
+    let mask = handle.join().unwrap();
+
+    if caps.can_query_affinity {
+        assert_eq!(mask, Ok(vec![id]));
+    }
+}
+
+pub fn check_clear_for_current() {
+    let (caps, ids) = match pinnable() {
+        Some(pinnable) => pinnable,
+        None => {
+            assert_unsupported(platform::clear_for_current());
+            return;
+        },
+    };
+
+    thread::spawn(move || {
+        assert!(platform::set_for_current(ids[0]).is_ok());
+        check_read_back(caps, &[ids[0]]);
+
+        assert!(platform::clear_for_current().is_ok());
+        check_read_back(caps, &ids);
+    }).join().unwrap();
+}
+
+// What the platform can do and the cores the current thread
+// may use, or `None` if it can not pin at all.
+fn pinnable() -> Option<(Capabilities, Vec<CoreId>)> {
+    let caps = platform::capabilities();
+
+    if !caps.hard_pinning && !caps.soft_affinity {
+        return None;
+    }
+
+    let ids: Vec<CoreId> = platform::core_ids().unwrap().collect();
+    assert!(!ids.is_empty());
+
+    Some((caps, ids))
+}
+
+// Checks that the current thread is confined to `expected`,
+// where the platform can tell.
+fn check_read_back(caps: Capabilities, expected: &[CoreId]) {
+    if caps.can_query_affinity {
+        assert_eq!(platform::get_affinity_for_current().unwrap(), expected);
+        assert_eq!(platform::thread_core_ids().unwrap().collect::<Vec<_>>(), expected);
+    }
+    else {
+        assert_unsupported(platform::get_affinity_for_current());
+    }
+}
+
+fn assert_unsupported<T: Debug>(res: Result<T, Error>) {
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::Unsupported);
+}
@@ -0,0 +1,259 @@
+//! `tokio` support, enabled by the `tokio` feature.
+//!
+//! `pin_worker_threads` hands the cores of a list out to the
+//! threads of a multi-threaded runtime as they start, through
+//! a `CoreAllocator`, so the workers end up on distinct cores
+//! as long as there are enough of them. A thread that stops
+//! gives its core back, so a thread started later to replace
+//! it, such as after `tokio::task::block_in_place`, picks the
+//! freed core up again.
+//!
+//! tokio starts its workers and the threads of its blocking
+//! pool the same way, and `on_thread_start` runs on both.
+//! Only workers park, so to leave the blocking pool alone a
+//! thread is pinned the first time it parks instead. A worker
+//! that finds tasks waiting as it starts runs them before
+//! then.
+//!
+//! The hooks are installed with `on_thread_start`,
+//! `on_thread_stop` and `on_thread_park` on the builder. Later
+//! calls to those methods replace them.
+
+use std::cell::Cell;
+use std::sync::Arc;
+
+use tokio_crate::runtime::Builder;
+
+use super::{CoreAllocator, CoreId, set_for_current_checked};
+use instrument::unpinned;
+
+thread_local! {
+    // The core this thread got from its runtime.
+    static ASSIGNED: Cell<Option<CoreId>> = const { Cell::new(None) };
+}
+
+/// Which threads of the blocking pool of a runtime are pinned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlockingThreads {
+    /// Pins them as they start, taking turns with the workers
+    /// for the cores. The workers start first, so they still
+    /// get a core each.
+    #[default]
+    Shared,
+    /// Leaves them unpinned, and pins the workers when they
+    /// first park.
+    Unpinned,
+}
+
+/// How `pin_worker_threads_with` pins the threads of a runtime.
+///
+/// The default pins the blocking pool along with the workers
+/// and gives the core of a stopped thread back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PinOptions {
+    blocking: BlockingThreads,
+    release_on_stop: bool,
+}
+
+impl Default for PinOptions {
+    fn default() -> PinOptions {
+        PinOptions { blocking: BlockingThreads::Shared, release_on_stop: true }
+    }
+}
+
+impl PinOptions {
+    /// Creates the default options.
+    pub fn new() -> PinOptions {
+        PinOptions::default()
+    }
+
+    /// Sets which threads of the blocking pool are pinned.
+    pub fn blocking(self, blocking: BlockingThreads) -> PinOptions {
+        PinOptions { blocking, ..self }
+    }
+
+    /// Sets whether a thread that stops gives its core back for
+    /// the threads started after it. Without, the cores keep
+    /// being handed out round-robin.
+    pub fn release_on_stop(self, release_on_stop: bool) -> PinOptions {
+        PinOptions { release_on_stop, ..self }
+    }
+}
+
+/// This function makes the runtime built by `builder` run one
+/// worker per entry of `cores` and pin every thread it starts
+/// to one of them, with the default `PinOptions`.
+///
+/// `builder` should be a `Builder::new_multi_thread`. For a
+/// current-thread runtime, pin the thread that calls `block_on`
+/// instead.
+///
+/// # Panics
+///
+/// Panics if `cores` is empty, as `Builder::worker_threads`
+/// does for zero workers.
+///
+/// # Arguments
+///
+/// * builder - the builder of the runtime
+/// * cores - the cores to hand out
+pub fn pin_worker_threads(builder: &mut Builder, cores: Vec<CoreId>) -> &mut Builder {
+    pin_worker_threads_with(builder, cores, PinOptions::default())
+}
+
+/// This function makes the runtime built by `builder` run one
+/// worker per entry of `cores`, pinning its threads as
+/// `options` says.
+///
+/// A thread that can not be pinned gives its core back and
+/// keeps running unpinned, with a warning through `tracing` if
+/// that feature is on.
+///
+/// # Panics
+///
+/// Panics if `cores` is empty.
+///
+/// # Arguments
+///
+/// * builder - the builder of the runtime
+/// * cores - the cores to hand out
+/// * options - which threads to pin
+pub fn pin_worker_threads_with(builder: &mut Builder, cores: Vec<CoreId>, options: PinOptions) -> &mut Builder {
+    let allocator = match CoreAllocator::from_core_ids(&cores) {
+        Some(allocator) => Arc::new(allocator),
+        None => panic!("pin_worker_threads needs at least one core"),
+    };
+
+    builder.worker_threads(cores.len());
+
+    let (starting, parking, stopping) = (allocator.clone(), allocator.clone(), allocator);
+
+    match options.blocking {
+        BlockingThreads::Shared => builder.on_thread_start(move || assign(&starting)),
+        BlockingThreads::Unpinned => builder.on_thread_park(move || {
+            if assigned_core().is_none() {
+                assign(&parking);
+            }
+        }),
+    };
+
+    builder.on_thread_stop(move || {
+        if let Some(core_id) = ASSIGNED.with(|assigned| assigned.take()) {
+            if options.release_on_stop {
+                stopping.release(core_id);
+            }
+        }
+    })
+}
+
+/// This function returns the core that `pin_worker_threads`
+/// pinned the current thread to, or `None` on threads it did
+/// not pin.
+pub fn assigned_core() -> Option<CoreId> {
+    ASSIGNED.with(Cell::get)
+}
+
+fn assign(allocator: &CoreAllocator) {
+    let core_id = allocator.next();
+
+    match set_for_current_checked(core_id) {
+        Ok(()) => ASSIGNED.with(|assigned| assigned.set(Some(core_id))),
+        Err(err) => {
+            allocator.release(core_id);
+            unpinned(format_args!("tokio thread to core {}", core_id.id), &err);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::future;
+    use std::task::Poll;
+    use std::thread::{self, ThreadId};
+    use std::time::Duration;
+
+    use tokio_crate::runtime::Runtime;
+    use tokio_crate::task::JoinHandle;
+
+    use super::*;
+    use super::super::get_core_ids;
+
+    // Spawns `f` as a task on the workers.
+    fn spawn<T, F>(runtime: &Runtime, f: F) -> JoinHandle<T>
+        where F: FnOnce() -> T + Send + 'static, T: Send + 'static
+    {
+        let mut f = Some(f);
+        runtime.spawn(future::poll_fn(move |_| Poll::Ready(f.take().unwrap()())))
+    }
+
+    // Runs a batch of tasks that take a little while, so each
+    // worker gets some, and returns the core each thread had.
+    fn assignments(runtime: &Runtime) -> HashMap<ThreadId, Option<CoreId>> {
+        let tasks: Vec<_> = (0..64).map(|_| spawn(runtime, || {
+            thread::sleep(Duration::from_millis(1));
+            (thread::current().id(), assigned_core())
+        })).collect();
+
+        let mut placed = HashMap::new();
+        for task in tasks {
+            let (thread, core_id) = runtime.block_on(task).unwrap();
+            assert_eq!(*placed.entry(thread).or_insert(core_id), core_id);
+        }
+
+        placed
+    }
+
+    fn assert_distinct(placed: &HashMap<ThreadId, Option<CoreId>>, cores: &[CoreId]) {
+        let mut seen: Vec<CoreId> = placed.values().flatten().cloned().collect();
+        seen.sort();
+        seen.dedup();
+
+        assert_eq!(seen.len(), placed.values().flatten().count());
+        assert!(seen.iter().all(|core_id| cores.contains(core_id)));
+    }
+
+    #[test]
+    fn test_pin_worker_threads() {
+        let cores = get_core_ids().unwrap();
+        let runtime = pin_worker_threads(&mut Builder::new_multi_thread(), cores.clone()).build().unwrap();
+
+        assert_eq!(runtime.metrics().num_workers(), cores.len());
+
+        let placed = assignments(&runtime);
+        assert!(placed.values().all(Option::is_some));
+        assert_distinct(&placed, &cores);
+
+        // `current_core` is not supported on macOS.
+        #[cfg(not(target_os = "macos"))]
+        {
+            let task = spawn(&runtime, || (assigned_core(), super::super::current_core()));
+            let (assigned, current) = runtime.block_on(task).unwrap();
+            assert_eq!(assigned, current);
+        }
+
+        // The blocking pool shares the cores.
+        let core_id = runtime.block_on(runtime.spawn_blocking(assigned_core)).unwrap();
+        assert!(core_id.is_some_and(|core_id| cores.contains(&core_id)));
+    }
+
+    #[test]
+    fn test_pin_worker_threads_unpinned_blocking() {
+        let cores = get_core_ids().unwrap();
+        let options = PinOptions::new().blocking(BlockingThreads::Unpinned).release_on_stop(false);
+        let runtime = pin_worker_threads_with(&mut Builder::new_multi_thread(), cores.clone(), options)
+            .build()
+            .unwrap();
+
+        assert_distinct(&assignments(&runtime), &cores);
+
+        let core_id = runtime.block_on(runtime.spawn_blocking(assigned_core)).unwrap();
+        assert_eq!(core_id, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one core")]
+    fn test_pin_worker_threads_empty() {
+        pin_worker_threads(&mut Builder::new_multi_thread(), Vec::new());
+    }
+}
@@ -0,0 +1,261 @@
+use super::{CacheClusterId, ClusterId, CoreId, CoreKind, DieId, Error, ErrorKind, KindSource, NumaNode, PackageId};
+use super::PhysicalCoreId;
+use super::{allowed_for_process, cache_clusters, core_kind_source, cores_by_kind, get_all_core_ids_helper};
+use super::{clusters, dies, get_physical_cores, l2_groups, numa_node_of, packages, record};
+use frequency::max_khz;
+use numa::node_cores;
+
+/// The layout of the machine as this crate sees it, read once
+/// by `detect`, such as for attaching to a bug report.
+///
+/// Once detected, a `Topology` answers from what it read and
+/// does not query the system again, so it goes stale when cores
+/// go on- or offline or the affinity mask of the process
+/// changes. With the `serde` feature, `to_json` writes it out
+/// in a schema described on `SCHEMA_VERSION`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Topology {
+    cores: Vec<CoreTopology>,
+    kind_source: Option<KindSource>,
+}
+
+/// Where one online core sits in the machine, as part of a
+/// `Topology`. Parts the platform does not report are `None`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CoreTopology {
+    pub core_id: CoreId,
+    /// Whether the process may run on the core.
+    pub allowed: bool,
+    pub package: Option<PackageId>,
+    pub die: Option<DieId>,
+    pub cluster: Option<ClusterId>,
+    pub numa_node: Option<NumaNode>,
+    pub physical_core: Option<PhysicalCoreId>,
+    /// The L2 group in the order of `l2_groups`.
+    pub l2_group: Option<usize>,
+    pub cache_cluster: Option<CacheClusterId>,
+    pub kind: Option<CoreKind>,
+    pub max_frequency_khz: Option<u64>,
+}
+
+impl Topology {
+    /// The version of the JSON schema of `to_json`, which goes up
+    /// whenever a field changes its meaning or goes away. New
+    /// fields may be added without a new version.
+    ///
+    /// Version 1 is an object with `version`, `kind_source`, one
+    /// of `"reported"`, `"heuristic"` or `null`, and `cores`, an
+    /// array of objects in ascending order of `id`, each with
+    /// `id`, `allowed`, `package`, `die`, `cluster`, `numa_node`,
+    /// `physical_core`, `l2_group`, `cache_cluster`, `kind`, one
+    /// of `"performance"`, `"efficiency"`, `"unknown"` or `null`,
+    /// and `max_frequency_khz`. Unknown values are `null`.
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// Reads the topology of the machine: the online cores with
+    /// their packages, dies, clusters, NUMA nodes, SMT siblings,
+    /// caches, kinds and highest frequencies.
+    ///
+    /// Fails with `ErrorKind::Unsupported` where the online cores
+    /// can not be listed, and with the error of reading the
+    /// affinity mask of the process. Other parts that can not be
+    /// read are left unknown.
+    pub fn detect() -> Result<Topology, Error> {
+        let online = record(get_all_core_ids_helper().ok_or_else(|| Error::new(ErrorKind::Unsupported)))?;
+        let allowed = record(allowed_for_process())?;
+
+        let packages = packages().unwrap_or_default();
+        let dies = dies().unwrap_or_default();
+        let clusters = clusters().unwrap_or_default();
+        let nodes = node_cores().unwrap_or_default();
+        let physical = get_physical_cores().unwrap_or_default();
+        let l2 = l2_groups().unwrap_or_default();
+        let cache_clusters = cache_clusters().unwrap_or_default();
+        let kinds = cores_by_kind().unwrap_or_default();
+
+        let cores = online.iter().map(|&core_id| CoreTopology {
+            core_id,
+            allowed: allowed.contains(core_id),
+            package: group_of(&packages, core_id),
+            die: group_of(&dies, core_id),
+            cluster: group_of(&clusters, core_id),
+            numa_node: group_of(&nodes, core_id),
+            physical_core: physical.iter().find(|core| core.siblings().contains(&core_id)).map(|core| core.id()),
+            l2_group: l2.iter().position(|core_ids| core_ids.contains(&core_id)),
+            cache_cluster: group_of(&cache_clusters, core_id),
+            kind: kinds.iter().find(|&(_, core_ids)| core_ids.contains(&core_id)).map(|(&kind, _)| kind),
+            max_frequency_khz: max_khz(core_id),
+        }).collect();
+
+        Ok(Topology { cores, kind_source: core_kind_source() })
+    }
+
+    /// Returns every online core, in ascending order.
+    pub fn cores(&self) -> &[CoreTopology] {
+        &self.cores
+    }
+
+    /// Returns the core `core_id`, or `None` for a core that was
+    /// not online.
+    ///
+    /// # Arguments
+    ///
+    /// * core_id - the core to look up
+    pub fn core(&self, core_id: CoreId) -> Option<&CoreTopology> {
+        self.cores.iter().find(|core| core.core_id == core_id)
+    }
+
+    /// Returns the packages with their cores, both in ascending
+    /// order, as `packages` did when detected.
+    pub fn packages(&self) -> Vec<(PackageId, Vec<CoreId>)> {
+        self.group_by(|core| core.package)
+    }
+
+    /// Returns the dies with their cores, both in ascending
+    /// order, as `dies` did when detected.
+    pub fn dies(&self) -> Vec<(DieId, Vec<CoreId>)> {
+        self.group_by(|core| core.die)
+    }
+
+    /// Returns the clusters with their cores, both in ascending
+    /// order, as `clusters` did when detected.
+    pub fn clusters(&self) -> Vec<(ClusterId, Vec<CoreId>)> {
+        self.group_by(|core| core.cluster)
+    }
+
+    /// Returns the NUMA nodes that have cores with their cores,
+    /// both in ascending order.
+    pub fn numa_nodes(&self) -> Vec<(NumaNode, Vec<CoreId>)> {
+        self.group_by(|core| core.numa_node)
+    }
+
+    /// Returns the other logical cores on the physical core of
+    /// `core_id`, or `None` where the physical cores were not
+    /// known.
+    ///
+    /// # Arguments
+    ///
+    /// * core_id - the logical core to look up
+    pub fn smt_siblings(&self, core_id: CoreId) -> Option<Vec<CoreId>> {
+        let physical = self.core(core_id)?.physical_core?;
+
+        Some(self.cores.iter()
+            .filter(|core| core.physical_core == Some(physical) && core.core_id != core_id)
+            .map(|core| core.core_id)
+            .collect())
+    }
+
+    /// Returns the cache clusters with their cores, both in
+    /// ascending order, as `cache_clusters` did when detected.
+    pub fn cache_clusters(&self) -> Vec<(CacheClusterId, Vec<CoreId>)> {
+        self.group_by(|core| core.cache_cluster)
+    }
+
+    /// Returns where the kinds of the cores came from.
+    pub fn kind_source(&self) -> Option<KindSource> {
+        self.kind_source
+    }
+
+    /// Writes the topology out in the JSON schema of
+    /// `SCHEMA_VERSION`.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        ::serde_json::to_string_pretty(self).expect("a topology always serializes")
+    }
+
+    fn group_by<T, F>(&self, key: F) -> Vec<(T, Vec<CoreId>)>
+        where T: Ord, F: Fn(&CoreTopology) -> Option<T>
+    {
+        let mut groups: Vec<(T, Vec<CoreId>)> = Vec::new();
+
+        for core in &self.cores {
+            if let Some(group) = key(core) {
+                match groups.iter_mut().find(|(other, _)| *other == group) {
+                    Some((_, core_ids)) => core_ids.push(core.core_id),
+                    None => groups.push((group, vec![core.core_id])),
+                }
+            }
+        }
+
+        groups.sort();
+        groups
+    }
+}
+
+// The group of `groups` that has `core_id`.
+fn group_of<T: Copy>(groups: &[(T, Vec<CoreId>)], core_id: CoreId) -> Option<T> {
+    groups.iter().find(|(_, core_ids)| core_ids.contains(&core_id)).map(|&(group, _)| group)
+}
+
+// Where a core sits in the machine, as far as the platform
+// tells. Unknown parts are `None`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CoreInfo {
+    pub core_id: CoreId,
+    pub package: Option<usize>,
+    // The die within the package.
+    pub die: Option<usize>,
+    pub node: Option<usize>,
+}
+
+impl CoreInfo {
+    #[cfg(test)]
+    pub fn unknown(core_id: CoreId) -> CoreInfo {
+        CoreInfo { core_id, package: None, die: None, node: None }
+    }
+}
+
+// Looks up the package, die and NUMA node of every core in
+// `core_ids`, as numbered by `packages`, `dies` and
+// `numa_nodes`. Dies are numbered across the machine, which
+// tells them apart just as well.
+pub(crate) fn core_info(core_ids: &[CoreId]) -> Vec<CoreInfo> {
+    let packages = packages().unwrap_or_default();
+    let dies = dies().unwrap_or_default();
+
+    core_ids.iter().map(|&core_id| CoreInfo {
+        core_id,
+        package: group_of(&packages, core_id).map(|package| package.id),
+        die: group_of(&dies, core_id).map(|die| die.id),
+        node: numa_node_of(core_id).map(|node| node.id),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{die_of, get_all_core_ids, package_of};
+
+    #[test]
+    fn test_core_info() {
+        let online = match get_all_core_ids() {
+            Some(online) => online,
+            None => return,
+        };
+
+        let infos = core_info(&online);
+        assert_eq!(infos.iter().map(|info| info.core_id).collect::<Vec<_>>(), online);
+
+        for info in infos {
+            assert_eq!(info.package, package_of(info.core_id).map(|package| package.id));
+            assert_eq!(info.die, die_of(info.core_id).map(|die| die.id));
+            assert_eq!(info.node, numa_node_of(info.core_id).map(|node| node.id));
+        }
+    }
+
+    #[test]
+    fn test_topology_detect() {
+        let online = match get_all_core_ids() {
+            Some(online) => online,
+            None => return,
+        };
+        let topology = Topology::detect().unwrap();
+
+        assert_eq!(topology.cores().iter().map(|core| core.core_id).collect::<Vec<_>>(), online);
+        assert_eq!(topology.core(CoreId { id: usize::MAX }), None);
+
+        // Every core is on one package, where they are known.
+        let count: usize = topology.packages().iter().map(|(_, core_ids)| core_ids.len()).sum();
+        assert!(count == 0 || count == online.len());
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::{CoreId, CpuSet, get_all_core_set, refresh_core_ids};
+
+/// How often `watch_topology` looks at the online cores.
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The cores that went on- or offline since the previous look
+/// of a watch started by `watch_topology`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TopologyChange {
+    /// The cores that came online, in ascending order.
+    pub added: Vec<CoreId>,
+    /// The cores that went offline, in ascending order.
+    pub removed: Vec<CoreId>,
+}
+
+/// Stops the watch started by `watch_topology` when dropped.
+///
+/// Dropping the handle wakes the watching thread and joins it,
+/// so once the drop returns, the callback is not called again.
+/// A callback that is running finishes first.
+#[must_use = "the watch stops as soon as the handle is dropped"]
+#[derive(Debug)]
+pub struct WatchHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        // Closing the channel ends the wait of the thread.
+        drop(self.stop.take());
+
+        if let Some(thread) = self.thread.take() {
+            // A callback that drops the handle can not join itself.
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// This function watches for cores going on- or offline, such
+/// as vCPUs added to or removed from a virtual machine, and
+/// calls `callback` with the cores that changed.
+///
+/// It is `watch_topology_every` with `DEFAULT_WATCH_INTERVAL`.
+///
+/// # Arguments
+///
+/// * callback - called with every change, on the watching
+///   thread
+pub fn watch_topology<F>(callback: F) -> WatchHandle
+    where F: Fn(TopologyChange) + Send + 'static
+{
+    watch_topology_every(DEFAULT_WATCH_INTERVAL, callback)
+}
+
+/// This function is like `watch_topology`, but looks at the
+/// online cores every `interval`.
+///
+/// A background thread compares the cores of `get_all_core_ids`
+/// with the ones it saw before: on Linux, it reads
+/// `/sys/devices/system/cpu/online`, which inotify does not
+/// report changes to, and on Windows it counts the active
+/// processors. Cores that go offline and come back between two
+/// looks are missed. Before the callback runs, the lists of
+/// `cached_core_ids` and `is_online` are refreshed, as by
+/// `refresh_core_ids`.
+///
+/// The cores seen when this function is called are the first
+/// ones compared against. The watch runs until the returned
+/// handle is dropped.
+///
+/// # Arguments
+///
+/// * interval - the time between two looks
+/// * callback - called with every change, on the watching
+///   thread
+pub fn watch_topology_every<F>(interval: Duration, callback: F) -> WatchHandle
+    where F: Fn(TopologyChange) + Send + 'static
+{
+    let (stop, stopped) = mpsc::channel::<()>();
+    let mut online = get_all_core_set();
+
+    let thread = thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            // Platforms that fail to list the cores once are
+            // asked again at the next look.
+            let current = match get_all_core_set() {
+                Some(current) => current,
+                None => continue,
+            };
+
+            if let Some(change) = online.as_ref().and_then(|online| diff(online, &current)) {
+                refresh_core_ids();
+                callback(change);
+            }
+
+            online = Some(current);
+        }
+    });
+
+    WatchHandle { stop: Some(stop), thread: Some(thread) }
+}
+
+// The change from `before` to `after`, if there is one.
+fn diff(before: &CpuSet, after: &CpuSet) -> Option<TopologyChange> {
+    let change = TopologyChange {
+        added: (after - before).to_vec(),
+        removed: (before - after).to_vec(),
+    };
+
+    if change.added.is_empty() && change.removed.is_empty() { None } else { Some(change) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_diff() {
+        let set = |ids: &[usize]| ids.iter().map(|&id| CoreId { id }).collect::<CpuSet>();
+
+        assert_eq!(diff(&set(&[0, 1, 2]), &set(&[0, 1, 2])), None);
+        assert_eq!(diff(&set(&[0, 1, 2, 3]), &set(&[0, 2, 4, 5])), Some(TopologyChange {
+            added: vec![CoreId { id: 4 }, CoreId { id: 5 }],
+            removed: vec![CoreId { id: 1 }, CoreId { id: 3 }],
+        }));
+        assert_eq!(diff(&set(&[]), &set(&[7])).unwrap().added, vec![CoreId { id: 7 }]);
+    }
+
+    #[test]
+    fn test_watch_topology_drop() {
+        let start = Instant::now();
+
+        // Dropping the handle does not wait out the interval.
+        drop(watch_topology_every(Duration::from_secs(3600), |_| ()));
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,959 @@
+#[cfg(feature = "hwloc")]
+use std::convert::TryFrom;
+use std::io;
+use std::mem;
+use std::os::windows::io::AsRawHandle;
+use std::os::windows::process::CommandExt;
+use std::process::{Child, Command};
+use std::ptr;
+use std::slice;
+use std::thread::JoinHandle;
+
+use winapi::shared::basetsd::{DWORD_PTR, PDWORD_PTR};
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{BOOL, BYTE, DWORD, FALSE, ULONG, USHORT, WORD};
+use winapi::shared::ntdef::LONG;
+use winapi::shared::winerror::ERROR_INVALID_PARAMETER;
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentThread, OpenProcess};
+use winapi::um::processthreadsapi::{GetCurrentProcessorNumberEx, OpenThread, ResumeThread};
+use winapi::um::processthreadsapi::{GetThreadIdealProcessorEx, SetThreadIdealProcessorEx};
+use winapi::um::processtopologyapi::{GetThreadGroupAffinity, SetThreadGroupAffinity};
+use winapi::um::sysinfoapi::GetLogicalProcessorInformationEx;
+use winapi::um::systemtopologyapi::{GetNumaHighestNodeNumber, GetNumaNodeProcessorMaskEx};
+use winapi::um::winbase::{GetActiveProcessorCount, GetActiveProcessorGroupCount, GetNumaProcessorNodeEx};
+use winapi::um::winbase::{GetMaximumProcessorCount, GetProcessAffinityMask};
+use winapi::um::tlhelp32::{CreateToolhelp32Snapshot, TH32CS_SNAPTHREAD};
+use winapi::um::tlhelp32::{THREADENTRY32, Thread32First, Thread32Next};
+use winapi::um::winbase::{CREATE_SUSPENDED, SetThreadAffinityMask};
+use winapi::um::winnt::{ALL_PROCESSOR_GROUPS, GROUP_AFFINITY, HANDLE, PROCESSOR_NUMBER};
+use winapi::um::winnt::{PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SET_INFORMATION};
+use winapi::um::winnt::{LOGICAL_PROCESSOR_RELATIONSHIP, PROCESSOR_RELATIONSHIP, RelationProcessorCore, RelationProcessorPackage};
+use winapi::um::winnt::{CacheData, CacheInstruction, CacheUnified, RelationCache};
+use winapi::um::winnt::{CpuSetInformation, ProcessorInformation, SYSTEM_CPU_SET_INFORMATION};
+use winapi::um::winnt::SYSTEM_CPU_SET_INFORMATION_CpuSet;
+use winapi::um::powerbase::CallNtPowerInformation;
+use winapi::um::winnt::{SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX, THREAD_SUSPEND_RESUME};
+
+use super::{CacheLevel, CacheType, Capabilities, CoreId, CoreIds, CoreKind, CpuTimes, Error, ErrorKind, KindSource};
+use super::{CoreState, NumaNode};
+use kind::classify;
+use super::StableCoreId;
+
+pub fn core_ids() -> Result<CoreIds, Error> {
+    let mask = get_affinity_mask()?;
+    let mut core_ids = CoreIds::new();
+
+    for i in (0..64).filter(|i| mask & (1 << i) != 0) {
+        core_ids.insert(i);
+    }
+
+    Ok(core_ids)
+}
+
+pub fn thread_core_ids() -> Result<CoreIds, Error> {
+    let mut affinity: GROUP_AFFINITY = unsafe { mem::zeroed() };
+
+    let res = unsafe {
+        GetThreadGroupAffinity(GetCurrentThread(), &mut affinity)
+    };
+
+    if res == 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let offset = group_offset(affinity.Group);
+    let mask = affinity.Mask as u64;
+    let mut core_ids = CoreIds::new();
+
+    for i in (0..64).filter(|i| mask & (1 << i) != 0) {
+        core_ids.insert(offset + i);
+    }
+
+    Ok(core_ids)
+}
+
+pub fn process_core_ids() -> Result<CoreIds, Error> {
+    // `core_ids` already reads the mask of the process.
+    core_ids()
+}
+
+pub fn get_all_core_ids() -> Option<Vec<CoreId>> {
+    // Count the active processors in every processor group,
+    // not just the ones in the process affinity mask.
+    let count = unsafe { GetActiveProcessorCount(ALL_PROCESSOR_GROUPS) };
+
+    if count > 0 {
+        Some((0..count as usize).map(|id| CoreId { id }).collect())
+    }
+    else {
+        None
+    }
+}
+
+pub fn core_ids_by_state(state: CoreState) -> Option<Vec<CoreId>> {
+    let online = get_all_core_ids()?;
+    // The maximum includes the processors that could be added
+    // while the system runs.
+    let count = unsafe { GetMaximumProcessorCount(ALL_PROCESSOR_GROUPS) } as usize;
+    let possible = (0..count.max(online.len())).map(|id| CoreId { id });
+
+    match state {
+        CoreState::Online => Some(online),
+        CoreState::Offline => Some(possible.filter(|core_id| !online.contains(core_id)).collect()),
+        CoreState::Present | CoreState::Possible => Some(possible.collect()),
+    }
+}
+
+pub fn get_affinity_for_current() -> Result<Vec<CoreId>, Error> {
+    get_for_handle(unsafe { GetCurrentThread() })
+}
+
+pub fn get_for_thread<T>(handle: &JoinHandle<T>) -> Result<Vec<CoreId>, Error> {
+    get_for_handle(handle.as_raw_handle() as HANDLE)
+}
+
+fn get_for_handle(thread: HANDLE) -> Result<Vec<CoreId>, Error> {
+    let mut affinity: GROUP_AFFINITY = unsafe { mem::zeroed() };
+
+    let res = unsafe {
+        GetThreadGroupAffinity(thread, &mut affinity)
+    };
+
+    if res == 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let offset = group_offset(affinity.Group);
+    let mask = affinity.Mask as u64;
+
+    Ok((0..64)
+       .filter(|i| (mask & (1 << i)) != 0)
+       .map(|i| CoreId { id: offset + i as usize })
+       .collect())
+}
+
+pub fn set_for_current(core_id: CoreId) -> Result<(), Error> {
+    if core_id.id >= MASK_BITS {
+        return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+    }
+
+    // Convert `CoreId` back into mask.
+    let mask: u64 = 1 << core_id.id;
+
+    set_affinity_mask(unsafe { GetCurrentThread() }, mask)
+        .map_err(|err| err.with_core_id(core_id))
+}
+
+pub fn set_for_current_set(core_ids: &[CoreId]) -> Result<(), Error> {
+    set_affinity_mask(unsafe { GetCurrentThread() }, to_mask(core_ids)?)
+}
+
+pub fn set_for_thread<T>(handle: &JoinHandle<T>, core_id: CoreId) -> Result<(), Error> {
+    if core_id.id >= MASK_BITS {
+        return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+    }
+
+    set_affinity_mask(handle.as_raw_handle() as HANDLE, 1 << core_id.id)
+        .map_err(|err| err.with_core_id(core_id))
+}
+
+pub fn set_for_process(pid: u32, core_ids: &[CoreId]) -> Result<(), Error> {
+    let mask = to_mask(core_ids)?;
+
+    // Setting the mask also needs to read the system mask.
+    let process = open_process(pid, PROCESS_SET_INFORMATION | PROCESS_QUERY_LIMITED_INFORMATION)?;
+
+    let res = unsafe { SetProcessAffinityMask(process, mask as DWORD_PTR) };
+    let err = Error::last_os_error();
+
+    unsafe { CloseHandle(process) };
+
+    if res != 0 {
+        Ok(())
+    }
+    else {
+        Err(err)
+    }
+}
+
+pub fn get_for_process(pid: u32) -> Result<Vec<CoreId>, Error> {
+    let process = open_process(pid, PROCESS_QUERY_LIMITED_INFORMATION)?;
+
+    let res = get_affinity_mask_for(process);
+
+    unsafe { CloseHandle(process) };
+
+    Ok(to_core_ids(res?))
+}
+
+pub fn spawn_with_affinity(command: &mut Command, core_ids: &[CoreId]) -> io::Result<Child> {
+    if core_ids.is_empty() {
+        return Err(Error::new(ErrorKind::EmptySet).into());
+    }
+
+    let mask = to_mask(core_ids)?;
+
+    // Keep the child from running any code until
+    // its affinity mask is in place.
+    let mut child = command.creation_flags(CREATE_SUSPENDED).spawn()?;
+
+    let process = child.as_raw_handle() as HANDLE;
+    let res = if unsafe { SetProcessAffinityMask(process, mask as DWORD_PTR) } != 0 {
+        resume_threads(child.id())
+    }
+    else {
+        Err(Error::last_os_error())
+    };
+
+    if let Err(err) = res {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(err.into());
+    }
+
+    Ok(child)
+}
+
+// `Child` does not expose the handle of the suspended
+// main thread, so look up the threads of the process.
+fn resume_threads(pid: u32) -> Result<(), Error> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) };
+
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Err(Error::last_os_error());
+    }
+
+    let mut entry: THREADENTRY32 = unsafe { mem::zeroed() };
+    entry.dwSize = mem::size_of::<THREADENTRY32>() as DWORD;
+
+    let mut res = Ok(());
+    let mut more = unsafe { Thread32First(snapshot, &mut entry) } != 0;
+
+    while more && res.is_ok() {
+        if entry.th32OwnerProcessID == pid {
+            res = resume_thread(entry.th32ThreadID);
+        }
+
+        more = unsafe { Thread32Next(snapshot, &mut entry) } != 0;
+    }
+
+    unsafe { CloseHandle(snapshot) };
+
+    res
+}
+
+fn resume_thread(tid: DWORD) -> Result<(), Error> {
+    let thread = unsafe { OpenThread(THREAD_SUSPEND_RESUME, FALSE, tid) };
+
+    if thread.is_null() {
+        return Err(Error::last_os_error());
+    }
+
+    // `ResumeThread` returns `(DWORD) -1` on failure.
+    let res = unsafe { ResumeThread(thread) };
+    let err = Error::last_os_error();
+
+    unsafe { CloseHandle(thread) };
+
+    if res != DWORD::MAX {
+        Ok(())
+    }
+    else {
+        Err(err)
+    }
+}
+
+fn open_process(pid: u32, access: DWORD) -> Result<HANDLE, Error> {
+    let process = unsafe { OpenProcess(access, FALSE, pid) };
+
+    if !process.is_null() {
+        return Ok(process);
+    }
+
+    let err = Error::last_os_error();
+
+    // `OpenProcess` rejects the ID of a process that
+    // does not exist as an invalid parameter.
+    if err.raw_os_error() == Some(ERROR_INVALID_PARAMETER as i32) {
+        Err(Error::new(ErrorKind::NotFound))
+    }
+    else {
+        Err(err)
+    }
+}
+
+pub fn current_core() -> Result<CoreId, Error> {
+    let mut number: PROCESSOR_NUMBER = unsafe { mem::zeroed() };
+
+    // This never fails and does not enter the kernel.
+    unsafe { GetCurrentProcessorNumberEx(&mut number) };
+
+    Ok(CoreId { id: group_offset(number.Group) + number.Number as usize })
+}
+
+pub fn current_location() -> Result<(CoreId, NumaNode), Error> {
+    let mut number: PROCESSOR_NUMBER = unsafe { mem::zeroed() };
+    let mut node: USHORT = 0;
+
+    unsafe { GetCurrentProcessorNumberEx(&mut number) };
+
+    if unsafe { GetNumaProcessorNodeEx(&mut number, &mut node) } == 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok((CoreId { id: group_offset(number.Group) + number.Number as usize }, NumaNode { id: node as usize }))
+}
+
+pub fn set_preferred_for_current(core_id: CoreId) -> Result<(), Error> {
+    let mut number = to_processor_number(core_id)?;
+
+    let res = unsafe {
+        SetThreadIdealProcessorEx(GetCurrentThread(), &mut number, ptr::null_mut())
+    };
+
+    if res != 0 {
+        Ok(())
+    }
+    else {
+        Err(Error::last_os_error().with_core_id(core_id))
+    }
+}
+
+pub fn capabilities() -> Capabilities {
+    let thread = unsafe { GetCurrentThread() };
+
+    let mut affinity: GROUP_AFFINITY = unsafe { mem::zeroed() };
+    let queried = unsafe { GetThreadGroupAffinity(thread, &mut affinity) } != 0;
+
+    let mut ideal: PROCESSOR_NUMBER = unsafe { mem::zeroed() };
+    let ideal_queried = unsafe { GetThreadIdealProcessorEx(thread, &mut ideal) } != 0;
+
+    // Reapplying the current values changes nothing, but
+    // fails where the calls are not allowed.
+    Capabilities {
+        hard_pinning: queried && unsafe {
+            SetThreadGroupAffinity(thread, &affinity, ptr::null_mut())
+        } != 0,
+        soft_affinity: ideal_queried && unsafe {
+            SetThreadIdealProcessorEx(thread, &mut ideal, ptr::null_mut())
+        } != 0,
+        can_query_affinity: queried,
+        max_cores_representable: MASK_BITS,
+    }
+}
+
+pub fn clear_for_current() -> Result<(), Error> {
+    // The process mask is the widest mask a thread may use.
+    set_affinity_mask(unsafe { GetCurrentThread() }, get_affinity_mask()?)
+}
+
+pub fn core_siblings() -> Option<Vec<Vec<CoreId>>> {
+    let mut groups = Vec::new();
+
+    for_each_processor_information(RelationProcessorCore, |info| {
+        groups.push(processor_core_ids(unsafe { info.u.Processor() }));
+    })?;
+
+    Some(groups)
+}
+
+// Windows does not number the packages, so they are numbered
+// in the order they are listed.
+pub fn packages() -> Option<Vec<(usize, Vec<CoreId>)>> {
+    let mut packages = Vec::new();
+
+    for_each_processor_information(RelationProcessorPackage, |info| {
+        packages.push((packages.len(), processor_core_ids(unsafe { info.u.Processor() })));
+    })?;
+
+    Some(packages)
+}
+
+pub fn dies() -> Option<Vec<Vec<CoreId>>> {
+    // Missing from winapi, and only known since Windows 10
+    // version 2004.
+    const RELATION_PROCESSOR_DIE: LOGICAL_PROCESSOR_RELATIONSHIP = 5;
+
+    let mut dies = Vec::new();
+
+    for_each_processor_information(RELATION_PROCESSOR_DIE, |info| {
+        dies.push(processor_core_ids(unsafe { info.u.Processor() }));
+    })?;
+
+    Some(dies)
+}
+
+pub fn clusters() -> Option<Vec<Vec<CoreId>>> {
+    None
+}
+
+pub fn stable_core_ids() -> Option<Vec<(CoreId, StableCoreId)>> {
+    None
+}
+
+// Only the first processor group of a cache is listed.
+pub fn cache_groups(level: usize) -> Option<Vec<Vec<CoreId>>> {
+    let mut groups = Vec::new();
+
+    for_each_processor_information(RelationCache, |info| {
+        let cache = unsafe { info.u.Cache() };
+
+        if cache.Level as usize == level && cache.Type != CacheInstruction {
+            groups.push(group_affinity_core_ids(&cache.GroupMask));
+        }
+    })?;
+
+    if groups.is_empty() { None } else { Some(groups) }
+}
+
+pub fn cache_info(core_id: CoreId) -> Option<Vec<CacheLevel>> {
+    // Fully associative caches report 0xff ways.
+    const FULLY_ASSOCIATIVE: u8 = 0xff;
+
+    let mut caches = Vec::new();
+
+    for_each_processor_information(RelationCache, |info| {
+        let cache = unsafe { info.u.Cache() };
+
+        let cache_type = if cache.Type == CacheUnified {
+            CacheType::Unified
+        }
+        else if cache.Type == CacheInstruction {
+            CacheType::Instruction
+        }
+        else if cache.Type == CacheData {
+            CacheType::Data
+        }
+        else {
+            // Trace caches hold decoded instructions.
+            return;
+        };
+
+        if group_affinity_core_ids(&cache.GroupMask).contains(&core_id) {
+            caches.push(CacheLevel {
+                level: cache.Level as usize,
+                cache_type,
+                size: Some(cache.CacheSize as usize).filter(|&size| size > 0),
+                line_size: Some(cache.LineSize as usize).filter(|&size| size > 0),
+                associativity: Some(cache.Associativity)
+                    .filter(|&ways| ways > 0 && ways != FULLY_ASSOCIATIVE)
+                    .map(|ways| ways as usize),
+            });
+        }
+    })?;
+
+    if caches.is_empty() { None } else { Some(caches) }
+}
+
+// Cores with a higher efficiency class are faster. Machines
+// whose cores are all alike put them all in class 0.
+pub fn core_kinds() -> Option<(Vec<(CoreId, CoreKind)>, KindSource)> {
+    let mut classes = Vec::new();
+
+    for_each_cpu_set(|core_id, cpu_set| classes.push((core_id, cpu_set.EfficiencyClass as u64)))?;
+
+    if classes.is_empty() { None } else { Some((classify(&classes), KindSource::Reported)) }
+}
+
+// The scheduler prefers cores of a higher scheduling class,
+// and then those of a higher efficiency class.
+pub fn core_rankings() -> Option<Vec<(CoreId, u64)>> {
+    let mut rankings = Vec::new();
+
+    for_each_cpu_set(|core_id, cpu_set| {
+        // The low byte of `Reserved` is `SchedulingClass` in
+        // newer headers.
+        let scheduling_class = (cpu_set.Reserved & 0xff) as u64;
+
+        rankings.push((core_id, scheduling_class << 8 | cpu_set.EfficiencyClass as u64));
+    })?;
+
+    Some(rankings)
+}
+
+// Calls `f` with every core `GetSystemCpuSetInformation` lists.
+// Returns `None` if it fails.
+fn for_each_cpu_set<F>(mut f: F) -> Option<()>
+    where F: FnMut(CoreId, &SYSTEM_CPU_SET_INFORMATION_CpuSet)
+{
+    let mut len: ULONG = 0;
+
+    // The first call only asks for the size of the entries.
+    unsafe {
+        GetSystemCpuSetInformation(ptr::null_mut(), 0, &mut len, ptr::null_mut(), 0);
+    }
+
+    if len == 0 {
+        return None;
+    }
+
+    let mut buf: Vec<u64> = vec![0; (len as usize).div_ceil(8)];
+
+    let res = unsafe {
+        GetSystemCpuSetInformation(buf.as_mut_ptr() as *mut _, len, &mut len, ptr::null_mut(), 0)
+    };
+
+    if res == 0 {
+        return None;
+    }
+
+    let mut offset = 0;
+
+    while offset < len as usize {
+        let info = unsafe {
+            &*((buf.as_ptr() as *const u8).add(offset) as *const SYSTEM_CPU_SET_INFORMATION)
+        };
+
+        if info.Size == 0 {
+            break;
+        }
+
+        if info.Type == CpuSetInformation {
+            let cpu_set = &info.CpuSet;
+            f(CoreId { id: group_offset(cpu_set.Group) + cpu_set.LogicalProcessorIndex as usize }, cpu_set);
+        }
+
+        offset += info.Size as usize;
+    }
+
+    Some(())
+}
+
+pub fn max_frequency_khz(core_id: CoreId) -> Option<u64> {
+    processor_power_information(core_id).map(|info| info.MaxMhz as u64 * 1000)
+}
+
+pub fn current_frequency_khz(core_id: CoreId) -> Option<u64> {
+    processor_power_information(core_id).map(|info| info.CurrentMhz as u64 * 1000)
+}
+
+// Only lists the processors of the group of the calling
+// thread, so other groups are not known.
+fn processor_power_information(core_id: CoreId) -> Option<PROCESSOR_POWER_INFORMATION> {
+    let number = to_processor_number(core_id).ok()?;
+    let mut current: PROCESSOR_NUMBER = unsafe { mem::zeroed() };
+
+    unsafe { GetCurrentProcessorNumberEx(&mut current) };
+
+    if number.Group != current.Group {
+        return None;
+    }
+
+    let count = unsafe { GetActiveProcessorCount(number.Group) } as usize;
+    let mut infos = vec![PROCESSOR_POWER_INFORMATION::default(); count];
+    let len = (count * mem::size_of::<PROCESSOR_POWER_INFORMATION>()) as ULONG;
+
+    let status = unsafe {
+        CallNtPowerInformation(ProcessorInformation, ptr::null_mut(), 0, infos.as_mut_ptr() as *mut _, len)
+    };
+
+    if status != 0 {
+        return None;
+    }
+
+    infos.into_iter().find(|info| info.Number == number.Number as ULONG)
+}
+
+// Only lists the processors of the group of the calling
+// thread, like `processor_power_information`.
+pub fn cpu_times() -> Option<Vec<(CoreId, CpuTimes)>> {
+    let mut current: PROCESSOR_NUMBER = unsafe { mem::zeroed() };
+
+    unsafe { GetCurrentProcessorNumberEx(&mut current) };
+
+    let count = unsafe { GetActiveProcessorCount(current.Group) } as usize;
+    let mut infos = vec![SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION::default(); count];
+    let len = (count * mem::size_of::<SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION>()) as ULONG;
+    let mut returned: ULONG = 0;
+
+    let status = unsafe {
+        NtQuerySystemInformation(SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION_CLASS, infos.as_mut_ptr() as *mut _,
+                                 len, &mut returned)
+    };
+
+    if status != 0 {
+        return None;
+    }
+
+    infos.truncate(returned as usize / mem::size_of::<SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION>());
+
+    let offset = group_offset(current.Group);
+
+    // The kernel time includes the idle time.
+    Some(infos.into_iter().enumerate().map(|(number, info)| {
+        let times = CpuTimes {
+            user: info.UserTime as u64,
+            system: (info.KernelTime - info.IdleTime) as u64,
+            idle: info.IdleTime as u64,
+        };
+
+        (CoreId { id: offset + number }, times)
+    }).collect())
+}
+
+pub fn capacity(_core_id: CoreId) -> Option<u32> {
+    None
+}
+
+pub fn numa_nodes() -> Option<Vec<(NumaNode, Vec<CoreId>)>> {
+    let mut highest: ULONG = 0;
+
+    if unsafe { GetNumaHighestNodeNumber(&mut highest) } == 0 {
+        return None;
+    }
+
+    // Node numbers may have gaps, which fail here. A node that
+    // spans processor groups only reports its first group.
+    Some((0..=highest as USHORT).filter_map(|node| {
+        let mut affinity: GROUP_AFFINITY = unsafe { mem::zeroed() };
+
+        if unsafe { GetNumaNodeProcessorMaskEx(node, &mut affinity) } == 0 {
+            return None;
+        }
+
+        Some((NumaNode { id: node as usize }, group_affinity_core_ids(&affinity)))
+    }).collect())
+}
+
+// Calls `f` with every entry `GetLogicalProcessorInformationEx`
+// returns for `relation`. Returns `None` if it fails.
+fn for_each_processor_information<F>(relation: LOGICAL_PROCESSOR_RELATIONSHIP, mut f: F) -> Option<()>
+    where F: FnMut(&SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX)
+{
+    let mut len: DWORD = 0;
+
+    // The first call only asks for the size of the entries.
+    unsafe {
+        GetLogicalProcessorInformationEx(relation, ptr::null_mut(), &mut len);
+    }
+
+    if len == 0 {
+        return None;
+    }
+
+    // The entries have variable sizes, but stay aligned to 8.
+    let mut buf: Vec<u64> = vec![0; (len as usize).div_ceil(8)];
+
+    let res = unsafe {
+        GetLogicalProcessorInformationEx(relation, buf.as_mut_ptr() as *mut _, &mut len)
+    };
+
+    if res == 0 {
+        return None;
+    }
+
+    let mut offset = 0;
+
+    while offset < len as usize {
+        let info = unsafe {
+            &*((buf.as_ptr() as *const u8).add(offset) as *const SYSTEM_LOGICAL_PROCESSOR_INFORMATION_EX)
+        };
+
+        if info.Size == 0 {
+            break;
+        }
+
+        if info.Relationship == relation {
+            f(info);
+        }
+
+        offset += info.Size as usize;
+    }
+
+    Some(())
+}
+
+// The cores in the group masks of `processor`.
+fn processor_core_ids(processor: &PROCESSOR_RELATIONSHIP) -> Vec<CoreId> {
+    let masks = unsafe {
+        slice::from_raw_parts(processor.GroupMask.as_ptr(), processor.GroupCount as usize)
+    };
+
+    masks.iter().flat_map(group_affinity_core_ids).collect()
+}
+
+fn group_affinity_core_ids(affinity: &GROUP_AFFINITY) -> Vec<CoreId> {
+    let offset = group_offset(affinity.Group);
+    let mask = affinity.Mask as u64;
+
+    (0..64usize)
+        .filter(|&i| mask & (1 << i) != 0)
+        .map(|i| CoreId { id: offset + i })
+        .collect()
+}
+
+// hwloc numbers a processor by its bit in the masks of all
+// groups laid end to end, so groups with fewer processors
+// than mask bits leave gaps that core IDs do not have.
+#[cfg(feature = "hwloc")]
+pub fn to_hwloc_index(core_id: CoreId) -> Result<usize, Error> {
+    let number = to_processor_number(core_id)?;
+
+    Ok(number.Group as usize * MASK_BITS + number.Number as usize)
+}
+
+// `None` for bits past the processors of their group.
+#[cfg(feature = "hwloc")]
+pub fn from_hwloc_index(index: usize) -> Option<CoreId> {
+    let group = WORD::try_from(index / MASK_BITS).ok()?;
+    let number = index % MASK_BITS;
+
+    if number < unsafe { GetActiveProcessorCount(group) } as usize {
+        Some(CoreId { id: group_offset(group) + number })
+    }
+    else {
+        None
+    }
+}
+
+// The entries `CallNtPowerInformation` returns for
+// `ProcessorInformation`, which winapi does not have.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+#[allow(non_snake_case)]
+struct PROCESSOR_POWER_INFORMATION {
+    Number: ULONG,
+    MaxMhz: ULONG,
+    CurrentMhz: ULONG,
+    MhzLimit: ULONG,
+    MaxIdleState: ULONG,
+    CurrentIdleState: ULONG,
+}
+
+// The entries `NtQuerySystemInformation` returns for
+// `SystemProcessorPerformanceInformation`, in intervals of
+// 100 ns, which winapi does not have either.
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+#[allow(non_snake_case)]
+struct SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION {
+    IdleTime: i64,
+    KernelTime: i64,
+    UserTime: i64,
+    DpcTime: i64,
+    InterruptTime: i64,
+    InterruptCount: ULONG,
+}
+
+const SYSTEM_PROCESSOR_PERFORMANCE_INFORMATION_CLASS: ULONG = 8;
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn NtQuerySystemInformation(class: ULONG, information: *mut c_void, length: ULONG, returned: *mut ULONG) -> LONG;
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    // winapi declares the mask as a `DWORD`, which would
+    // drop the upper cores on 64-bit targets.
+    fn SetProcessAffinityMask(process: HANDLE, mask: DWORD_PTR) -> BOOL;
+    // Missing from winapi.
+    fn GetSystemCpuSetInformation(information: *mut SYSTEM_CPU_SET_INFORMATION, length: ULONG,
+                                  returned: *mut ULONG, process: HANDLE, flags: ULONG) -> BOOL;
+}
+
+// Number of cores that fit into an affinity mask.
+const MASK_BITS: usize = 8 * mem::size_of::<DWORD_PTR>();
+
+// Core IDs are numbered across all processor groups,
+// so skip the processors of every lower group.
+fn group_offset(group: WORD) -> usize {
+    (0..group)
+        .map(|group| unsafe { GetActiveProcessorCount(group) } as usize)
+        .sum()
+}
+
+// Find the processor group and the number within that
+// group of `core_id`.
+fn to_processor_number(core_id: CoreId) -> Result<PROCESSOR_NUMBER, Error> {
+    let groups = unsafe { GetActiveProcessorGroupCount() };
+    let mut first = 0;
+
+    for group in 0..groups {
+        let count = unsafe { GetActiveProcessorCount(group) } as usize;
+
+        if core_id.id < first + count {
+            let mut number: PROCESSOR_NUMBER = unsafe { mem::zeroed() };
+            number.Group = group;
+            number.Number = (core_id.id - first) as BYTE;
+
+            return Ok(number);
+        }
+
+        first += count;
+    }
+
+    Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id))
+}
+
+// OR every `CoreId` into a single mask.
+fn to_mask(core_ids: &[CoreId]) -> Result<u64, Error> {
+    let mut mask: u64 = 0;
+
+    for &core_id in core_ids {
+        if core_id.id >= MASK_BITS {
+            return Err(Error::new(ErrorKind::InvalidCore).with_core_id(core_id));
+        }
+
+        mask |= 1 << core_id.id;
+    }
+
+    Ok(mask)
+}
+
+// Find all active cores in the bitmask.
+fn to_core_ids(mask: u64) -> Vec<CoreId> {
+    let mut core_ids: Vec<CoreId> = Vec::new();
+
+    for i in 0..64 as u64 {
+        let test_mask = 1 << i;
+
+        if (mask & test_mask) == test_mask {
+            core_ids.push(CoreId { id: i as usize });
+        }
+    }
+
+    core_ids
+}
+
+fn set_affinity_mask(thread: HANDLE, mask: u64) -> Result<(), Error> {
+    // Set core affinity for the thread.
+    let res = unsafe {
+        SetThreadAffinityMask(
+            thread,
+            mask as DWORD_PTR
+        )
+    };
+
+    if res != 0 {
+        Ok(())
+    }
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+fn get_affinity_mask() -> Result<u64, Error> {
+    get_affinity_mask_for(unsafe { GetCurrentProcess() })
+}
+
+fn get_affinity_mask_for(process: HANDLE) -> Result<u64, Error> {
+    let mut system_mask: usize = 0;
+    let mut process_mask: usize = 0;
+
+    let res = unsafe {
+        GetProcessAffinityMask(
+            process,
+            &mut process_mask as PDWORD_PTR,
+            &mut system_mask as PDWORD_PTR
+        )
+    };
+
+    // Successfully retrieved affinity mask
+    if res != 0 {
+        Ok(process_mask as u64)
+    }
+    // Failed to retrieve affinity mask
+    else {
+        Err(Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use num_cpus;
+
+    use super::*;
+    use super::super::{get_core_ids, testing};
+
+    #[test]
+    fn test_windows_core_ids() {
+        match core_ids() {
+            Ok(core_ids) => {
+                assert_eq!(core_ids.len(), num_cpus::get());
+            },
+            Err(_) => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_windows_get_all_core_ids() {
+        match get_all_core_ids() {
+            Some(set) => {
+                assert!(set.len() >= num_cpus::get());
+            },
+            None => { panic!(); },
+        }
+    }
+
+    #[test]
+    fn test_windows_get_all_core_ids_when_pinned() {
+        testing::check_get_all_core_ids_when_pinned();
+    }
+
+    #[test]
+    fn test_windows_set_for_current_set() {
+        testing::check_set_for_current_set();
+    }
+
+    #[test]
+    fn test_windows_set_for_thread() {
+        testing::check_set_for_thread();
+    }
+
+    #[test]
+    fn test_windows_set_for_process() {
+        use std::process::Command;
+
+        let id = *get_core_ids().unwrap().last().unwrap();
+        let mut child = Command::new("ping").args(&["-n", "10", "127.0.0.1"]).spawn().unwrap();
+
+        let res = set_for_process(child.id(), &[id]);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(res.is_ok());
+    }
+
+    #[test]
+    fn test_windows_clear_for_current() {
+        testing::check_clear_for_current();
+    }
+
+    #[test]
+    fn test_windows_set_for_current() {
+        testing::check_set_for_current();
+    }
+
+    #[test]
+    fn test_windows_set_for_current_invalid_core() {
+        let id = CoreId { id: usize::MAX };
+        let err = set_for_current(id).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+        assert_eq!(err.core_id(), Some(id));
+    }
+
+    #[test]
+    fn test_windows_set_preferred_for_current() {
+        use winapi::um::processthreadsapi::GetThreadIdealProcessorEx;
+
+        let id = *get_core_ids().unwrap().last().unwrap();
+
+        thread::spawn(move || {
+            set_preferred_for_current(id).unwrap();
+
+            let mut number: PROCESSOR_NUMBER = unsafe { mem::zeroed() };
+            assert_ne!(unsafe { GetThreadIdealProcessorEx(GetCurrentThread(), &mut number) }, 0);
+            assert_eq!(group_offset(number.Group) + number.Number as usize, id.id);
+
+            // Only a hint: the affinity mask is unchanged.
+            assert_eq!(get_affinity_for_current().unwrap(), get_core_ids().unwrap());
+        }).join().unwrap();
+
+        let err = set_preferred_for_current(CoreId { id: usize::MAX }).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+    }
+}
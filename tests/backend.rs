@@ -0,0 +1,117 @@
+extern crate core_affinity;
+
+use std::collections::HashMap;
+use std::panic;
+use std::sync::{Mutex, mpsc};
+use std::thread::{self, Thread, ThreadId};
+
+use core_affinity::{Backend, CoreId, CoreIds, Error, ErrorKind, LoadSampler, PlatformSampler};
+
+// A made-up platform with two cores, on which a thread may
+// only be pinned to a single core at a time.
+#[derive(Default)]
+struct TwoCores {
+    pinned: Mutex<HashMap<ThreadId, CoreId>>,
+}
+
+impl TwoCores {
+    fn mask(&self, thread: ThreadId) -> Vec<CoreId> {
+        match self.pinned.lock().unwrap().get(&thread) {
+            Some(&core_id) => vec![core_id],
+            None => vec![CoreId { id: 0 }, CoreId { id: 1 }],
+        }
+    }
+
+    fn pin(&self, thread: ThreadId, core_id: CoreId) -> Result<(), Error> {
+        if core_id.id > 1 {
+            return Err(Error::new(ErrorKind::InvalidCore));
+        }
+
+        self.pinned.lock().unwrap().insert(thread, core_id);
+        Ok(())
+    }
+}
+
+impl Backend for TwoCores {
+    fn core_ids(&self) -> Result<CoreIds, Error> {
+        Ok(self.mask(thread::current().id()).into_iter().collect())
+    }
+
+    fn get_affinity_for_current(&self) -> Result<Vec<CoreId>, Error> {
+        Ok(self.mask(thread::current().id()))
+    }
+
+    fn set_for_current(&self, core_id: CoreId) -> Result<(), Error> {
+        self.pin(thread::current().id(), core_id)
+    }
+
+    fn set_for_thread(&self, thread: &Thread, core_id: CoreId) -> Result<(), Error> {
+        self.pin(thread.id(), core_id)
+    }
+
+    fn get_for_thread(&self, thread: &Thread) -> Result<Vec<CoreId>, Error> {
+        Ok(self.mask(thread.id()))
+    }
+}
+
+// The backend is installed for the whole process, so this
+// file holds a single test that runs the steps in order.
+#[test]
+fn test_custom_backend() {
+    core_affinity::set_backend(Box::new(TwoCores::default()));
+
+    let core = |id| CoreId { id };
+
+    assert_eq!(core_affinity::get_core_ids(), Some(vec![core(0), core(1)]));
+    assert_eq!(core_affinity::get_all_core_ids(), Some(vec![core(0), core(1)]));
+    assert_eq!(core_affinity::core_count(), Some(2));
+
+    assert!(core_affinity::set_for_current(core(1)));
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(1)]));
+    assert!(!core_affinity::set_for_current(core(2)));
+    assert!(core_affinity::set_for_current_set(&[core(0)]));
+    assert!(!core_affinity::set_for_current_set(&[core(0), core(1)]));
+
+    let err = core_affinity::set_preferred_for_current(core(0)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    // Other threads are reached through their `Thread`.
+    let (tx, rx) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || {
+        rx.recv().unwrap();
+        core_affinity::get_affinity_for_current()
+    });
+
+    assert!(core_affinity::set_for_thread(&handle, core(1)));
+    assert_eq!(core_affinity::get_for_thread(&handle), Some(vec![core(1)]));
+    tx.send(()).unwrap();
+    assert_eq!(handle.join().unwrap(), Some(vec![core(1)]));
+
+    let handle = core_affinity::spawn_pinned(core(0), core_affinity::get_affinity_for_current).unwrap();
+    assert_eq!(handle.join().unwrap(), Some(vec![core(0)]));
+
+    // The backend describes no topology, so the queries built
+    // on it report none rather than that of the platform.
+    assert!(core_affinity::get_physical_cores().is_none());
+    assert!(core_affinity::numa_nodes().is_none());
+    assert!(core_affinity::current_numa_node().is_none());
+    assert!(core_affinity::packages().is_none());
+    assert!(core_affinity::dies().is_none());
+    assert!(core_affinity::clusters().is_none());
+    assert!(core_affinity::cache_clusters().is_none());
+    assert!(core_affinity::l3_domains().is_none());
+    assert!(core_affinity::l2_groups().is_none());
+    assert!(core_affinity::cache_info(core(0)).is_none());
+    assert!(core_affinity::cores_by_kind().is_none());
+    assert!(core_affinity::max_frequency_khz(core(0)).is_none());
+    assert!(core_affinity::favored_cores().is_none());
+    assert!(core_affinity::capacity_of(core(0)).is_none());
+    assert!(core_affinity::current_frequency_khz(core(0)).is_none());
+    assert_eq!(PlatformSampler.sample().unwrap_err().kind(), ErrorKind::Unsupported);
+    assert!(core_affinity::get_core_ids_by_state(core_affinity::CoreState::Present).is_none());
+    assert!(core(0).stable().is_none());
+
+    // A second backend is rejected.
+    let res = panic::catch_unwind(|| core_affinity::set_backend(Box::new(TwoCores::default())));
+    assert!(res.is_err());
+}
@@ -0,0 +1,74 @@
+#![cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd")))]
+
+extern crate core_affinity;
+
+use std::env;
+use std::process::{Command, Stdio};
+
+use core_affinity::{CommandAffinityExt, CoreId};
+
+// Re-run this test binary as the helper, so the tests
+// do not depend on any particular tool being installed.
+const HELPER: &str = "CORE_AFFINITY_HELPER";
+
+#[test]
+fn helper_print_affinity() {
+    if env::var_os(HELPER).is_none() {
+        return;
+    }
+
+    let ids = core_affinity::get_core_ids().unwrap();
+    println!("affinity: {:?}", ids.iter().map(|id| id.id).collect::<Vec<_>>());
+}
+
+fn helper() -> Command {
+    let mut command = Command::new(env::current_exe().unwrap());
+    command
+        .args(["--exact", "helper_print_affinity", "--nocapture", "--test-threads=1"])
+        .env(HELPER, "1")
+        .stdout(Stdio::piped());
+    command
+}
+
+// The test harness prints the test name on the same line.
+fn reported_affinity(stdout: &[u8]) -> String {
+    let stdout = String::from_utf8_lossy(stdout);
+    let start = stdout.find("affinity: ").unwrap();
+
+    stdout[start..].lines().next().unwrap().to_string()
+}
+
+#[test]
+fn test_spawn_with_affinity() {
+    let id = *core_affinity::get_core_ids().unwrap().last().unwrap();
+
+    let output = helper().spawn_with_affinity(&[id]).unwrap().wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(reported_affinity(&output.stdout), format!("affinity: [{}]", id.id));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_core_affinity() {
+    let id = *core_affinity::get_core_ids().unwrap().last().unwrap();
+
+    let output = helper().core_affinity(&[id]).output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(reported_affinity(&output.stdout), format!("affinity: [{}]", id.id));
+}
+
+#[test]
+fn test_spawn_with_affinity_invalid_core() {
+    let res = helper().spawn_with_affinity(&[CoreId { id: usize::MAX }]);
+
+    assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_spawn_with_affinity_empty_set() {
+    let res = helper().spawn_with_affinity(&[]);
+
+    assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,43 @@
+extern crate core_affinity;
+
+use std::env;
+use std::time::Duration;
+
+use core_affinity::CoreId;
+
+// Pinning is switched off for the whole process, so this
+// file holds a single test that runs the steps in order.
+#[test]
+fn test_disabled() {
+    env::set_var("CORE_AFFINITY_DISABLED", "1");
+
+    let before = core_affinity::get_core_ids().unwrap();
+    let invalid = CoreId { id: usize::MAX };
+
+    // The environment is read on first use.
+    assert!(!core_affinity::is_enabled());
+    assert!(core_affinity::set_for_current(invalid));
+    assert!(core_affinity::set_for_current_verified(invalid).is_ok());
+    assert!(core_affinity::set_for_current_set(&[invalid]));
+    assert!(core_affinity::clear_for_current());
+    assert!(!core_affinity::set_for_current_set(&[]));
+
+    // Getters still report the real affinity.
+    assert_eq!(core_affinity::get_core_ids().unwrap(), before);
+    assert!(core_affinity::get_all_core_ids().is_some());
+
+    // Measuring a core needs a thread pinned to it.
+    assert_eq!(core_affinity::sample_busy(Duration::from_millis(1)), None);
+
+    // Only the first read looks at the environment.
+    env::remove_var("CORE_AFFINITY_DISABLED");
+    assert!(!core_affinity::is_enabled());
+
+    core_affinity::set_enabled(true);
+    assert!(core_affinity::is_enabled());
+    assert!(!core_affinity::set_for_current(invalid));
+
+    core_affinity::set_enabled(false);
+    assert!(!core_affinity::is_enabled());
+    assert!(core_affinity::set_for_current(invalid));
+}
@@ -0,0 +1,55 @@
+#![cfg(all(feature = "ffi", not(feature = "mock")))]
+
+extern crate core_affinity;
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::thread;
+
+use core_affinity::ffi::{CORE_AFFINITY_ERROR_INVALID_CORE, CORE_AFFINITY_ERROR_NULL_POINTER, CORE_AFFINITY_OK};
+
+// Declared as in `include/core_affinity.h`, so the calls go
+// through the C ABI and the exported symbols.
+extern "C" {
+    fn core_affinity_get_core_ids(buf: *mut usize, cap: usize) -> isize;
+    fn core_affinity_set_for_current(core: usize) -> c_int;
+    fn core_affinity_error_message(code: c_int) -> *const c_char;
+}
+
+#[test]
+fn test_round_trip() {
+    thread::spawn(|| unsafe {
+        let expected: Vec<usize> = core_affinity::get_core_ids().unwrap().iter().map(|id| id.id).collect();
+
+        let count = core_affinity_get_core_ids(ptr::null_mut(), 0);
+        assert_eq!(count, expected.len() as isize);
+
+        let mut buf = vec![usize::MAX; expected.len() + 1];
+        assert_eq!(core_affinity_get_core_ids(buf.as_mut_ptr(), buf.len()), count);
+        assert_eq!(&buf[..expected.len()], &expected[..]);
+        assert_eq!(buf[expected.len()], usize::MAX);
+
+        // A short buffer still gets the first cores.
+        let mut short = [usize::MAX; 1];
+        assert_eq!(core_affinity_get_core_ids(short.as_mut_ptr(), 1), count);
+        assert_eq!(short[0], expected[0]);
+
+        assert_eq!(core_affinity_set_for_current(expected[0]), CORE_AFFINITY_OK);
+        assert_eq!(core_affinity_get_core_ids(buf.as_mut_ptr(), buf.len()), 1);
+        assert_eq!(buf[0], expected[0]);
+    }).join().unwrap();
+}
+
+#[test]
+fn test_errors() {
+    unsafe {
+        assert_eq!(core_affinity_get_core_ids(ptr::null_mut(), 4), CORE_AFFINITY_ERROR_NULL_POINTER as isize);
+
+        let code = core_affinity_set_for_current(usize::MAX);
+        assert_eq!(code, CORE_AFFINITY_ERROR_INVALID_CORE);
+
+        let message = CStr::from_ptr(core_affinity_error_message(code));
+        assert_eq!(message.to_str().unwrap(), "invalid core");
+    }
+}
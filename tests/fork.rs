@@ -0,0 +1,93 @@
+#![cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux")))]
+
+extern crate core_affinity;
+extern crate libc;
+
+use std::mem;
+use std::thread;
+
+use core_affinity::raw::{cpu_set_t, from_cpu_set_t};
+use core_affinity::{CoreId, CpuSet, ErrorKind, ForkPolicy};
+
+// Forks and returns the mask the child starts with. The child
+// only makes async-signal-safe calls before it exits.
+fn child_mask() -> CpuSet {
+    unsafe {
+        let mut fds = [0; 2];
+        assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+
+        let pid = libc::fork();
+        assert!(pid >= 0);
+
+        if pid == 0 {
+            let mut set = mem::zeroed::<cpu_set_t>();
+            let size = mem::size_of::<cpu_set_t>();
+
+            let ok = libc::sched_getaffinity(0, size, &mut set) == 0
+                && libc::write(fds[1], &set as *const cpu_set_t as *const _, size) == size as isize;
+            libc::_exit(if ok { 0 } else { 1 });
+        }
+
+        libc::close(fds[1]);
+
+        let mut set = mem::zeroed::<cpu_set_t>();
+        let size = mem::size_of::<cpu_set_t>();
+        assert_eq!(libc::read(fds[0], &mut set as *mut cpu_set_t as *mut _, size), size as isize);
+        libc::close(fds[0]);
+
+        let mut status = 0;
+        assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+
+        from_cpu_set_t(&set)
+    }
+}
+
+// Reset may widen the mask past `allowed`, such as when the
+// tests run under `taskset`.
+fn assert_widened(mask: CpuSet, allowed: &CpuSet) {
+    assert_eq!(&mask & allowed, *allowed);
+}
+
+// The policy is shared by the whole process, so this file
+// holds a single test.
+#[test]
+fn test_at_fork_reset() {
+    thread::spawn(|| {
+        let allowed = core_affinity::get_core_set().unwrap();
+        let first = allowed.iter().next().unwrap();
+        let last = allowed.iter().last().unwrap();
+
+        assert!(core_affinity::set_for_current(first));
+
+        // Nothing happens before the handler is registered.
+        assert_eq!(child_mask(), CpuSet::from(vec![first]));
+
+        assert!(core_affinity::at_fork_reset().is_ok());
+        assert!(core_affinity::at_fork_reset().is_ok());
+
+        // The default policy widens the mask again.
+        assert_widened(child_mask(), &allowed);
+
+        core_affinity::set_fork_policy(ForkPolicy::Set(CpuSet::from(vec![last]))).unwrap();
+        assert_eq!(child_mask(), CpuSet::from(vec![last]));
+
+        core_affinity::set_fork_policy(ForkPolicy::Inherit).unwrap();
+        assert_eq!(child_mask(), CpuSet::from(vec![first]));
+
+        core_affinity::set_fork_policy(ForkPolicy::Reset).unwrap();
+        assert_widened(child_mask(), &allowed);
+
+        // The parent keeps its own mask throughout.
+        assert_eq!(core_affinity::get_affinity_for_current().unwrap(), vec![first]);
+
+        let err = core_affinity::set_fork_policy(ForkPolicy::Set(CpuSet::new())).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::EmptySet);
+
+        let err = core_affinity::set_fork_policy(ForkPolicy::Set(CpuSet::from(vec![CoreId { id: 1 << 20 }]))).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidCore);
+
+        // A failed call keeps the policy.
+        assert_widened(child_mask(), &allowed);
+    }).join().unwrap();
+}
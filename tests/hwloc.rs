@@ -0,0 +1,29 @@
+#![cfg(all(feature = "hwloc", not(feature = "mock")))]
+
+extern crate core_affinity;
+extern crate hwloc2;
+
+use std::thread;
+
+use core_affinity::CpuSet;
+use hwloc2::{ObjectType, Topology};
+
+// Topologies can not be shared between threads, so every
+// thread that pins itself loads its own.
+#[test]
+fn test_set_for_current_to_hwloc() {
+    for object_type in [ObjectType::Package, ObjectType::L3Cache, ObjectType::PU] {
+        thread::spawn(move || {
+            let topology = Topology::new().unwrap();
+            let objects = topology.objects_with_type(&object_type).unwrap();
+
+            // Not every machine has an L3 cache.
+            if let Some(object) = objects.first() {
+                let expected = CpuSet::from_hwloc(&object.cpuset().unwrap()).to_vec();
+
+                core_affinity::set_for_current_to_hwloc(object).unwrap();
+                assert_eq!(core_affinity::get_affinity_for_current().unwrap(), expected);
+            }
+        }).join().unwrap();
+    }
+}
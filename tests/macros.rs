@@ -0,0 +1,62 @@
+#![cfg(all(feature = "macros", not(feature = "mock")))]
+
+extern crate core_affinity;
+
+use std::thread;
+
+use core_affinity::CoreId;
+
+#[core_affinity::pinned(core = 0)]
+fn affinity() -> Vec<CoreId> {
+    core_affinity::get_affinity_for_current().unwrap()
+}
+
+#[core_affinity::pinned(core = 0, strict)]
+fn generic<T: Clone>(value: &T) -> (T, Vec<CoreId>) {
+    (value.clone(), core_affinity::get_affinity_for_current().unwrap())
+}
+
+#[core_affinity::pinned(core = 0)]
+fn early_return(fail: bool) -> Result<Vec<CoreId>, String> {
+    if fail {
+        return Err("failed".to_string());
+    }
+
+    let core_ids = core_affinity::get_affinity_for_current().ok_or("unreadable")?;
+    Ok(core_ids)
+}
+
+#[core_affinity::pinned(core = 100000)]
+fn lenient() -> Vec<CoreId> {
+    core_affinity::get_affinity_for_current().unwrap()
+}
+
+#[core_affinity::pinned(core = 100000, strict)]
+fn strict() {}
+
+#[test]
+fn test_pinned() {
+    thread::spawn(|| {
+        let before = core_affinity::get_affinity_for_current().unwrap();
+        let core_0 = vec![CoreId { id: 0 }];
+
+        assert_eq!(affinity(), core_0);
+        assert_eq!(generic(&"value"), ("value", core_0.clone()));
+        assert_eq!(early_return(false), Ok(core_0));
+        assert_eq!(early_return(true), Err("failed".to_string()));
+
+        // The previous affinity is back after every call.
+        assert_eq!(core_affinity::get_affinity_for_current().unwrap(), before);
+
+        // Without `strict`, the function runs unpinned.
+        assert_eq!(lenient(), before);
+    }).join().unwrap();
+}
+
+#[test]
+fn test_pinned_strict() {
+    let err = thread::spawn(strict).join().unwrap_err();
+    let message = err.downcast_ref::<String>().unwrap();
+
+    assert!(message.starts_with("failed to pin `strict` to core 100000: "));
+}
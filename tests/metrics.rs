@@ -0,0 +1,45 @@
+#![cfg(feature = "metrics")]
+
+extern crate core_affinity;
+
+use std::sync::mpsc;
+use std::thread;
+
+use core_affinity::{CoreId, ErrorKind};
+
+// The counters are shared by the whole process, so this file
+// holds a single test.
+#[test]
+fn test_metrics_snapshot() {
+    let id = core_affinity::get_core_ids().unwrap()[0];
+    let before = core_affinity::metrics_snapshot();
+
+    assert_eq!(before.attempted, 0);
+    assert_eq!(before.failed(), 0);
+
+    // Two pins of this thread count it once.
+    assert!(core_affinity::set_for_current(id));
+    assert!(core_affinity::set_for_current_checked(id).is_ok());
+    assert!(!core_affinity::set_for_current(CoreId { id: usize::MAX }));
+
+    thread::spawn(move || {
+        assert!(core_affinity::set_for_current(id));
+    }).join().unwrap();
+
+    // Another thread pinned from here is not counted as a
+    // thread, only as a pin.
+    let (tx, rx) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || rx.recv().unwrap());
+    assert!(core_affinity::set_for_thread(&handle, id));
+    tx.send(()).unwrap();
+    handle.join().unwrap();
+
+    let after = core_affinity::metrics_snapshot();
+    assert_eq!(after.attempted, 5);
+    assert_eq!(after.succeeded, 4);
+    assert_eq!(after.failed(), 1);
+    assert_eq!(after.failed_with(ErrorKind::InvalidCore), 1);
+    assert_eq!(after.failed_with(ErrorKind::PermissionDenied), 0);
+    assert_eq!(after.threads_pinned, 2);
+    assert_eq!(after.failed_by_kind.len(), 8);
+}
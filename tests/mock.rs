@@ -0,0 +1,1028 @@
+#![cfg(feature = "mock")]
+
+extern crate core_affinity;
+
+use std::process;
+use std::sync::{Mutex, MutexGuard, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use core_affinity::{CacheLevel, CacheType, CoreId, CoreKind, CoreState, CpuTimes, ErrorKind, KindSource, LoadSampler};
+use core_affinity::{NumaNode, PhysicalCoreId};
+use core_affinity::{CoreQuery, Order, PinPolicy, Priority, PriorityFallback, StableCoreId};
+#[cfg(feature = "serde")]
+use core_affinity::Topology;
+use core_affinity::mock::{self, Target};
+
+// The settings of the mock are shared by the whole process,
+// so the tests in this file take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> MutexGuard<'static, ()> {
+    let guard = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    mock::reset();
+    guard
+}
+
+fn core(id: usize) -> CoreId {
+    CoreId { id }
+}
+
+// Hands out the samples it was given, one per call.
+struct Scripted(Vec<Vec<(CoreId, CpuTimes)>>);
+
+impl LoadSampler for Scripted {
+    fn sample(&mut self) -> Result<Vec<(CoreId, CpuTimes)>, core_affinity::Error> {
+        Ok(self.0.remove(0))
+    }
+}
+
+#[test]
+fn test_mock_core_count() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::get_all_core_ids().unwrap().len(), mock::DEFAULT_CORE_COUNT);
+
+    mock::set_core_count(8);
+    assert_eq!(core_affinity::get_all_core_ids().unwrap().len(), 8);
+    assert_eq!(core_affinity::core_count(), Some(8));
+    assert_eq!(core_affinity::run_per_core(|core_id| core_id).len(), 8);
+
+    let err = core_affinity::set_for_current_checked(core(8)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidCore);
+    assert_eq!(err.core_id(), Some(core(8)));
+}
+
+#[test]
+fn test_mock_process_mask() {
+    let _serial = serial();
+
+    mock::set_process_mask(&[core(0), core(1)]);
+    assert_eq!(core_affinity::get_core_ids(), Some(vec![core(0), core(1)]));
+    assert_eq!(core_affinity::get_for_process(process::id()), Ok(vec![core(0), core(1)]));
+
+    let err = core_affinity::set_for_current_checked(core(3)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAllowed);
+    assert_eq!(core_affinity::set_for_current_with(core(3), PinPolicy::NearestAllowed), Ok(core(1)));
+
+    // Restricting the process resets every thread.
+    assert!(core_affinity::set_for_current(core(0)));
+    assert_eq!(core_affinity::set_for_process(process::id(), &[core(1), core(2)]), Ok(()));
+    assert_eq!(core_affinity::get_core_ids(), Some(vec![core(1), core(2)]));
+    assert_eq!(mock::set_calls().last().unwrap().target, Target::Process(process::id()));
+}
+
+#[test]
+fn test_mock_failures() {
+    let _serial = serial();
+
+    mock::fail_on(core(3), ErrorKind::PermissionDenied);
+
+    let err = core_affinity::set_for_current_checked(core(3)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert_eq!(err.core_id(), Some(core(3)));
+    assert!(!core_affinity::set_for_current_set(&[core(2), core(3)]));
+    assert!(core_affinity::set_for_current(core(2)));
+
+    let calls = mock::set_calls();
+    assert_eq!(calls.len(), 3);
+    assert_eq!(calls[0].result, Err(err));
+    assert!(calls[1].result.is_err());
+    assert_eq!(calls[2].result, Ok(()));
+
+    mock::clear_failures();
+    assert!(core_affinity::set_for_current(core(3)));
+}
+
+#[test]
+fn test_mock_priority() {
+    let _serial = serial();
+
+    core_affinity::set_for_current_with_priority(core(1), Priority::Realtime(10)).unwrap();
+    assert_eq!(mock::priority_for_current(), Some(Priority::Realtime(10)));
+
+    // An unprivileged thread keeps the pin by default, and
+    // learns that the priority failed.
+    mock::fail_priority(ErrorKind::PermissionDenied);
+
+    let err = core_affinity::set_for_current_with_priority(core(2), Priority::Realtime(20)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    assert!(err.is_priority_error());
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(2)]));
+    assert_eq!(mock::priority_for_current(), Some(Priority::Realtime(10)));
+
+    // It can carry on without the priority instead.
+    let fallback = PriorityFallback::IgnoreDenied;
+    assert_eq!(core_affinity::set_for_current_with_priority_or(core(3), Priority::Realtime(20), fallback), Ok(()));
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(3)]));
+    assert!(core_affinity::last_error().is_none());
+
+    // Or give the pin back.
+    let fallback = PriorityFallback::Revert;
+    let err = core_affinity::set_for_current_with_priority_or(core(0), Priority::High, fallback).unwrap_err();
+    assert!(err.is_priority_error());
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(3)]));
+
+    // Failing to pin leaves the priority alone.
+    mock::clear_failures();
+    mock::fail_on(core(1), ErrorKind::PermissionDenied);
+
+    let err = core_affinity::set_for_current_with_priority(core(1), Priority::Low).unwrap_err();
+    assert!(!err.is_priority_error());
+    assert_eq!(err.core_id(), Some(core(1)));
+    assert_eq!(mock::priority_for_current(), Some(Priority::Realtime(10)));
+}
+
+#[test]
+fn test_mock_threads() {
+    let _serial = serial();
+
+    // Threads running in parallel have masks of their own.
+    let handles = (0..mock::DEFAULT_CORE_COUNT).map(|id| {
+        thread::spawn(move || {
+            assert!(core_affinity::set_for_current(core(id)));
+            thread::yield_now();
+            assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(id)]));
+            assert_eq!(mock::set_calls().len(), 1);
+        })
+    }).collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(mock::all_set_calls().len(), mock::DEFAULT_CORE_COUNT);
+    assert!(mock::set_calls().is_empty());
+}
+
+#[test]
+fn test_mock_physical_cores() {
+    let _serial = serial();
+
+    // Without simultaneous multithreading, every core is a
+    // physical core of its own.
+    let cores = core_affinity::get_physical_cores().unwrap();
+    assert_eq!(cores.len(), mock::DEFAULT_CORE_COUNT);
+    assert!(cores.iter().all(|core| core.siblings().len() == 1));
+    assert_eq!(core_affinity::smt_active(), Some(false));
+
+    // Two threads per core, numbered apart, with one offline.
+    mock::set_core_count(8);
+    mock::set_siblings(&[vec![core(0), core(4)], vec![core(1), core(5)], vec![core(2), core(6)], vec![core(3), core(7)]]);
+    mock::set_online(&[core(0), core(1), core(2), core(3), core(4), core(5), core(6)]);
+
+    let cores = core_affinity::get_physical_cores().unwrap();
+    let siblings: Vec<_> = cores.iter().map(|core| core.siblings().to_vec()).collect();
+    assert_eq!(siblings, vec![vec![core(0), core(4)], vec![core(1), core(5)], vec![core(2), core(6)], vec![core(3)]]);
+    assert_eq!(siblings.iter().map(Vec::len).sum::<usize>(), 7);
+    assert_eq!(cores[2].id(), PhysicalCoreId { id: 2 });
+
+    assert_eq!(core_affinity::smt_siblings(core(1)), Some(vec![core(5)]));
+    assert_eq!(core_affinity::smt_siblings(core(5)), Some(vec![core(1)]));
+    assert_eq!(core_affinity::smt_siblings(core(3)), Some(vec![]));
+    assert_eq!(core_affinity::smt_siblings(core(7)), None);
+    assert_eq!(core_affinity::smt_active(), Some(true));
+}
+
+#[test]
+fn test_mock_pin_to_physical_core() {
+    let _serial = serial();
+
+    mock::set_core_count(8);
+    mock::set_siblings(&[vec![core(0), core(4)], vec![core(1), core(5)], vec![core(2), core(6)], vec![core(3), core(7)]]);
+
+    let first = PhysicalCoreId { id: 0 };
+
+    // The siblings are tried in order, skipping reserved ones.
+    assert_eq!(core_affinity::pin_to_physical_core(first), Ok(core(0)));
+
+    let reservation = core_affinity::reserve_specific(core(0)).unwrap();
+    assert_eq!(core_affinity::pin_to_physical_core(first), Ok(core(4)));
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(4)]));
+
+    // Only reserved siblings are left in the mask.
+    mock::set_process_mask(&[core(0), core(1), core(2)]);
+    assert_eq!(core_affinity::pin_to_physical_core(first), Ok(core(0)));
+    drop(reservation);
+
+    assert_eq!(core_affinity::pin_to_physical_core(PhysicalCoreId { id: 1 }), Ok(core(1)));
+
+    let err = core_affinity::pin_to_physical_core(PhysicalCoreId { id: 3 }).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAllowed);
+    assert_eq!(err.physical_core_id(), Some(PhysicalCoreId { id: 3 }));
+    assert_eq!(err.to_string(), "physical core 3: core not allowed");
+}
+
+#[test]
+fn test_mock_colocate_pair() {
+    let _serial = serial();
+
+    // Without simultaneous multithreading there are no pairs.
+    assert_eq!(core_affinity::colocate_pair().unwrap_err().kind(), ErrorKind::Unsupported);
+
+    mock::set_core_count(6);
+    mock::set_siblings(&[vec![core(0), core(3)], vec![core(1), core(4)], vec![core(2), core(5)]]);
+    mock::set_process_mask(&[core(0), core(1), core(2), core(3), core(4)]);
+
+    let physical = |core_id: CoreId| core_affinity::get_physical_cores().unwrap()
+        .into_iter()
+        .find(|core| core.siblings().contains(&core_id))
+        .unwrap()
+        .id();
+
+    // Each pair shares a physical core, and later calls move on.
+    let (a, b) = core_affinity::colocate_pair().unwrap();
+    assert_eq!((a.core_id(), b.core_id()), (core(0), core(3)));
+    assert_eq!(physical(a.core_id()), physical(b.core_id()));
+
+    let (c, d) = core_affinity::colocate_pair().unwrap();
+    assert_eq!((c.core_id(), d.core_id()), (core(1), core(4)));
+
+    // Core 5 is not allowed, so the last core has no pair, and
+    // its free sibling is not kept reserved.
+    assert_eq!(core_affinity::colocate_pair().unwrap_err().kind(), ErrorKind::EmptySet);
+    assert!(!core_affinity::is_reserved(core(2)));
+
+    drop((c, d));
+
+    let err = core_affinity::colocate_pair_on(PhysicalCoreId { id: 0 }).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::EmptySet);
+    assert_eq!(err.physical_core_id(), Some(PhysicalCoreId { id: 0 }));
+
+    let (c, d) = core_affinity::colocate_pair_on(PhysicalCoreId { id: 1 }).unwrap();
+    assert_eq!((c.core_id(), d.core_id()), (core(1), core(4)));
+
+    let err = core_affinity::colocate_pair_on(PhysicalCoreId { id: 3 }).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidCore);
+}
+
+#[test]
+fn test_mock_spread_across_physical_cores() {
+    let _serial = serial();
+
+    // Four physical cores with two threads each.
+    mock::set_core_count(8);
+    mock::set_siblings(&[vec![core(0), core(4)], vec![core(1), core(5)], vec![core(2), core(6)], vec![core(3), core(7)]]);
+
+    let spread = core_affinity::spread_across_physical_cores;
+
+    // Fewer threads than physical cores, and as many.
+    assert_eq!(spread(3), Ok(vec![core(0), core(1), core(2)]));
+    assert_eq!(spread(4), Ok(vec![core(0), core(1), core(2), core(3)]));
+
+    // More only then share physical cores.
+    assert_eq!(spread(6), Ok(vec![core(0), core(1), core(2), core(3), core(4), core(5)]));
+    assert_eq!(spread(8).unwrap().len(), 8);
+    assert_eq!(spread(9).unwrap_err().kind(), ErrorKind::TooFewCores);
+
+    // Cores outside the mask of the thread are left out.
+    assert!(core_affinity::set_for_current_set(&[core(0), core(4), core(5), core(6)]));
+    assert_eq!(spread(3), Ok(vec![core(0), core(5), core(6)]));
+    assert_eq!(spread(4), Ok(vec![core(0), core(5), core(6), core(4)]));
+    assert_eq!(spread(5).unwrap_err().kind(), ErrorKind::TooFewCores);
+}
+
+#[test]
+fn test_mock_numa_nodes() {
+    let _serial = serial();
+
+    // A machine without NUMA is a single node.
+    assert_eq!(core_affinity::numa_nodes(), Some(vec![NumaNode { id: 0 }]));
+
+    mock::set_core_count(8);
+    mock::set_numa_nodes(&[(0..4).map(core).collect(), (4..8).map(core).collect(), vec![]]);
+
+    let nodes = core_affinity::numa_nodes().unwrap();
+    assert_eq!(nodes, vec![NumaNode { id: 0 }, NumaNode { id: 1 }, NumaNode { id: 2 }]);
+
+    assert_eq!(core_affinity::cores_for_numa_node(NumaNode { id: 1 }), Some((4..8).map(core).collect()));
+    assert_eq!(core_affinity::cores_for_numa_node(NumaNode { id: 2 }), Some(vec![]));
+    assert_eq!(core_affinity::cores_for_numa_node(NumaNode { id: 3 }), None);
+
+    assert_eq!(core_affinity::numa_node_of(core(2)), Some(NumaNode { id: 0 }));
+    assert_eq!(core_affinity::numa_node_of(core(7)), Some(NumaNode { id: 1 }));
+    assert_eq!(core_affinity::numa_node_of(core(8)), None);
+
+    assert!(core_affinity::set_for_current(core(6)));
+    assert_eq!(core_affinity::current_location(), Some((core(6), NumaNode { id: 1 })));
+    assert_eq!(core_affinity::current_numa_node(), Some(NumaNode { id: 1 }));
+
+    // Pinning to a node leaves out the cores the process may
+    // not use.
+    mock::set_process_mask(&[core(0), core(1), core(5), core(6)]);
+    core_affinity::set_for_current_numa_node(NumaNode { id: 1 }).unwrap();
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(5), core(6)]));
+
+    mock::set_process_mask(&[core(0), core(1)]);
+    let err = core_affinity::set_for_current_numa_node(NumaNode { id: 1 }).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAllowed);
+    assert_eq!(err.to_string(), "NUMA node 1: core not allowed");
+
+    let err = core_affinity::set_for_current_numa_node(NumaNode { id: 2 }).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::EmptySet);
+    assert_eq!(err.numa_node(), Some(NumaNode { id: 2 }));
+}
+
+#[test]
+fn test_mock_packages() {
+    let _serial = serial();
+
+    let packages = core_affinity::packages().unwrap();
+    assert_eq!(packages.len(), 1);
+    assert_eq!(packages[0].1, core_affinity::get_all_core_ids().unwrap());
+
+    // One package split into two NUMA nodes, and a second one
+    // with a core that is offline.
+    mock::set_core_count(6);
+    mock::set_packages(&[(0..4).map(core).collect(), vec![core(4), core(5), core(9)]]);
+    mock::set_numa_nodes(&[vec![core(0), core(1)], vec![core(2), core(3)], vec![core(4), core(5)]]);
+
+    let packages = core_affinity::packages().unwrap();
+    let ids: Vec<_> = packages.iter().map(|(package, _)| package.id).collect();
+    assert_eq!(ids, vec![0, 1]);
+    assert_eq!(packages[1].1, vec![core(4), core(5)]);
+    assert_eq!(core_affinity::numa_nodes().unwrap().len(), 3);
+
+    assert_eq!(core_affinity::package_of(core(3)).map(|package| package.id), Some(0));
+    assert_eq!(core_affinity::package_of(core(5)).map(|package| package.raw_id()), Some(1));
+    assert_eq!(core_affinity::package_of(core(9)), None);
+}
+
+#[test]
+fn test_mock_dies() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::dies(), None);
+    assert_eq!(core_affinity::die_of(core(0)), None);
+
+    mock::set_core_count(8);
+    mock::set_dies(&[(0..4).map(core).collect(), (4..8).map(core).collect()]);
+
+    let dies = core_affinity::dies().unwrap();
+    assert_eq!(dies.len(), 2);
+    assert_eq!(dies[1].1, (4..8).map(core).collect::<Vec<_>>());
+    assert_eq!(core_affinity::die_of(core(5)).map(|die| die.id), Some(1));
+    assert_eq!(core_affinity::die_of(core(8)), None);
+}
+
+#[test]
+fn test_mock_distribute_with() {
+    let _serial = serial();
+
+    // 2 packages that take turns numbering their cores, each
+    // its own NUMA node, and the first with 2 dies.
+    let cores = |ids: &[usize]| ids.iter().cloned().map(core).collect::<Vec<_>>();
+    mock::set_core_count(8);
+    mock::set_packages(&[cores(&[0, 2, 4, 6]), cores(&[1, 3, 5, 7])]);
+    mock::set_numa_nodes(&[cores(&[0, 2, 4, 6]), cores(&[1, 3, 5, 7])]);
+    mock::set_dies(&[cores(&[0, 2]), cores(&[4, 6]), cores(&[1, 3, 5, 7])]);
+
+    let compact = core_affinity::distribute_with(2, core_affinity::Placement::Compact).unwrap();
+    assert_eq!(compact, vec![cores(&[0, 2, 4, 6]), cores(&[1, 3, 5, 7])]);
+
+    let scatter = core_affinity::distribute_with(2, core_affinity::Placement::Scatter).unwrap();
+    assert_eq!(scatter, vec![cores(&[0, 1, 2, 3]), cores(&[4, 5, 6, 7])]);
+
+    let interleave = core_affinity::Placement::Interleave(core_affinity::NumaGranularity::Node);
+    assert_eq!(core_affinity::distribute_with(1, interleave).unwrap(), vec![cores(&[0, 1, 2, 3, 4, 5, 6, 7])]);
+
+    let groups = core_affinity::distribute_within_dies(3).unwrap();
+    assert_eq!(groups, vec![cores(&[0, 2]), cores(&[4, 6]), cores(&[1, 3, 5, 7])]);
+}
+
+#[test]
+fn test_mock_clusters() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::clusters(), None);
+
+    // A big.LITTLE phone with 4 little cores and 4 big ones.
+    mock::set_core_count(8);
+    mock::set_clusters(&[(0..4).map(core).collect(), (4..8).map(core).collect()]);
+
+    let clusters = core_affinity::clusters().unwrap();
+    assert_eq!(clusters.len(), 2);
+    assert_eq!(clusters[0].1, (0..4).map(core).collect::<Vec<_>>());
+
+    let big = core_affinity::cluster_of(core(6)).unwrap();
+    assert_eq!(big.id, 1);
+
+    let allocator = core_affinity::CoreAllocator::new().unwrap().within_cluster(big);
+    assert_eq!(allocator.core_ids(), (4..8).map(core).collect::<Vec<_>>());
+    assert_eq!((0..5).map(|_| allocator.next()).collect::<Vec<_>>(), vec![core(4), core(5), core(6), core(7), core(4)]);
+
+    // A cluster with none of the cores changes nothing.
+    let allocator = core_affinity::CoreAllocator::from_core_ids(&[core(0), core(1)]).unwrap().within_cluster(big);
+    assert_eq!(allocator.core_ids(), vec![core(0), core(1)]);
+}
+
+#[test]
+fn test_mock_cache_clusters() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::cache_clusters(), None);
+
+    // Two CCXs of 4 cores.
+    mock::set_core_count(8);
+    mock::set_cache_groups(3, &[(4..8).map(core).collect(), (0..4).map(core).collect()]);
+
+    let clusters = core_affinity::cache_clusters().unwrap();
+    assert_eq!(clusters.len(), 2);
+    assert_eq!(clusters[0].1, (0..4).map(core).collect::<Vec<_>>());
+    assert_eq!(core_affinity::cache_cluster_of(core(5)).map(|cluster| cluster.id), Some(1));
+
+    let groups = core_affinity::distribute_within_cache_clusters(2).unwrap();
+    assert_eq!(groups, vec![(0..4).map(core).collect::<Vec<_>>(), (4..8).map(core).collect()]);
+
+    mock::set_process_mask(&[core(2), core(3), core(4), core(5), core(6)]);
+
+    let (first, second) = core_affinity::colocate_pair_within(core_affinity::PairScope::CacheCluster).unwrap();
+    assert_eq!((first.core_id(), second.core_id()), (core(2), core(3)));
+
+    let (third, fourth) = core_affinity::colocate_pair_within(core_affinity::PairScope::CacheCluster).unwrap();
+    assert_eq!((third.core_id(), fourth.core_id()), (core(4), core(5)));
+
+    let err = core_affinity::colocate_pair_within(core_affinity::PairScope::CacheCluster).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::EmptySet);
+}
+
+#[test]
+fn test_mock_l3_domains() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::l3_domains(), None);
+
+    // A monolithic L3 over 8 cores with an L2 per pair of them.
+    mock::set_core_count(8);
+    mock::set_cache_groups(2, &[vec![core(0), core(1)], vec![core(2), core(3)], vec![core(4), core(5)]]);
+    assert_eq!(core_affinity::l3_domains(), Some(vec![vec![core(0), core(1)], vec![core(2), core(3)],
+                                                      vec![core(4), core(5)], vec![core(6)], vec![core(7)]]));
+
+    mock::set_cache_groups(3, &[(0..8).map(core).collect()]);
+    assert_eq!(core_affinity::l3_domains(), Some(vec![(0..8).map(core).collect()]));
+
+    // Two CCDs, listed in any order and once for every core,
+    // with the threads numbered after the cores.
+    let ccds = [vec![core(2), core(3), core(6), core(7)], vec![core(0), core(1), core(4), core(5)]];
+    let cores = (0..8).map(|id| ccds[if id % 4 < 2 { 1 } else { 0 }].clone()).collect::<Vec<_>>();
+    mock::set_cache_groups(3, &cores);
+
+    assert_eq!(core_affinity::l3_domains(), Some(vec![ccds[1].clone(), ccds[0].clone()]));
+}
+
+#[test]
+fn test_mock_l2_groups() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::l2_groups(), None);
+
+    // An Alder Lake: 8 P-cores with 2 threads and a private L2
+    // each, numbered pair by pair, and 8 E-cores in modules of
+    // four.
+    mock::set_core_count(24);
+    let mut groups: Vec<Vec<CoreId>> = (0..8).map(|id| vec![core(id * 2), core(id * 2 + 1)]).collect();
+    groups.push((16..20).map(core).collect());
+    groups.push((20..24).map(core).collect());
+    mock::set_cache_groups(2, &groups);
+
+    assert_eq!(core_affinity::l2_groups(), Some(groups));
+
+    // Only the E-cores are left, so the pairs share a module.
+    mock::set_process_mask(&(15..24).map(core).collect::<Vec<_>>());
+
+    let scope = core_affinity::PairScope::L2;
+    let (first, second) = core_affinity::colocate_pair_within(scope).unwrap();
+    assert_eq!((first.core_id(), second.core_id()), (core(16), core(17)));
+
+    let (third, fourth) = core_affinity::colocate_pair_within(scope).unwrap();
+    assert_eq!((third.core_id(), fourth.core_id()), (core(18), core(19)));
+
+    let (fifth, _sixth) = core_affinity::colocate_pair_within(scope).unwrap();
+    assert_eq!(fifth.core_id(), core(20));
+}
+
+#[test]
+fn test_mock_cache_info() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::cache_info(core(0)), None);
+
+    let cache = |level, cache_type, size| CacheLevel {
+        level,
+        cache_type,
+        size: Some(size),
+        line_size: Some(64),
+        associativity: None,
+    };
+
+    mock::set_cache_info(&[
+        cache(2, CacheType::Unified, 512 << 10),
+        cache(1, CacheType::Data, 32 << 10),
+        cache(1, CacheType::Instruction, 32 << 10),
+    ]);
+
+    // Sorted by level and type.
+    let caches = core_affinity::cache_info(core(1)).unwrap();
+    let order: Vec<_> = caches.iter().map(|cache| (cache.level, cache.cache_type)).collect();
+    assert_eq!(order, vec![(1, CacheType::Instruction), (1, CacheType::Data), (2, CacheType::Unified)]);
+
+    // Offline cores have none.
+    assert_eq!(core_affinity::cache_info(core(mock::DEFAULT_CORE_COUNT)), None);
+}
+
+#[test]
+fn test_mock_core_kinds() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::cores_by_kind(), None);
+    assert_eq!(core_affinity::kind_of(core(0)), None);
+
+    // 2 P-cores and 2 E-cores, with a fifth core left out.
+    mock::set_core_count(5);
+    mock::set_core_kinds(&[
+        (core(0), CoreKind::Performance),
+        (core(1), CoreKind::Performance),
+        (core(2), CoreKind::Efficiency),
+        (core(3), CoreKind::Efficiency),
+    ], KindSource::Reported);
+
+    let by_kind = core_affinity::cores_by_kind().unwrap();
+    assert_eq!(by_kind[&CoreKind::Performance], vec![core(0), core(1)]);
+    assert_eq!(by_kind[&CoreKind::Efficiency], vec![core(2), core(3)]);
+    assert_eq!(by_kind[&CoreKind::Unknown], vec![core(4)]);
+
+    assert_eq!(core_affinity::kind_of(core(3)), Some(CoreKind::Efficiency));
+    assert_eq!(core_affinity::kind_of(core(5)), None);
+    assert_eq!(core_affinity::core_kind_source(), Some(KindSource::Reported));
+}
+
+#[test]
+fn test_mock_set_for_current_kind() {
+    let _serial = serial();
+
+    // Without efficiency cores, performance means every core.
+    mock::set_core_count(4);
+    let all: Vec<_> = (0..4).map(core).collect();
+    assert_eq!(core_affinity::set_for_current_kind(CoreKind::Performance), Ok(all.clone()));
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(all));
+
+    let err = core_affinity::set_for_current_kind(CoreKind::Efficiency).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    mock::set_core_kinds(&[(core(0), CoreKind::Performance), (core(1), CoreKind::Performance)], KindSource::Reported);
+    let err = core_affinity::set_for_current_kind(CoreKind::Efficiency).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    // Guessed efficiency cores are not pinned to.
+    mock::set_core_kinds(&[(core(0), CoreKind::Performance), (core(1), CoreKind::Efficiency)], KindSource::Heuristic);
+    let err = core_affinity::set_for_current_kind(CoreKind::Efficiency).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(core_affinity::set_for_current_kind(CoreKind::Performance), Ok(vec![core(0)]));
+
+    // A hybrid CPU, of which the process may not use core 1.
+    mock::set_core_count(6);
+    mock::set_core_kinds(&[
+        (core(0), CoreKind::Performance),
+        (core(1), CoreKind::Performance),
+        (core(2), CoreKind::Efficiency),
+        (core(3), CoreKind::Efficiency),
+        (core(4), CoreKind::Efficiency),
+        (core(5), CoreKind::Efficiency),
+    ], KindSource::Reported);
+    mock::set_process_mask(&[core(0), core(3), core(4)]);
+
+    assert_eq!(core_affinity::set_for_current_kind(CoreKind::Efficiency), Ok(vec![core(3), core(4)]));
+    assert_eq!(core_affinity::get_affinity_for_current(), Some(vec![core(3), core(4)]));
+    assert_eq!(core_affinity::pin_to_performance_cores(), Ok(vec![core(0)]));
+    assert_eq!(core_affinity::pin_to_efficiency_cores(), Ok(vec![core(3), core(4)]));
+
+    mock::set_process_mask(&[core(2)]);
+    let err = core_affinity::set_for_current_kind(CoreKind::Performance).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAllowed);
+
+    let err = core_affinity::set_for_current_kind(CoreKind::Unknown).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::EmptySet);
+}
+
+#[test]
+fn test_mock_max_frequency() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::cores_by_max_frequency(), None);
+
+    // Two favored cores boost higher than the rest, and core 3
+    // reports nothing.
+    mock::set_core_count(5);
+    mock::set_max_frequencies(&[(core(0), 4_500_000), (core(1), 5_000_000), (core(2), 4_500_000),
+                                (core(4), 5_000_000), (core(7), 6_000_000)]);
+
+    // Ties stay in ID order, and offline cores are left out.
+    let cores = core_affinity::cores_by_max_frequency().unwrap();
+    assert_eq!(cores, vec![core(1), core(4), core(0), core(2)]);
+
+    let frequencies: Vec<_> = cores.iter().map(|&core_id| core_affinity::max_frequency_khz(core_id).unwrap()).collect();
+    assert_eq!(frequencies, vec![5_000_000, 5_000_000, 4_500_000, 4_500_000]);
+
+    assert_eq!(core_affinity::max_frequency_khz(core(3)), None);
+    assert_eq!(core_affinity::max_frequency_khz(core(7)), None);
+}
+
+#[test]
+fn test_mock_favored_cores() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::favored_cores(), None);
+
+    mock::set_core_count(4);
+    mock::set_core_rankings(&[(core(0), 166), (core(1), 196), (core(2), 181), (core(3), 196), (core(9), 255)]);
+    assert_eq!(core_affinity::favored_cores(), Some(vec![core(1), core(3), core(2), core(0)]));
+
+    // Without a favored core, there is no ranking.
+    mock::set_core_rankings(&[(core(0), 68), (core(1), 68), (core(2), 68), (core(3), 68)]);
+    assert_eq!(core_affinity::favored_cores(), None);
+}
+
+#[test]
+fn test_mock_capacities() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::cores_by_capacity(), None);
+
+    // A prime core, 3 big and 4 little ones, numbered from the
+    // little cores up.
+    mock::set_core_count(8);
+    let capacities = [381, 381, 381, 381, 903, 903, 903, 1024];
+    mock::set_capacities(&capacities.iter().enumerate().map(|(id, &capacity)| (core(id), capacity)).collect::<Vec<_>>());
+
+    assert_eq!(core_affinity::capacity_of(core(7)), Some(1024));
+    assert_eq!(core_affinity::capacity_of(core(8)), None);
+
+    let cores = core_affinity::cores_by_capacity().unwrap();
+    assert_eq!(cores, vec![core(7), core(4), core(5), core(6), core(0), core(1), core(2), core(3)]);
+}
+
+#[test]
+fn test_mock_least_throttled_cores() {
+    let _serial = serial();
+
+    assert_eq!(core_affinity::least_throttled_cores(2), None);
+
+    mock::set_core_count(4);
+    mock::set_max_frequencies(&[(core(0), 4_800_000), (core(1), 4_800_000), (core(2), 4_800_000), (core(3), 3_000_000)]);
+    mock::set_current_frequencies(&[(core(0), 2_400_000), (core(1), 4_800_000), (core(2), 1_200_000), (core(3), 2_700_000)]);
+
+    assert_eq!(core_affinity::current_frequency_khz(core(2)), Some(1_200_000));
+    assert_eq!(core_affinity::current_frequency_khz(core(4)), None);
+    assert_eq!(core_affinity::least_throttled_cores(2), Some(vec![core(1), core(3)]));
+    assert_eq!(core_affinity::least_throttled_cores(8).unwrap().len(), 4);
+
+    // Only the cores of the process are loaded, each by a thread
+    // of its own.
+    mock::set_process_mask(&[core(0), core(1), core(3)]);
+    mock::clear_set_calls();
+
+    let frequencies = core_affinity::sample_busy(Duration::from_millis(1)).unwrap();
+    assert_eq!(frequencies, vec![(core(0), 2_400_000), (core(1), 4_800_000), (core(3), 2_700_000)]);
+
+    let pinned: Vec<_> = mock::all_set_calls().into_iter().map(|call| call.core_ids).collect();
+    assert_eq!(pinned, vec![vec![core(0)], vec![core(1)], vec![core(3)]]);
+
+    let cores = core_affinity::least_throttled_cores_under_load(1, Duration::from_millis(1));
+    assert_eq!(cores, Some(vec![core(1)]));
+}
+
+#[test]
+fn test_mock_least_loaded_cores() {
+    let _serial = serial();
+
+    let err = core_affinity::least_loaded_cores(1, Duration::from_millis(0)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+
+    // The counters of the mock stand still, so every core was
+    // idle over the window.
+    mock::set_core_count(4);
+    mock::set_cpu_times(&[
+        (core(0), CpuTimes { user: 900, system: 50, idle: 50 }),
+        (core(1), CpuTimes { user: 100, system: 100, idle: 800 }),
+        (core(2), CpuTimes { user: 0, system: 0, idle: 0 }),
+        (core(3), CpuTimes { user: 300, system: 0, idle: 700 }),
+    ]);
+
+    mock::set_process_mask(&[core(1), core(2), core(3)]);
+    let cores = core_affinity::least_loaded_cores(2, Duration::from_millis(1)).unwrap();
+    assert_eq!(cores, vec![core(1), core(2)]);
+
+    // Samplers of their own move the counters on.
+    let mut sampler = Scripted(vec![
+        vec![(core(1), CpuTimes { user: 0, system: 0, idle: 0 }), (core(3), CpuTimes { user: 0, system: 0, idle: 0 })],
+        vec![(core(1), CpuTimes { user: 70, system: 20, idle: 10 }), (core(3), CpuTimes { user: 5, system: 5, idle: 90 })],
+    ]);
+
+    let cores = core_affinity::least_loaded_cores_with(&mut sampler, 4, Duration::from_millis(0)).unwrap();
+    assert_eq!(cores, vec![core(3), core(1)]);
+
+    mock::set_core_count(8);
+    mock::set_process_mask(&[core(4)]);
+    let err = core_affinity::least_loaded_cores(1, Duration::from_millis(0)).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotAllowed);
+}
+
+#[test]
+fn test_mock_utilization_snapshot() {
+    let _serial = serial();
+
+    mock::set_core_count(3);
+    mock::set_process_mask(&[core(0), core(2)]);
+    mock::set_cpu_times(&[
+        (core(2), CpuTimes { user: 30, system: 10, idle: 60 }),
+        (core(0), CpuTimes { user: 10, system: 0, idle: 90 }),
+        (core(1), CpuTimes { user: 50, system: 50, idle: 0 }),
+    ]);
+
+    // Cores the process may not run on are still reported.
+    let loads = core_affinity::utilization_snapshot().unwrap();
+    let allowed: Vec<_> = loads.iter().map(|load| (load.core_id, load.allowed)).collect();
+    assert_eq!(allowed, vec![(core(0), true), (core(1), false), (core(2), true)]);
+
+    // Nothing has moved since.
+    let later = core_affinity::utilization_snapshot().unwrap();
+    assert!(later.iter().all(|load| load.idle == 100.0));
+    assert_eq!(later[1].times(), CpuTimes { user: 50, system: 50, idle: 0 });
+    assert_eq!(later[1].since(&loads[1]).idle, 100.0);
+}
+
+#[test]
+fn test_mock_distance_hybrid() {
+    let _serial = serial();
+
+    // 4 P-cores with 2 threads each and 8 E-cores in 2 modules
+    // that each share an L2, all on one L3.
+    let cores = |ids: std::ops::Range<usize>| ids.map(core).collect::<Vec<_>>();
+    mock::set_core_count(16);
+    mock::set_siblings(&[cores(0..2), cores(2..4), cores(4..6), cores(6..8)]);
+    mock::set_cache_groups(2, &[cores(0..2), cores(2..4), cores(4..6), cores(6..8), cores(8..12), cores(12..16)]);
+    mock::set_cache_groups(3, &[cores(0..16)]);
+    mock::set_packages(&[cores(0..16)]);
+
+    let distance = |a, b| core_affinity::distance(core(a), core(b));
+    assert_eq!(distance(0, 0), Some(0));
+    assert_eq!(distance(0, 1), Some(1));
+    assert_eq!(distance(0, 2), Some(3));
+    assert_eq!(distance(8, 11), Some(2));
+    assert_eq!(distance(8, 12), Some(3));
+    assert_eq!(distance(7, 15), Some(3));
+    assert_eq!(distance(0, 16), None);
+}
+
+#[test]
+fn test_mock_distance_dual_socket() {
+    let _serial = serial();
+
+    // 2 sockets of 8 cores, each its own NUMA node, in CCXs of
+    // 4 cores, without SMT and with a private L2 for each core.
+    let cores = |ids: std::ops::Range<usize>| ids.map(core).collect::<Vec<_>>();
+    mock::set_core_count(16);
+    mock::set_cache_groups(2, &(0..16).map(|id| vec![core(id)]).collect::<Vec<_>>());
+    mock::set_cache_groups(3, &[cores(0..4), cores(4..8), cores(8..12), cores(12..16)]);
+    mock::set_packages(&[cores(0..8), cores(8..16)]);
+    mock::set_numa_nodes(&[cores(0..8), cores(8..16)]);
+
+    let distance = |a, b| core_affinity::distance(core(a), core(b));
+    assert_eq!(distance(3, 3), Some(0));
+    assert_eq!(distance(0, 3), Some(3));
+    assert_eq!(distance(0, 4), Some(4));
+    assert_eq!(distance(4, 0), Some(4));
+    assert_eq!(distance(0, 8), Some(5));
+    assert_eq!(distance(15, 7), Some(5));
+
+    // Without the packages, the NUMA nodes still tell.
+    mock::set_packages(&[]);
+    assert_eq!(distance(0, 4), Some(4));
+}
+
+#[test]
+fn test_mock_get_core_ids_ordered() {
+    let _serial = serial();
+
+    // 2 sockets that take turns numbering their cores, with
+    // SMT siblings 4 apart.
+    let cores = |ids: &[usize]| ids.iter().cloned().map(core).collect::<Vec<_>>();
+    let siblings = [cores(&[0, 4]), cores(&[2, 6]), cores(&[1, 5]), cores(&[3, 7])];
+    mock::set_core_count(8);
+    mock::set_siblings(&siblings);
+    mock::set_cache_groups(2, &siblings);
+    mock::set_cache_groups(3, &[cores(&[0, 2, 4, 6]), cores(&[1, 3, 5, 7])]);
+    mock::set_packages(&[cores(&[0, 2, 4, 6]), cores(&[1, 3, 5, 7])]);
+
+    let ordered = |order| core_affinity::get_core_ids_ordered(order).unwrap();
+    assert_eq!(ordered(Order::Numeric), cores(&[0, 1, 2, 3, 4, 5, 6, 7]));
+    assert_eq!(ordered(Order::PackageMajor), cores(&[0, 2, 4, 6, 1, 3, 5, 7]));
+    assert_eq!(ordered(Order::CacheMajor), cores(&[0, 4, 2, 6, 1, 5, 3, 7]));
+    assert_eq!(ordered(Order::SmtLast), cores(&[0, 1, 2, 3, 4, 5, 6, 7]));
+
+    // Only the cores of the current thread are listed.
+    assert!(core_affinity::set_for_current_set(&cores(&[1, 2, 5, 6])));
+    assert_eq!(ordered(Order::PackageMajor), cores(&[2, 6, 1, 5]));
+    assert_eq!(ordered(Order::SmtLast), cores(&[1, 2, 5, 6]));
+    assert_eq!(ordered(Order::PackageMajor), ordered(Order::PackageMajor));
+}
+
+#[test]
+fn test_mock_core_query() {
+    let _serial = serial();
+
+    let cores = |ids: &[usize]| ids.iter().cloned().map(core).collect::<Vec<_>>();
+    mock::set_core_count(8);
+
+    // Without the kinds, the filter fails rather than matching
+    // every core.
+    let err = CoreQuery::new().kind(CoreKind::Performance).collect().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Unsupported);
+    assert_eq!(err.filter(), Some("kind"));
+
+    // A machine without NUMA has a single node.
+    assert_eq!(CoreQuery::new().numa_node(NumaNode { id: 0 }).collect().unwrap().len(), 8);
+
+    // 4 P-cores and 4 E-cores, in pairs of SMT siblings, with a
+    // NUMA node for each half of either kind.
+    mock::set_siblings(&[cores(&[0, 1]), cores(&[2, 3]), cores(&[4, 5]), cores(&[6, 7])]);
+    mock::set_numa_nodes(&[cores(&[0, 1, 4, 5]), cores(&[2, 3, 6, 7])]);
+    let kinds: Vec<_> = (0..8).map(|id| (core(id), if id < 4 { CoreKind::Performance } else { CoreKind::Efficiency })).collect();
+    mock::set_core_kinds(&kinds, KindSource::Reported);
+
+    // Each filter on its own.
+    assert_eq!(CoreQuery::new().collect(), Ok(cores(&[0, 1, 2, 3, 4, 5, 6, 7])));
+    assert_eq!(CoreQuery::new().online().collect(), Ok(cores(&[0, 1, 2, 3, 4, 5, 6, 7])));
+    assert_eq!(CoreQuery::new().kind(CoreKind::Efficiency).collect(), Ok(cores(&[4, 5, 6, 7])));
+    assert_eq!(CoreQuery::new().numa_node(NumaNode { id: 1 }).collect(), Ok(cores(&[2, 3, 6, 7])));
+    assert_eq!(CoreQuery::new().one_per_physical_core().collect(), Ok(cores(&[0, 2, 4, 6])));
+
+    mock::set_process_mask(&cores(&[1, 2, 3, 7]));
+    assert_eq!(CoreQuery::new().allowed().collect(), Ok(cores(&[1, 2, 3, 7])));
+
+    // All of them together.
+    let query = CoreQuery::new().allowed().online().kind(CoreKind::Performance).numa_node(NumaNode { id: 0 });
+    assert_eq!(query.clone().collect(), Ok(cores(&[1])));
+    assert_eq!(query.one_per_physical_core().collect(), Ok(cores(&[1])));
+
+    let query = CoreQuery::new().allowed().kind(CoreKind::Performance).one_per_physical_core();
+    assert_eq!(query.collect(), Ok(cores(&[1, 2])));
+    assert_eq!(CoreQuery::new().allowed().kind(CoreKind::Unknown).collect(), Ok(vec![]));
+
+    let missing = NumaNode { id: 5 };
+    let err = CoreQuery::new().numa_node(missing).collect().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidCore);
+    assert_eq!(err.numa_node(), Some(missing));
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_mock_topology_json() {
+    let _serial = serial();
+
+    // A dual-socket machine with 2 threads per physical core, a
+    // die, a shared L3 and a NUMA node per socket and no clusters,
+    // which the process may only run on half of.
+    let cores = |ids: &[usize]| ids.iter().cloned().map(core).collect::<Vec<_>>();
+    let siblings = [cores(&[0, 1]), cores(&[2, 3]), cores(&[4, 5]), cores(&[6, 7])];
+    mock::set_core_count(8);
+    mock::set_process_mask(&cores(&[0, 1, 2, 3]));
+    mock::set_siblings(&siblings);
+    mock::set_cache_groups(2, &siblings);
+    mock::set_cache_groups(3, &[cores(&[0, 1, 2, 3]), cores(&[4, 5, 6, 7])]);
+    mock::set_packages(&[cores(&[0, 1, 2, 3]), cores(&[4, 5, 6, 7])]);
+    mock::set_dies(&[cores(&[0, 1, 2, 3]), cores(&[4, 5, 6, 7])]);
+    mock::set_numa_nodes(&[cores(&[0, 1, 2, 3]), cores(&[4, 5, 6, 7])]);
+    mock::set_max_frequencies(&(0..8).map(|id| (core(id), 3_500_000)).collect::<Vec<_>>());
+
+    let topology = Topology::detect().unwrap();
+    assert_eq!(topology.to_json(), include_str!("data/topology.json").trim_end());
+
+    // The queries answer from what was read.
+    mock::set_core_count(2);
+    assert_eq!(topology.cores().len(), 8);
+    assert_eq!(topology.smt_siblings(core(5)), Some(vec![core(4)]));
+    assert_eq!(topology.numa_nodes()[1], (NumaNode { id: 1 }, cores(&[4, 5, 6, 7])));
+    assert_eq!(topology.cache_clusters().len(), 2);
+    assert_eq!(topology.packages()[0].1, cores(&[0, 1, 2, 3]));
+    assert_eq!(topology.dies()[1].1, cores(&[4, 5, 6, 7]));
+    assert!(topology.clusters().is_empty());
+    assert!(!topology.core(core(6)).unwrap().allowed);
+    assert_eq!(topology.kind_source(), None);
+}
+
+#[test]
+fn test_mock_get_core_ids_by_state() {
+    let _serial = serial();
+
+    // Room for 16 cores, 8 of them in the machine and core 6
+    // taken offline.
+    let cores = |ids: &[usize]| ids.iter().cloned().map(core).collect::<Vec<_>>();
+    mock::set_online(&cores(&[0, 1, 2, 3, 4, 5, 7]));
+    mock::set_core_states(&cores(&[6]), &cores(&[8, 9, 10, 11, 12, 13, 14, 15]));
+
+    let by_state = core_affinity::get_core_ids_by_state;
+    assert_eq!(by_state(CoreState::Online), Some(cores(&[0, 1, 2, 3, 4, 5, 7])));
+    assert_eq!(by_state(CoreState::Offline), Some(cores(&[6, 8, 9, 10, 11, 12, 13, 14, 15])));
+    assert_eq!(by_state(CoreState::Present), Some(cores(&[0, 1, 2, 3, 4, 5, 6, 7])));
+    assert_eq!(by_state(CoreState::Possible), Some((0..16).map(core).collect()));
+
+    // Only the online cores are handed out or pinned to.
+    assert_eq!(core_affinity::get_core_ids(), by_state(CoreState::Online));
+    assert_eq!(core_affinity::get_all_core_ids(), by_state(CoreState::Online));
+    assert_eq!(core_affinity::max_core_id(), Some(core(7)));
+    assert_eq!(core_affinity::set_for_current_checked(core(6)).unwrap_err().kind(), ErrorKind::InvalidCore);
+
+    // Without offline cores, every list is the online one.
+    mock::reset();
+    assert_eq!(by_state(CoreState::Offline), Some(vec![]));
+    assert_eq!(by_state(CoreState::Possible), core_affinity::get_all_core_ids());
+}
+
+#[test]
+fn test_mock_is_online() {
+    let _serial = serial();
+
+    mock::set_online(&[core(0), core(1), core(3)]);
+    core_affinity::refresh_core_ids();
+
+    assert_eq!(core_affinity::is_online(core(1)), Some(true));
+    assert_eq!(core_affinity::is_online(core(2)), Some(false));
+    assert_eq!(core_affinity::is_online(core(3)), Some(true));
+    assert_eq!(core_affinity::is_online(core(4)), Some(false));
+    assert_eq!(core_affinity::is_online(core(usize::MAX)), Some(false));
+
+    // The online cores are kept until the next refresh.
+    mock::set_online(&[core(0), core(1), core(2), core(3)]);
+    assert_eq!(core_affinity::is_online(core(2)), Some(false));
+
+    core_affinity::refresh_core_ids();
+    assert_eq!(core_affinity::is_online(core(2)), Some(true));
+
+    mock::reset();
+    core_affinity::refresh_core_ids();
+}
+
+#[test]
+fn test_mock_watch_topology() {
+    let _serial = serial();
+
+    let (tx, rx) = mpsc::channel();
+    let watch = core_affinity::watch_topology_every(Duration::from_millis(5), move |change| tx.send(change).unwrap());
+
+    // A vCPU is added and another one removed.
+    mock::set_online(&[core(0), core(1), core(3), core(4)]);
+
+    let change = rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(change.added, vec![core(4)]);
+    assert_eq!(change.removed, vec![core(2)]);
+
+    // The caches were refreshed before the callback.
+    assert_eq!(core_affinity::is_online(core(4)), Some(true));
+    assert_eq!(core_affinity::cached_core_ids(), &[core(0), core(1), core(3), core(4)][..]);
+
+    // Once the handle is dropped, the callback is gone too.
+    drop(watch);
+    mock::set_core_count(2);
+    assert_eq!(rx.recv(), Err(mpsc::RecvError));
+
+    mock::reset();
+    core_affinity::refresh_core_ids();
+}
+
+#[test]
+fn test_mock_stable_core_id() {
+    let _serial = serial();
+
+    // Two physical cores with two threads each, numbered apart.
+    mock::set_hardware_ids(&[(core(0), (0, 0, 0)), (core(1), (0, 0, 1)), (core(2), (0, 0, 0)), (core(3), (0, 0, 1))]);
+
+    let plan: Vec<StableCoreId> = (0..4).map(|id| core(id).stable().unwrap()).collect();
+    assert_eq!(plan[2], StableCoreId { package: 0, die: 0, core: 0, thread: 1 });
+    assert_eq!(core(4).stable(), None);
+
+    // After a migration, the same cores are numbered thread by
+    // thread.
+    mock::set_hardware_ids(&[(core(0), (0, 0, 0)), (core(1), (0, 0, 0)), (core(2), (0, 0, 1)), (core(3), (0, 0, 1))]);
+
+    let resolved: Vec<_> = plan.iter().map(|id| id.resolve()).collect();
+    assert_eq!(resolved, vec![Some(core(0)), Some(core(2)), Some(core(1)), Some(core(3))]);
+
+    // The first thread of physical core 0 going offline does not
+    // move the second one.
+    mock::set_online(&[core(1), core(2), core(3)]);
+    assert_eq!(plan[0].resolve(), None);
+    assert_eq!(plan[2].resolve(), Some(core(1)));
+    assert_eq!(core(1).stable(), Some(plan[2]));
+
+    // Physical core 1 is gone.
+    mock::set_core_count(2);
+    assert_eq!(plan[1].resolve(), None);
+    assert_eq!(plan[3].resolve(), None);
+    assert_eq!(plan[2].resolve(), Some(core(1)));
+
+    // Without the hardware IDs, nothing resolves.
+    mock::reset();
+    assert_eq!(plan[0].resolve(), None);
+    assert_eq!(core(0).stable(), None);
+}
@@ -0,0 +1,92 @@
+#![cfg(all(not(feature = "mock"), any(target_os = "android", target_os = "linux", target_os = "windows", target_os = "freebsd")))]
+
+extern crate core_affinity;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+extern crate libc;
+
+use std::process::{Child, Command};
+
+// Spawn a child that stays alive until it is killed.
+#[cfg(not(target_os = "windows"))]
+fn spawn_child() -> Child {
+    Command::new("sleep").arg("10").spawn().unwrap()
+}
+
+#[cfg(target_os = "windows")]
+fn spawn_child() -> Child {
+    Command::new("ping").args(&["-n", "10", "127.0.0.1"]).spawn().unwrap()
+}
+
+#[test]
+fn test_set_and_get_for_process() {
+    let id = *core_affinity::get_core_ids().unwrap().last().unwrap();
+    let mut child = spawn_child();
+
+    let set = core_affinity::set_for_process(child.id(), &[id]);
+    let get = core_affinity::get_for_process(child.id());
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert!(set.is_ok());
+    assert_eq!(get, Ok(vec![id]));
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn test_get_for_process_exited() {
+    use core_affinity::ErrorKind;
+
+    let mut child = spawn_child();
+    let pid = child.id();
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    // Once reaped, the ID no longer names a process.
+    let err = core_affinity::get_for_process(pid).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+#[test]
+fn test_set_for_current_excluding_with_main_thread_pinned() {
+    use std::mem;
+
+    use core_affinity::ErrorKind;
+
+    let ids = core_affinity::get_core_ids().unwrap();
+    let pid = std::process::id();
+
+    // Pin the main thread, which the test harness leaves
+    // waiting, to the first core. Its thread ID is the one of
+    // the process.
+    unsafe {
+        let mut set = mem::zeroed::<libc::cpu_set_t>();
+        libc::CPU_SET(ids[0].id, &mut set);
+        assert_eq!(libc::sched_setaffinity(pid as libc::pid_t, mem::size_of::<libc::cpu_set_t>(), &set), 0);
+    }
+
+    let process = core_affinity::get_for_process(pid);
+    let excluded = core_affinity::set_for_current_excluding(&ids[..1]);
+    let current = core_affinity::get_affinity_for_current();
+
+    unsafe {
+        let mut set = mem::zeroed::<libc::cpu_set_t>();
+        for core_id in &ids {
+            libc::CPU_SET(core_id.id, &mut set);
+        }
+        libc::sched_setaffinity(pid as libc::pid_t, mem::size_of::<libc::cpu_set_t>(), &set);
+    }
+
+    // The other threads still may run on every core.
+    assert_eq!(process, Ok(ids.clone()));
+
+    if ids.len() > 1 {
+        assert_eq!(excluded, Ok(()));
+        assert_eq!(current, Some(ids[1..].to_vec()));
+    }
+    else {
+        assert_eq!(excluded.unwrap_err().kind(), ErrorKind::EmptySet);
+    }
+}
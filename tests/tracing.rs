@@ -0,0 +1,135 @@
+#![cfg(feature = "tracing")]
+
+extern crate core_affinity;
+extern crate tracing;
+
+use std::fmt;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+use core_affinity::CoreId;
+
+// The level and fields of one event, formatted as text.
+#[derive(Debug)]
+struct Captured {
+    level: Level,
+    fields: Vec<(String, String)>,
+}
+
+impl Captured {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|field| field.0 == name).map(|field| field.1.as_str())
+    }
+}
+
+struct Fields<'a>(&'a mut Vec<(String, String)>);
+
+impl<'a> Visit for Fields<'a> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name().to_string(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+#[derive(Clone, Default)]
+struct Capture {
+    events: Arc<Mutex<Vec<Captured>>>,
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &Attributes) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event) {
+        let mut fields = Vec::new();
+        event.record(&mut Fields(&mut fields));
+
+        self.events.lock().unwrap().push(Captured { level: *event.metadata().level(), fields });
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn test_tracing_events() {
+    let capture = Capture::default();
+    let events = capture.events.clone();
+    let id = core_affinity::get_core_ids().unwrap()[0];
+    let all = core_affinity::format_cpulist(&core_affinity::get_core_ids().unwrap());
+    // Representable, but not online here, so the OS rejects it.
+    let offline = CoreId { id: 1023 };
+    let mut os_error = None;
+
+    // The subscriber only sees the events of this thread.
+    tracing::subscriber::with_default(capture, || {
+        assert!(core_affinity::set_for_current(id));
+        assert!(!core_affinity::set_for_current(offline));
+        os_error = core_affinity::last_error().and_then(|err| err.raw_os_error());
+
+        let (tx, rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || rx.recv().unwrap());
+        assert!(core_affinity::set_for_thread(&handle, id));
+        tx.send(()).unwrap();
+        handle.join().unwrap();
+    });
+
+    let events = events.lock().unwrap();
+    let current = format!("{:?}", thread::current().id());
+    assert_eq!(events.len(), 3);
+
+    assert_eq!(events[0].level, Level::DEBUG);
+    assert_eq!(events[0].field("thread"), Some(current.as_str()));
+    assert_eq!(events[0].field("core"), Some(id.id.to_string().as_str()));
+    assert_eq!(events[0].field("previous"), Some(all.as_str()));
+    assert_eq!(events[0].field("result"), Some("ok"));
+    assert_eq!(events[0].field("os_error"), None);
+
+    assert_eq!(events[1].level, Level::WARN);
+    assert_eq!(events[1].field("thread"), Some(current.as_str()));
+    assert_eq!(events[1].field("core"), Some("1023"));
+    assert_eq!(events[1].field("previous"), Some(id.to_string().as_str()));
+    assert!(events[1].field("result").unwrap().starts_with("core 1023: "));
+    assert_eq!(events[1].field("os_error"), os_error.map(|code| code.to_string()).as_deref());
+
+    assert_eq!(events[2].level, Level::DEBUG);
+    assert_ne!(events[2].field("thread"), Some(current.as_str()));
+    assert_eq!(events[2].field("core"), Some(id.id.to_string().as_str()));
+    assert_eq!(events[2].field("result"), Some("ok"));
+}
+
+#[test]
+fn test_tracing_unpinned() {
+    let capture = Capture::default();
+    let events = capture.events.clone();
+
+    tracing::subscriber::with_default(capture, || {
+        assert!(core_affinity::__pin_function("work", 1023, false).is_none());
+    });
+
+    // The failed pin, and then the warning that the function
+    // runs unpinned.
+    let events = events.lock().unwrap();
+    let last = events.last().unwrap();
+
+    assert_eq!(last.level, Level::WARN);
+    assert_eq!(last.field("message"), Some("failed to pin `work` to core 1023, running unpinned"));
+    assert!(last.field("result").unwrap().starts_with("core 1023: "));
+}